@@ -68,6 +68,8 @@ what="$base --count-samples --words"
 $what calc/ceec-samples-vs-words.json
 what="$base --count-samples"
 $what calc/ceec-samples-vs-tokens.json
+what="$base --samples"
+$what calc/ceec-types-vs-samples.json
 what="$base --type-ratio"
 $what calc/ceec-type-ratio-none.json
 what="$base --type-ratio --split-samples"
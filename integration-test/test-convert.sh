@@ -9,4 +9,7 @@ mkdir -p convert
 ../types3-convert ../data-format/example-samples.csv ../data-format/example-tokens.csv convert/example.json
 diff ../data-format/example.json convert/example.json
 
+../types3-import ../data-format/example-long.csv convert/example-long.json
+python3 -c 'import json, sys; assert json.load(open(sys.argv[1])) == json.load(open(sys.argv[2]))' ../data-format/example.json convert/example-long.json
+
 echo "SUCCESS: all results agree."
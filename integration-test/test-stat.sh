@@ -12,6 +12,8 @@ $what stat/ceec.xlsx
 $what stat/ceec-ity.xlsx --restrict-tokens variant=ity
 $what stat/ceec-female.xlsx --restrict-samples gender=female
 $what stat/ceec-ity-female.xlsx --restrict-tokens variant=ity --restrict-samples gender=female
+$what stat/ceec-crosstab.xlsx --crosstab gender socmob
+$what stat/ceec-1720.xlsx --only-period 1720:1739
 
 base="../types3-stat --window 50 --step 10 ../sample-data/ced-ppceme-chelar.json"
 $what stat/ced-ppceme-chelar.xlsx
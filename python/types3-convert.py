@@ -44,7 +44,7 @@ def convert(args):
             sample = dict(
                 id=r['id'],
                 words=int(r['words']),
-                year=int(r['year']),
+                year=int(r['year']) if r['year'] else None,
                 metadata=metadata,
                 tokens=[],
             )
@@ -35,7 +35,7 @@ def plot(args):
         matplotlib.rcParams['axes.titlepad'] = 10
     logging.info(f'read: {args.infile}')
     with open(args.infile) as f:
-        data = json.load(f)
+        data = types3.plot.unwrap(json.load(f))
     if args.outfile.endswith('.txt'):
         logging.info('export...')
         text = types3.plot.text(data)
@@ -259,7 +259,8 @@ class App:
         self.sample_metadata = defaultdict(set)
         self.token_metadata = defaultdict(set)
         for s in data['samples']:
-            years.add(s['year'])
+            if s.get('year') is not None:
+                years.add(s['year'])
             for k, v in s['metadata'].items():
                 self.sample_metadata[k].add(v)
             for t in s['tokens']:
@@ -56,6 +56,13 @@ DIMS_PLOT_WIDE = Dims(
 )
 
 
+def unwrap(data):
+    # Output written with --envelope
+    if 'format_version' in data:
+        return data['output']
+    return data
+
+
 def _catname(cats):
     s = []
     for cat in cats:
@@ -153,6 +160,36 @@ def _title(data):
         else:
             what = _upcase(mark_tokens[1])
             return f'{what} types in subcorpora with {limit} total {measure_x}'
+    elif measure_y == 'markedtokens':
+        if mark_tokens is None:
+            return f'Tokens in subcorpora with {limit} {measure_x}'
+        else:
+            what = _upcase(mark_tokens[1])
+            return f'{what} tokens in subcorpora with {limit} total {measure_x}'
+    elif measure_y == 'meanfrequency':
+        return f'Mean frequency (tokens per type × 1000) in subcorpora with {limit} {measure_x}'
+    elif measure_y == 'hapaxtokenratio':
+        return f'Hapaxes per token × 1000 in subcorpora with {limit} {measure_x}'
+    elif measure_y == 'typetokenratio':
+        return f'Types per token × 10000 in subcorpora with {limit} {measure_x}'
+    elif measure_y == 'yulek':
+        return f"Yule's K × 100 in subcorpora with {limit} {measure_x}"
+    elif measure_y == 'entropy':
+        return f'Entropy (millibits) in subcorpora with {limit} {measure_x}'
+    elif measure_y == 'guiraudr':
+        return f"Guiraud's R × 1000 in subcorpora with {limit} {measure_x}"
+    elif measure_y == 'herdanc':
+        return f"Herdan's C × 10000 in subcorpora with {limit} {measure_x}"
+    elif measure_y == 'zipfslope':
+        return f'Zipf slope × 1000 in subcorpora with {limit} {measure_x}'
+    elif measure_y == 'knowntypes':
+        return f'Known types in subcorpora with {limit} {measure_x}'
+    elif isinstance(measure_y, dict) and 'legomena' in measure_y:
+        n = measure_y['legomena']['n']
+        return f'Types with exactly {n} tokens in subcorpora with {limit} {measure_x}'
+    elif isinstance(measure_y, dict) and 'frequenttypes' in measure_y:
+        per_million = measure_y['frequenttypes']['per_million']
+        return f'Types with at least {per_million} per million in subcorpora with {limit} {measure_x}'
     else:
         measure_y_cased = _upcase(measure_y)
         return f'{measure_y_cased} in subcorpora with {limit} {measure_x}'
@@ -298,6 +335,9 @@ def plot(fig, data, dims, legend):
                                 linewidth=0)
             ax.axhline(0, color='#000000', linewidth=0.8)
 
+        if curve['results'][0]['vs_time'] is None:
+            continue
+
         points = [_get_vs(r, 'vs_time') for r in curve['results']]
         plotter(axs2[i], points)
 
@@ -352,6 +392,8 @@ def text(data):
         'vs_time': 'Significance of differences in time:',
         'vs_categories': 'Significance in comparison with other categories:'
     }
+    if curves[0]['results'] and curves[0]['results'][0]['vs_time'] is None:
+        cases = []
     for case in cases:
         result += [expl[case], '']
         for curve in curves:
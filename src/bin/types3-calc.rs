@@ -1,26 +1,50 @@
 use clap::Parser;
 use clap_verbosity_flag::{Verbosity, WarnLevel};
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use log::{error, info};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::path::Path;
 use std::{error, fs, io, process};
+use types3::cache::Cache;
 use types3::categories;
-use types3::driver::{self, DriverArgs};
+use types3::driver::{self, Boundary, DriverArgs, WindowMode};
 use types3::errors::{self, Result};
-use types3::input::{Input, Year};
-use types3::output::{MeasureX, MeasureY, OError};
+use types3::input::{self, NumericType, Year};
+use types3::logging;
+use types3::output::{
+    self, MeasureX, MeasureY, NullModel, OError, Output, OutputEnvelope, TestSide,
+};
+#[cfg(feature = "parquet")]
+use types3::parquet;
+#[cfg(feature = "sqlite")]
+use types3::sqlite;
 
 const DEFAULT_ITER: u64 = 1_000_000;
+const STDOUT: &str = "-";
+const GZ_EXTENSION: &str = ".gz";
 
 /// Calculate type accumulation curves (used by types3-ui)
 #[derive(Parser)]
 #[command(version)]
 struct Args {
-    /// Input file (JSON)
-    infile: String,
-    /// Output file (JSON)
-    outfile: String,
+    /// Input files (JSON, possibly compressed with gzip), whose samples are used together; without --output, a single input file followed by the output file (with --split-output, the output file can be left out)
+    #[arg(value_name = "FILE", num_args = 1.., required_unless_present_any = ["diff", "print_schema"])]
+    files: Vec<String>,
+    /// Output file (JSON or CSV, see --format), or "-" for standard output, compressed with gzip if the name ends with .gz; then all FILE arguments are input files
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<String>,
     /// Sample metadata key to consider
     #[arg(long)]
     category: Option<String>,
+    /// Fail if --category has more than this many distinct values
+    #[arg(long, value_name = "N", requires = "category")]
+    max_categories: Option<usize>,
+    /// Sample metadata key with integer values to use instead of years (window, step, start, and end then refer to its values)
+    #[arg(long)]
+    group_by: Option<String>,
     /// Count tokens (instead of types)
     #[arg(long, default_value_t = false)]
     count_tokens: bool,
@@ -30,15 +54,69 @@ struct Args {
     /// Count samples (instead of types)
     #[arg(long, default_value_t = false)]
     count_samples: bool,
+    /// Calculate mean frequency, i.e., tokens per type, scaled by 1000 (instead of types)
+    #[arg(long, default_value_t = false)]
+    mean_frequency: bool,
+    /// Calculate the proportion of tokens that are hapaxes, scaled by 1000 (instead of types)
+    #[arg(long, default_value_t = false)]
+    hapax_token_ratio: bool,
+    /// Calculate the type-token ratio, scaled by 10000 (instead of types)
+    #[arg(long, default_value_t = false)]
+    type_token_ratio: bool,
+    /// Calculate Yule's K, scaled by 100 (instead of types)
+    #[arg(long, default_value_t = false)]
+    yule_k: bool,
+    /// Calculate the entropy of lemma frequencies in millibits (instead of types)
+    #[arg(long, default_value_t = false)]
+    entropy: bool,
+    /// Calculate Guiraud's R, scaled by 1000 (instead of types)
+    #[arg(long, default_value_t = false)]
+    guiraud_r: bool,
+    /// Calculate Herdan's C, scaled by 10000 (instead of types)
+    #[arg(long, default_value_t = false)]
+    herdan_c: bool,
+    /// Calculate the slope of the Zipf curve, negated and scaled by 1000 (instead of types)
+    #[arg(long, default_value_t = false)]
+    zipf_slope: bool,
+    /// Count types with at least this many tokens per million tokens (instead of types)
+    #[arg(long, value_name = "PER_MILLION")]
+    frequent_types: Option<u64>,
+    /// Count types with exactly --legomena-n tokens (instead of types)
+    #[arg(long, default_value_t = false)]
+    count_legomena: bool,
+    /// Number of tokens for --count-legomena, e.g. 2 for dis legomena
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    legomena_n: u64,
+    /// Count marked tokens (instead of types), see --mark-tokens
+    #[arg(long, default_value_t = false)]
+    count_marked_tokens: bool,
+    /// Count distinct values of this sample metadata key, e.g. authors (instead of types)
+    #[arg(long, value_name = "KEY")]
+    count_sources: Option<String>,
+    /// Count types in this reference vocabulary, a file with one lemma per line (instead of types)
+    #[arg(long, value_name = "FILE")]
+    vocabulary: Option<String>,
     /// Compare with running words (instead of tokens)
     #[arg(long, default_value_t = false)]
     words: bool,
+    /// Compare with the number of samples (instead of tokens)
+    #[arg(long, default_value_t = false)]
+    samples: bool,
+    /// Compare with the number of characters (instead of tokens)
+    #[arg(long, default_value_t = false)]
+    chars: bool,
     /// Compare marked types vs. types
     #[arg(long, default_value_t = false)]
     type_ratio: bool,
     /// Number of iterations
     #[arg(short, long, default_value_t = DEFAULT_ITER)]
     iter: u64,
+    /// Random seed; different seeds give independent but reproducible results
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Repeat the calculation of averages R times with different seeds and report the spread
+    #[arg(long, value_name = "R")]
+    repeats: Option<u64>,
     /// Starting offset
     #[arg(long, default_value_t = 0)]
     offset: Year,
@@ -49,14 +127,62 @@ struct Args {
     #[arg(long, default_value_t = 9999)]
     end: Year,
     /// Window length (years)
-    #[arg(long)]
-    window: Year,
+    #[arg(long, required_unless_present_any = ["no_time", "diff", "print_schema"])]
+    window: Option<Year>,
     /// Step length (years)
+    #[arg(long, required_unless_present_any = ["no_time", "diff", "print_schema"])]
+    step: Option<Year>,
+    /// Use one period covering all years (window and step are ignored)
     #[arg(long)]
-    step: Year,
+    no_time: bool,
     /// Minimum size for subsets
     #[arg(long, default_value_t = 1)]
     minimum_size: u64,
+    /// Use this size limit instead of the size of the smallest subset (smaller subsets are skipped)
+    #[arg(long, value_name = "N")]
+    fixed_limit: Option<u64>,
+    /// Ignore samples with fewer than this many tokens (after --restrict-tokens)
+    #[arg(long, value_name = "N")]
+    min_sample_tokens: Option<u64>,
+    /// Use only a random subsample of this fraction of the samples (determined by --seed)
+    #[arg(long, value_name = "FRACTION")]
+    subsample: Option<f64>,
+    /// Warn if the size limit is below this fraction of the median subset size
+    #[arg(long, value_name = "FRACTION", default_value_t = 0.1)]
+    skew_threshold: f64,
+    /// Fail instead of warning if the size limit is below --skew-threshold
+    #[arg(long)]
+    strict: bool,
+    /// Compare each subset with the rest of the data, leaving out the subset itself
+    #[arg(long)]
+    leave_out_self: bool,
+    /// Report which subset determined the size limit
+    #[arg(long)]
+    explain_limit: bool,
+    /// Report the size limit also in words, tokens, and types, as measured in the subset that determined it
+    #[arg(long)]
+    limit_units: bool,
+    /// Report comparison results also as explicit fractions above and below random, with direction labels and two-sided p-values
+    #[arg(long)]
+    explicit: bool,
+    /// Also report p-values of significance tests with this alternative hypothesis
+    #[arg(long, value_name = "SIDE", value_parser = ["two-sided", "greater", "less"])]
+    test_side: Option<String>,
+    /// Null model for comparisons with other time periods: random order of samples, or random reassignment of years
+    #[arg(long, value_name = "MODEL", value_parser = ["sample-order", "year-label"], default_value = "sample-order")]
+    null_model: String,
+    /// Only calculate average curves, without significance testing (much faster)
+    #[arg(long)]
+    no_significance: bool,
+    /// Also report results after these fractions of iterations, e.g. 0.1,0.25,0.5
+    #[arg(long, value_name = "FRACTIONS", value_delimiter = ',')]
+    convergence_points: Option<Vec<f64>>,
+    /// Assign each sample to exactly one period even if windows overlap
+    #[arg(long)]
+    disjoint: bool,
+    /// Each period contains the samples from the most recent K years up to its end
+    #[arg(long, value_name = "K")]
+    trailing_years: Option<Year>,
     /// Sample metadata restriction, of the form key=value
     #[arg(long)]
     restrict_samples: Option<String>,
@@ -66,22 +192,188 @@ struct Args {
     /// Which tokens to mark, of the form key=value
     #[arg(long)]
     mark_tokens: Option<String>,
+    /// Parse the values of this sample metadata key as real numbers (an error if some value is not a number)
+    #[arg(long, value_name = "KEY")]
+    numeric_metadata: Vec<String>,
+    /// Parse the values of this sample metadata key as integers (an error if some value is not an integer)
+    #[arg(long, value_name = "KEY")]
+    integer_metadata: Vec<String>,
+    /// Also report marked types for these token markings, of the form key=value (with --type-ratio)
+    #[arg(long, value_name = "KEY=VALUE", requires_all = ["mark_tokens", "type_ratio"])]
+    also_mark_tokens: Vec<String>,
+    /// Report an error if some tokens do not have the key of --mark-tokens at all
+    #[arg(long, requires = "mark_tokens")]
+    require_mark_key: bool,
     /// Can we split samples?
     #[arg(long)]
     split_samples: bool,
-    /// Report errors as a JSON file
+    /// Count each lemma at most once per sample
+    #[arg(long)]
+    presence_absence: bool,
+    /// Accumulate all samples with the same value of this metadata key together, e.g. authors
+    #[arg(long, value_name = "KEY")]
+    accumulate_by: Option<String>,
+    /// Count lemma n-grams of this length as types (tokens must be in text order)
+    #[arg(long, default_value_t = 1)]
+    ngram: usize,
+    /// Scale the number of iterations by subset size (--iter is the maximum)
+    #[arg(long)]
+    scale_iter: bool,
+    /// Also group results into buckets of this many years, for display only
+    #[arg(long)]
+    display_bucket: Option<Year>,
+    /// Also compare each period with other time periods at several sizes, combining the results with weights proportional to size^EXPONENT
+    #[arg(long, value_name = "EXPONENT")]
+    region_weight: Option<f64>,
+    /// Also write all comparison results to this file (JSON)
+    #[arg(long, value_name = "FILE")]
+    dump_toplevel: Option<String>,
+    /// Also write the internal representation of samples in each subset to this file (JSON)
+    #[arg(long, value_name = "FILE")]
+    dump_samples: Option<String>,
+    /// Report Monte Carlo standard errors of significance estimates
+    #[arg(long)]
+    standard_error: bool,
+    /// Only output results that are significant at this level
+    #[arg(long)]
+    only_significant: Option<f64>,
+    /// Also write one JSON file per curve and an index file index.json to this directory
+    #[arg(long, value_name = "DIR")]
+    split_output: Option<String>,
+    /// Calculate everything separately for each value of this sample metadata key, and write all results to the output file
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["restrict_samples", "dump_toplevel", "dump_samples", "split_output"])]
+    split_by: Option<String>,
+    /// Also report the exact expected number of types (types vs. tokens only)
+    #[arg(long)]
+    expected_types: bool,
+    /// Also report the number of samples in the reference sets of the comparisons
+    #[arg(long)]
+    comparison_n: bool,
+    /// Also report the average number of samples in random subcorpora at the limit
+    #[arg(long)]
+    samples_at_limit: bool,
+    /// Also report the average contribution of the last sample in random subcorpora at the limit
+    #[arg(long)]
+    marginal: bool,
+    /// Also report the smallest and largest value in random subcorpora at the limit
+    #[arg(long)]
+    extremes: bool,
+    /// Also report z-scores of the observed values under the normal approximation
+    #[arg(long)]
+    z_score: bool,
+    /// Also report the number of distinct orderings of samples, and warn about subsets with too few samples for --iter
+    #[arg(long)]
+    distinct_orderings: bool,
+    /// Also report how much the categories differ from each other in each period
+    #[arg(long)]
+    category_spread: bool,
+    /// Also record the measures, size limit, and sample splitting that the results are based on
+    #[arg(long)]
+    basis: bool,
+    /// Also report the size at which random subcorpora first reach this value of the measure, e.g. this many types
+    #[arg(long, value_name = "N")]
+    type_target: Option<u64>,
+    /// Also report bootstrap confidence intervals for the observed values, resampling the samples of each subset
+    #[arg(long)]
+    observed_ci: bool,
+    /// Also report the expected number of types interpolated and extrapolated up to this many tokens (types vs. tokens only)
+    #[arg(long, value_name = "TO_X")]
+    extrapolate: Option<u64>,
+    /// Also report the mean accumulation curve of random subcorpora up to the size limit
+    #[arg(long)]
+    mean_curve: bool,
+    /// Also report the full accumulation curve of random subcorpora of each subset, up to its total size
+    #[arg(long)]
+    full_curve: bool,
+    /// Also report these quantiles of the values in random subcorpora at the size limit, e.g. 0.025,0.975
+    #[arg(long, value_name = "LOW,HIGH", value_delimiter = ',')]
+    percentiles: Option<Vec<f64>>,
+    /// Compare two output files (JSON) and report the results that changed, instead of calculating anything
+    #[arg(long, num_args = 2, value_names = ["A", "B"], conflicts_with_all = ["files"])]
+    diff: Option<Vec<String>>,
+    /// Significance level used by --diff for reporting results that became significant or ceased to be significant
+    #[arg(long, default_value_t = 0.01)]
+    diff_alpha: f64,
+    /// Print the JSON Schema of the output or input file, instead of calculating anything (requires feature schemars)
+    #[arg(long, value_name = "KIND", value_parser = ["output", "input"], conflicts_with_all = ["files", "diff"])]
+    print_schema: Option<String>,
+    /// Also write the results to this SQLite database, as a new run
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "FILE")]
+    sqlite: Option<String>,
+    /// Also write the results to this Parquet file, one row per category and time period
+    #[cfg(feature = "parquet")]
+    #[arg(long, value_name = "FILE")]
+    parquet: Option<String>,
+    /// Reuse results cached in this file (JSON) and add new results to it
+    #[arg(long, value_name = "FILE")]
+    cache: Option<String>,
+    /// Report errors as a JSON file, or "-" for standard output
     #[arg(long)]
     error_file: Option<String>,
+    /// Also write log messages to this file
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<String>,
     /// Produce compact JSON files
     #[arg(long)]
     compact: bool,
+    /// Format of the output file: JSON, or CSV with one row per category and time period
+    #[arg(long, value_parser = ["json", "csv"], default_value = "json")]
+    format: String,
+    /// Wrap the output file in an envelope with the format version
+    #[arg(long)]
+    envelope: bool,
+    /// Compress the output file with gzip, e.g. when writing to standard output for piping into other tools (implied by a .gz output file name)
+    #[arg(long)]
+    gzip_out: bool,
     /// Verbosity
     #[command(flatten)]
     verbose: Verbosity<WarnLevel>,
 }
 
 impl Args {
+    /// Input files; see [Args::files].
+    fn infiles(&self) -> &[String] {
+        if self.output.is_some() {
+            return &self.files;
+        }
+        match self.files.len() {
+            0 | 1 => &self.files,
+            n => &self.files[..n - 1],
+        }
+    }
+
+    /// Output file, if any; see [Args::files] and [Args::output].
+    fn outfile(&self) -> Option<&str> {
+        if let Some(output) = &self.output {
+            return Some(output);
+        }
+        match self.files.len() {
+            0 | 1 => None,
+            n => Some(&self.files[n - 1]),
+        }
+    }
+
     fn sanity(&self) -> Result<()> {
+        if self.output.is_none() {
+            if self.files.len() == 1 && self.split_output.is_none() {
+                return Err(errors::invalid_argument_ref(
+                    "expected an input file and an output file",
+                ));
+            }
+            if self.files.len() > 2 {
+                return Err(errors::invalid_argument_ref(
+                    "several input files need --output for the output file",
+                ));
+            }
+        }
+        if let Some(outfile) = self.outfile() {
+            if self.infiles().iter().any(|f| same_file(f, outfile)) {
+                return Err(errors::invalid_argument(format!(
+                    "output file {outfile} is also an input file"
+                )));
+            }
+        }
         if self.minimum_size == 0 {
             return Err(errors::invalid_argument_ref("minimum size cannot be 0"));
         }
@@ -90,16 +382,117 @@ impl Args {
                 "cannot select both --words and --split-samples",
             ));
         }
+        if self.chars && self.split_samples {
+            return Err(errors::invalid_argument_ref(
+                "cannot select both --chars and --split-samples",
+            ));
+        }
+        if self.samples && self.split_samples {
+            return Err(errors::invalid_argument_ref(
+                "cannot select both --samples and --split-samples",
+            ));
+        }
+        if self.chars && (self.words || self.samples) {
+            return Err(errors::invalid_argument_ref(
+                "can select at most one of --words, --samples, and --chars",
+            ));
+        }
+        if self.chars && self.type_ratio {
+            return Err(errors::invalid_argument_ref(
+                "cannot select both --chars and --type-ratio",
+            ));
+        }
+        if self.split_samples && self.presence_absence {
+            return Err(errors::invalid_argument_ref(
+                "cannot select both --split-samples and --presence-absence",
+            ));
+        }
+        if self.words && self.samples {
+            return Err(errors::invalid_argument_ref(
+                "cannot select both --words and --samples",
+            ));
+        }
+        if self.samples && self.type_ratio {
+            return Err(errors::invalid_argument_ref(
+                "cannot select both --samples and --type-ratio",
+            ));
+        }
         if self.words && self.type_ratio {
             return Err(errors::invalid_argument_ref(
                 "cannot select both --words and --type-ratio",
             ));
         }
+        if self.repeats == Some(0) {
+            return Err(errors::invalid_argument_ref(
+                "number of repetitions must be positive",
+            ));
+        }
+        if self.percentiles.as_ref().is_some_and(|p| p.len() != 2) {
+            return Err(errors::invalid_argument_ref(
+                "--percentiles requires exactly two levels",
+            ));
+        }
+        if self.display_bucket.is_some_and(|w| w <= 0) {
+            return Err(errors::invalid_argument_ref(
+                "display bucket width must be positive",
+            ));
+        }
+        for alpha in self.only_significant.into_iter().chain([self.diff_alpha]) {
+            if !(alpha > 0.0 && alpha <= 1.0) {
+                return Err(errors::invalid_argument_ref(
+                    "significance level must be in range (0, 1]",
+                ));
+            }
+        }
+        if self.no_significance
+            && (self.only_significant.is_some()
+                || self.standard_error
+                || self.region_weight.is_some()
+                || self.explicit
+                || self.leave_out_self
+                || self.dump_toplevel.is_some()
+                || self.test_side.is_some()
+                || self.z_score)
+        {
+            return Err(errors::invalid_argument_ref(
+                "cannot combine --no-significance with --only-significant, --standard-error, --region-weight, --explicit, --leave-out-self, --dump-toplevel, --test-side, or --z-score",
+            ));
+        }
+        if self.format == "csv" && (self.split_by.is_some() || self.envelope) {
+            return Err(errors::invalid_argument_ref(
+                "cannot combine --format csv with --split-by or --envelope",
+            ));
+        }
+        #[cfg(feature = "sqlite")]
+        if self.split_by.is_some() && self.sqlite.is_some() {
+            return Err(errors::invalid_argument_ref(
+                "cannot combine --split-by with --sqlite",
+            ));
+        }
+        #[cfg(feature = "parquet")]
+        if self.split_by.is_some() && self.parquet.is_some() {
+            return Err(errors::invalid_argument_ref(
+                "cannot combine --split-by with --parquet",
+            ));
+        }
         let mut c = 0;
         for f in [
             self.count_tokens,
             self.count_hapaxes,
             self.count_samples,
+            self.mean_frequency,
+            self.hapax_token_ratio,
+            self.type_token_ratio,
+            self.yule_k,
+            self.entropy,
+            self.guiraud_r,
+            self.herdan_c,
+            self.zipf_slope,
+            self.frequent_types.is_some(),
+            self.count_legomena,
+            self.count_sources.is_some(),
+            self.vocabulary.is_some(),
+            self.count_marked_tokens,
             self.type_ratio,
         ] {
             if f {
@@ -108,13 +501,25 @@ impl Args {
         }
         if c > 1 {
             return Err(errors::invalid_argument_ref(
-                "can select at most one of --count-tokens, --count-hapaxes, --count-samples, and --type-ratio",
+                "can select at most one of --count-tokens, --count-hapaxes, --count-samples, --mean-frequency, --hapax-token-ratio, --type-token-ratio, --yule-k, --entropy, --guiraud-r, --herdan-c, --zipf-slope, --frequent-types, --count-legomena, --count-sources, --vocabulary, --count-marked-tokens, and --type-ratio",
             ));
         }
         Ok(())
     }
 
-    fn to_driver_args(&self) -> Result<DriverArgs> {
+    fn numeric_keys(&self) -> Vec<(&str, NumericType)> {
+        let floats = self
+            .numeric_metadata
+            .iter()
+            .map(|k| (k.as_str(), NumericType::Float));
+        let integers = self
+            .integer_metadata
+            .iter()
+            .map(|k| (k.as_str(), NumericType::Integer));
+        floats.chain(integers).collect()
+    }
+
+    fn to_driver_args(&self) -> Result<DriverArgs<'_>> {
         let category: Option<&str> = match &self.category {
             None => None,
             Some(key) => Some(key),
@@ -126,6 +531,10 @@ impl Args {
             MeasureX::Types
         } else if self.words {
             MeasureX::Words
+        } else if self.samples {
+            MeasureX::Samples
+        } else if self.chars {
+            MeasureX::Chars
         } else {
             MeasureX::Tokens
         };
@@ -137,43 +546,348 @@ impl Args {
             MeasureY::Hapaxes
         } else if self.count_samples {
             MeasureY::Samples
+        } else if self.mean_frequency {
+            MeasureY::MeanFrequency
+        } else if self.hapax_token_ratio {
+            MeasureY::HapaxTokenRatio
+        } else if self.type_token_ratio {
+            MeasureY::TypeTokenRatio
+        } else if self.yule_k {
+            MeasureY::YuleK
+        } else if self.entropy {
+            MeasureY::Entropy
+        } else if self.guiraud_r {
+            MeasureY::GuiraudR
+        } else if self.herdan_c {
+            MeasureY::HerdanC
+        } else if self.zipf_slope {
+            MeasureY::ZipfSlope
+        } else if let Some(per_million) = self.frequent_types {
+            MeasureY::FrequentTypes { per_million }
+        } else if self.count_legomena {
+            MeasureY::Legomena { n: self.legomena_n }
+        } else if self.count_sources.is_some() {
+            MeasureY::Sources
+        } else if self.vocabulary.is_some() {
+            MeasureY::KnownTypes
+        } else if self.count_marked_tokens {
+            MeasureY::MarkedTokens
         } else {
             MeasureY::Types
         };
+        let test_side = match self.test_side.as_deref() {
+            None => None,
+            Some("two-sided") => Some(TestSide::TwoSided),
+            Some("greater") => Some(TestSide::GreaterOnly),
+            Some("less") => Some(TestSide::LessOnly),
+            Some(side) => {
+                return Err(errors::invalid_argument(format!(
+                    "unknown test side '{side}'"
+                )))
+            }
+        };
+        let null_model = match self.null_model.as_str() {
+            "sample-order" => NullModel::SampleOrder,
+            "year-label" => NullModel::YearLabel,
+            model => {
+                return Err(errors::invalid_argument(format!(
+                    "unknown null model '{model}'"
+                )))
+            }
+        };
         Ok(DriverArgs {
             category,
+            max_categories: self.max_categories,
+            group_by: self.group_by.as_deref(),
             measure_x,
             measure_y,
             iter: self.iter,
             offset: self.offset,
             start: self.start,
             end: self.end,
-            window: self.window,
-            step: self.step,
+            window: self.window.unwrap_or(0),
+            step: self.step.unwrap_or(0),
+            no_time: self.no_time,
             minimum_size: self.minimum_size,
+            fixed_limit: self.fixed_limit,
+            min_sample_tokens: self.min_sample_tokens,
+            subsample: self.subsample,
+            skew_threshold: Some(self.skew_threshold),
+            strict: self.strict,
             restrict_samples,
             restrict_tokens,
             mark_tokens,
+            also_mark_tokens: &[],
             split_samples: self.split_samples,
+            presence_absence: self.presence_absence,
+            accumulate_by: self.accumulate_by.as_deref(),
+            ngram: self.ngram,
+            standard_error: self.standard_error,
+            scale_iter: self.scale_iter,
+            display_bucket: self.display_bucket,
+            region_weight: self.region_weight,
+            dump_toplevel: self.dump_toplevel.is_some(),
+            dump_samples: self.dump_samples.is_some(),
+            boundary: if self.disjoint {
+                Boundary::Disjoint
+            } else {
+                Boundary::Overlap
+            },
+            window_mode: match self.trailing_years {
+                None => WindowMode::Fixed,
+                Some(years) => WindowMode::TrailingRecent { years },
+            },
+            leave_out_self: self.leave_out_self,
+            explain_limit: self.explain_limit,
+            limit_units: self.limit_units,
+            explicit: self.explicit,
+            no_significance: self.no_significance,
+            convergence: self.convergence_points.as_deref(),
+            source_key: self.count_sources.as_deref(),
+            vocabulary: None,
+            seed: self.seed,
+            repeats: self.repeats,
+            require_mark_key: self.require_mark_key,
+            test_side,
+            null_model,
+            cancel: None,
+            cache: None,
+            expected_types: self.expected_types,
+            comparison_n: self.comparison_n,
+            fingerprints: false,
+            samples_at_limit: self.samples_at_limit,
+            marginal: self.marginal,
+            extremes: self.extremes,
+            z_score: self.z_score,
+            distinct_orderings: self.distinct_orderings,
+            category_spread: self.category_spread,
+            basis: self.basis,
+            type_target: self.type_target,
+            observed_ci: self.observed_ci,
+            extrapolate: self.extrapolate,
+            mean_curve: self.mean_curve,
+            full_curve: self.full_curve,
+            percentiles: self.percentiles.as_deref().map(|p| (p[0], p[1])),
         })
     }
 }
 
 fn process(args: &Args) -> Result<()> {
     args.sanity()?;
-    info!(target: "types3", "read: {}", args.infile);
-    let indata = fs::read_to_string(&args.infile)?;
-    let input: Input = serde_json::from_str(&indata)?;
-    let driver_args = &args.to_driver_args()?;
-    let output = driver::calc(driver_args, &input)?;
-    info!(target: "types3", "write: {}", args.outfile);
-    let file = fs::File::create(&args.outfile)?;
+    if let Some(files) = &args.diff {
+        return process_diff(&files[0], &files[1], args.diff_alpha);
+    }
+    if let Some(which) = &args.print_schema {
+        return print_schema(which);
+    }
+    let inputs = args
+        .infiles()
+        .iter()
+        .map(|infile| {
+            info!(target: "types3", "read: {}", infile);
+            input::read_input(infile)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut input = input::merge(inputs)?;
+    input.parse_numeric(&args.numeric_keys())?;
+    let cache = match &args.cache {
+        Some(filename) if Path::new(filename).exists() => {
+            info!(target: "types3", "read: {}", filename);
+            Some(Cache::load(filename)?)
+        }
+        Some(_) => Some(Cache::new()),
+        None => None,
+    };
+    let also_mark_tokens = categories::parse_restrictions(&args.also_mark_tokens)?;
+    let vocabulary = match &args.vocabulary {
+        Some(filename) => {
+            info!(target: "types3", "read: {}", filename);
+            Some(read_vocabulary(&fs::read_to_string(filename)?))
+        }
+        None => None,
+    };
+    let driver_args = &DriverArgs {
+        also_mark_tokens: &also_mark_tokens,
+        vocabulary: vocabulary.as_ref(),
+        cache: cache.as_ref(),
+        ..args.to_driver_args()?
+    };
+    if let Some(key) = &args.split_by {
+        let mut partitions = driver::calc_partitions(driver_args, &input, key)?;
+        save_cache(args, cache.as_ref())?;
+        if let Some(alpha) = args.only_significant {
+            for p in &mut partitions.partitions {
+                p.output.retain_significant(alpha);
+            }
+        }
+        if let Some(filename) = args.outfile() {
+            info!(target: "types3", "write: {}", filename);
+            write_outfile(filename, &partitions, args)?;
+        }
+        return Ok(());
+    }
+    let mut output = driver::calc(driver_args, &input)?;
+    save_cache(args, cache.as_ref())?;
+    if let Some(alpha) = args.only_significant {
+        output.retain_significant(alpha);
+    }
+    if let Some(filename) = &args.dump_toplevel {
+        let toplevel = output.toplevel.take().expect("toplevel results requested");
+        info!(target: "types3", "write: {}", filename);
+        write_json(filename, &toplevel, args.compact)?;
+    }
+    if let Some(filename) = &args.dump_samples {
+        let samples = output.samples.take().expect("samples requested");
+        info!(target: "types3", "write: {}", filename);
+        write_json(filename, &samples, args.compact)?;
+    }
+    if let Some(filename) = args.outfile() {
+        info!(target: "types3", "write: {}", filename);
+        if args.envelope {
+            let envelope = OutputEnvelope::new(output);
+            write_outfile(filename, &envelope, args)?;
+            output = envelope.output;
+        } else if args.format == "csv" {
+            write_with(filename, args, |mut w| Ok(output::to_csv(&output, &mut w)?))?;
+        } else {
+            write_outfile(filename, &output, args)?;
+        }
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(filename) = &args.sqlite {
+        info!(target: "types3", "write: {}", filename);
+        let mut conn = rusqlite::Connection::open(filename)?;
+        let cmdline = std::env::args().collect::<Vec<_>>().join(" ");
+        let run = sqlite::write_output(&mut conn, &output, &cmdline, &args.infiles().join(" "))?;
+        info!(target: "types3", "sqlite run: {}", run);
+    }
+    #[cfg(feature = "parquet")]
+    if let Some(filename) = &args.parquet {
+        info!(target: "types3", "write: {}", filename);
+        parquet::write_output(fs::File::create(filename)?, &output)?;
+    }
+    if let Some(dir) = &args.split_output {
+        write_split(dir, output, args.compact)?;
+    }
+    Ok(())
+}
+
+/// Do `a` and `b` refer to the same file?
+fn same_file(a: &str, b: &str) -> bool {
+    a == b
+        || matches!(
+            (fs::canonicalize(a), fs::canonicalize(b)),
+            (Ok(x), Ok(y)) if x == y
+        )
+}
+
+fn process_diff(file_a: &str, file_b: &str, alpha: f64) -> Result<()> {
+    let mut outputs = vec![];
+    for filename in [file_a, file_b] {
+        info!(target: "types3", "read: {}", filename);
+        let mut data = String::new();
+        input::open_file(filename)?.read_to_string(&mut data)?;
+        outputs.push(output::load_output(&data)?);
+    }
+    let diffs = output::diff(&outputs[0], &outputs[1], alpha);
+    for d in &diffs {
+        println!("{}", output::diff_string(d));
+    }
+    let crossed = diffs.iter().filter(|d| d.crossed).count();
+    info!(target: "types3", "{} results changed, {} crossed significance level {}", diffs.len(), crossed, alpha);
+    Ok(())
+}
+
+#[cfg(feature = "schemars")]
+fn print_schema(which: &str) -> Result<()> {
+    let schema = match which {
+        "output" => types3::schema::output_schema(),
+        "input" => types3::schema::input_schema(),
+        _ => return Err(errors::invalid_argument(format!("unknown schema: {which}"))),
+    };
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
+#[cfg(not(feature = "schemars"))]
+fn print_schema(_which: &str) -> Result<()> {
+    Err(errors::invalid_argument_ref(
+        "printing the JSON Schema requires feature schemars",
+    ))
+}
+
+/// Parse a vocabulary file: one lemma per line, ignoring surrounding whitespace and empty lines.
+fn read_vocabulary(data: &str) -> HashSet<String> {
+    data.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_owned())
+        .collect()
+}
+
+fn save_cache(args: &Args, cache: Option<&Cache>) -> Result<()> {
+    if let (Some(cache), Some(filename)) = (cache, &args.cache) {
+        cache.report();
+        info!(target: "types3", "write: {}", filename);
+        cache.save(filename)?;
+    }
+    Ok(())
+}
+
+fn write_split(dir: &str, output: Output, compact: bool) -> Result<()> {
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir)?;
+    let (index, curves) = output.split();
+    for (entry, curve) in index.files.iter().zip(&curves) {
+        let filename = dir.join(&entry.file);
+        info!(target: "types3", "write: {}", filename.display());
+        write_json(&filename, curve, compact)?;
+    }
+    let filename = dir.join(output::INDEX_FILE);
+    info!(target: "types3", "write: {}", filename.display());
+    write_json(&filename, &index, compact)
+}
+
+fn write_json<T: Serialize>(filename: impl AsRef<Path>, value: &T, compact: bool) -> Result<()> {
+    let file = fs::File::create(filename)?;
     let writer = io::BufWriter::new(file);
-    if args.compact {
-        serde_json::to_writer(writer, &output)?;
+    to_writer(writer, value, compact)
+}
+
+/// Write the main output file, which can also be standard output ([STDOUT]),
+/// compressed if requested with --gzip-out or a .gz file name. Log messages go to standard error.
+fn write_outfile<T: Serialize>(filename: &str, value: &T, args: &Args) -> Result<()> {
+    write_with(filename, args, |w| to_writer(w, value, args.compact))
+}
+
+/// Open the output file (or standard output), compressed if requested, and write it with `f`.
+fn write_with<F>(filename: &str, args: &Args, f: F) -> Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> Result<()>,
+{
+    let mut writer: Box<dyn Write> = if filename == STDOUT {
+        Box::new(io::BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(io::BufWriter::new(fs::File::create(filename)?))
+    };
+    if args.gzip_out || filename.ends_with(GZ_EXTENSION) {
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        f(&mut encoder)?;
+        encoder.finish()?.flush()?;
+    } else {
+        f(&mut writer)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn to_writer<T: Serialize, W: Write>(mut writer: W, value: &T, compact: bool) -> Result<()> {
+    if compact {
+        serde_json::to_writer(&mut writer, value)?;
     } else {
-        serde_json::to_writer_pretty(writer, &output)?;
+        serde_json::to_writer_pretty(&mut writer, value)?;
     }
+    writer.flush()?;
     Ok(())
 }
 
@@ -181,17 +895,22 @@ fn store_error(error_file: &str, e: &dyn error::Error) -> Result<()> {
     let error = OError {
         error: format!("{e}"),
     };
-    let file = fs::File::create(error_file)?;
-    let writer = io::BufWriter::new(file);
-    serde_json::to_writer(writer, &error)?;
+    let mut writer: Box<dyn Write> = if error_file == STDOUT {
+        Box::new(io::BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(io::BufWriter::new(fs::File::create(error_file)?))
+    };
+    serde_json::to_writer(&mut writer, &error)?;
+    writer.flush()?;
     Ok(())
 }
 
 fn main() {
     let args = Args::parse();
-    pretty_env_logger::formatted_timed_builder()
-        .filter_level(args.verbose.log_level_filter())
-        .init();
+    if let Err(e) = logging::init(args.verbose.log_level_filter(), args.log_file.as_deref()) {
+        eprintln!("{e}");
+        process::exit(1);
+    }
     match process(&args) {
         Ok(()) => (),
         Err(e) => {
@@ -229,6 +948,37 @@ mod test {
         assert_eq!(da.iter, DEFAULT_ITER);
     }
 
+    #[test]
+    fn args_split_by() {
+        let args = Args::parse_from([
+            "",
+            "--window",
+            "100",
+            "--step",
+            "10",
+            "--split-by",
+            "x",
+            "a",
+            "b",
+        ]);
+        args.sanity().unwrap();
+        assert_eq!(args.split_by.as_deref(), Some("x"));
+        assert!(Args::try_parse_from([
+            "",
+            "--window",
+            "100",
+            "--step",
+            "10",
+            "--split-by",
+            "x",
+            "--restrict-samples",
+            "x=y",
+            "a",
+            "b",
+        ])
+        .is_err());
+    }
+
     #[test]
     fn args_basic() {
         let args = Args::parse_from([
@@ -266,6 +1016,234 @@ mod test {
         assert_eq!(da.iter, DEFAULT_ITER);
     }
 
+    #[test]
+    fn args_count_sources() {
+        let args = Args::parse_from(["", "--no-time", "--count-sources", "author", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_y, MeasureY::Sources);
+        assert_eq!(da.source_key, Some("author"));
+        let args = Args::parse_from([
+            "",
+            "--no-time",
+            "--count-sources",
+            "author",
+            "--count-tokens",
+            "a",
+            "b",
+        ]);
+        args.sanity().unwrap_err();
+    }
+
+    #[test]
+    fn args_type_token_ratio() {
+        let args = Args::parse_from(["", "--no-time", "--type-token-ratio", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_y, MeasureY::TypeTokenRatio);
+        let args = Args::parse_from([
+            "",
+            "--no-time",
+            "--type-token-ratio",
+            "--hapax-token-ratio",
+            "a",
+            "b",
+        ]);
+        args.sanity().unwrap_err();
+    }
+
+    #[test]
+    fn args_yule_k() {
+        let args = Args::parse_from(["", "--no-time", "--yule-k", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_y, MeasureY::YuleK);
+        let args = Args::parse_from(["", "--no-time", "--yule-k", "--zipf-slope", "a", "b"]);
+        args.sanity().unwrap_err();
+    }
+
+    #[test]
+    fn args_entropy() {
+        let args = Args::parse_from(["", "--no-time", "--entropy", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_y, MeasureY::Entropy);
+        let args = Args::parse_from(["", "--no-time", "--entropy", "--yule-k", "a", "b"]);
+        args.sanity().unwrap_err();
+    }
+
+    #[test]
+    fn args_guiraud_r_herdan_c() {
+        let args = Args::parse_from(["", "--no-time", "--guiraud-r", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_y, MeasureY::GuiraudR);
+        let args = Args::parse_from(["", "--no-time", "--herdan-c", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_y, MeasureY::HerdanC);
+        let args = Args::parse_from(["", "--no-time", "--guiraud-r", "--herdan-c", "a", "b"]);
+        args.sanity().unwrap_err();
+    }
+
+    #[test]
+    fn args_samples() {
+        let args = Args::parse_from(["", "--no-time", "--samples", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_x, MeasureX::Samples);
+        assert_eq!(da.measure_y, MeasureY::Types);
+        let args = Args::parse_from(["", "--no-time", "--samples", "--words", "a", "b"]);
+        args.sanity().unwrap_err();
+        let args = Args::parse_from(["", "--no-time", "--samples", "--type-ratio", "a", "b"]);
+        args.sanity().unwrap_err();
+        let args = Args::parse_from(["", "--no-time", "--samples", "--split-samples", "a", "b"]);
+        args.sanity().unwrap_err();
+    }
+
+    #[test]
+    fn args_chars() {
+        let args = Args::parse_from(["", "--no-time", "--chars", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_x, MeasureX::Chars);
+        for other in ["--split-samples", "--words", "--samples", "--type-ratio"] {
+            let args = Args::parse_from(["", "--no-time", "--chars", other, "a", "b"]);
+            args.sanity().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn args_format() {
+        let args = Args::parse_from(["", "--no-time", "a", "b"]);
+        assert_eq!(args.format, "json");
+        let args = Args::parse_from(["", "--no-time", "--format", "csv", "a", "b"]);
+        args.sanity().unwrap();
+        assert!(Args::try_parse_from(["", "--no-time", "--format", "xml", "a", "b"]).is_err());
+        for other in [&["--split-by", "x"][..], &["--envelope"]] {
+            let args = Args::parse_from(
+                ["", "--no-time", "--format", "csv"]
+                    .iter()
+                    .chain(other)
+                    .chain(&["a", "b"]),
+            );
+            args.sanity().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn args_percentiles() {
+        let args = Args::parse_from(["", "--no-time", "a", "b"]);
+        assert_eq!(args.to_driver_args().unwrap().percentiles, None);
+        let args = Args::parse_from(["", "--no-time", "--percentiles", "0.05,0.95", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.percentiles, Some((0.05, 0.95)));
+        for bad in ["0.5", "0.1,0.5,0.9"] {
+            let args = Args::parse_from(["", "--no-time", "--percentiles", bad, "a", "b"]);
+            args.sanity().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn args_count_legomena() {
+        let args = Args::parse_from(["", "--no-time", "--count-legomena", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_y, MeasureY::Legomena { n: 2 });
+        let args = Args::parse_from([
+            "",
+            "--no-time",
+            "--count-legomena",
+            "--legomena-n",
+            "3",
+            "a",
+            "b",
+        ]);
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_y, MeasureY::Legomena { n: 3 });
+        let args = Args::parse_from([
+            "",
+            "--no-time",
+            "--count-legomena",
+            "--count-hapaxes",
+            "a",
+            "b",
+        ]);
+        args.sanity().unwrap_err();
+    }
+
+    #[test]
+    fn args_vocabulary() {
+        let args = Args::parse_from(["", "--no-time", "--vocabulary", "v.txt", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_y, MeasureY::KnownTypes);
+        let args = Args::parse_from([
+            "",
+            "--no-time",
+            "--vocabulary",
+            "v.txt",
+            "--count-sources",
+            "author",
+            "a",
+            "b",
+        ]);
+        args.sanity().unwrap_err();
+        let vocabulary = read_vocabulary("a\n  b \n\nc\n");
+        assert_eq!(vocabulary.len(), 3);
+        assert!(vocabulary.contains("b"));
+    }
+
+    #[test]
+    fn args_count_marked_tokens() {
+        let args = Args::parse_from([
+            "",
+            "--no-time",
+            "--count-marked-tokens",
+            "--mark-tokens",
+            "variant=ness",
+            "a",
+            "b",
+        ]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.measure_y, MeasureY::MarkedTokens);
+        assert_eq!(da.measure_x, MeasureX::Tokens);
+    }
+
+    #[test]
+    fn args_null_model() {
+        let args = Args::parse_from(["", "--no-time", "a", "b"]);
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.null_model, NullModel::SampleOrder);
+        let args = Args::parse_from(["", "--no-time", "--null-model", "year-label", "a", "b"]);
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.null_model, NullModel::YearLabel);
+        assert!(Args::try_parse_from(["", "--no-time", "--null-model", "year", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn args_test_side() {
+        let args = Args::parse_from(["", "--no-time", "--test-side", "greater", "a", "b"]);
+        args.sanity().unwrap();
+        let da = args.to_driver_args().unwrap();
+        assert_eq!(da.test_side, Some(TestSide::GreaterOnly));
+        let args = Args::parse_from(["", "--no-time", "a", "b"]);
+        assert_eq!(args.to_driver_args().unwrap().test_side, None);
+        assert!(Args::try_parse_from(["", "--no-time", "--test-side", "both", "a", "b"]).is_err());
+        let args = Args::parse_from([
+            "",
+            "--no-time",
+            "--test-side",
+            "less",
+            "--no-significance",
+            "a",
+            "b",
+        ]);
+        args.sanity().unwrap_err();
+    }
+
     #[test]
     fn args_bad() {
         let args = Args::parse_from([
@@ -281,4 +1259,126 @@ mod test {
         ]);
         args.sanity().unwrap_err();
     }
+
+    #[test]
+    fn args_split_output() {
+        let args = Args::parse_from(["", "--no-time", "--split-output", "d", "a"]);
+        args.sanity().unwrap();
+        assert_eq!(args.infiles(), ["a"]);
+        assert_eq!(args.outfile(), None);
+        assert_eq!(args.split_output.as_deref(), Some("d"));
+        let args = Args::parse_from(["", "--no-time", "--split-output", "d", "a", "b"]);
+        assert_eq!(args.infiles(), ["a"]);
+        assert_eq!(args.outfile(), Some("b"));
+        assert!(Args::try_parse_from(["", "--no-time"]).is_err());
+        let args = Args::parse_from(["", "--no-time", "a"]);
+        args.sanity().unwrap_err();
+    }
+
+    #[test]
+    fn args_files() {
+        use clap::CommandFactory;
+        Args::command().debug_assert();
+        let args = Args::parse_from(["", "--no-time", "a", "b"]);
+        args.sanity().unwrap();
+        assert_eq!(args.infiles(), ["a"]);
+        assert_eq!(args.outfile(), Some("b"));
+        let args = Args::parse_from(["", "--no-time", "a", "b", "c", "-o", "-"]);
+        args.sanity().unwrap();
+        assert_eq!(args.infiles(), ["a", "b", "c"]);
+        assert_eq!(args.outfile(), Some("-"));
+        let args = Args::parse_from(["", "--no-time", "a", "--output", "b"]);
+        args.sanity().unwrap();
+        assert_eq!(args.infiles(), ["a"]);
+        assert_eq!(args.outfile(), Some("b"));
+        let args = Args::parse_from(["", "--no-time", "a", "b", "c"]);
+        args.sanity().unwrap_err();
+        let args = Args::parse_from(["", "--no-time", "a", "b", "-o", "b"]);
+        args.sanity().unwrap_err();
+        let args = Args::parse_from(["", "--no-time", "a", "a"]);
+        args.sanity().unwrap_err();
+    }
+
+    #[test]
+    fn args_diff() {
+        let args = Args::parse_from(["", "--diff", "a", "b"]);
+        args.sanity().unwrap();
+        assert_eq!(args.diff, Some(vec!["a".to_owned(), "b".to_owned()]));
+        assert!(args.files.is_empty());
+        assert_eq!(args.diff_alpha, 0.01);
+        assert!(Args::try_parse_from(["", "--diff", "a"]).is_err());
+        assert!(Args::try_parse_from(["", "--diff", "a", "b", "c"]).is_err());
+        let args = Args::parse_from(["", "--diff", "a", "b", "--diff-alpha", "0"]);
+        args.sanity().unwrap_err();
+    }
+
+    #[test]
+    fn args_print_schema() {
+        let args = Args::parse_from(["", "--print-schema", "output"]);
+        args.sanity().unwrap();
+        assert_eq!(args.print_schema.as_deref(), Some("output"));
+        assert!(args.files.is_empty());
+        assert!(Args::try_parse_from(["", "--print-schema", "samples"]).is_err());
+        assert!(Args::try_parse_from(["", "--print-schema", "input", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn args_numeric_metadata() {
+        let args = Args::parse_from([
+            "",
+            "--no-time",
+            "--numeric-metadata",
+            "score",
+            "--integer-metadata",
+            "rank",
+            "--numeric-metadata",
+            "age",
+            "a",
+            "b",
+        ]);
+        assert_eq!(
+            args.numeric_keys(),
+            [
+                ("score", NumericType::Float),
+                ("age", NumericType::Float),
+                ("rank", NumericType::Integer)
+            ]
+        );
+    }
+
+    #[test]
+    fn args_no_time() {
+        let args = Args::parse_from(["", "--no-time", "a", "b"]);
+        let da = args.to_driver_args().unwrap();
+        assert!(da.no_time);
+        assert!(Args::try_parse_from(["", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn args_bad_significance() {
+        let args = Args::parse_from([
+            "",
+            "--window",
+            "100",
+            "--step",
+            "10",
+            "--only-significant",
+            "0",
+            "a",
+            "b",
+        ]);
+        args.sanity().unwrap_err();
+        let args = Args::parse_from([
+            "",
+            "--window",
+            "100",
+            "--step",
+            "10",
+            "--only-significant",
+            "0.01",
+            "a",
+            "b",
+        ]);
+        args.sanity().unwrap();
+    }
 }
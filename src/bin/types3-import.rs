@@ -0,0 +1,125 @@
+use clap::Parser;
+use clap_verbosity_flag::{Verbosity, WarnLevel};
+use log::{error, info};
+use std::io::Write;
+use std::{error, fs, io, process};
+use types3::errors::Result;
+use types3::import;
+use types3::input;
+use types3::logging;
+use types3::output::OError;
+
+const STDOUT: &str = "-";
+const TSV_EXTENSION: &str = ".tsv";
+
+/// Convert a CSV or TSV file in long format (one row per token) to types3-compatible format
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Input file (CSV or TSV), with columns sample_id, year, words, lemma, and optionally chars, count, sample.KEY, and token.KEY
+    infile: String,
+    /// Output file (JSON), or "-" for standard output
+    outfile: String,
+    /// Input is tab-separated (implied by a .tsv file name)
+    #[arg(long)]
+    tsv: bool,
+    /// Produce compact JSON files
+    #[arg(long)]
+    compact: bool,
+    /// Report errors as a JSON file, or "-" for standard output
+    #[arg(long)]
+    error_file: Option<String>,
+    /// Also write log messages to this file
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<String>,
+    /// Verbosity
+    #[command(flatten)]
+    verbose: Verbosity<WarnLevel>,
+}
+
+impl Args {
+    fn delimiter(&self) -> char {
+        if self.tsv || self.infile.ends_with(TSV_EXTENSION) {
+            '\t'
+        } else {
+            ','
+        }
+    }
+}
+
+fn process(args: &Args) -> Result<()> {
+    info!(target: "types3", "read: {}", args.infile);
+    let data = fs::read_to_string(&args.infile)?;
+    let input = import::import(&data, args.delimiter())?;
+    input::validate(&input)?;
+    let tokens = input.samples.iter().map(|s| s.tokens.len()).sum::<usize>();
+    info!(target: "types3", "{} samples, {} tokens", input.samples.len(), tokens);
+    info!(target: "types3", "write: {}", args.outfile);
+    let mut writer: Box<dyn Write> = if args.outfile == STDOUT {
+        Box::new(io::BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(io::BufWriter::new(fs::File::create(&args.outfile)?))
+    };
+    if args.compact {
+        serde_json::to_writer(&mut writer, &input)?;
+    } else {
+        serde_json::to_writer_pretty(&mut writer, &input)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn store_error(error_file: &str, e: &dyn error::Error) -> Result<()> {
+    let error = OError {
+        error: format!("{e}"),
+    };
+    let mut writer: Box<dyn Write> = if error_file == STDOUT {
+        Box::new(io::BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(io::BufWriter::new(fs::File::create(error_file)?))
+    };
+    serde_json::to_writer(&mut writer, &error)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(e) = logging::init(args.verbose.log_level_filter(), args.log_file.as_deref()) {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+    match process(&args) {
+        Ok(()) => (),
+        Err(e) => {
+            match args.error_file {
+                Some(filename) => match store_error(&filename, &*e) {
+                    Ok(()) => {
+                        info!(target: "types3", "error reported: {e}");
+                    }
+                    Err(e2) => {
+                        error!(target: "types3", "{e}");
+                        error!(target: "types3", "{e2}");
+                    }
+                },
+                None => error!(target: "types3", "{e}"),
+            }
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn args_delimiter() {
+        let args = Args::parse_from(["", "a.csv", "b.json"]);
+        assert_eq!(args.delimiter(), ',');
+        let args = Args::parse_from(["", "a.tsv", "b.json"]);
+        assert_eq!(args.delimiter(), '\t');
+        let args = Args::parse_from(["", "--tsv", "a.txt", "b.json"]);
+        assert_eq!(args.delimiter(), '\t');
+    }
+}
@@ -4,21 +4,25 @@ use itertools::Itertools;
 use log::{error, info};
 use rust_xlsxwriter::{Format, Workbook};
 use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::{error, fs, io, process};
-use types3::categories;
+use types3::categories::{self, Category};
 use types3::driver;
 use types3::errors::{self, Result};
-use types3::input::{ISample, Input, Year};
-use types3::output::{self, OError};
+use types3::input::{self, ISample, Year};
+use types3::logging;
+use types3::output::{self, OError, Years};
 use types3::samples::{self, CSample};
 
+const STDOUT: &str = "-";
+
 /// Export statistics (used by types3-ui)
 #[derive(Parser)]
 #[command(version)]
 struct Args {
-    /// Input file (JSON)
+    /// Input file (JSON), possibly compressed with gzip
     infile: String,
-    /// Output file (XLSX)
+    /// Output file (XLSX); writing to standard output is not supported
     outfile: String,
     /// Starting offset
     #[arg(long, default_value_t = 0)]
@@ -30,25 +34,35 @@ struct Args {
     #[arg(long, default_value_t = 9999)]
     end: Year,
     /// Window length (years)
-    #[arg(long)]
-    window: Year,
+    #[arg(long, required_unless_present = "only_period")]
+    window: Option<Year>,
     /// Step length (years)
-    #[arg(long)]
-    step: Year,
+    #[arg(long, required_unless_present = "only_period")]
+    step: Option<Year>,
+    /// Only report this period (and everything), e.g. 1700:1749 (window and step are ignored)
+    #[arg(long, value_name = "START:END")]
+    only_period: Option<String>,
     /// Sample metadata restriction, of the form key=value
     #[arg(long)]
     restrict_samples: Option<String>,
     /// Token metadata restriction, of the form key=value
     #[arg(long)]
     restrict_tokens: Option<String>,
-    /// Report errors as a JSON file
+    /// Also report the joint distribution of two sample metadata keys
+    #[arg(long, num_args = 2, value_names = ["KEY1", "KEY2"])]
+    crosstab: Option<Vec<String>>,
+    /// Report errors as a JSON file, or "-" for standard output
     #[arg(long)]
     error_file: Option<String>,
+    /// Also write log messages to this file
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<String>,
     /// Verbosity
     #[command(flatten)]
     verbose: Verbosity<WarnLevel>,
 }
 
+#[derive(PartialEq, Eq, Debug)]
 struct RawStat<'a> {
     samples: u64,
     words: u64,
@@ -70,7 +84,7 @@ impl<'a> RawStat<'a> {
         self.samples += 1;
         self.words += sample.words;
         for token in &sample.tokens {
-            self.tokens += 1;
+            self.tokens += token.count;
             self.types.insert(token.token);
         }
     }
@@ -87,6 +101,92 @@ impl<'a> RawStat<'a> {
 
 type MdPair<'a> = (&'a String, &'a String);
 
+/// Is `md` the sample-level restriction? It is then the same for all samples, so we skip it.
+fn skip(restrict_samples: Category, md: &MdPair) -> bool {
+    match restrict_samples {
+        None => false,
+        Some((k, v)) => md.0 == k && md.1 == v,
+    }
+}
+
+/// Parse a period of the form `START:END`, with both endpoints included.
+fn parse_period(arg: &str) -> Result<Years> {
+    let parsed = arg
+        .split_once(':')
+        .and_then(|(a, b)| Some((a.parse::<Year>().ok()?, b.parse::<Year>().ok()?)));
+    match parsed {
+        Some((start, end)) if start <= end && end < Year::MAX => Ok((start, end + 1)),
+        _ => Err(errors::invalid_argument(format!(
+            "period should be of the form 'START:END', got '{arg}'"
+        ))),
+    }
+}
+
+/// Statistics for each period in `periods`, followed by the totals for all data,
+/// labeled with `years`: everything, and each value in `smd` separately.
+///
+/// Undated samples are only included in the totals; see [samples::in_period].
+fn period_stats<'a, 'p>(
+    periods: &'p [Years],
+    years: &'p Years,
+    restrict_samples: Category,
+    smd: &[MdPair<'a>],
+    samples: &'a [CSample],
+) -> Vec<(&'p Years, RawStat<'a>, Vec<RawStat<'a>>)> {
+    let smd_map: HashMap<MdPair, usize> = smd.iter().enumerate().map(|(i, &x)| (x, i)).collect();
+    let mut by_period = vec![];
+    let ranges = periods.iter().map(|p| (p, false)).chain([(years, true)]);
+    for (period, all_time) in ranges {
+        let mut overall = RawStat::new();
+        let mut by_smd = (0..smd.len()).map(|_| RawStat::new()).collect_vec();
+        for sample in samples {
+            if all_time || samples::in_period(period, sample) {
+                overall.feed_sample(sample);
+                for md in sample.metadata {
+                    if !skip(restrict_samples, &md) {
+                        by_smd[smd_map[&md]].feed_sample(sample);
+                    }
+                }
+            }
+        }
+        by_period.push((period, overall, by_smd));
+    }
+    by_period
+}
+
+/// Values of two metadata keys; `None` if the sample does not have the key.
+type ValuePair<'a> = (Option<&'a String>, Option<&'a String>);
+
+/// Statistics for each combination of values of `key1` and `key2`, sorted by values,
+/// together with the statistics for all samples.
+fn crosstab<'a>(
+    key1: &str,
+    key2: &str,
+    samples: &'a [CSample<'a>],
+) -> Result<(Vec<(ValuePair<'a>, RawStat<'a>)>, RawStat<'a>)> {
+    for key in [key1, key2] {
+        if samples::count_missing_key(key, samples) == samples.len() {
+            return Err(errors::invalid_input(format!(
+                "there are no samples with metadata key {}",
+                key
+            )));
+        }
+    }
+    let mut overall = RawStat::new();
+    let mut cells: HashMap<ValuePair, RawStat> = HashMap::new();
+    for sample in samples {
+        overall.feed_sample(sample);
+        let values = (sample.metadata.get(key1), sample.metadata.get(key2));
+        cells
+            .entry(values)
+            .or_insert_with(RawStat::new)
+            .feed_sample(sample);
+    }
+    let mut cells = cells.into_iter().collect_vec();
+    cells.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((cells, overall))
+}
+
 enum Kind {
     Samples,
     Words,
@@ -116,52 +216,38 @@ fn stat(args: &Args, samples: &[ISample]) -> Result<Workbook> {
         restrict_samples,
         restrict_tokens,
         None,
+        &[],
         samples,
     );
     if samples.is_empty() {
         return Err(errors::invalid_input_ref("no samples found"));
     }
-    let years = samples::get_years(&samples);
+    let years = samples::get_years(&samples)
+        .ok_or_else(|| errors::invalid_input_ref("no dated samples found"))?;
     info!(target: "types3", "years in input data: {}", output::pretty_period(&years));
 
-    let mut periods = driver::get_periods(args.offset, args.window, args.step, &years);
-    periods.push(years);
-
-    let skip = |md: &MdPair| -> bool {
-        match restrict_samples {
-            None => false,
-            Some((k, v)) => md.0 == k && md.1 == v,
-        }
+    let periods = match &args.only_period {
+        Some(period) => vec![parse_period(period)?],
+        None => driver::get_periods(
+            args.offset,
+            args.window.unwrap_or(0),
+            args.step.unwrap_or(0),
+            &years,
+        ),
     };
 
     let mut smd: HashSet<MdPair> = HashSet::new();
     for sample in &samples {
         for md in sample.metadata {
-            if !skip(&md) {
+            if !skip(restrict_samples, &md) {
                 smd.insert(md);
             }
         }
     }
     let mut smd: Vec<MdPair> = smd.into_iter().collect_vec();
     smd.sort();
-    let smd_map: HashMap<MdPair, usize> = smd.iter().enumerate().map(|(i, &x)| (x, i)).collect();
 
-    let mut by_period = vec![];
-    for period in &periods {
-        let mut overall = RawStat::new();
-        let mut by_smd = (0..smd.len()).map(|_| RawStat::new()).collect_vec();
-        for sample in &samples {
-            if period.0 <= sample.year && sample.year < period.1 {
-                overall.feed_sample(sample);
-                for md in sample.metadata {
-                    if !skip(&md) {
-                        by_smd[smd_map[&md]].feed_sample(sample);
-                    }
-                }
-            }
-        }
-        by_period.push((period, overall, by_smd));
-    }
+    let by_period = period_stats(&periods, &years, restrict_samples, &smd, &samples);
 
     let mut workbook = Workbook::new();
     let bold = Format::new().set_bold();
@@ -204,13 +290,44 @@ fn stat(args: &Args, samples: &[ISample]) -> Result<Workbook> {
             }
         }
     }
+    if let Some(keys) = &args.crosstab {
+        let (cells, overall) = crosstab(&keys[0], &keys[1], &samples)?;
+        let sheet = workbook.add_worksheet();
+        sheet.set_name("crosstab")?;
+        sheet.write_with_format(0, 0, &keys[0], &bold)?;
+        sheet.write_with_format(0, 1, &keys[1], &bold)?;
+        for (j, kind) in SHEETS.iter().enumerate() {
+            sheet.write_with_format(0, j as u16 + 2, kind.sheetname(), &bold)?;
+        }
+        let rows = cells
+            .iter()
+            .map(|(values, st)| (values.0.map(|v| v as &str), values.1.map(|v| v as &str), st))
+            .chain([(Some("Everything"), None, &overall)]);
+        for (i, (v1, v2, st)) in rows.enumerate() {
+            let row = i as u32 + 1;
+            if let Some(v1) = v1 {
+                sheet.write_with_format(row, 0, v1, &bold)?;
+            }
+            if let Some(v2) = v2 {
+                sheet.write_with_format(row, 1, v2, &bold)?;
+            }
+            for (j, kind) in SHEETS.iter().enumerate() {
+                sheet.write(row, j as u16 + 2, st.get(kind))?;
+            }
+        }
+    }
     Ok(workbook)
 }
 
 fn process(args: &Args) -> Result<()> {
+    if args.outfile == STDOUT {
+        return Err(errors::invalid_argument_ref(
+            "cannot write XLSX to standard output, please give the name of the output file",
+        ));
+    }
     info!(target: "types3", "read: {}", args.infile);
-    let indata = fs::read_to_string(&args.infile)?;
-    let input: Input = serde_json::from_str(&indata)?;
+    let input = input::read_input(&args.infile)?;
+    input::validate(&input)?;
     let mut workbook = stat(args, &input.samples)?;
     info!(target: "types3", "write: {}", args.outfile);
     workbook.save(&args.outfile)?;
@@ -221,17 +338,22 @@ fn store_error(error_file: &str, e: &dyn error::Error) -> Result<()> {
     let error = OError {
         error: format!("{e}"),
     };
-    let file = fs::File::create(error_file)?;
-    let writer = io::BufWriter::new(file);
-    serde_json::to_writer(writer, &error)?;
+    let mut writer: Box<dyn Write> = if error_file == STDOUT {
+        Box::new(io::BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(io::BufWriter::new(fs::File::create(error_file)?))
+    };
+    serde_json::to_writer(&mut writer, &error)?;
+    writer.flush()?;
     Ok(())
 }
 
 fn main() {
     let args = Args::parse();
-    pretty_env_logger::formatted_timed_builder()
-        .filter_level(args.verbose.log_level_filter())
-        .init();
+    if let Err(e) = logging::init(args.verbose.log_level_filter(), args.log_file.as_deref()) {
+        eprintln!("{e}");
+        process::exit(1);
+    }
     match process(&args) {
         Ok(()) => (),
         Err(e) => {
@@ -251,3 +373,135 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ceec() -> input::Input {
+        input::read_input("sample-data/ceec.json").unwrap()
+    }
+
+    #[test]
+    fn crosstab_sums() {
+        let input = ceec();
+        let samples = samples::get_samples(&(0, 9999), None, None, None, &[], &input.samples);
+        let (cells, overall) = crosstab("gender", "socmob", &samples).unwrap();
+        assert_eq!(overall.samples, samples.len() as u64);
+        assert!(cells.len() > 2);
+        assert!(cells.iter().all(|(values, _)| values.0.is_some()));
+        assert_eq!(
+            cells.iter().map(|(_, st)| st.samples).sum::<u64>(),
+            overall.samples
+        );
+        assert_eq!(
+            cells.iter().map(|(_, st)| st.words).sum::<u64>(),
+            overall.words
+        );
+        assert_eq!(
+            cells.iter().map(|(_, st)| st.tokens).sum::<u64>(),
+            overall.tokens
+        );
+        let types = cells
+            .iter()
+            .flat_map(|(_, st)| &st.types)
+            .collect::<HashSet<_>>();
+        assert_eq!(types.len(), overall.types.len());
+        for (_, st) in &cells {
+            assert!(st.types.len() as u64 <= st.tokens);
+        }
+        assert!(crosstab("gender", "nonexistent", &samples).is_err());
+    }
+
+    #[test]
+    fn crosstab_sheet() {
+        let args = Args::parse_from([
+            "",
+            "--window",
+            "20",
+            "--step",
+            "20",
+            "--crosstab",
+            "gender",
+            "socmob",
+            "a",
+            "b",
+        ]);
+        assert_eq!(
+            args.crosstab.as_deref(),
+            Some(&["gender".to_owned(), "socmob".to_owned()][..])
+        );
+        let mut workbook = stat(&args, &ceec().samples).unwrap();
+        assert!(workbook.worksheet_from_name("crosstab").is_ok());
+        assert!(Args::try_parse_from([
+            "",
+            "--window",
+            "1",
+            "--step",
+            "1",
+            "--crosstab",
+            "gender",
+            "a",
+            "b"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn parse_period_basic() {
+        assert_eq!(parse_period("1700:1749").unwrap(), (1700, 1750));
+        assert_eq!(parse_period("1700:1700").unwrap(), (1700, 1701));
+        assert!(parse_period("1700").is_err());
+        assert!(parse_period("1750:1700").is_err());
+        assert!(parse_period("a:b").is_err());
+    }
+
+    #[test]
+    fn only_period_matches_full() {
+        let input = ceec();
+        let samples = samples::get_samples(&(0, 9999), None, None, None, &[], &input.samples);
+        let years = samples::get_years(&samples).unwrap();
+        let mut smd = samples
+            .iter()
+            .flat_map(|s| s.metadata.iter())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect_vec();
+        smd.sort();
+        let periods = driver::get_periods(0, 20, 20, &years);
+        let full = period_stats(&periods, &years, None, &smd, &samples);
+        let period = parse_period("1720:1739").unwrap();
+        let only = [period];
+        let single = period_stats(&only, &years, None, &smd, &samples);
+        let row = full.iter().find(|(p, _, _)| **p == period).unwrap();
+        assert_eq!(single[0], *row);
+        assert_eq!(single[1], *full.last().unwrap());
+        assert!(row.1.samples > 0);
+
+        let args = Args::parse_from(["", "--only-period", "1720:1739", "a", "b"]);
+        assert!(stat(&args, &input.samples).is_ok());
+        assert!(Args::try_parse_from(["", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn undated_only_in_totals() {
+        let no_metadata = HashMap::new();
+        let samples = [Some(1900), Some(1910), None]
+            .into_iter()
+            .map(|year| CSample {
+                year,
+                metadata: &no_metadata,
+                words: 10,
+                chars: 0,
+                tokens: vec![],
+                source: None,
+            })
+            .collect_vec();
+        let years = samples::get_years(&samples).unwrap();
+        let periods = [years];
+        let stats = period_stats(&periods, &years, None, &[], &samples);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].1.samples, 2);
+        assert_eq!(stats[1].1.samples, 3);
+    }
+}
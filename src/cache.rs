@@ -0,0 +1,368 @@
+//! Cache for the results of the calculations.
+//!
+//! When the same data set is analyzed repeatedly with different parameters,
+//! many subsets stay the same. Since the random permutations are determined by the seed,
+//! the results for the same subset and the same parameters are also the same,
+//! so we can reuse them instead of recomputing. Subsets are identified by a hash
+//! of their samples, so a hash collision would give wrong results; see [Cache].
+
+use crate::calc_avg::AvgExtras;
+use crate::calc_point::Point;
+use crate::calculation::{self, Sample};
+use crate::errors::Result;
+use crate::output::{AvgResult, MeasureY, PointResult};
+use crate::parallelism;
+use itertools::Itertools;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Identifies a calculation of averages; see [crate::output::OResult::average_at_limit].
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize, Serialize)]
+pub(crate) struct AvgKey {
+    samples: u64,
+    measure_y: MeasureY,
+    iter: u64,
+    limit: u64,
+    checkpoints: Vec<u64>,
+    seed: u64,
+    /// Quantile levels of [AvgResult::percentiles], as bits; missing in older cache files.
+    #[serde(default)]
+    percentiles: Option<(u64, u64)>,
+    /// Optional statistics; older cache files always have all of them.
+    #[serde(default = "all_extras")]
+    extras: AvgExtras,
+}
+
+impl AvgKey {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        measure_y: MeasureY,
+        samples: &[Sample],
+        iter: u64,
+        limit: u64,
+        checkpoints: &[f64],
+        percentiles: Option<(f64, f64)>,
+        extras: AvgExtras,
+        seed: u64,
+    ) -> AvgKey {
+        AvgKey {
+            samples: calculation::hash_samples(samples),
+            measure_y,
+            iter,
+            limit,
+            checkpoints: checkpoint_bits(checkpoints),
+            seed,
+            percentiles: percentiles.map(|(low, high)| (low.to_bits(), high.to_bits())),
+            extras,
+        }
+    }
+}
+
+/// Identifies a comparison with points; see [crate::calc_point::compare_with_points].
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize, Serialize)]
+pub(crate) struct PointKey {
+    samples: u64,
+    measure_y: MeasureY,
+    iter: u64,
+    points: Vec<Point>,
+    checkpoints: Vec<u64>,
+    seed: u64,
+    /// Do we fill in [PointResult::moments]? Older cache files always do.
+    #[serde(default = "all_moments")]
+    moments: bool,
+}
+
+impl PointKey {
+    pub(crate) fn new(
+        measure_y: MeasureY,
+        samples: &[Sample],
+        iter: u64,
+        points: &[Point],
+        checkpoints: &[f64],
+        moments: bool,
+        seed: u64,
+    ) -> PointKey {
+        PointKey {
+            samples: calculation::hash_samples(samples),
+            measure_y,
+            iter,
+            points: points.to_vec(),
+            checkpoints: checkpoint_bits(checkpoints),
+            seed,
+            moments,
+        }
+    }
+}
+
+fn all_extras() -> AvgExtras {
+    AvgExtras::ALL
+}
+
+fn all_moments() -> bool {
+    true
+}
+
+fn checkpoint_bits(checkpoints: &[f64]) -> Vec<u64> {
+    checkpoints.iter().map(|f| f.to_bits()).collect_vec()
+}
+
+/// On-disk representation of [Cache].
+#[derive(Default, Deserialize, Serialize)]
+struct CacheData {
+    averages: Vec<(AvgKey, Vec<AvgResult>)>,
+    points: Vec<(PointKey, Vec<Vec<PointResult>>)>,
+}
+
+/// Cache for the results of the calculations.
+///
+/// Results are identified by a 64-bit hash of the samples (see [calculation::hash_samples])
+/// together with all parameters that affect the results.
+/// A cache hit gives the same results as recomputing unless two different sets of samples
+/// have the same hash; this is extremely unlikely, but not impossible.
+/// A cache can be shared by any number of calculations; see [crate::driver::DriverArgs::cache].
+#[derive(Default)]
+pub struct Cache {
+    averages: Mutex<HashMap<AvgKey, Vec<AvgResult>>>,
+    points: Mutex<HashMap<PointKey, Vec<Vec<PointResult>>>>,
+    hits: AtomicU64,
+    computed: AtomicU64,
+}
+
+impl Cache {
+    /// Create an empty cache.
+    pub fn new() -> Cache {
+        Cache::default()
+    }
+
+    /// Read a cache from a file (JSON) written with [Cache::save].
+    pub fn load(filename: impl AsRef<Path>) -> Result<Cache> {
+        let data: CacheData = serde_json::from_str(&fs::read_to_string(filename)?)?;
+        Ok(Cache {
+            averages: Mutex::new(data.averages.into_iter().collect()),
+            points: Mutex::new(data.points.into_iter().collect()),
+            ..Cache::default()
+        })
+    }
+
+    /// Write the cache to a file (JSON).
+    pub fn save(&self, filename: impl AsRef<Path>) -> Result<()> {
+        let data = CacheData {
+            averages: to_entries(&self.averages),
+            points: to_entries(&self.points),
+        };
+        let file = fs::File::create(filename)?;
+        serde_json::to_writer(io::BufWriter::new(file), &data)?;
+        Ok(())
+    }
+
+    /// Number of results found in the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of results that were not found in the cache and had to be computed.
+    pub fn computed(&self) -> u64 {
+        self.computed.load(Ordering::Relaxed)
+    }
+
+    /// Number of results stored in the cache.
+    pub fn len(&self) -> usize {
+        self.averages.lock().unwrap().len() + self.points.lock().unwrap().len()
+    }
+
+    /// Is the cache empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Log cache statistics.
+    pub fn report(&self) {
+        info!(target: "types3", "cache: {} hits, {} computed, {} stored", self.hits(), self.computed(), self.len());
+    }
+
+    pub(crate) fn averages(
+        &self,
+        key: AvgKey,
+        cancel: Option<&AtomicBool>,
+        compute: impl FnOnce() -> Vec<AvgResult>,
+    ) -> Vec<AvgResult> {
+        self.get_or_compute(&self.averages, key, cancel, compute)
+    }
+
+    pub(crate) fn points(
+        &self,
+        key: PointKey,
+        cancel: Option<&AtomicBool>,
+        compute: impl FnOnce() -> Vec<Vec<PointResult>>,
+    ) -> Vec<Vec<PointResult>> {
+        self.get_or_compute(&self.points, key, cancel, compute)
+    }
+
+    fn get_or_compute<K, V>(
+        &self,
+        map: &Mutex<HashMap<K, V>>,
+        key: K,
+        cancel: Option<&AtomicBool>,
+        compute: impl FnOnce() -> V,
+    ) -> V
+    where
+        K: Eq + Hash,
+        V: Clone,
+    {
+        if let Some(v) = map.lock().unwrap().get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return v.clone();
+        }
+        let v = compute();
+        // Results of a cancelled calculation are incomplete.
+        if !parallelism::is_cancelled(cancel) {
+            self.computed.fetch_add(1, Ordering::Relaxed);
+            map.lock().unwrap().insert(key, v.clone());
+        }
+        v
+    }
+}
+
+fn to_entries<K, V>(map: &Mutex<HashMap<K, V>>) -> Vec<(K, V)>
+where
+    K: Clone,
+    V: Clone,
+{
+    map.lock()
+        .unwrap()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect_vec()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::calculation::SToken;
+
+    fn sample(x: u64) -> Sample {
+        Sample {
+            x,
+            token_count: x,
+            tokens: vec![SToken {
+                id: 0,
+                count: x,
+                marked_count: 0,
+                also_marked: 0,
+                known: false,
+            }],
+            source: None,
+        }
+    }
+
+    fn avg(low: u64) -> Vec<AvgResult> {
+        vec![AvgResult {
+            low,
+            high: low,
+            iter: 1,
+            samples: None,
+            marginal: None,
+            min_y: None,
+            max_y: None,
+            percentiles: None,
+        }]
+    }
+
+    fn key(samples: &[Sample], limit: u64) -> AvgKey {
+        AvgKey::new(
+            MeasureY::Types,
+            samples,
+            10,
+            limit,
+            &[1.0],
+            None,
+            AvgExtras::ALL,
+            0,
+        )
+    }
+
+    #[test]
+    fn cache_hit() {
+        let cache = Cache::new();
+        let samples = [sample(1), sample(2)];
+        assert_eq!(cache.averages(key(&samples, 1), None, || avg(1)), avg(1));
+        assert_eq!(cache.averages(key(&samples, 1), None, || avg(2)), avg(1));
+        assert_eq!(cache.averages(key(&samples, 2), None, || avg(3)), avg(3));
+        let other = [sample(2), sample(1)];
+        assert_eq!(cache.averages(key(&other, 1), None, || avg(4)), avg(4));
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.computed(), 3);
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn cache_cancelled() {
+        let cache = Cache::new();
+        let samples = [sample(1)];
+        let cancel = AtomicBool::new(true);
+        assert_eq!(
+            cache.averages(key(&samples, 1), Some(&cancel), || avg(1)),
+            avg(1)
+        );
+        assert_eq!(cache.averages(key(&samples, 1), None, || avg(2)), avg(2));
+        assert_eq!(cache.hits(), 0);
+        assert_eq!(cache.computed(), 1);
+    }
+
+    #[test]
+    fn cache_save_load() {
+        let filename =
+            std::env::temp_dir().join(format!("types3-cache-{}.json", std::process::id()));
+        let cache = Cache::new();
+        let samples = [sample(1)];
+        let points = [Point { x: 1, y: 1 }];
+        let pr = vec![vec![PointResult {
+            above: 1,
+            below: 2,
+            iter: 3,
+            moments: None,
+        }]];
+        let pkey = || PointKey::new(MeasureY::Types, &samples, 10, &points, &[1.0], true, 0);
+        cache.averages(key(&samples, 1), None, || avg(1));
+        cache.points(pkey(), None, || pr.clone());
+        cache.save(&filename).unwrap();
+        let loaded = Cache::load(&filename).unwrap();
+        fs::remove_file(&filename).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.averages(key(&samples, 1), None, || avg(2)), avg(1));
+        assert_eq!(loaded.points(pkey(), None, Vec::new), pr);
+        assert_eq!(loaded.hits(), 2);
+        assert_eq!(loaded.computed(), 0);
+    }
+
+    #[test]
+    fn cache_extras() {
+        let cache = Cache::new();
+        let samples = [sample(1)];
+        let none = AvgKey::new(
+            MeasureY::Types,
+            &samples,
+            10,
+            1,
+            &[1.0],
+            None,
+            AvgExtras::default(),
+            0,
+        );
+        cache.averages(key(&samples, 1), None, || avg(1));
+        assert_eq!(cache.averages(none.clone(), None, || avg(2)), avg(2));
+        assert_eq!(cache.hits(), 0);
+        // Older cache files have all optional statistics.
+        let mut json = serde_json::to_value(&none).unwrap();
+        json.as_object_mut().unwrap().remove("extras");
+        let old: AvgKey = serde_json::from_value(json).unwrap();
+        assert_eq!(old, key(&samples, 1));
+    }
+}
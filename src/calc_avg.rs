@@ -1,50 +1,261 @@
+//! Averages of random subcorpora at a given size.
+
 use crate::calculation::{self, Sample};
 use crate::counter::{
-    self, Counter, HapaxCounter, SampleCounter, TokenCounter, TypeCounter, TypeRatioCounter,
+    self, Counter, EntropyCounter, FrequentTypesCounter, GuiraudRCounter, HapaxCounter,
+    HapaxTokenRatioCounter, HerdanCCounter, KnownTypeCounter, MarkedTokenCounter,
+    MeanFrequencyCounter, NLegomenaCounter, SampleCounter, SourceCounter, TokenCounter,
+    TypeCounter, TypeRatioCounter, TypeRatiosCounter, TypeTokenRatioCounter, YuleKCounter,
+    ZipfSlopeCounter,
+};
+use crate::output::{
+    self, AvgResult, MeasureY, OMeanCurve, OMeanPoint, OTarget, MEAN_CURVE_STEPS, TARGET_HIGH,
+    TARGET_LOW,
 };
-use crate::output::{AvgResult, MeasureY};
 use crate::parallelism::{self, ParResult};
-use crate::shuffle;
+use crate::shuffle::{self, Permutations};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicBool;
 
+/// Average value of [MeasureY] in random subcorpora with `limit` many things of type
+/// [crate::output::MeasureX], over `iter` random permutations of `samples`.
+///
+/// See [crate::output::OResult::average_at_limit].
 pub fn average_at_limit(
     measure_y: MeasureY,
     samples: &[Sample],
     iter: u64,
     limit: u64,
+    seed: u64,
+    cancel: Option<&AtomicBool>,
 ) -> AvgResult {
-    match measure_y {
-        MeasureY::Types => do_count::<TypeCounter>(samples, iter, limit),
-        MeasureY::Tokens => do_count::<TokenCounter>(samples, iter, limit),
-        MeasureY::Hapaxes => do_count::<HapaxCounter>(samples, iter, limit),
-        MeasureY::Samples => do_count::<SampleCounter>(samples, iter, limit),
-        MeasureY::MarkedTypes => do_count::<TypeRatioCounter>(samples, iter, limit),
-    }
+    average_at_limit_checkpoints(
+        measure_y,
+        samples,
+        iter,
+        limit,
+        &[1.0],
+        None,
+        AvgExtras::ALL,
+        seed,
+        cancel,
+    )
+    .pop()
+    .unwrap()
+}
+
+/// Optional statistics of the random subcorpora in [average_at_limit_checkpoints].
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug, Deserialize, Serialize)]
+pub struct AvgExtras {
+    /// Fill in [AvgResult::samples].
+    pub samples: bool,
+    /// Fill in [AvgResult::marginal].
+    pub marginal: bool,
+    /// Fill in [AvgResult::min_y] and [AvgResult::max_y].
+    pub extremes: bool,
+}
+
+impl AvgExtras {
+    /// All optional statistics.
+    pub const ALL: AvgExtras = AvgExtras {
+        samples: true,
+        marginal: true,
+        extremes: true,
+    };
+}
+
+/// Like [average_at_limit], but also report the results after a fraction of iterations.
+///
+/// There is one result for each checkpoint; see [parallelism::compute_parallel].
+/// If `percentiles` is `(low, high)`, also fill in [AvgResult::percentiles] with
+/// the `low` quantile of the lower bounds and the `high` quantile of the upper bounds
+/// in the random subcorpora. The other optional statistics are filled in as
+/// selected by `extras`; [average_at_limit] fills in all of them.
+///
+/// # Examples
+/// ```
+/// use types3::calc_avg::{self, AvgExtras};
+/// use types3::calculation::{SToken, Sample};
+/// use types3::output::MeasureY;
+/// let sample = |id| Sample { x: 1, token_count: 1, tokens: vec![SToken { id, count: 1, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(0), sample(1), sample(0)];
+/// let r = calc_avg::average_at_limit_checkpoints(MeasureY::Types, &samples, 1000, 2, &[1.0], Some((0.025, 0.975)), AvgExtras::default(), 0, None);
+/// // With two samples, there are 1 or 2 types, and 2 types with probability 2/3.
+/// assert_eq!(r[0].percentiles, Some((1, 2)));
+/// assert_eq!((r[0].samples, r[0].min_y), (None, None));
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn average_at_limit_checkpoints(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    limit: u64,
+    checkpoints: &[f64],
+    percentiles: Option<(f64, f64)>,
+    extras: AvgExtras,
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> Vec<AvgResult> {
+    let permutations = Permutations::Random { iter, seed };
+    calc(
+        measure_y,
+        samples,
+        limit,
+        checkpoints,
+        permutations,
+        percentiles,
+        extras,
+        cancel,
+    )
+}
+
+/// Like [average_at_limit], but with the given source of permutations.
+///
+/// With [Permutations::Random], this is the same as [average_at_limit].
+/// With [Permutations::Fixed], the result is fully determined by the permutations,
+/// and [AvgResult::iter] is the number of permutations.
+///
+/// # Examples
+/// ```
+/// use types3::calc_avg;
+/// use types3::calculation::{SToken, Sample};
+/// use types3::output::MeasureY;
+/// use types3::shuffle::Permutations;
+/// let sample = |count| Sample { x: 1, token_count: count, tokens: vec![SToken { id: 0, count, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(1), sample(5)];
+/// let permutations = [vec![1, 0], vec![1, 0], vec![0, 1]];
+/// let r = calc_avg::average_at_limit_with(MeasureY::Tokens, &samples, 1, Permutations::Fixed(&permutations));
+/// assert_eq!((r.low, r.high, r.iter), (11, 11, 3));
+/// ```
+pub fn average_at_limit_with(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    limit: u64,
+    permutations: Permutations,
+) -> AvgResult {
+    calc(
+        measure_y,
+        samples,
+        limit,
+        &[1.0],
+        permutations,
+        None,
+        AvgExtras::ALL,
+        None,
+    )
+    .pop()
+    .unwrap()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    limit: u64,
+    checkpoints: &[f64],
+    permutations: Permutations,
+    percentiles: Option<(f64, f64)>,
+    extras: AvgExtras,
+    cancel: Option<&AtomicBool>,
+) -> Vec<AvgResult> {
+    let f = match measure_y {
+        MeasureY::Types => do_count::<TypeCounter>,
+        MeasureY::Tokens => do_count::<TokenCounter>,
+        MeasureY::Hapaxes => do_count::<HapaxCounter>,
+        MeasureY::Samples => do_count::<SampleCounter>,
+        MeasureY::MarkedTypes => do_count::<TypeRatioCounter>,
+        MeasureY::MeanFrequency => do_count::<MeanFrequencyCounter>,
+        MeasureY::HapaxTokenRatio => do_count::<HapaxTokenRatioCounter>,
+        MeasureY::Sources => do_count::<SourceCounter>,
+        MeasureY::MarkedTokens => do_count::<MarkedTokenCounter>,
+        MeasureY::ZipfSlope => do_count::<ZipfSlopeCounter>,
+        MeasureY::FrequentTypes { .. } => do_count::<FrequentTypesCounter>,
+        MeasureY::KnownTypes => do_count::<KnownTypeCounter>,
+        MeasureY::Legomena { .. } => do_count::<NLegomenaCounter>,
+        MeasureY::TypeTokenRatio => do_count::<TypeTokenRatioCounter>,
+        MeasureY::YuleK => do_count::<YuleKCounter>,
+        MeasureY::Entropy => do_count::<EntropyCounter>,
+        MeasureY::GuiraudR => do_count::<GuiraudRCounter>,
+        MeasureY::HerdanC => do_count::<HerdanCCounter>,
+    };
+    f(
+        measure_y,
+        samples,
+        limit,
+        checkpoints,
+        permutations,
+        percentiles,
+        extras,
+        cancel,
+    )
 }
 
-fn do_count<TCounter>(samples: &[Sample], iter: u64, limit: u64) -> AvgResult
+#[allow(clippy::too_many_arguments)]
+fn do_count<TCounter>(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    limit: u64,
+    checkpoints: &[f64],
+    permutations: Permutations,
+    percentiles: Option<(f64, f64)>,
+    extras: AvgExtras,
+    cancel: Option<&AtomicBool>,
+) -> Vec<AvgResult>
 where
     TCounter: Counter,
 {
     calculation::verify_samples(samples);
     let total_types = counter::count_types(samples);
-    let (r, iter) = parallelism::compute_parallel(
-        || AvgParResult { low: 0, high: 0 },
-        |job, result| {
-            let mut counter = TCounter::new(total_types);
-            shuffle::shuffle_job(
-                |idx| calc_one(samples, limit, idx, &mut counter, result),
+    let init = || AvgParResult::new(percentiles.is_some(), extras);
+    let results = match permutations {
+        Permutations::Random { iter, seed } => parallelism::compute_parallel(
+            init,
+            |job, result| {
+                let mut counter = TCounter::for_measure(measure_y, total_types);
+                shuffle::shuffle_job(
+                    |idx| calc_one(samples, limit, idx, &mut counter, result),
+                    samples.len(),
+                    job,
+                );
+            },
+            iter,
+            checkpoints,
+            seed,
+            cancel,
+        ),
+        Permutations::Fixed(permutations) => {
+            assert_eq!(checkpoints, [1.0]);
+            let mut result = init();
+            let mut counter = TCounter::for_measure(measure_y, total_types);
+            shuffle::fixed_job(
+                |idx| calc_one(samples, limit, idx, &mut counter, &mut result),
                 samples.len(),
-                job,
+                permutations,
             );
-        },
-        iter,
-    );
-    AvgResult {
-        low: r.low,
-        high: r.high,
-        iter,
-    }
+            vec![(result, permutations.len() as u64)]
+        }
+    };
+    results
+        .into_iter()
+        .map(|(r, iter)| AvgResult {
+            low: r.low,
+            high: r.high,
+            iter,
+            samples: extras.samples.then_some(r.samples),
+            marginal: extras.marginal.then_some(r.marginal),
+            min_y: r.min_y,
+            max_y: r.max_y,
+            percentiles: percentiles.and_then(|(low, high)| {
+                Some((
+                    output::histogram_quantile(&r.low_values, low)?,
+                    output::histogram_quantile(&r.high_values, high)?,
+                ))
+            }),
+        })
+        .collect_vec()
 }
 
 fn calc_one<TCounter>(
@@ -58,20 +269,28 @@ fn calc_one<TCounter>(
 {
     counter.reset();
     if limit == 0 {
+        result.add_bounds(0, 0);
         return;
     }
     for i in idx {
         let c = counter.feed_sample(&samples[*i]);
+        if result.extras.samples {
+            result.samples += 1;
+        }
         match c.x.cmp(&limit) {
             Ordering::Less => (),
             Ordering::Equal => {
                 result.low += c.y;
                 result.high += c.y;
+                result.add_marginal(c.low_y, c.high_y);
+                result.add_bounds(c.y, c.y);
                 return;
             }
             Ordering::Greater => {
                 result.low += c.low_y;
                 result.high += c.high_y;
+                result.add_marginal(c.low_y, c.high_y);
+                result.add_bounds(c.low_y, c.high_y);
                 return;
             }
         }
@@ -79,16 +298,507 @@ fn calc_one<TCounter>(
     unreachable!();
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// Size at which random subcorpora first reach value `target` of [MeasureY],
+/// over `iter` random permutations of `samples`.
+///
+/// This inverts [average_at_limit]: in each permutation, we accumulate samples until
+/// the value is at least `target`, and record the size at that point, in the units of
+/// [crate::output::MeasureX]. As we add entire samples, this is the size after the sample
+/// that reaches the target. If all samples together do not reach the target,
+/// the permutation only counts in [OTarget::iter].
+///
+/// # Examples
+/// ```
+/// use types3::calc_avg;
+/// use types3::calculation::{SToken, Sample};
+/// use types3::output::MeasureY;
+/// let sample = |id| Sample { x: 1, token_count: 1, tokens: vec![SToken { id, count: 1, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(0), sample(1), sample(2)];
+/// let r = calc_avg::x_at_target(MeasureY::Types, &samples, 100, 2, 0, None);
+/// assert_eq!((r.reached, r.mean, r.median), (100, Some(2.0), Some(2)));
+/// ```
+pub fn x_at_target(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    target: u64,
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> OTarget {
+    let f = match measure_y {
+        MeasureY::Types => do_target::<TypeCounter>,
+        MeasureY::Tokens => do_target::<TokenCounter>,
+        MeasureY::Hapaxes => do_target::<HapaxCounter>,
+        MeasureY::Samples => do_target::<SampleCounter>,
+        MeasureY::MarkedTypes => do_target::<TypeRatioCounter>,
+        MeasureY::MeanFrequency => do_target::<MeanFrequencyCounter>,
+        MeasureY::HapaxTokenRatio => do_target::<HapaxTokenRatioCounter>,
+        MeasureY::Sources => do_target::<SourceCounter>,
+        MeasureY::MarkedTokens => do_target::<MarkedTokenCounter>,
+        MeasureY::ZipfSlope => do_target::<ZipfSlopeCounter>,
+        MeasureY::FrequentTypes { .. } => do_target::<FrequentTypesCounter>,
+        MeasureY::KnownTypes => do_target::<KnownTypeCounter>,
+        MeasureY::Legomena { .. } => do_target::<NLegomenaCounter>,
+        MeasureY::TypeTokenRatio => do_target::<TypeTokenRatioCounter>,
+        MeasureY::YuleK => do_target::<YuleKCounter>,
+        MeasureY::Entropy => do_target::<EntropyCounter>,
+        MeasureY::GuiraudR => do_target::<GuiraudRCounter>,
+        MeasureY::HerdanC => do_target::<HerdanCCounter>,
+    };
+    f(measure_y, samples, iter, target, seed, cancel)
+}
+
+fn do_target<TCounter>(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    target: u64,
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> OTarget
+where
+    TCounter: Counter,
+{
+    calculation::verify_samples(samples);
+    let total_types = counter::count_types(samples);
+    let (r, iter) = parallelism::compute_parallel(
+        TargetParResult::default,
+        |job, result| {
+            let mut counter = TCounter::for_measure(measure_y, total_types);
+            shuffle::shuffle_job(
+                |idx| calc_one_target(samples, target, idx, &mut counter, result),
+                samples.len(),
+                job,
+            );
+        },
+        iter,
+        &[1.0],
+        seed,
+        cancel,
+    )
+    .pop()
+    .unwrap();
+    let reached = r.sizes.values().sum::<u64>();
+    let quantile = |q| output::histogram_quantile(&r.sizes, q);
+    let total = r
+        .sizes
+        .iter()
+        .map(|(&x, &n)| x as f64 * n as f64)
+        .sum::<f64>();
+    OTarget {
+        target,
+        iter,
+        reached,
+        mean: (reached > 0).then(|| total / reached as f64),
+        low: quantile(TARGET_LOW),
+        median: quantile(0.5),
+        high: quantile(TARGET_HIGH),
+    }
+}
+
+fn calc_one_target<TCounter>(
+    samples: &[Sample],
+    target: u64,
+    idx: &[usize],
+    counter: &mut TCounter,
+    result: &mut TargetParResult,
+) where
+    TCounter: Counter,
+{
+    counter.reset();
+    for i in idx {
+        let c = counter.feed_sample(&samples[*i]);
+        if c.y >= target {
+            *result.sizes.entry(c.x).or_default() += 1;
+            return;
+        }
+    }
+}
+
+/// Number of permutations that first reach the target at each size.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+struct TargetParResult {
+    sizes: BTreeMap<u64, u64>,
+}
+
+impl ParResult for TargetParResult {
+    fn add(&mut self, other: Self) {
+        for (x, n) in other.sizes {
+            *self.sizes.entry(x).or_default() += n;
+        }
+    }
+}
+
+/// Mean accumulation curve of random subcorpora, over `iter` random permutations of `samples`.
+///
+/// This is [average_at_limit] at every [MEAN_CURVE_STEPS]th of `limit`
+/// (see [output::size_grid]), calculated from the same random permutations,
+/// so the last point is the same as what [average_at_limit] reports with the same seed.
+///
+/// # Examples
+/// ```
+/// use types3::calc_avg;
+/// use types3::calculation::{SToken, Sample};
+/// use types3::output::MeasureY;
+/// let sample = |id| Sample { x: 1, token_count: 1, tokens: vec![SToken { id, count: 1, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(0), sample(1), sample(2)];
+/// let r = calc_avg::mean_curve(MeasureY::Types, &samples, 100, 3, 0, None);
+/// let xy = r.points.iter().map(|p| (p.x, p.low / r.iter)).collect::<Vec<_>>();
+/// assert_eq!(xy, [(0, 0), (1, 1), (2, 2), (3, 3)]);
+/// ```
+pub fn mean_curve(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    limit: u64,
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> OMeanCurve {
+    let xs = output::size_grid(limit, MEAN_CURVE_STEPS);
+    let (r, iter) = curve(measure_y, samples, iter, &xs, seed, cancel);
+    OMeanCurve {
+        iter,
+        points: xs
+            .iter()
+            .zip(r.elems)
+            .map(|(&x, (low, high))| OMeanPoint { x, low, high })
+            .collect_vec(),
+    }
+}
+
+/// Like [average_at_limit], but at each of the sizes in `limits`, which have to be sorted
+/// and at most the total size of `samples`.
+///
+/// All sizes are calculated from the same random permutations, in one pass over each
+/// permutation, so the result at each size is the same as what [average_at_limit] reports
+/// with the same seed.
+///
+/// # Examples
+/// ```
+/// use types3::calc_avg;
+/// use types3::calculation::{SToken, Sample};
+/// use types3::output::MeasureY;
+/// let sample = |id| Sample { x: 1, token_count: 1, tokens: vec![SToken { id, count: 1, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(0), sample(1), sample(2)];
+/// let r = calc_avg::average_at_limits(MeasureY::Types, &samples, 100, &[1, 3], 0, None);
+/// let y = r.iter().map(|ar| (ar.low / ar.iter, ar.high / ar.iter)).collect::<Vec<_>>();
+/// assert_eq!(y, [(1, 1), (3, 3)]);
+/// ```
+pub fn average_at_limits(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    limits: &[u64],
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> Vec<AvgResult> {
+    let (r, iter) = curve(measure_y, samples, iter, limits, seed, cancel);
+    r.elems
+        .into_iter()
+        .map(|(low, high)| AvgResult {
+            low,
+            high,
+            iter,
+            samples: None,
+            marginal: None,
+            min_y: None,
+            max_y: None,
+            percentiles: None,
+        })
+        .collect_vec()
+}
+
+fn curve(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    xs: &[u64],
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> (CurveParResult, u64) {
+    let f = match measure_y {
+        MeasureY::Types => do_curve::<TypeCounter>,
+        MeasureY::Tokens => do_curve::<TokenCounter>,
+        MeasureY::Hapaxes => do_curve::<HapaxCounter>,
+        MeasureY::Samples => do_curve::<SampleCounter>,
+        MeasureY::MarkedTypes => do_curve::<TypeRatioCounter>,
+        MeasureY::MeanFrequency => do_curve::<MeanFrequencyCounter>,
+        MeasureY::HapaxTokenRatio => do_curve::<HapaxTokenRatioCounter>,
+        MeasureY::Sources => do_curve::<SourceCounter>,
+        MeasureY::MarkedTokens => do_curve::<MarkedTokenCounter>,
+        MeasureY::ZipfSlope => do_curve::<ZipfSlopeCounter>,
+        MeasureY::FrequentTypes { .. } => do_curve::<FrequentTypesCounter>,
+        MeasureY::KnownTypes => do_curve::<KnownTypeCounter>,
+        MeasureY::Legomena { .. } => do_curve::<NLegomenaCounter>,
+        MeasureY::TypeTokenRatio => do_curve::<TypeTokenRatioCounter>,
+        MeasureY::YuleK => do_curve::<YuleKCounter>,
+        MeasureY::Entropy => do_curve::<EntropyCounter>,
+        MeasureY::GuiraudR => do_curve::<GuiraudRCounter>,
+        MeasureY::HerdanC => do_curve::<HerdanCCounter>,
+    };
+    f(measure_y, samples, iter, xs, seed, cancel)
+}
+
+fn do_curve<TCounter>(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    xs: &[u64],
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> (CurveParResult, u64)
+where
+    TCounter: Counter,
+{
+    calculation::verify_samples(samples);
+    let total_types = counter::count_types(samples);
+    parallelism::compute_parallel(
+        || CurveParResult::new(xs.len()),
+        |job, result| {
+            let mut counter = TCounter::for_measure(measure_y, total_types);
+            shuffle::shuffle_job(
+                |idx| calc_one_curve(samples, xs, idx, &mut counter, result),
+                samples.len(),
+                job,
+            );
+        },
+        iter,
+        &[1.0],
+        seed,
+        cancel,
+    )
+    .pop()
+    .unwrap()
+}
+
+/// Accumulate one permutation in the same way as [calc_one], at each size in `xs`,
+/// which has to be sorted.
+fn calc_one_curve<TCounter>(
+    samples: &[Sample],
+    xs: &[u64],
+    idx: &[usize],
+    counter: &mut TCounter,
+    result: &mut CurveParResult,
+) where
+    TCounter: Counter,
+{
+    counter.reset();
+    // Points at size 0 are always 0.
+    let mut j = xs.iter().take_while(|&&x| x == 0).count();
+    for i in idx {
+        if j == xs.len() {
+            return;
+        }
+        let c = counter.feed_sample(&samples[*i]);
+        while j < xs.len() && xs[j] <= c.x {
+            let e = &mut result.elems[j];
+            if xs[j] == c.x {
+                e.0 += c.y;
+                e.1 += c.y;
+            } else {
+                e.0 += c.low_y;
+                e.1 += c.high_y;
+            }
+            j += 1;
+        }
+    }
+    assert_eq!(j, xs.len());
+}
+
+/// Sums of the lower and upper bounds at each point of the curve.
+#[derive(Clone)]
+struct CurveParResult {
+    elems: Vec<(u64, u64)>,
+}
+
+impl CurveParResult {
+    fn new(n: usize) -> CurveParResult {
+        CurveParResult {
+            elems: vec![(0, 0); n],
+        }
+    }
+}
+
+impl ParResult for CurveParResult {
+    fn add(&mut self, other: Self) {
+        debug_assert_eq!(self.elems.len(), other.elems.len());
+        for (a, b) in self.elems.iter_mut().zip(other.elems) {
+            a.0 += b.0;
+            a.1 += b.1;
+        }
+    }
+}
+
+/// Like [average_at_limit] with [MeasureY::MarkedTypes], but for several markings at once.
+///
+/// There is one result for each marking: first the primary marking
+/// ([crate::calculation::SToken::marked_count]), and then `also_marked` additional markings
+/// ([crate::calculation::SToken::also_marked]). All markings use the same random permutations,
+/// so the first result is the same as what [average_at_limit] reports.
+pub fn marked_types_at_limit(
+    samples: &[Sample],
+    also_marked: usize,
+    iter: u64,
+    limit: u64,
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> Vec<AvgResult> {
+    calculation::verify_samples(samples);
+    let total_types = counter::count_types(samples);
+    let markings = also_marked + 1;
+    let (r, iter) = parallelism::compute_parallel(
+        || RatiosParResult {
+            low: vec![0; markings],
+            high: vec![0; markings],
+        },
+        |job, result| {
+            let mut counter = TypeRatiosCounter::new(total_types, markings);
+            shuffle::shuffle_job(
+                |idx| calc_one_ratios(samples, limit, idx, &mut counter, result),
+                samples.len(),
+                job,
+            );
+        },
+        iter,
+        &[1.0],
+        seed,
+        cancel,
+    )
+    .pop()
+    .unwrap();
+    r.low
+        .into_iter()
+        .zip(r.high)
+        .map(|(low, high)| AvgResult {
+            low,
+            high,
+            iter,
+            samples: None,
+            marginal: None,
+            min_y: None,
+            max_y: None,
+            percentiles: None,
+        })
+        .collect_vec()
+}
+
+fn calc_one_ratios(
+    samples: &[Sample],
+    limit: u64,
+    idx: &[usize],
+    counter: &mut TypeRatiosCounter,
+    result: &mut RatiosParResult,
+) {
+    counter.reset();
+    if limit == 0 {
+        return;
+    }
+    for i in idx {
+        let x = counter.feed_sample(&samples[*i]);
+        if x < limit {
+            continue;
+        }
+        for m in 0..result.low.len() {
+            let (prev, cur) = counter.marked(m);
+            if x == limit {
+                result.low[m] += cur;
+            } else {
+                result.low[m] += prev;
+            }
+            result.high[m] += cur;
+        }
+        return;
+    }
+    unreachable!();
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct RatiosParResult {
+    low: Vec<u64>,
+    high: Vec<u64>,
+}
+
+impl ParResult for RatiosParResult {
+    fn add(&mut self, other: Self) {
+        for (a, b) in self.low.iter_mut().zip(other.low) {
+            *a += b;
+        }
+        for (a, b) in self.high.iter_mut().zip(other.high) {
+            *a += b;
+        }
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
 struct AvgParResult {
     low: u64,
     high: u64,
+    /// Number of samples that we fed to the counter, including the one that crossed the limit.
+    samples: u64,
+    /// Contribution of the sample that reached the limit, `high_y - low_y`.
+    marginal: i64,
+    /// Smallest `low_y` at the limit.
+    min_y: Option<u64>,
+    /// Largest `high_y` at the limit.
+    max_y: Option<u64>,
+    /// Do we keep track of the histograms, for [AvgResult::percentiles]?
+    histograms: bool,
+    /// Which of the other optional statistics do we keep track of?
+    extras: AvgExtras,
+    /// Number of random subcorpora with each `low_y` at the limit.
+    low_values: BTreeMap<u64, u64>,
+    /// Number of random subcorpora with each `high_y` at the limit.
+    high_values: BTreeMap<u64, u64>,
+}
+
+impl AvgParResult {
+    fn new(histograms: bool, extras: AvgExtras) -> AvgParResult {
+        AvgParResult {
+            histograms,
+            extras,
+            ..Default::default()
+        }
+    }
+
+    fn add_extremes(&mut self, low_y: u64, high_y: u64) {
+        self.min_y = Some(self.min_y.map_or(low_y, |y| y.min(low_y)));
+        self.max_y = Some(self.max_y.map_or(high_y, |y| y.max(high_y)));
+    }
+
+    /// Record the contribution of the sample that reached the limit.
+    fn add_marginal(&mut self, low_y: u64, high_y: u64) {
+        if self.extras.marginal {
+            self.marginal += high_y as i64 - low_y as i64;
+        }
+    }
+
+    /// Record the bounds at the limit in one random subcorpus.
+    fn add_bounds(&mut self, low_y: u64, high_y: u64) {
+        if self.extras.extremes {
+            self.add_extremes(low_y, high_y);
+        }
+        if self.histograms {
+            *self.low_values.entry(low_y).or_default() += 1;
+            *self.high_values.entry(high_y).or_default() += 1;
+        }
+    }
 }
 
 impl ParResult for AvgParResult {
     fn add(&mut self, other: Self) {
         self.low += other.low;
         self.high += other.high;
+        self.samples += other.samples;
+        self.marginal += other.marginal;
+        if let (Some(min_y), Some(max_y)) = (other.min_y, other.max_y) {
+            self.add_extremes(min_y, max_y);
+        }
+        for (y, n) in other.low_values {
+            *self.low_values.entry(y).or_default() += n;
+        }
+        for (y, n) in other.high_values {
+            *self.high_values.entry(y).or_default() += n;
+        }
     }
 }
 
@@ -96,6 +806,7 @@ impl ParResult for AvgParResult {
 mod test {
     use super::*;
     use crate::calculation::SToken;
+    use crate::output::{HAPAX_TOKEN_RATIO_SCALE, MEAN_FREQUENCY_SCALE};
 
     const TOLERANCE: f64 = 0.01;
     const T1: f64 = 1.0 - TOLERANCE;
@@ -108,6 +819,8 @@ mod test {
             id,
             count,
             marked_count: 0,
+            also_marked: 0,
+            known: false,
         }
     }
 
@@ -116,6 +829,141 @@ mod test {
             id,
             count,
             marked_count,
+            also_marked: 0,
+            known: false,
+        }
+    }
+
+    #[test]
+    fn average_at_limit_single_permutation() {
+        use rand::seq::SliceRandom;
+        use rand_xoshiro::rand_core::SeedableRng;
+        use rand_xoshiro::Xoshiro256PlusPlus;
+        // With limit 1, the result is the size of the first sample of the permutation.
+        let samples = (0..10)
+            .map(|i| Sample {
+                x: 1,
+                token_count: i + 1,
+                tokens: vec![st(0, i + 1)],
+                source: None,
+            })
+            .collect_vec();
+        for seed in [parallelism::DEFAULT_SEED, 5] {
+            let mut idx = (0..samples.len()).collect_vec();
+            let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed * 1000);
+            idx.shuffle(&mut rng);
+            let expected = samples[idx[0]].token_count;
+            let r = average_at_limit(MeasureY::Tokens, &samples, 1, 1, seed, None);
+            assert_eq!(
+                r,
+                AvgResult {
+                    low: expected,
+                    high: expected,
+                    iter: 1,
+                    samples: Some(1),
+                    marginal: Some(expected as i64),
+                    min_y: Some(expected),
+                    max_y: Some(expected),
+                    percentiles: None,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn average_at_limit_fixed_permutations() {
+        let samples = vec![
+            Sample {
+                x: 2,
+                token_count: 2,
+                tokens: vec![st(0, 2)],
+                source: None,
+            },
+            Sample {
+                x: 3,
+                token_count: 3,
+                tokens: vec![st(0, 1), st(1, 1), st(2, 1)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![st(3, 1)],
+                source: None,
+            },
+        ];
+        let permutations = [vec![0, 1, 2], vec![2, 0, 1], vec![1, 2, 0]];
+        // At size 3: 1–3 types, exactly 2 types, exactly 3 types.
+        let r = average_at_limit_with(
+            MeasureY::Types,
+            &samples,
+            3,
+            Permutations::Fixed(&permutations),
+        );
+        assert_eq!(
+            r,
+            AvgResult {
+                low: 6,
+                high: 8,
+                iter: 3,
+                samples: Some(5),
+                marginal: Some(6),
+                min_y: Some(1),
+                max_y: Some(3),
+                percentiles: None,
+            }
+        );
+        let r = average_at_limit_with(
+            MeasureY::Types,
+            &samples,
+            3,
+            Permutations::Fixed(&permutations[1..2]),
+        );
+        assert_eq!((r.low, r.high, r.iter), (2, 2, 1));
+        let random = Permutations::Random {
+            iter: 1000,
+            seed: parallelism::DEFAULT_SEED,
+        };
+        assert_eq!(
+            average_at_limit_with(MeasureY::Types, &samples, 3, random),
+            average_at_limit(
+                MeasureY::Types,
+                &samples,
+                1000,
+                3,
+                parallelism::DEFAULT_SEED,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn average_at_limit_expected_types() {
+        // One token per sample: types 0, 1, 2, ... with 1, 2, 3, ... tokens.
+        let samples = (0..10)
+            .flat_map(|id| {
+                (0..=id).map(move |_| Sample {
+                    x: 1,
+                    token_count: 1,
+                    tokens: vec![st(id, 1)],
+                    source: None,
+                })
+            })
+            .collect_vec();
+        for limit in [1, 5, 20, 55] {
+            let expected = crate::exact::expected_types_at_limit(&samples, limit);
+            let r = average_at_limit(
+                MeasureY::Types,
+                &samples,
+                ITER,
+                limit,
+                parallelism::DEFAULT_SEED,
+                None,
+            );
+            assert_eq!(r.low, r.high);
+            let avg = r.low as f64 / FITER;
+            assert!(expected * T1 <= avg, "{limit}: {avg} vs. {expected}");
+            assert!(avg <= expected * T2, "{limit}: {avg} vs. {expected}");
         }
     }
 
@@ -126,44 +974,46 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
         let idx = vec![0, 1];
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 0, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 0, high: 0 });
+            assert_eq!((result.low, result.high), (0, 0));
         }
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 1, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 0, high: 10 });
+            assert_eq!((result.low, result.high), (0, 10));
         }
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 1233, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 0, high: 10 });
+            assert_eq!((result.low, result.high), (0, 10));
         }
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 1234, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 10, high: 10 });
+            assert_eq!((result.low, result.high), (10, 10));
         }
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 1235, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 10, high: 15 });
+            assert_eq!((result.low, result.high), (10, 15));
         }
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 1234 + 5678, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 15, high: 15 });
+            assert_eq!((result.low, result.high), (15, 15));
         }
     }
 
@@ -174,44 +1024,46 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
         let idx = vec![1, 0];
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 0, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 0, high: 0 });
+            assert_eq!((result.low, result.high), (0, 0));
         }
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 1, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 0, high: 5 });
+            assert_eq!((result.low, result.high), (0, 5));
         }
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 5677, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 0, high: 5 });
+            assert_eq!((result.low, result.high), (0, 5));
         }
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 5678, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 5, high: 5 });
+            assert_eq!((result.low, result.high), (5, 5));
         }
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 5679, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 5, high: 15 });
+            assert_eq!((result.low, result.high), (5, 15));
         }
         {
-            let mut result = AvgParResult { low: 0, high: 0 };
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
             calc_one(&samples, 5678 + 1234, &idx, &mut counter, &mut result);
-            assert_eq!(result, AvgParResult { low: 15, high: 15 });
+            assert_eq!((result.low, result.high), (15, 15));
         }
     }
 
@@ -222,24 +1074,274 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
-        let mut result = AvgParResult { low: 0, high: 0 };
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 10, high: 15 });
+        assert_eq!((result.low, result.high), (10, 15));
         let idx = vec![1, 0];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 10, high: 20 });
+        assert_eq!((result.low, result.high), (10, 20));
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 20, high: 35 });
+        assert_eq!((result.low, result.high), (20, 35));
+    }
+
+    #[test]
+    fn calc_one_samples_at_limit() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 10,
+                tokens: vec![st(0, 10)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![st(0, 5)],
+                source: None,
+            },
+        ];
+        let mut counter = TokenCounter::new(counter::count_types(&samples));
+        for (idx, limit, expected) in [
+            ([0, 1], 0, 0),
+            ([0, 1], 1000, 1),
+            ([0, 1], 1234, 1),
+            ([0, 1], 2000, 2),
+            ([1, 0], 2000, 1),
+            ([1, 0], 5679, 2),
+        ] {
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
+            calc_one(&samples, limit, &idx, &mut counter, &mut result);
+            assert_eq!(result.samples, expected);
+        }
+    }
+
+    #[test]
+    fn average_at_limit_samples_at_limit() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 10,
+                tokens: vec![st(0, 10)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![st(0, 5)],
+                source: None,
+            },
+        ];
+        let avg = |limit| {
+            let r = average_at_limit(
+                MeasureY::Tokens,
+                &samples,
+                ITER,
+                limit,
+                parallelism::DEFAULT_SEED,
+                None,
+            );
+            r.samples.unwrap() as f64 / r.iter as f64
+        };
+        assert_eq!(avg(1000), 1.0);
+        assert_eq!(avg(1234 + 5678), 2.0);
+        // With limit 2000, we need both samples if the first one is the smaller one.
+        let expected = 1.5;
+        assert!(expected * T1 <= avg(2000));
+        assert!(avg(2000) <= expected * T2);
+    }
+
+    #[test]
+    fn calc_one_marginal() {
+        // Types 0, 1, 2 in the first sample and types 2, 3 in the second sample.
+        let samples = vec![
+            Sample {
+                x: 10,
+                token_count: 3,
+                tokens: vec![st(0, 1), st(1, 1), st(2, 1)],
+                source: None,
+            },
+            Sample {
+                x: 20,
+                token_count: 2,
+                tokens: vec![st(2, 1), st(3, 1)],
+                source: None,
+            },
+        ];
+        let mut counter = TypeCounter::new(counter::count_types(&samples));
+        for (idx, limit, expected) in [
+            ([0, 1], 5, 3),
+            ([0, 1], 10, 3),
+            ([0, 1], 15, 1),
+            ([1, 0], 15, 2),
+            ([1, 0], 20, 2),
+            ([1, 0], 30, 2),
+        ] {
+            let mut result = AvgParResult::new(false, AvgExtras::ALL);
+            calc_one(&samples, limit, &idx, &mut counter, &mut result);
+            assert_eq!(result.marginal, expected);
+            assert!(result.high - result.low <= expected as u64);
+        }
+    }
+
+    #[test]
+    fn average_at_limit_marginal() {
+        // Identical samples with 3 types each: the first sample contributes 3 types,
+        // and all other samples contribute nothing.
+        let samples = (0..5)
+            .map(|_| Sample {
+                x: 1,
+                token_count: 3,
+                tokens: vec![st(0, 1), st(1, 1), st(2, 1)],
+                source: None,
+            })
+            .collect_vec();
+        let avg = |samples: &[Sample], my, limit| {
+            let r = average_at_limit(my, samples, ITER, limit, parallelism::DEFAULT_SEED, None);
+            r.marginal.unwrap() as f64 / r.iter as f64
+        };
+        assert_eq!(avg(&samples, MeasureY::Types, 1), 3.0);
+        for limit in 2..=5 {
+            assert_eq!(avg(&samples, MeasureY::Types, limit), 0.0);
+        }
+        // With disjoint types, the first sample contributes all of its types;
+        // with 1, 2, 3, 4 types, this is 2.5 on average.
+        let samples = (0..4)
+            .map(|i| Sample {
+                x: 1,
+                token_count: i + 1,
+                tokens: (0..=i).map(|j| st((i * 4 + j) as usize, 1)).collect_vec(),
+                source: None,
+            })
+            .collect_vec();
+        let expected = 2.5;
+        let a = avg(&samples, MeasureY::Types, 1);
+        assert!(expected * T1 <= a && a <= expected * T2, "{a}");
+        // Each token is a new type, so each type is a hapax, and both measures agree.
+        assert_eq!(
+            avg(&samples, MeasureY::Hapaxes, 4),
+            avg(&samples, MeasureY::Types, 4)
+        );
+    }
+
+    #[test]
+    fn calc_one_extremes() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 10,
+                tokens: vec![st(0, 10)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![st(0, 5)],
+                source: None,
+            },
+        ];
+        let mut counter = TokenCounter::new(counter::count_types(&samples));
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
+        calc_one(&samples, 1234, &[0, 1], &mut counter, &mut result);
+        assert_eq!((result.min_y, result.max_y), (Some(10), Some(10)));
+        calc_one(&samples, 5678, &[1, 0], &mut counter, &mut result);
+        assert_eq!((result.min_y, result.max_y), (Some(5), Some(10)));
+        calc_one(&samples, 2000, &[0, 1], &mut counter, &mut result);
+        assert_eq!((result.min_y, result.max_y), (Some(5), Some(15)));
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
+        calc_one(&samples, 0, &[0, 1], &mut counter, &mut result);
+        assert_eq!((result.min_y, result.max_y), (Some(0), Some(0)));
+    }
+
+    #[test]
+    fn average_at_limit_extremes() {
+        // Samples with 1, 2, ..., 5 disjoint types: with limit 2, the smallest subcorpus
+        // consists of the samples with 1 and 2 types, and the largest of 4 and 5 types.
+        let samples = (0..5)
+            .map(|i| Sample {
+                x: 1,
+                token_count: i + 1,
+                tokens: (0..=i).map(|j| st((i * 5 + j) as usize, 1)).collect_vec(),
+                source: None,
+            })
+            .collect_vec();
+        let r = average_at_limit(
+            MeasureY::Types,
+            &samples,
+            ITER,
+            2,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
+        assert_eq!((r.min_y, r.max_y), (Some(3), Some(9)));
+        assert!(r.min_y.unwrap() * ITER < r.low);
+        assert!(r.high < r.max_y.unwrap() * ITER);
+        // The same with a fixed set of permutations.
+        let permutations = [vec![2, 0, 1, 3, 4], vec![4, 1, 0, 2, 3]];
+        let r = average_at_limit_with(
+            MeasureY::Types,
+            &samples,
+            2,
+            Permutations::Fixed(&permutations),
+        );
+        assert_eq!((r.min_y, r.max_y), (Some(4), Some(7)));
+    }
+
+    #[test]
+    fn average_at_limit_extras() {
+        let samples = (0..5)
+            .map(|i| Sample {
+                x: 1,
+                token_count: i + 1,
+                tokens: (0..=i).map(|j| st((i * 5 + j) as usize, 1)).collect_vec(),
+                source: None,
+            })
+            .collect_vec();
+        let run = |extras| {
+            average_at_limit_checkpoints(
+                MeasureY::Types,
+                &samples,
+                ITER,
+                2,
+                &[1.0],
+                None,
+                extras,
+                parallelism::DEFAULT_SEED,
+                None,
+            )
+            .pop()
+            .unwrap()
+        };
+        let all = run(AvgExtras::ALL);
+        let none = run(AvgExtras::default());
+        assert_eq!(
+            (none.low, none.high, none.iter),
+            (all.low, all.high, all.iter)
+        );
+        assert_eq!(
+            (none.samples, none.marginal, none.min_y, none.max_y),
+            (None, None, None, None)
+        );
+        let some = run(AvgExtras {
+            marginal: true,
+            ..AvgExtras::default()
+        });
+        assert_eq!(
+            (some.samples, some.marginal, some.min_y),
+            (None, all.marginal, None)
+        );
+        assert_eq!(all.samples, Some(2 * ITER));
     }
 
     #[test]
@@ -250,19 +1352,113 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
         let idx = vec![0, 1];
-        let mut result = AvgParResult { low: 0, high: 0 };
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
         calc_one(&samples, 1234 + 5678 + 1, &idx, &mut counter, &mut result);
     }
 
+    #[test]
+    fn par_result_merge_order() {
+        let parts = [(3, 5), (0, 7), (11, 11), (2, 4)];
+        let mut results = vec![];
+        for order in [[0, 1, 2, 3], [3, 2, 1, 0], [2, 0, 3, 1]] {
+            let mut total = AvgParResult::default();
+            for i in order {
+                let (low, high) = parts[i];
+                total.add(AvgParResult {
+                    low,
+                    high,
+                    samples: 1,
+                    marginal: high as i64 - low as i64,
+                    min_y: Some(low),
+                    max_y: Some(high),
+                    ..Default::default()
+                });
+            }
+            results.push(total);
+        }
+        for r in &results {
+            assert_eq!(
+                *r,
+                AvgParResult {
+                    low: 16,
+                    high: 27,
+                    samples: 4,
+                    marginal: 11,
+                    min_y: Some(0),
+                    max_y: Some(11),
+                    ..Default::default()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn percentiles_fixed() {
+        let sample = |id| Sample {
+            x: 1,
+            token_count: 1,
+            tokens: vec![st(id, 1)],
+            source: None,
+        };
+        let samples = [sample(0), sample(1), sample(0)];
+        // Types in the first two samples: 2, 1, 1, 1.
+        let permutations = [vec![0, 1, 2], vec![0, 2, 1], vec![2, 0, 1], vec![0, 2, 1]];
+        let run = |percentiles| {
+            calc(
+                MeasureY::Types,
+                &samples,
+                2,
+                &[1.0],
+                Permutations::Fixed(&permutations),
+                percentiles,
+                AvgExtras::ALL,
+                None,
+            )
+            .pop()
+            .unwrap()
+        };
+        assert_eq!(run(None).percentiles, None);
+        assert_eq!(run(Some((0.0, 1.0))).percentiles, Some((1, 2)));
+        assert_eq!(run(Some((0.5, 0.5))).percentiles, Some((1, 1)));
+        assert_eq!(run(Some((0.8, 0.8))).percentiles, Some((2, 2)));
+        let r = run(Some((0.025, 0.975)));
+        assert_eq!((r.low, r.high, r.iter), (5, 5, 4));
+    }
+
+    #[test]
+    fn percentiles_envelope() {
+        // The limit is in the middle of the first sample: 0 to 2 types.
+        let samples = [Sample {
+            x: 2,
+            token_count: 2,
+            tokens: vec![st(0, 1), st(1, 1)],
+            source: None,
+        }];
+        let r = average_at_limit_checkpoints(
+            MeasureY::Types,
+            &samples,
+            100,
+            1,
+            &[1.0],
+            Some((0.025, 0.975)),
+            AvgExtras::ALL,
+            0,
+            None,
+        );
+        assert_eq!(r[0].percentiles, Some((0, 2)));
+    }
+
     #[test]
     fn calc_one_types_1() {
         let samples = vec![
@@ -270,24 +1466,26 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TypeCounter::new(counter::count_types(&samples));
-        let mut result = AvgParResult { low: 0, high: 0 };
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 1, high: 1 });
+        assert_eq!((result.low, result.high), (1, 1));
         let idx = vec![1, 0];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 1, high: 2 });
+        assert_eq!((result.low, result.high), (1, 2));
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 2, high: 3 });
+        assert_eq!((result.low, result.high), (2, 3));
     }
 
     #[test]
@@ -297,24 +1495,26 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = HapaxCounter::new(counter::count_types(&samples));
-        let mut result = AvgParResult { low: 0, high: 0 };
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 0, high: 0 });
+        assert_eq!((result.low, result.high), (0, 0));
         let idx = vec![1, 0];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 0, high: 1 });
+        assert_eq!((result.low, result.high), (0, 1));
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 0, high: 1 });
+        assert_eq!((result.low, result.high), (0, 1));
     }
 
     #[test]
@@ -324,24 +1524,26 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(1, 5)],
+                source: None,
             },
         ];
         let mut counter = TypeCounter::new(counter::count_types(&samples));
-        let mut result = AvgParResult { low: 0, high: 0 };
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 1, high: 2 });
+        assert_eq!((result.low, result.high), (1, 2));
         let idx = vec![1, 0];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 1, high: 3 });
+        assert_eq!((result.low, result.high), (1, 3));
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 2, high: 5 });
+        assert_eq!((result.low, result.high), (2, 5));
     }
 
     #[test]
@@ -351,24 +1553,26 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(1, 5)],
+                source: None,
             },
         ];
         let mut counter = HapaxCounter::new(counter::count_types(&samples));
-        let mut result = AvgParResult { low: 0, high: 0 };
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 0, high: 1 });
+        assert_eq!((result.low, result.high), (0, 1));
         let idx = vec![1, 0];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 0, high: 2 });
+        assert_eq!((result.low, result.high), (0, 2));
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 0, high: 3 });
+        assert_eq!((result.low, result.high), (0, 3));
     }
 
     #[test]
@@ -389,24 +1593,26 @@ mod test {
                     st(8, 1),
                     st(9, 1),
                 ],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(10, 1), st(11, 1), st(12, 1), st(13, 1), st(14, 1)],
+                source: None,
             },
         ];
         let mut counter = TypeCounter::new(counter::count_types(&samples));
-        let mut result = AvgParResult { low: 0, high: 0 };
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 10, high: 15 });
+        assert_eq!((result.low, result.high), (10, 15));
         let idx = vec![1, 0];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 10, high: 20 });
+        assert_eq!((result.low, result.high), (10, 20));
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 20, high: 35 });
+        assert_eq!((result.low, result.high), (20, 35));
     }
 
     #[test]
@@ -427,24 +1633,26 @@ mod test {
                     st(8, 1),
                     st(9, 1),
                 ],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(10, 1), st(11, 1), st(12, 1), st(13, 1), st(14, 1)],
+                source: None,
             },
         ];
         let mut counter = HapaxCounter::new(counter::count_types(&samples));
-        let mut result = AvgParResult { low: 0, high: 0 };
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 10, high: 15 });
+        assert_eq!((result.low, result.high), (10, 15));
         let idx = vec![1, 0];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 10, high: 20 });
+        assert_eq!((result.low, result.high), (10, 20));
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 20, high: 35 });
+        assert_eq!((result.low, result.high), (20, 35));
     }
 
     #[test]
@@ -465,24 +1673,26 @@ mod test {
                     st(8, 1),
                     st(9, 1),
                 ],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 1), st(1, 1), st(2, 1), st(3, 1), st(4, 1)],
+                source: None,
             },
         ];
         let mut counter = TypeCounter::new(counter::count_types(&samples));
-        let mut result = AvgParResult { low: 0, high: 0 };
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 10, high: 10 });
+        assert_eq!((result.low, result.high), (10, 10));
         let idx = vec![1, 0];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 10, high: 15 });
+        assert_eq!((result.low, result.high), (10, 15));
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 20, high: 25 });
+        assert_eq!((result.low, result.high), (20, 25));
     }
 
     #[test]
@@ -503,34 +1713,45 @@ mod test {
                     st(8, 1),
                     st(9, 1),
                 ],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 1), st(1, 1), st(2, 1), st(3, 1), st(4, 1)],
+                source: None,
             },
         ];
         let mut counter = HapaxCounter::new(counter::count_types(&samples));
-        let mut result = AvgParResult { low: 0, high: 0 };
+        let mut result = AvgParResult::new(false, AvgExtras::ALL);
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 5, high: 10 });
+        assert_eq!((result.low, result.high), (5, 10));
         let idx = vec![1, 0];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 5, high: 15 });
+        assert_eq!((result.low, result.high), (5, 15));
         let idx = vec![0, 1];
         calc_one(&samples, 2000, &idx, &mut counter, &mut result);
-        assert_eq!(result, AvgParResult { low: 10, high: 25 });
+        assert_eq!((result.low, result.high), (10, 25));
     }
 
     #[test]
+    #[allow(clippy::erasing_op)]
     fn average_at_limit_tokens_1() {
         let samples = vec![Sample {
             x: 1234,
             token_count: 10,
             tokens: vec![st(0, 10)],
+            source: None,
         }];
-        let result = average_at_limit(MeasureY::Tokens, &samples, ITER, 1000);
+        let result = average_at_limit(
+            MeasureY::Tokens,
+            &samples,
+            ITER,
+            1000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         assert_eq!(result.iter, ITER);
         assert_eq!(result.low, 0 * ITER);
         assert_eq!(result.high, 10 * ITER);
@@ -542,8 +1763,16 @@ mod test {
             x: 1234,
             token_count: 10,
             tokens: vec![st(0, 10)],
+            source: None,
         }];
-        let result = average_at_limit(MeasureY::Tokens, &samples, ITER, 1234);
+        let result = average_at_limit(
+            MeasureY::Tokens,
+            &samples,
+            ITER,
+            1234,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         assert_eq!(result.iter, ITER);
         assert_eq!(result.low, 10 * ITER);
         assert_eq!(result.high, 10 * ITER);
@@ -556,14 +1785,23 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Tokens, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Tokens,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 10.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 15.0 * FITER / 2.0 + 5.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -581,14 +1819,23 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
-        let _result = average_at_limit(MeasureY::Tokens, &samples, ITER, 1234 + 5678 + 1);
+        let _result = average_at_limit(
+            MeasureY::Tokens,
+            &samples,
+            ITER,
+            1234 + 5678 + 1,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
     }
 
     #[test]
@@ -598,14 +1845,23 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Types, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Types,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 1.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 1.0 * FITER / 2.0 + 1.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -622,14 +1878,23 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Hapaxes, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Hapaxes,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 0.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 0.0 * FITER / 2.0 + 1.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -646,14 +1911,23 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(1, 5)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Types, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Types,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 1.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 2.0 * FITER / 2.0 + 1.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -670,14 +1944,23 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(1, 5)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Hapaxes, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Hapaxes,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 0.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 1.0 * FITER / 2.0 + 1.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -705,14 +1988,23 @@ mod test {
                     st(8, 1),
                     st(9, 1),
                 ],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(10, 1), st(11, 1), st(12, 1), st(13, 1), st(14, 1)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Types, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Types,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 10.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 15.0 * FITER / 2.0 + 5.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -740,14 +2032,23 @@ mod test {
                     st(8, 1),
                     st(9, 1),
                 ],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(10, 1), st(11, 1), st(12, 1), st(13, 1), st(14, 1)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Hapaxes, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Hapaxes,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 10.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 15.0 * FITER / 2.0 + 5.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -775,14 +2076,23 @@ mod test {
                     st(8, 1),
                     st(9, 1),
                 ],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 1), st(1, 1), st(2, 1), st(3, 1), st(4, 1)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Types, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Types,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 10.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 10.0 * FITER / 2.0 + 5.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -810,14 +2120,23 @@ mod test {
                     st(8, 1),
                     st(9, 1),
                 ],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 1), st(1, 1), st(2, 1), st(3, 1), st(4, 1)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Hapaxes, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Hapaxes,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 5.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 10.0 * FITER / 2.0 + 5.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -834,14 +2153,23 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(1, 5)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Samples, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Samples,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 1.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 2.0 * FITER / 2.0 + 1.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -858,14 +2186,23 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::Samples, &samples, ITER, 2000);
+        let result = average_at_limit(
+            MeasureY::Samples,
+            &samples,
+            ITER,
+            2000,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 1.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 2.0 * FITER / 2.0 + 1.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -875,6 +2212,130 @@ mod test {
         assert!(result.high as f64 <= T2 * expect_high);
     }
 
+    #[test]
+    fn average_at_limit_mean_frequency_1() {
+        let samples = vec![
+            Sample {
+                x: 2,
+                token_count: 2,
+                tokens: vec![st(0, 1), st(1, 1)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![st(2, 1)],
+                source: None,
+            },
+        ];
+        let result = average_at_limit(
+            MeasureY::MeanFrequency,
+            &samples,
+            ITER,
+            3,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
+        assert_eq!(result.iter, ITER);
+        assert_eq!(result.low, MEAN_FREQUENCY_SCALE * ITER);
+        assert_eq!(result.high, MEAN_FREQUENCY_SCALE * ITER);
+        for limit in 1..=2 {
+            let result = average_at_limit(
+                MeasureY::MeanFrequency,
+                &samples,
+                ITER,
+                limit,
+                parallelism::DEFAULT_SEED,
+                None,
+            );
+            assert_eq!(result.iter, ITER);
+            assert!(result.low <= MEAN_FREQUENCY_SCALE * ITER);
+            assert!(result.high >= MEAN_FREQUENCY_SCALE * ITER);
+        }
+    }
+
+    #[test]
+    fn average_at_limit_hapax_token_ratio_1() {
+        let samples = vec![
+            Sample {
+                x: 1,
+                token_count: 2,
+                tokens: vec![st(0, 1), st(1, 1)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 2,
+                tokens: vec![st(2, 2)],
+                source: None,
+            },
+        ];
+        let expected = HAPAX_TOKEN_RATIO_SCALE / 2 * ITER;
+        let result = average_at_limit(
+            MeasureY::HapaxTokenRatio,
+            &samples,
+            ITER,
+            2,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
+        assert_eq!(result.iter, ITER);
+        assert_eq!(result.low, expected);
+        assert_eq!(result.high, expected);
+        let result = average_at_limit(
+            MeasureY::HapaxTokenRatio,
+            &samples,
+            ITER,
+            1,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
+        assert_eq!(result.iter, ITER);
+        assert!(result.low as f64 <= T2 * expected as f64);
+        assert!(result.high as f64 >= T1 * expected as f64);
+    }
+
+    #[test]
+    fn average_at_limit_marked_tokens_1() {
+        let samples = vec![
+            Sample {
+                x: 1,
+                token_count: 3,
+                tokens: vec![stm(0, 3, 2)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 4,
+                tokens: vec![st(1, 4)],
+                source: None,
+            },
+        ];
+        let result = average_at_limit(
+            MeasureY::MarkedTokens,
+            &samples,
+            ITER,
+            2,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
+        assert_eq!(result.iter, ITER);
+        assert_eq!(result.low, 2 * ITER);
+        assert_eq!(result.high, 2 * ITER);
+        let result = average_at_limit(
+            MeasureY::MarkedTokens,
+            &samples,
+            ITER,
+            1,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
+        assert_eq!(result.iter, ITER);
+        assert_eq!(result.low, result.high);
+        assert!(result.low as f64 >= T1 * FITER);
+        assert!(result.low as f64 <= T2 * FITER);
+    }
+
     #[test]
     fn average_at_limit_type_ratio_1() {
         let samples = vec![
@@ -882,19 +2343,91 @@ mod test {
                 x: 0,
                 token_count: 11,
                 tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
+                source: None,
             },
             Sample {
                 x: 0,
                 token_count: 5,
                 tokens: vec![stm(1, 5, 0)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::MarkedTypes, &samples, ITER, 2);
+        let result = average_at_limit(
+            MeasureY::MarkedTypes,
+            &samples,
+            ITER,
+            2,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         assert_eq!(result.iter, ITER);
         assert_eq!(result.low, ITER);
         assert_eq!(result.high, ITER);
     }
 
+    #[test]
+    fn marked_types_at_limit_basic() {
+        // Marking 0 marks type 0, the additional marking marks types 1 and 2.
+        let sample = |tokens: &[(usize, u64)]| {
+            let tokens = tokens
+                .iter()
+                .map(|&(id, count)| SToken {
+                    id,
+                    count,
+                    marked_count: if id == 0 { count } else { 0 },
+                    also_marked: if id == 0 { 0 } else { 1 },
+                    known: false,
+                })
+                .collect_vec();
+            Sample {
+                x: 0,
+                token_count: tokens.iter().map(|t| t.count).sum(),
+                tokens,
+                source: None,
+            }
+        };
+        let samples = vec![
+            sample(&[(0, 3), (1, 1)]),
+            sample(&[(1, 2), (2, 2)]),
+            sample(&[(2, 1), (3, 4)]),
+        ];
+        // The same samples with the markings swapped.
+        let swapped = samples
+            .iter()
+            .map(|s| Sample {
+                tokens: s
+                    .tokens
+                    .iter()
+                    .map(|t| SToken {
+                        marked_count: t.also_marked * t.count,
+                        also_marked: 0,
+                        known: false,
+                        ..*t
+                    })
+                    .collect_vec(),
+                source: None,
+                ..*s
+            })
+            .collect_vec();
+        for limit in [1, 2, 3, 4] {
+            let results =
+                marked_types_at_limit(&samples, 1, ITER, limit, parallelism::DEFAULT_SEED, None);
+            assert_eq!(results.len(), 2);
+            for (r, s) in results.iter().zip([&samples, &swapped]) {
+                let expected = average_at_limit(
+                    MeasureY::MarkedTypes,
+                    s,
+                    ITER,
+                    limit,
+                    parallelism::DEFAULT_SEED,
+                    None,
+                );
+                assert_eq!((r.low, r.high, r.iter), (expected.low, expected.high, ITER));
+            }
+            assert_ne!(results[0], results[1]);
+        }
+    }
+
     #[test]
     fn average_at_limit_type_ratio_2() {
         let samples = vec![
@@ -902,14 +2435,23 @@ mod test {
                 x: 0,
                 token_count: 11,
                 tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
+                source: None,
             },
             Sample {
                 x: 0,
                 token_count: 5,
                 tokens: vec![stm(1, 5, 0)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::MarkedTypes, &samples, ITER, 1);
+        let result = average_at_limit(
+            MeasureY::MarkedTypes,
+            &samples,
+            ITER,
+            1,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 0.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 1.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -926,14 +2468,23 @@ mod test {
                 x: 0,
                 token_count: 11,
                 tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
+                source: None,
             },
             Sample {
                 x: 0,
                 token_count: 5,
                 tokens: vec![stm(1, 5, 0)],
+                source: None,
             },
         ];
-        let result = average_at_limit(MeasureY::MarkedTypes, &samples, ITER, 0);
+        let result = average_at_limit(
+            MeasureY::MarkedTypes,
+            &samples,
+            ITER,
+            0,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
         let expect_low = 0.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         let expect_high = 0.0 * FITER / 2.0 + 0.0 * FITER / 2.0;
         assert_eq!(result.iter, ITER);
@@ -942,4 +2493,109 @@ mod test {
         assert!(result.high as f64 >= T1 * expect_high);
         assert!(result.high as f64 <= T2 * expect_high);
     }
+
+    #[test]
+    fn x_at_target_basic() {
+        let sample = |id| Sample {
+            x: 1,
+            token_count: 1,
+            tokens: vec![st(id, 1)],
+            source: None,
+        };
+        let samples = [sample(0), sample(0), sample(1)];
+        // Two types are reached after 2 samples, unless the sample with type 1 comes last
+        // (probability 1/3), in which case we need all 3 samples.
+        let r = x_at_target(MeasureY::Types, &samples, ITER, 2, 0, None);
+        assert_eq!((r.target, r.iter, r.reached), (2, ITER, ITER));
+        let expected = 2.0 * 2.0 / 3.0 + 3.0 / 3.0;
+        let mean = r.mean.unwrap();
+        assert!(mean >= T1 * expected && mean <= T2 * expected);
+        assert_eq!((r.low, r.median, r.high), (Some(2), Some(2), Some(3)));
+        // One type is reached by the first sample.
+        let r = x_at_target(MeasureY::Types, &samples, ITER, 1, 0, None);
+        assert_eq!((r.reached, r.mean), (ITER, Some(1.0)));
+        assert_eq!((r.low, r.median, r.high), (Some(1), Some(1), Some(1)));
+        // Three types are never reached.
+        let r = x_at_target(MeasureY::Types, &samples, ITER, 3, 0, None);
+        assert_eq!((r.iter, r.reached, r.mean), (ITER, 0, None));
+        assert_eq!((r.low, r.median, r.high), (None, None, None));
+    }
+
+    #[test]
+    fn mean_curve_basic() {
+        let sample = |ids: &[usize]| Sample {
+            x: ids.len() as u64,
+            token_count: ids.len() as u64,
+            tokens: ids.iter().map(|&id| st(id, 1)).collect_vec(),
+            source: None,
+        };
+        let samples = [
+            sample(&[0, 1, 2]),
+            sample(&[0]),
+            sample(&[1, 3]),
+            sample(&[4, 5, 6, 7]),
+            sample(&[0, 8]),
+        ];
+        for measure_y in [MeasureY::Types, MeasureY::Tokens, MeasureY::Hapaxes] {
+            for limit in [0, 1, 7, 12] {
+                let r = mean_curve(measure_y, &samples, ITER, limit, 1, None);
+                assert_eq!(r.iter, ITER);
+                assert_eq!(
+                    r.points[0],
+                    OMeanPoint {
+                        x: 0,
+                        low: 0,
+                        high: 0
+                    }
+                );
+                assert_eq!(r.points.last().unwrap().x, limit);
+                for p in &r.points {
+                    assert!(p.low <= p.high);
+                }
+                if measure_y != MeasureY::Hapaxes {
+                    for (p, q) in r.points.iter().tuple_windows() {
+                        assert!(p.x < q.x);
+                        assert!(p.low <= q.low && p.high <= q.high);
+                    }
+                }
+                let ar = average_at_limit(measure_y, &samples, ITER, limit, 1, None);
+                let last = r.points.last().unwrap();
+                assert_eq!((last.low, last.high), (ar.low, ar.high));
+            }
+        }
+    }
+
+    #[test]
+    fn average_at_limits_basic() {
+        let sample = |ids: &[usize]| Sample {
+            x: ids.len() as u64,
+            token_count: ids.len() as u64,
+            tokens: ids.iter().map(|&id| st(id, 1)).collect_vec(),
+            source: None,
+        };
+        let samples = [
+            sample(&[0, 1, 2]),
+            sample(&[0]),
+            sample(&[1, 3]),
+            sample(&[4, 5, 6, 7]),
+            sample(&[0, 8]),
+        ];
+        let limits = [0, 1, 5, 7, 12];
+        for measure_y in [MeasureY::Types, MeasureY::Hapaxes, MeasureY::TypeTokenRatio] {
+            let r = average_at_limits(measure_y, &samples, ITER, &limits, 1, None);
+            assert_eq!(r.len(), limits.len());
+            assert_eq!((r[0].low, r[0].high), (0, 0));
+            for (&limit, ar) in limits.iter().zip(&r) {
+                let expected = average_at_limit(measure_y, &samples, ITER, limit, 1, None);
+                assert_eq!(
+                    (ar.low, ar.high, ar.iter),
+                    (expected.low, expected.high, expected.iter)
+                );
+            }
+            // With all samples, there is no randomness.
+            let (_, y) = counter::count_xy(measure_y, &samples);
+            assert_eq!((r[4].low, r[4].high), (y * ITER, y * ITER));
+        }
+        assert!(average_at_limits(MeasureY::Types, &samples, ITER, &[], 1, None).is_empty());
+    }
 }
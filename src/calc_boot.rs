@@ -0,0 +1,159 @@
+//! Bootstrap confidence intervals for observed values.
+
+use crate::calculation::{self, Sample};
+use crate::counter;
+use crate::output::{self, MeasureY, OObservedCi, OBSERVED_CI_HIGH, OBSERVED_CI_LOW};
+use crate::parallelism::{self, ParResult};
+use crate::shuffle;
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicBool;
+
+/// Bootstrap confidence interval for the value of [MeasureY] in all of `samples`.
+///
+/// In each of `iter` iterations, we resample `samples` with replacement,
+/// keeping the number of samples, and calculate the value of [MeasureY] for
+/// the resampled set with [crate::counter::count_xy_of]. This reflects the uncertainty
+/// due to the choice of samples, unlike the random orderings of [crate::calc_avg],
+/// which keep the set of samples fixed.
+///
+/// A resample usually contains some samples several times and misses others.
+/// For additive measures such as tokens, the interval is centered around the observed value,
+/// but e.g. the number of types is lower in a resample than in the original samples,
+/// so the interval can be entirely below the observed value.
+///
+/// # Examples
+/// ```
+/// use types3::calc_boot;
+/// use types3::calculation::{SToken, Sample};
+/// use types3::output::MeasureY;
+/// let sample = |id| Sample { x: 1, token_count: 1, tokens: vec![SToken { id, count: 1, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(0), sample(0)];
+/// let r = calc_boot::observed_ci(MeasureY::Types, &samples, 100, 0, None);
+/// assert_eq!((r.observed, r.mean, r.low, r.high), (1, 1.0, 1, 1));
+/// ```
+pub fn observed_ci(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> OObservedCi {
+    calculation::verify_samples(samples);
+    let (_, observed) = counter::count_xy(measure_y, samples);
+    let (r, iter) = parallelism::compute_parallel(
+        BootParResult::default,
+        |job, result| {
+            shuffle::bootstrap_job(
+                |idx| {
+                    let resample = idx.iter().map(|&i| &samples[i]);
+                    let (_, y) = counter::count_xy_of(measure_y, resample);
+                    *result.values.entry(y).or_default() += 1;
+                },
+                samples.len(),
+                job,
+            );
+        },
+        iter,
+        &[1.0],
+        seed,
+        cancel,
+    )
+    .pop()
+    .unwrap();
+    let total = r
+        .values
+        .iter()
+        .map(|(&y, &n)| y as f64 * n as f64)
+        .sum::<f64>();
+    let quantile = |q| output::histogram_quantile(&r.values, q).unwrap_or(observed);
+    OObservedCi {
+        observed,
+        iter,
+        mean: if iter == 0 {
+            observed as f64
+        } else {
+            total / iter as f64
+        },
+        low: quantile(OBSERVED_CI_LOW),
+        high: quantile(OBSERVED_CI_HIGH),
+    }
+}
+
+/// Number of resamples with each value.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+struct BootParResult {
+    values: BTreeMap<u64, u64>,
+}
+
+impl ParResult for BootParResult {
+    fn add(&mut self, other: Self) {
+        for (y, n) in other.values {
+            *self.values.entry(y).or_default() += n;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::calculation::SToken;
+    use itertools::Itertools;
+
+    const ITER: u64 = 10000;
+
+    fn samples(n: u64) -> Vec<Sample> {
+        (0..n)
+            .map(|i| {
+                let count = i % 10 + 1;
+                Sample {
+                    x: count,
+                    token_count: count,
+                    tokens: vec![SToken {
+                        id: i as usize,
+                        count,
+                        marked_count: 0,
+                        also_marked: 0,
+                        known: false,
+                    }],
+                    source: None,
+                }
+            })
+            .collect_vec()
+    }
+
+    #[test]
+    fn observed_ci_brackets_observed() {
+        let samples = samples(20);
+        let r = observed_ci(MeasureY::Tokens, &samples, ITER, 0, None);
+        assert_eq!(r.iter, ITER);
+        assert_eq!(r.observed, counter::count_xy(MeasureY::Tokens, &samples).1);
+        assert!(r.low < r.observed && r.observed < r.high, "{r:?}");
+        assert!(r.low as f64 <= r.mean && r.mean <= r.high as f64, "{r:?}");
+        // Each sample has its own type, so the number of types in a resample is
+        // the number of distinct samples in it, and all samples are rarely chosen.
+        let r = observed_ci(MeasureY::Types, &samples, ITER, 0, None);
+        assert_eq!(r.observed, 20);
+        assert!(r.low <= r.high && r.high < r.observed, "{r:?}");
+    }
+
+    #[test]
+    fn observed_ci_shrinks() {
+        // The relative width of the interval for the total number of tokens
+        // shrinks with the square root of the number of samples.
+        let width = |n| {
+            let r = observed_ci(MeasureY::Tokens, &samples(n), ITER, 0, None);
+            assert!(r.low <= r.observed && r.observed <= r.high);
+            (r.high - r.low) as f64 / r.observed as f64
+        };
+        let (small, large) = (width(20), width(320));
+        assert!(large < 0.5 * small, "{small} {large}");
+    }
+
+    #[test]
+    fn observed_ci_deterministic() {
+        let samples = samples(20);
+        let a = observed_ci(MeasureY::Tokens, &samples, ITER, 1, None);
+        let b = observed_ci(MeasureY::Tokens, &samples, ITER, 1, None);
+        assert_eq!(a, b);
+    }
+}
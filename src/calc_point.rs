@@ -1,36 +1,289 @@
+//! Comparing random subcorpora with given points.
+
 use crate::calculation::{self, Sample};
 use crate::counter::{
-    self, Counter, HapaxCounter, SampleCounter, TokenCounter, TypeCounter, TypeRatioCounter,
+    self, Counter, EntropyCounter, FrequentTypesCounter, GuiraudRCounter, HapaxCounter,
+    HapaxTokenRatioCounter, HerdanCCounter, KnownTypeCounter, MarkedTokenCounter,
+    MeanFrequencyCounter, NLegomenaCounter, SampleCounter, SourceCounter, TokenCounter,
+    TypeCounter, TypeRatioCounter, TypeTokenRatioCounter, YuleKCounter, ZipfSlopeCounter,
 };
-use crate::output::{MeasureY, PointResult};
+use crate::errors::{self, Result};
+use crate::output::{MeasureY, PointMoments, PointResult};
 use crate::parallelism::{self, ParResult};
-use crate::shuffle;
+use crate::shuffle::{self, Permutations};
 use is_sorted::IsSorted;
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::sync::atomic::AtomicBool;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A point in the plane, in units of [crate::output::MeasureX] and [MeasureY].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Point {
+    /// Size.
     pub x: u64,
+    /// Value.
     pub y: u64,
 }
 
+/// Compare random subcorpora with given points.
+///
+/// For each point, count how often the point is above or below a random accumulation curve
+/// formed from `samples`.
+/// If there are no points, the result is empty, and nothing is calculated.
+/// The points have to be sorted, and they cannot extend beyond the total size of `samples`;
+/// this is checked with assertions. See [try_compare_with_points] for a version that
+/// returns an error instead. [PointResult::moments] is left empty.
 pub fn compare_with_points(
     measure_y: MeasureY,
     samples: &[Sample],
     iter: u64,
     points: &[Point],
 ) -> Vec<PointResult> {
-    match measure_y {
-        MeasureY::Types => do_count::<TypeCounter>(samples, iter, points),
-        MeasureY::Tokens => do_count::<TokenCounter>(samples, iter, points),
-        MeasureY::Hapaxes => do_count::<HapaxCounter>(samples, iter, points),
-        MeasureY::Samples => do_count::<SampleCounter>(samples, iter, points),
-        MeasureY::MarkedTypes => do_count::<TypeRatioCounter>(samples, iter, points),
+    compare_with_points_checkpoints(
+        measure_y,
+        samples,
+        iter,
+        points,
+        &[1.0],
+        false,
+        parallelism::DEFAULT_SEED,
+        None,
+    )
+    .pop()
+    .unwrap()
+}
+
+/// Compare random subcorpora with given points, reporting also the results after a fraction of iterations.
+///
+/// Like [compare_with_points], but there is one list of results for each checkpoint.
+/// Checkpoint `f` uses the first `f` fraction of the random permutations (rounded up to a
+/// whole number of parallel jobs), so that we can see how the results converge.
+/// The checkpoints have to be increasing, and the last one has to be 1;
+/// the last list of results is then the same as what [compare_with_points] returns
+/// if `seed` is the default seed 0. If `moments` is true, this also fills in
+/// [PointResult::moments].
+/// The random permutations are determined by `seed`.
+#[allow(clippy::too_many_arguments)]
+pub fn compare_with_points_checkpoints(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    points: &[Point],
+    checkpoints: &[f64],
+    moments: bool,
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> Vec<Vec<PointResult>> {
+    let permutations = Permutations::Random { iter, seed };
+    calc(
+        measure_y,
+        samples,
+        points,
+        checkpoints,
+        permutations,
+        moments,
+        cancel,
+    )
+}
+
+/// Compare random subcorpora with given points, with the given source of permutations.
+///
+/// With [Permutations::Random], this is the same as [compare_with_points], except that
+/// this also fills in [PointResult::moments].
+/// With [Permutations::Fixed], the results are fully determined by the permutations,
+/// and [PointResult::iter] is the number of permutations.
+///
+/// # Examples
+/// ```
+/// use types3::calc_point::{self, Point};
+/// use types3::calculation::{SToken, Sample};
+/// use types3::output::MeasureY;
+/// use types3::shuffle::Permutations;
+/// let sample = |count| Sample { x: 1, token_count: count, tokens: vec![SToken { id: 0, count, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(1), sample(5)];
+/// let permutations = [vec![1, 0], vec![1, 0], vec![0, 1]];
+/// let points = [Point { x: 1, y: 3 }];
+/// let r = calc_point::compare_with_points_with(MeasureY::Tokens, &samples, &points, Permutations::Fixed(&permutations));
+/// assert_eq!((r[0].above, r[0].below, r[0].iter), (1, 2, 3));
+/// ```
+pub fn compare_with_points_with(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    points: &[Point],
+    permutations: Permutations,
+) -> Vec<PointResult> {
+    calc(measure_y, samples, points, &[1.0], permutations, true, None)
+        .pop()
+        .unwrap()
+}
+
+fn calc(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    points: &[Point],
+    checkpoints: &[f64],
+    permutations: Permutations,
+    moments: bool,
+    cancel: Option<&AtomicBool>,
+) -> Vec<Vec<PointResult>> {
+    if points.is_empty() {
+        return vec![vec![]; checkpoints.len()];
+    }
+    let f = match measure_y {
+        MeasureY::Types => do_count::<TypeCounter>,
+        MeasureY::Tokens => do_count::<TokenCounter>,
+        MeasureY::Hapaxes => do_count::<HapaxCounter>,
+        MeasureY::Samples => do_count::<SampleCounter>,
+        MeasureY::MarkedTypes => do_count::<TypeRatioCounter>,
+        MeasureY::MeanFrequency => do_count::<MeanFrequencyCounter>,
+        MeasureY::HapaxTokenRatio => do_count::<HapaxTokenRatioCounter>,
+        MeasureY::Sources => do_count::<SourceCounter>,
+        MeasureY::MarkedTokens => do_count::<MarkedTokenCounter>,
+        MeasureY::ZipfSlope => do_count::<ZipfSlopeCounter>,
+        MeasureY::FrequentTypes { .. } => do_count::<FrequentTypesCounter>,
+        MeasureY::KnownTypes => do_count::<KnownTypeCounter>,
+        MeasureY::Legomena { .. } => do_count::<NLegomenaCounter>,
+        MeasureY::TypeTokenRatio => do_count::<TypeTokenRatioCounter>,
+        MeasureY::YuleK => do_count::<YuleKCounter>,
+        MeasureY::Entropy => do_count::<EntropyCounter>,
+        MeasureY::GuiraudR => do_count::<GuiraudRCounter>,
+        MeasureY::HerdanC => do_count::<HerdanCCounter>,
+    };
+    f(
+        measure_y,
+        samples,
+        points,
+        checkpoints,
+        permutations,
+        moments,
+        cancel,
+    )
+}
+
+/// Compare random subcorpora with given points, after checking that the points are valid.
+///
+/// Like [compare_with_points], but returns an error if the points are not sorted,
+/// or if some point extends beyond the total size of `samples`.
+pub fn try_compare_with_points(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    points: &[Point],
+) -> Result<Vec<PointResult>> {
+    if !IsSorted::is_sorted(&mut points.iter()) {
+        return Err(errors::invalid_argument_ref("points are not sorted"));
+    }
+    if let Some(last) = points.last() {
+        let (total_x, _) = counter::count_xy(measure_y, samples);
+        if last.x > total_x {
+            return Err(errors::invalid_argument(format!(
+                "point ({}, {}) is beyond total size {}",
+                last.x, last.y, total_x
+            )));
+        }
     }
+    Ok(compare_with_points(measure_y, samples, iter, points))
+}
+
+/// Compare random subcorpora with given points, in any order.
+///
+/// Like [try_compare_with_points], but the points do not need to be sorted,
+/// and they may contain duplicates. The results are in the same order as `points`.
+///
+/// # Examples
+/// ```
+/// use types3::calc_point::{self, Point};
+/// use types3::calculation::{SToken, Sample};
+/// use types3::output::{MeasureY, PointResult};
+/// let sample = || Sample { x: 5, token_count: 5, tokens: vec![SToken { id: 0, count: 5, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(), sample()];
+/// let points: Vec<Point> = serde_json::from_str(r#"[{"x": 10, "y": 11}, {"x": 5, "y": 4}]"#).unwrap();
+/// let results = calc_point::compare_with_points_checked(MeasureY::Tokens, &samples, 1000, &points).unwrap();
+/// assert_eq!(results[0], PointResult { above: 1000, below: 0, iter: 1000, moments: None });
+/// assert_eq!(results[1], PointResult { above: 0, below: 1000, iter: 1000, moments: None });
+/// ```
+pub fn compare_with_points_checked(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    iter: u64,
+    points: &[Point],
+) -> Result<Vec<PointResult>> {
+    let sorted = points.iter().copied().sorted().dedup().collect_vec();
+    let results = try_compare_with_points(measure_y, samples, iter, &sorted)?;
+    Ok(points
+        .iter()
+        .map(|p| results[sorted.binary_search(p).expect("point is included")])
+        .collect_vec())
+}
+
+/// Observed accumulation curve, with samples in the given order.
+///
+/// This is the deterministic counterpart of the random accumulation curves:
+/// we add the samples one by one in the order in which they appear in `samples`
+/// (e.g. the input order, or sorted by year), and after each sample we record
+/// the total size and value so far as a point.
+/// Hence the result has one point per sample, and the last point is the total.
+///
+/// # Examples
+/// ```
+/// use types3::calc_point::{self, Point};
+/// use types3::calculation::{SToken, Sample};
+/// use types3::output::MeasureY;
+/// let sample = |id, count| Sample { x: count, token_count: count, tokens: vec![SToken { id, count, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(0, 2), sample(0, 3), sample(1, 1)];
+/// let curve = calc_point::observed_curve(MeasureY::Types, &samples);
+/// assert_eq!(curve, [Point { x: 2, y: 1 }, Point { x: 5, y: 1 }, Point { x: 6, y: 2 }]);
+/// ```
+pub fn observed_curve(measure_y: MeasureY, samples: &[Sample]) -> Vec<Point> {
+    let f = match measure_y {
+        MeasureY::Types => do_observed::<TypeCounter>,
+        MeasureY::Tokens => do_observed::<TokenCounter>,
+        MeasureY::Hapaxes => do_observed::<HapaxCounter>,
+        MeasureY::Samples => do_observed::<SampleCounter>,
+        MeasureY::MarkedTypes => do_observed::<TypeRatioCounter>,
+        MeasureY::MeanFrequency => do_observed::<MeanFrequencyCounter>,
+        MeasureY::HapaxTokenRatio => do_observed::<HapaxTokenRatioCounter>,
+        MeasureY::Sources => do_observed::<SourceCounter>,
+        MeasureY::MarkedTokens => do_observed::<MarkedTokenCounter>,
+        MeasureY::ZipfSlope => do_observed::<ZipfSlopeCounter>,
+        MeasureY::FrequentTypes { .. } => do_observed::<FrequentTypesCounter>,
+        MeasureY::KnownTypes => do_observed::<KnownTypeCounter>,
+        MeasureY::Legomena { .. } => do_observed::<NLegomenaCounter>,
+        MeasureY::TypeTokenRatio => do_observed::<TypeTokenRatioCounter>,
+        MeasureY::YuleK => do_observed::<YuleKCounter>,
+        MeasureY::Entropy => do_observed::<EntropyCounter>,
+        MeasureY::GuiraudR => do_observed::<GuiraudRCounter>,
+        MeasureY::HerdanC => do_observed::<HerdanCCounter>,
+    };
+    f(measure_y, samples)
 }
 
-fn do_count<TCounter>(samples: &[Sample], iter: u64, points: &[Point]) -> Vec<PointResult>
+fn do_observed<TCounter>(measure_y: MeasureY, samples: &[Sample]) -> Vec<Point>
+where
+    TCounter: Counter,
+{
+    calculation::verify_samples(samples);
+    let mut counter = TCounter::for_measure(measure_y, counter::count_types(samples));
+    samples
+        .iter()
+        .map(|s| {
+            let c = counter.feed_sample(s);
+            Point { x: c.x, y: c.y }
+        })
+        .collect_vec()
+}
+
+fn do_count<TCounter>(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    points: &[Point],
+    checkpoints: &[f64],
+    permutations: Permutations,
+    moments: bool,
+    cancel: Option<&AtomicBool>,
+) -> Vec<Vec<PointResult>>
 where
     TCounter: Counter,
 {
@@ -38,26 +291,48 @@ where
     assert!(!points.is_empty());
     assert!(IsSorted::is_sorted(&mut points.iter()));
     let total_types = counter::count_types(samples);
-    let (r, iter) = parallelism::compute_parallel(
-        || PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        },
-        |job, result| {
-            let mut counter = TCounter::new(total_types);
-            shuffle::shuffle_job(
-                |idx| calc_one(samples, points, idx, &mut counter, result),
+    let results = match permutations {
+        Permutations::Random { iter, seed } => parallelism::compute_parallel(
+            || PointParResult::new(points.len(), moments),
+            |job, result| {
+                let mut counter = TCounter::for_measure(measure_y, total_types);
+                shuffle::shuffle_job(
+                    |idx| calc_one(samples, points, idx, &mut counter, result),
+                    samples.len(),
+                    job,
+                );
+            },
+            iter,
+            checkpoints,
+            seed,
+            cancel,
+        ),
+        Permutations::Fixed(permutations) => {
+            assert_eq!(checkpoints, [1.0]);
+            let mut result = PointParResult::new(points.len(), moments);
+            let mut counter = TCounter::for_measure(measure_y, total_types);
+            shuffle::fixed_job(
+                |idx| calc_one(samples, points, idx, &mut counter, &mut result),
                 samples.len(),
-                job,
+                permutations,
             );
-        },
-        iter,
-    );
-    r.elems
+            vec![(result, permutations.len() as u64)]
+        }
+    };
+    results
         .into_iter()
-        .map(|x| PointResult {
-            above: x.above,
-            below: x.below,
-            iter,
+        .map(|(r, iter)| {
+            let moments = r.moments;
+            r.elems
+                .into_iter()
+                .enumerate()
+                .map(|(j, x)| PointResult {
+                    above: x.above,
+                    below: x.below,
+                    iter,
+                    moments: moments.get(j).copied(),
+                })
+                .collect_vec()
         })
         .collect_vec()
 }
@@ -73,6 +348,7 @@ fn calc_one<TCounter>(
 {
     counter.reset();
     let mut j = 0;
+    // The random value at size 0 is 0, so the moments do not change.
     while points[j].x == 0 {
         if points[j].y > 0 {
             result.elems[j].above += 1;
@@ -88,21 +364,25 @@ fn calc_one<TCounter>(
             let p = &points[j];
             match c.x.cmp(&p.x) {
                 Ordering::Less => break,
-                Ordering::Equal =>
-                {
+                Ordering::Equal => {
                     #[allow(clippy::comparison_chain)]
                     if c.y < p.y {
                         result.elems[j].above += 1;
                     } else if c.y > p.y {
                         result.elems[j].below += 1;
                     }
+                    result.add_moments(j, 2 * c.y);
                 }
                 Ordering::Greater => {
+                    // The point is in the middle of the sample. We do not assume that
+                    // the measure is monotone, only that the value there is between
+                    // c.low_y and c.high_y.
                     if c.high_y < p.y {
                         result.elems[j].above += 1;
                     } else if c.low_y > p.y {
                         result.elems[j].below += 1;
                     }
+                    result.add_moments(j, c.low_y + c.high_y);
                 }
             }
             j += 1;
@@ -127,9 +407,29 @@ impl PointParResultElem {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 struct PointParResult {
     elems: Vec<PointParResultElem>,
+    /// See [PointResult::moments]; empty if we do not keep track of them.
+    moments: Vec<PointMoments>,
+}
+
+impl PointParResult {
+    fn new(n: usize, moments: bool) -> PointParResult {
+        let m = if moments { n } else { 0 };
+        PointParResult {
+            elems: vec![PointParResultElem { above: 0, below: 0 }; n],
+            moments: vec![PointMoments { sum: 0, sum_sq: 0 }; m],
+        }
+    }
+
+    /// Add `v`, the sum of the lower and upper bounds, to the moments of point `j`.
+    fn add_moments(&mut self, j: usize, v: u64) {
+        if let Some(m) = self.moments.get_mut(j) {
+            m.sum += v;
+            m.sum_sq += v as u128 * v as u128;
+        }
+    }
 }
 
 impl ParResult for PointParResult {
@@ -138,6 +438,10 @@ impl ParResult for PointParResult {
         for i in 0..self.elems.len() {
             self.elems[i].add(other.elems[i]);
         }
+        for (m, o) in self.moments.iter_mut().zip(other.moments) {
+            m.sum += o.sum;
+            m.sum_sq += o.sum_sq;
+        }
     }
 }
 
@@ -157,6 +461,8 @@ mod test {
             id,
             count,
             marked_count: 0,
+            also_marked: 0,
+            known: false,
         }
     }
 
@@ -165,6 +471,8 @@ mod test {
             id,
             count,
             marked_count,
+            also_marked: 0,
+            known: false,
         }
     }
 
@@ -173,7 +481,119 @@ mod test {
     }
 
     fn pr(above: u64, below: u64, iter: u64) -> PointResult {
-        PointResult { above, below, iter }
+        PointResult {
+            above,
+            below,
+            iter,
+            moments: None,
+        }
+    }
+
+    #[test]
+    fn compare_with_points_empty() {
+        let samples = vec![Sample {
+            x: 1,
+            token_count: 1,
+            tokens: vec![st(0, 1)],
+            source: None,
+        }];
+        assert_eq!(
+            compare_with_points(MeasureY::Types, &samples, ITER, &[]),
+            []
+        );
+        assert_eq!(compare_with_points(MeasureY::Types, &[], ITER, &[]), []);
+        let results = compare_with_points_checkpoints(
+            MeasureY::Types,
+            &samples,
+            ITER,
+            &[],
+            &[0.5, 1.0],
+            false,
+            parallelism::DEFAULT_SEED,
+            None,
+        );
+        assert_eq!(results, [vec![], vec![]]);
+        assert_eq!(
+            try_compare_with_points(MeasureY::Types, &samples, ITER, &[]).unwrap(),
+            []
+        );
+    }
+
+    #[test]
+    fn observed_curve_basic() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 11,
+                tokens: vec![stm(0, 10, 2), st(1, 1)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![st(1, 5)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![stm(2, 1, 1)],
+                source: None,
+            },
+        ];
+        let curve = |my| observed_curve(my, &samples);
+        assert_eq!(curve(MeasureY::Types), [p(1234, 2), p(6912, 2), p(6913, 3)]);
+        assert_eq!(
+            curve(MeasureY::Tokens),
+            [p(1234, 11), p(6912, 16), p(6913, 17)]
+        );
+        assert_eq!(
+            curve(MeasureY::Hapaxes),
+            [p(1234, 1), p(6912, 0), p(6913, 1)]
+        );
+        assert_eq!(
+            curve(MeasureY::Samples),
+            [p(1234, 1), p(6912, 2), p(6913, 3)]
+        );
+        assert_eq!(
+            curve(MeasureY::MarkedTokens),
+            [p(1234, 2), p(6912, 2), p(6913, 3)]
+        );
+        // The order matters.
+        let mut reversed = samples;
+        reversed.reverse();
+        assert_eq!(
+            observed_curve(MeasureY::Types, &reversed),
+            [p(1, 1), p(5679, 2), p(6913, 3)]
+        );
+        assert_eq!(observed_curve(MeasureY::Types, &[]), []);
+    }
+
+    #[test]
+    fn observed_curve_last_is_total() {
+        let samples = vec![
+            Sample {
+                x: 0,
+                token_count: 3,
+                tokens: vec![stm(0, 2, 1), st(1, 1)],
+                source: None,
+            },
+            Sample {
+                x: 0,
+                token_count: 2,
+                tokens: vec![stm(1, 1, 1), st(2, 1)],
+                source: None,
+            },
+        ];
+        for my in [MeasureY::MarkedTypes, MeasureY::Types, MeasureY::Tokens] {
+            let curve = observed_curve(my, &samples);
+            let (x, y) = counter::count_xy(my, &samples);
+            assert_eq!(curve.last(), Some(&p(x, y)));
+        }
+        assert_eq!(
+            observed_curve(MeasureY::MarkedTypes, &samples),
+            [p(2, 1), p(3, 2)]
+        );
     }
 
     #[test]
@@ -183,11 +603,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
@@ -200,22 +622,18 @@ mod test {
             p(1234 + 5678, 0),
         ];
         let idx = vec![0, 1];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 0, below: 0 }, // 0
-                    PointParResultElem { above: 0, below: 0 }, // 1
-                    PointParResultElem { above: 0, below: 0 }, // 1233
-                    PointParResultElem { above: 0, below: 1 }, // 1234
-                    PointParResultElem { above: 0, below: 1 }, // 1235
-                    PointParResultElem { above: 0, below: 1 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 0, below: 0 }, // 0
+                PointParResultElem { above: 0, below: 0 }, // 1
+                PointParResultElem { above: 0, below: 0 }, // 1233
+                PointParResultElem { above: 0, below: 1 }, // 1234
+                PointParResultElem { above: 0, below: 1 }, // 1235
+                PointParResultElem { above: 0, below: 1 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -226,11 +644,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
@@ -243,22 +663,18 @@ mod test {
             p(1234 + 5678, 7),
         ];
         let idx = vec![0, 1];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 1, below: 0 }, // 0
-                    PointParResultElem { above: 0, below: 0 }, // 1
-                    PointParResultElem { above: 0, below: 0 }, // 1233
-                    PointParResultElem { above: 0, below: 1 }, // 1234
-                    PointParResultElem { above: 0, below: 1 }, // 1235
-                    PointParResultElem { above: 0, below: 1 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 1, below: 0 }, // 0
+                PointParResultElem { above: 0, below: 0 }, // 1
+                PointParResultElem { above: 0, below: 0 }, // 1233
+                PointParResultElem { above: 0, below: 1 }, // 1234
+                PointParResultElem { above: 0, below: 1 }, // 1235
+                PointParResultElem { above: 0, below: 1 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -269,11 +685,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
@@ -286,22 +704,18 @@ mod test {
             p(1234 + 5678, 10),
         ];
         let idx = vec![0, 1];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 1, below: 0 }, // 0
-                    PointParResultElem { above: 0, below: 0 }, // 1
-                    PointParResultElem { above: 0, below: 0 }, // 1233
-                    PointParResultElem { above: 0, below: 0 }, // 1234
-                    PointParResultElem { above: 0, below: 0 }, // 1235
-                    PointParResultElem { above: 0, below: 1 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 1, below: 0 }, // 0
+                PointParResultElem { above: 0, below: 0 }, // 1
+                PointParResultElem { above: 0, below: 0 }, // 1233
+                PointParResultElem { above: 0, below: 0 }, // 1234
+                PointParResultElem { above: 0, below: 0 }, // 1235
+                PointParResultElem { above: 0, below: 1 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -312,11 +726,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
@@ -329,22 +745,18 @@ mod test {
             p(1234 + 5678, 11),
         ];
         let idx = vec![0, 1];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 1, below: 0 }, // 0
-                    PointParResultElem { above: 1, below: 0 }, // 1
-                    PointParResultElem { above: 1, below: 0 }, // 1233
-                    PointParResultElem { above: 1, below: 0 }, // 1234
-                    PointParResultElem { above: 0, below: 0 }, // 1235
-                    PointParResultElem { above: 0, below: 1 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 1, below: 0 }, // 0
+                PointParResultElem { above: 1, below: 0 }, // 1
+                PointParResultElem { above: 1, below: 0 }, // 1233
+                PointParResultElem { above: 1, below: 0 }, // 1234
+                PointParResultElem { above: 0, below: 0 }, // 1235
+                PointParResultElem { above: 0, below: 1 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -355,11 +767,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
@@ -372,22 +786,18 @@ mod test {
             p(1234 + 5678, 15),
         ];
         let idx = vec![0, 1];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 1, below: 0 }, // 0
-                    PointParResultElem { above: 1, below: 0 }, // 1
-                    PointParResultElem { above: 1, below: 0 }, // 1233
-                    PointParResultElem { above: 1, below: 0 }, // 1234
-                    PointParResultElem { above: 0, below: 0 }, // 1235
-                    PointParResultElem { above: 0, below: 0 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 1, below: 0 }, // 0
+                PointParResultElem { above: 1, below: 0 }, // 1
+                PointParResultElem { above: 1, below: 0 }, // 1233
+                PointParResultElem { above: 1, below: 0 }, // 1234
+                PointParResultElem { above: 0, below: 0 }, // 1235
+                PointParResultElem { above: 0, below: 0 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -398,11 +808,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
@@ -414,21 +826,17 @@ mod test {
             p(1234 + 5678, 16),
         ];
         let idx = vec![0, 1];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 1, below: 0 }, // 1
-                    PointParResultElem { above: 1, below: 0 }, // 1233
-                    PointParResultElem { above: 1, below: 0 }, // 1234
-                    PointParResultElem { above: 1, below: 0 }, // 1235
-                    PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 1, below: 0 }, // 1
+                PointParResultElem { above: 1, below: 0 }, // 1233
+                PointParResultElem { above: 1, below: 0 }, // 1234
+                PointParResultElem { above: 1, below: 0 }, // 1235
+                PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -439,11 +847,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
@@ -455,21 +865,17 @@ mod test {
             p(1234 + 5678, 16),
         ];
         let idx = vec![0, 1];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 0, below: 0 }, // 1
-                    PointParResultElem { above: 0, below: 0 }, // 1233
-                    PointParResultElem { above: 0, below: 1 }, // 1234
-                    PointParResultElem { above: 0, below: 1 }, // 1235
-                    PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 0, below: 0 }, // 1
+                PointParResultElem { above: 0, below: 0 }, // 1233
+                PointParResultElem { above: 0, below: 1 }, // 1234
+                PointParResultElem { above: 0, below: 1 }, // 1235
+                PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -480,11 +886,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
@@ -496,21 +904,17 @@ mod test {
             p(1234 + 5678, 16),
         ];
         let idx = vec![1, 0];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 1, below: 0 }, // 1
-                    PointParResultElem { above: 1, below: 0 }, // 1233
-                    PointParResultElem { above: 1, below: 0 }, // 1234
-                    PointParResultElem { above: 1, below: 0 }, // 1235
-                    PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 1, below: 0 }, // 1
+                PointParResultElem { above: 1, below: 0 }, // 1233
+                PointParResultElem { above: 1, below: 0 }, // 1234
+                PointParResultElem { above: 1, below: 0 }, // 1235
+                PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -522,11 +926,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TokenCounter::new(counter::count_types(&samples));
@@ -539,12 +945,56 @@ mod test {
             p(1234 + 5678 + 1, 16),
         ];
         let idx = vec![1, 0];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
     }
 
+    #[test]
+    fn par_result_merge_order() {
+        let parts = [
+            vec![(1, 0), (0, 2), (3, 3)],
+            vec![(5, 1), (0, 0), (2, 7)],
+            vec![(0, 4), (6, 0), (1, 1)],
+        ];
+        let build = |i: usize| PointParResult {
+            elems: parts[i]
+                .iter()
+                .map(|&(above, below)| PointParResultElem { above, below })
+                .collect_vec(),
+            moments: parts[i]
+                .iter()
+                .map(|&(above, below)| PointMoments {
+                    sum: above,
+                    sum_sq: below as u128,
+                })
+                .collect_vec(),
+        };
+        let empty = || PointParResult::new(3, true);
+        let mut results = vec![];
+        for order in [[0, 1, 2], [2, 1, 0], [1, 0, 2], [2, 0, 1]] {
+            let mut total = empty();
+            for i in order {
+                total.add(build(i));
+            }
+            results.push(total);
+        }
+        for r in &results {
+            assert_eq!(*r, results[0]);
+        }
+        assert_eq!(
+            results[0].elems,
+            vec![
+                PointParResultElem { above: 6, below: 5 },
+                PointParResultElem { above: 6, below: 2 },
+                PointParResultElem {
+                    above: 6,
+                    below: 11
+                },
+            ]
+        );
+        assert_eq!(results[0].moments[2], PointMoments { sum: 6, sum_sq: 11 });
+    }
+
     #[test]
     fn calc_one_types_1() {
         let samples = vec![
@@ -552,11 +1002,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(0, 5)],
+                source: None,
             },
         ];
         let mut counter = TypeCounter::new(counter::count_types(&samples));
@@ -568,21 +1020,17 @@ mod test {
             p(1234 + 5678, 2),
         ];
         let idx = vec![0, 1];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 0, below: 0 }, // 1
-                    PointParResultElem { above: 0, below: 0 }, // 1233
-                    PointParResultElem { above: 0, below: 1 }, // 1234
-                    PointParResultElem { above: 0, below: 1 }, // 1235
-                    PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 0, below: 0 }, // 1
+                PointParResultElem { above: 0, below: 0 }, // 1233
+                PointParResultElem { above: 0, below: 1 }, // 1234
+                PointParResultElem { above: 0, below: 1 }, // 1235
+                PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -593,11 +1041,13 @@ mod test {
                 x: 1234,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(1, 5)],
+                source: None,
             },
         ];
         let mut counter = TypeCounter::new(counter::count_types(&samples));
@@ -609,21 +1059,99 @@ mod test {
             p(1234 + 5678, 2),
         ];
         let idx = vec![0, 1];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 0, below: 0 }, // 1
-                    PointParResultElem { above: 0, below: 0 }, // 1233
-                    PointParResultElem { above: 0, below: 1 }, // 1234
-                    PointParResultElem { above: 0, below: 1 }, // 1235
-                    PointParResultElem { above: 0, below: 0 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 0, below: 0 }, // 1
+                PointParResultElem { above: 0, below: 0 }, // 1233
+                PointParResultElem { above: 0, below: 1 }, // 1234
+                PointParResultElem { above: 0, below: 1 }, // 1235
+                PointParResultElem { above: 0, below: 0 }, // 1234 + 5678
+            ]
+        );
+    }
+
+    #[test]
+    fn calc_one_entropy_1() {
+        // A single type has no entropy.
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 10,
+                tokens: vec![st(0, 10)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![st(0, 5)],
+                source: None,
+            },
+        ];
+        let mut counter = EntropyCounter::new(counter::count_types(&samples));
+        let points = vec![
+            p(1, 0),
+            p(1234, 0),
+            p(1235, 0),
+            p(1234 + 5678, 0),
+            p(1234 + 5678, 1),
+        ];
+        let idx = vec![0, 1];
+        let mut result = PointParResult::new(points.len(), true);
+        calc_one(&samples, &points, &idx, &mut counter, &mut result);
+        assert_eq!(
+            result.elems,
+            vec![
+                PointParResultElem { above: 0, below: 0 }, // 1
+                PointParResultElem { above: 0, below: 0 }, // 1234
+                PointParResultElem { above: 0, below: 0 }, // 1235
+                PointParResultElem { above: 0, below: 0 }, // 1234 + 5678
+                PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
+            ]
+        );
+    }
+
+    #[test]
+    fn calc_one_entropy_2() {
+        // Two types with frequencies 10 and 5: 0.918 bits.
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 10,
+                tokens: vec![st(0, 10)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![st(1, 5)],
+                source: None,
+            },
+        ];
+        let mut counter = EntropyCounter::new(counter::count_types(&samples));
+        let points = vec![
+            p(1234, 0),
+            p(1235, 500),
+            p(1235, 1001),
+            p(1234 + 5678, 917),
+            p(1234 + 5678, 918),
+            p(1234 + 5678, 919),
+        ];
+        let idx = vec![0, 1];
+        let mut result = PointParResult::new(points.len(), true);
+        calc_one(&samples, &points, &idx, &mut counter, &mut result);
+        assert_eq!(
+            result.elems,
+            vec![
+                PointParResultElem { above: 0, below: 0 }, // 1234
+                PointParResultElem { above: 0, below: 0 }, // 1235
+                PointParResultElem { above: 1, below: 0 }, // 1235
+                PointParResultElem { above: 0, below: 1 }, // 1234 + 5678
+                PointParResultElem { above: 0, below: 0 }, // 1234 + 5678
+                PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -645,11 +1173,13 @@ mod test {
                     st(8, 1),
                     st(9, 1),
                 ],
+                source: None,
             },
             Sample {
                 x: 5678,
                 token_count: 5,
                 tokens: vec![st(10, 1), st(11, 1), st(12, 1), st(13, 1), st(14, 1)],
+                source: None,
             },
         ];
         let mut counter = TypeCounter::new(counter::count_types(&samples));
@@ -661,21 +1191,17 @@ mod test {
             p(1234 + 5678, 16),
         ];
         let idx = vec![1, 0];
-        let mut result = PointParResult {
-            elems: vec![PointParResultElem { above: 0, below: 0 }; points.len()],
-        };
+        let mut result = PointParResult::new(points.len(), true);
         calc_one(&samples, &points, &idx, &mut counter, &mut result);
         assert_eq!(
-            result,
-            PointParResult {
-                elems: vec![
-                    PointParResultElem { above: 1, below: 0 }, // 1
-                    PointParResultElem { above: 1, below: 0 }, // 1233
-                    PointParResultElem { above: 1, below: 0 }, // 1234
-                    PointParResultElem { above: 1, below: 0 }, // 1235
-                    PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
-                ]
-            }
+            result.elems,
+            vec![
+                PointParResultElem { above: 1, below: 0 }, // 1
+                PointParResultElem { above: 1, below: 0 }, // 1233
+                PointParResultElem { above: 1, below: 0 }, // 1234
+                PointParResultElem { above: 1, below: 0 }, // 1235
+                PointParResultElem { above: 1, below: 0 }, // 1234 + 5678
+            ]
         );
     }
 
@@ -685,6 +1211,7 @@ mod test {
             x: 1234,
             token_count: 10,
             tokens: vec![st(0, 10)],
+            source: None,
         }];
         let points = vec![p(1, 7), p(1233, 7), p(1234, 7)];
         let result = compare_with_points(MeasureY::Tokens, &samples, ITER, &points);
@@ -700,6 +1227,7 @@ mod test {
             x: 1234,
             token_count: 10,
             tokens: vec![st(0, 10)],
+            source: None,
         }];
         let points = vec![p(1, 11), p(1233, 11), p(1234, 11)];
         let result = compare_with_points(MeasureY::Tokens, &samples, ITER, &points);
@@ -709,6 +1237,102 @@ mod test {
         );
     }
 
+    #[test]
+    fn compare_with_points_fixed_permutations() {
+        let samples = vec![
+            Sample {
+                x: 2,
+                token_count: 2,
+                tokens: vec![st(0, 2)],
+                source: None,
+            },
+            Sample {
+                x: 3,
+                token_count: 3,
+                tokens: vec![st(0, 1), st(1, 1), st(2, 1)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![st(3, 1)],
+                source: None,
+            },
+        ];
+        let permutations = [vec![0, 1, 2], vec![2, 0, 1], vec![1, 2, 0]];
+        let points = vec![p(1, 1), p(3, 2), p(3, 3), p(6, 4)];
+        let result = compare_with_points_with(
+            MeasureY::Types,
+            &samples,
+            &points,
+            Permutations::Fixed(&permutations),
+        );
+        let prm = |above, below, sum, sum_sq| PointResult {
+            above,
+            below,
+            iter: 3,
+            moments: Some(PointMoments { sum, sum_sq }),
+        };
+        assert_eq!(
+            result,
+            [
+                prm(0, 0, 6, 14),
+                prm(0, 1, 14, 68),
+                prm(1, 0, 14, 68),
+                prm(0, 0, 24, 192),
+            ]
+        );
+    }
+
+    #[test]
+    fn compare_with_points_z_score() {
+        // At size 1, the random value is 0 or 2 with equal probabilities.
+        let samples = vec![
+            Sample {
+                x: 1,
+                token_count: 0,
+                tokens: vec![],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 2,
+                tokens: vec![st(0, 2)],
+                source: None,
+            },
+        ];
+        let points = vec![p(1, 1), p(1, 2), p(2, 2)];
+        let result = compare_with_points_checkpoints(
+            MeasureY::Tokens,
+            &samples,
+            ITER,
+            &points,
+            &[1.0],
+            true,
+            parallelism::DEFAULT_SEED,
+            None,
+        )
+        .pop()
+        .unwrap();
+        // Exactly at the mean.
+        let z = result[0].z_score(1).unwrap();
+        assert!(z.abs() < TOLERANCE, "{z}");
+        // One standard deviation above the mean.
+        let z = result[1].z_score(2).unwrap();
+        assert!((z - 1.0).abs() < TOLERANCE, "{z}");
+        // No variation at the total size.
+        assert_eq!(
+            result[2].moments,
+            Some(PointMoments {
+                sum: 4 * ITER,
+                sum_sq: 16 * ITER as u128
+            })
+        );
+        assert_eq!(result[2].z_score(2), None);
+        let plain = compare_with_points(MeasureY::Tokens, &samples, ITER, &points);
+        assert!(plain.iter().all(|pr| pr.moments.is_none()));
+    }
+
     #[test]
     #[should_panic(expected = "is_sorted")]
     fn compare_with_points_tokens_fail_1() {
@@ -716,6 +1340,7 @@ mod test {
             x: 1234,
             token_count: 10,
             tokens: vec![st(0, 10)],
+            source: None,
         }];
         let points = vec![p(1, 11), p(1234, 11), p(1233, 11)];
         let _result = compare_with_points(MeasureY::Tokens, &samples, ITER, &points);
@@ -728,17 +1353,109 @@ mod test {
             x: 1234,
             token_count: 10,
             tokens: vec![st(0, 10)],
+            source: None,
         }];
         let points = vec![p(1, 11), p(1233, 11), p(1235, 11)];
         let _result = compare_with_points(MeasureY::Tokens, &samples, ITER, &points);
     }
 
+    #[test]
+    fn try_compare_with_points_ok() {
+        let samples = vec![Sample {
+            x: 1234,
+            token_count: 10,
+            tokens: vec![st(0, 10)],
+            source: None,
+        }];
+        let points = vec![p(1, 7), p(1233, 7), p(1234, 7)];
+        let result = try_compare_with_points(MeasureY::Tokens, &samples, ITER, &points).unwrap();
+        assert_eq!(
+            result,
+            vec![pr(0, 0, ITER), pr(0, 0, ITER), pr(0, ITER, ITER),]
+        );
+        let result = try_compare_with_points(MeasureY::Tokens, &samples, ITER, &[]).unwrap();
+        assert_eq!(result, vec![]);
+    }
+
+    #[test]
+    fn compare_with_points_checked_json() {
+        let samples = vec![
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![st(0, 1)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![st(1, 1)],
+                source: None,
+            },
+        ];
+        let points: Vec<Point> =
+            serde_json::from_str(r#"[{"x": 2, "y": 1}, {"x": 1, "y": 1}, {"x": 2, "y": 1}]"#)
+                .unwrap();
+        let result = compare_with_points_checked(MeasureY::Types, &samples, ITER, &points).unwrap();
+        assert_eq!(
+            result,
+            vec![pr(0, ITER, ITER), pr(0, 0, ITER), pr(0, ITER, ITER)]
+        );
+        let json = serde_json::to_string(&points[1]).unwrap();
+        assert_eq!(json, r#"{"x":1,"y":1}"#);
+        let points = vec![p(3, 1)];
+        compare_with_points_checked(MeasureY::Types, &samples, ITER, &points).unwrap_err();
+    }
+
+    #[test]
+    fn try_compare_with_points_unsorted() {
+        let samples = vec![Sample {
+            x: 1234,
+            token_count: 10,
+            tokens: vec![st(0, 10)],
+            source: None,
+        }];
+        let points = vec![p(1, 11), p(1234, 11), p(1233, 11)];
+        try_compare_with_points(MeasureY::Tokens, &samples, ITER, &points).unwrap_err();
+    }
+
+    #[test]
+    fn try_compare_with_points_out_of_range() {
+        let samples = vec![Sample {
+            x: 1234,
+            token_count: 10,
+            tokens: vec![st(0, 10)],
+            source: None,
+        }];
+        let points = vec![p(1, 11), p(1233, 11), p(1235, 11)];
+        try_compare_with_points(MeasureY::Tokens, &samples, ITER, &points).unwrap_err();
+        let samples = vec![
+            Sample {
+                x: 0,
+                token_count: 11,
+                tokens: vec![st(0, 10), st(1, 1)],
+                source: None,
+            },
+            Sample {
+                x: 0,
+                token_count: 5,
+                tokens: vec![st(1, 5)],
+                source: None,
+            },
+        ];
+        let points = vec![p(1, 0), p(2, 0)];
+        try_compare_with_points(MeasureY::MarkedTypes, &samples, ITER, &points).unwrap();
+        let points = vec![p(1, 0), p(3, 0)];
+        try_compare_with_points(MeasureY::MarkedTypes, &samples, ITER, &points).unwrap_err();
+    }
+
     #[test]
     fn compare_with_points_types_1() {
         let samples = vec![Sample {
             x: 1234,
             token_count: 10,
             tokens: vec![st(0, 10)],
+            source: None,
         }];
         let points = vec![p(1, 2), p(1233, 2), p(1234, 2)];
         let result = compare_with_points(MeasureY::Types, &samples, ITER, &points);
@@ -754,6 +1471,7 @@ mod test {
             x: 1234,
             token_count: 10,
             tokens: vec![st(0, 10)],
+            source: None,
         }];
         let points = vec![p(1, 2), p(1233, 2), p(1234, 2)];
         let result = compare_with_points(MeasureY::Hapaxes, &samples, ITER, &points);
@@ -770,16 +1488,19 @@ mod test {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(1, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(2, 10)],
+                source: None,
             },
         ];
         let points = vec![
@@ -817,16 +1538,19 @@ mod test {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(1, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(2, 10)],
+                source: None,
             },
         ];
         let points = vec![
@@ -864,16 +1588,19 @@ mod test {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(1, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(2, 10)],
+                source: None,
             },
         ];
         let points = vec![
@@ -911,16 +1638,19 @@ mod test {
                 x: 123,
                 token_count: 1,
                 tokens: vec![st(0, 1)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 1,
                 tokens: vec![st(1, 1)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 1,
                 tokens: vec![st(2, 1)],
+                source: None,
             },
         ];
         let points = vec![
@@ -958,16 +1688,19 @@ mod test {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(1, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(2, 10)],
+                source: None,
             },
         ];
         let points = vec![
@@ -1005,16 +1738,19 @@ mod test {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(1, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(2, 10)],
+                source: None,
             },
         ];
         let points = vec![
@@ -1052,16 +1788,19 @@ mod test {
                 x: 123,
                 token_count: 1,
                 tokens: vec![st(0, 1)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 1,
                 tokens: vec![st(1, 1)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 1,
                 tokens: vec![st(2, 1)],
+                source: None,
             },
         ];
         let points = vec![
@@ -1099,16 +1838,19 @@ mod test {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(1, 10)],
+                source: None,
             },
             Sample {
                 x: 123,
                 token_count: 10,
                 tokens: vec![st(2, 10)],
+                source: None,
             },
         ];
         let points = vec![
@@ -1146,16 +1888,19 @@ mod test {
                 x: 100,
                 token_count: 10,
                 tokens: vec![st(0, 10)],
+                source: None,
             },
             Sample {
                 x: 200,
                 token_count: 10,
                 tokens: vec![st(1, 10)],
+                source: None,
             },
             Sample {
                 x: 100,
                 token_count: 10,
                 tokens: vec![st(2, 10)],
+                source: None,
             },
         ];
         let points = vec![p(50, 1), p(150, 1), p(250, 1), p(350, 1)];
@@ -1169,12 +1914,135 @@ mod test {
         assert_eq!(result[3], pr(0, ITER, ITER));
     }
 
+    #[test]
+    fn compare_with_points_mean_frequency_1() {
+        let samples = vec![
+            Sample {
+                x: 1,
+                token_count: 2,
+                tokens: vec![st(0, 2)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![st(1, 1)],
+                source: None,
+            },
+        ];
+        let points = vec![p(2, 1499), p(2, 1500), p(2, 1501)];
+        let result = compare_with_points(MeasureY::MeanFrequency, &samples, ITER, &points);
+        assert_eq!(result[0], pr(0, ITER, ITER));
+        assert_eq!(result[1], pr(0, 0, ITER));
+        assert_eq!(result[2], pr(ITER, 0, ITER));
+    }
+
+    #[test]
+    fn compare_with_points_hapax_token_ratio_1() {
+        let samples = vec![
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![st(0, 1)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 3,
+                tokens: vec![st(1, 3)],
+                source: None,
+            },
+        ];
+        let points = vec![p(2, 249), p(2, 250), p(2, 251)];
+        let result = compare_with_points(MeasureY::HapaxTokenRatio, &samples, ITER, &points);
+        assert_eq!(result[0], pr(0, ITER, ITER));
+        assert_eq!(result[1], pr(0, 0, ITER));
+        assert_eq!(result[2], pr(ITER, 0, ITER));
+    }
+
+    #[test]
+    fn compare_with_points_type_token_ratio_1() {
+        let samples = vec![
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![st(0, 1)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 3,
+                tokens: vec![st(0, 3)],
+                source: None,
+            },
+        ];
+        // The ratio decreases: it is 10000 or 3333 after one sample, and 2500 after both.
+        let points = vec![p(1, 5000), p(2, 2499), p(2, 2500), p(2, 2501)];
+        let result = compare_with_points(MeasureY::TypeTokenRatio, &samples, ITER, &points);
+        assert_eq!(result[0].iter, ITER);
+        assert_eq!(result[0].above + result[0].below, ITER);
+        assert!(result[0].above > ITER / 3);
+        assert!(result[0].below > ITER / 3);
+        assert_eq!(result[1], pr(0, ITER, ITER));
+        assert_eq!(result[2], pr(0, 0, ITER));
+        assert_eq!(result[3], pr(ITER, 0, ITER));
+    }
+
+    #[test]
+    fn compare_with_points_guiraud_r_1() {
+        let samples = vec![
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![st(0, 1)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 3,
+                tokens: vec![st(1, 3)],
+                source: None,
+            },
+        ];
+        // After one sample, R is 1000 or 577; after both, 2 / √4.
+        let points = vec![p(1, 1001), p(2, 999), p(2, 1000), p(2, 1001)];
+        let result = compare_with_points(MeasureY::GuiraudR, &samples, ITER, &points);
+        assert_eq!(result[0], pr(ITER, 0, ITER));
+        assert_eq!(result[1], pr(0, ITER, ITER));
+        assert_eq!(result[2], pr(0, 0, ITER));
+        assert_eq!(result[3], pr(ITER, 0, ITER));
+    }
+
+    #[test]
+    fn compare_with_points_marked_tokens_1() {
+        let samples = vec![
+            Sample {
+                x: 1,
+                token_count: 2,
+                tokens: vec![stm(0, 2, 1)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 3,
+                tokens: vec![st(1, 3)],
+                source: None,
+            },
+        ];
+        let points = vec![p(2, 0), p(2, 1), p(2, 2)];
+        let result = compare_with_points(MeasureY::MarkedTokens, &samples, ITER, &points);
+        assert_eq!(result[0], pr(0, ITER, ITER));
+        assert_eq!(result[1], pr(0, 0, ITER));
+        assert_eq!(result[2], pr(ITER, 0, ITER));
+    }
+
     #[test]
     fn compare_with_points_type_ratio_1() {
         let samples = vec![Sample {
             x: 0,
             token_count: 2,
             tokens: vec![st(0, 1), stm(1, 1, 1)],
+            source: None,
         }];
         let points = vec![
             p(1, 0),
@@ -1211,6 +2079,7 @@ mod test {
                 st(5, 1),
                 st(6, 1),
             ],
+            source: None,
         }];
         let points = vec![
             p(6, 0),
@@ -1244,11 +2113,13 @@ mod test {
                 x: 0,
                 token_count: 1,
                 tokens: vec![st(0, 1)],
+                source: None,
             },
             Sample {
                 x: 0,
                 token_count: 1,
                 tokens: vec![stm(1, 1, 1)],
+                source: None,
             },
         ];
         let points = vec![
@@ -1299,6 +2170,7 @@ mod test {
             x: 0,
             token_count: 100,
             tokens,
+            source: None,
         });
         let mut tokens = vec![];
         for i in 100..300 {
@@ -1312,6 +2184,7 @@ mod test {
             x: 0,
             token_count: 200,
             tokens,
+            source: None,
         });
         let mut tokens = vec![];
         for i in 300..400 {
@@ -1325,6 +2198,7 @@ mod test {
             x: 0,
             token_count: 100,
             tokens,
+            source: None,
         });
         let points = vec![p(50, 1), p(150, 1), p(250, 1), p(350, 1)];
         let result = compare_with_points(MeasureY::MarkedTypes, &samples, ITER, &points);
@@ -0,0 +1,334 @@
+//! Comparing subsets with random reassignments of years.
+
+use crate::calc_point::Point;
+use crate::calculation::{self, Sample};
+use crate::counter::{
+    self, Counter, KnownTypeCounter, MarkedTokenCounter, SampleCounter, SourceCounter,
+    TokenCounter, TypeCounter, TypeRatioCounter,
+};
+use crate::input::Year;
+use crate::output::{MeasureY, PointResult, Years};
+use crate::parallelism::{self, ParResult};
+use crate::samples;
+use crate::shuffle::{self, Permutations};
+use itertools::Itertools;
+use std::sync::atomic::AtomicBool;
+
+/// A subset to compare with random reassignments of years; see [compare_with_year_labels].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct YearTarget {
+    /// The subset consists of the samples with years in this range.
+    pub period: Years,
+    /// Observed size and value of the subset.
+    pub point: Point,
+}
+
+/// Compare subsets with random reassignments of years to samples.
+///
+/// Here `samples` is the set of all samples and `sample_years` is the year of each sample.
+/// In each iteration, we randomly permute
+/// the years of the dated samples, so that the number of samples in each year stays the same,
+/// and for each target we form a random subset of the samples whose new year is in its period,
+/// in the same way as for the original years (see [samples::in_period]).
+/// Undated samples keep their years.
+///
+/// The target is above the random subset if it is at most as large but has a higher value,
+/// and below if it is at least as large but has a lower value. Otherwise the two are not
+/// comparable, as we only know one point of the accumulation curve of the random subset,
+/// and this counts as neither, in the same way as a tie.
+/// Hence `measure_y` has to be monotone (see [MeasureY::is_monotone]);
+/// the driver rejects other measures.
+///
+/// With [Permutations::Fixed], each permutation has to be a permutation of the dated samples,
+/// in their order in `samples`: dated sample `i` gets the year of dated sample `p[i]`.
+/// [PointResult::moments] is left empty.
+pub fn compare_with_year_labels(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    sample_years: &[Option<Year>],
+    targets: &[YearTarget],
+    permutations: Permutations,
+    cancel: Option<&AtomicBool>,
+) -> Vec<PointResult> {
+    assert_eq!(samples.len(), sample_years.len());
+    if targets.is_empty() {
+        return vec![];
+    }
+    let f = match measure_y {
+        MeasureY::Types => do_count::<TypeCounter>,
+        MeasureY::Tokens => do_count::<TokenCounter>,
+        MeasureY::Samples => do_count::<SampleCounter>,
+        MeasureY::MarkedTypes => do_count::<TypeRatioCounter>,
+        MeasureY::Sources => do_count::<SourceCounter>,
+        MeasureY::MarkedTokens => do_count::<MarkedTokenCounter>,
+        MeasureY::KnownTypes => do_count::<KnownTypeCounter>,
+        _ => panic!("{measure_y} is not monotone"),
+    };
+    let labels = Labels {
+        sample_years,
+        dated: (0..samples.len())
+            .filter(|&i| sample_years[i].is_some())
+            .collect_vec(),
+    };
+    f(measure_y, samples, &labels, targets, permutations, cancel)
+}
+
+/// Years of the samples; see [compare_with_year_labels].
+struct Labels<'a> {
+    sample_years: &'a [Option<Year>],
+    /// Indexes of the dated samples.
+    dated: Vec<usize>,
+}
+
+fn do_count<TCounter>(
+    measure_y: MeasureY,
+    samples: &[Sample],
+    labels: &Labels,
+    targets: &[YearTarget],
+    permutations: Permutations,
+    cancel: Option<&AtomicBool>,
+) -> Vec<PointResult>
+where
+    TCounter: Counter,
+{
+    calculation::verify_samples(samples);
+    let total_types = counter::count_types(samples);
+    let n = labels.dated.len();
+    let (result, iter) = match permutations {
+        Permutations::Random { iter, seed } => parallelism::compute_parallel(
+            || YearParResult::new(targets.len()),
+            |job, result| {
+                let mut counter = TCounter::for_measure(measure_y, total_types);
+                shuffle::shuffle_job(
+                    |idx| calc_one(samples, labels, targets, idx, &mut counter, result),
+                    n,
+                    job,
+                );
+            },
+            iter,
+            &[1.0],
+            seed,
+            cancel,
+        )
+        .pop()
+        .unwrap(),
+        Permutations::Fixed(permutations) => {
+            let mut result = YearParResult::new(targets.len());
+            let mut counter = TCounter::for_measure(measure_y, total_types);
+            shuffle::fixed_job(
+                |idx| calc_one(samples, labels, targets, idx, &mut counter, &mut result),
+                n,
+                permutations,
+            );
+            (result, permutations.len() as u64)
+        }
+    };
+    result
+        .elems
+        .into_iter()
+        .map(|(above, below)| PointResult {
+            above,
+            below,
+            iter,
+            moments: None,
+        })
+        .collect_vec()
+}
+
+fn calc_one<TCounter>(
+    samples: &[Sample],
+    labels: &Labels,
+    targets: &[YearTarget],
+    idx: &[usize],
+    counter: &mut TCounter,
+    result: &mut YearParResult,
+) where
+    TCounter: Counter,
+{
+    let mut new_years = labels.sample_years.to_vec();
+    for (&i, &j) in labels.dated.iter().zip(idx) {
+        new_years[i] = labels.sample_years[labels.dated[j]];
+    }
+    for (t, elem) in targets.iter().zip(result.elems.iter_mut()) {
+        counter.reset();
+        let mut random = Point { x: 0, y: 0 };
+        for (s, &year) in samples.iter().zip(&new_years) {
+            if samples::year_in_period(&t.period, year) {
+                let c = counter.feed_sample(s);
+                random = Point { x: c.x, y: c.y };
+            }
+        }
+        let p = t.point;
+        if p.x <= random.x && p.y > random.y {
+            elem.0 += 1;
+        } else if p.x >= random.x && p.y < random.y {
+            elem.1 += 1;
+        }
+    }
+}
+
+/// Numbers of times each target is above and below a random subset.
+#[derive(Clone)]
+struct YearParResult {
+    elems: Vec<(u64, u64)>,
+}
+
+impl YearParResult {
+    fn new(n: usize) -> YearParResult {
+        YearParResult {
+            elems: vec![(0, 0); n],
+        }
+    }
+}
+
+impl ParResult for YearParResult {
+    fn add(&mut self, other: Self) {
+        debug_assert_eq!(self.elems.len(), other.elems.len());
+        for (a, b) in self.elems.iter_mut().zip(other.elems) {
+            a.0 += b.0;
+            a.1 += b.1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::calculation::SToken;
+
+    const ITER: u64 = 10000;
+
+    fn sample(ids: &[usize]) -> Sample {
+        Sample {
+            x: ids.len() as u64,
+            token_count: ids.len() as u64,
+            tokens: ids
+                .iter()
+                .map(|&id| SToken {
+                    id,
+                    count: 1,
+                    marked_count: 0,
+                    also_marked: 0,
+                    known: false,
+                })
+                .collect_vec(),
+            source: None,
+        }
+    }
+
+    fn target(samples: &[Sample], sample_years: &[Option<Year>], period: Years) -> YearTarget {
+        let mut counter = TypeCounter::new(counter::count_types(samples));
+        let mut point = Point { x: 0, y: 0 };
+        for (s, &year) in samples.iter().zip(sample_years) {
+            if samples::year_in_period(&period, year) {
+                let c = counter.feed_sample(s);
+                point = Point { x: c.x, y: c.y };
+            }
+        }
+        YearTarget { period, point }
+    }
+
+    fn compare(
+        samples: &[Sample],
+        sample_years: &[Option<Year>],
+        permutations: Permutations,
+    ) -> Vec<PointResult> {
+        let targets = [
+            target(samples, sample_years, (1900, 1910)),
+            target(samples, sample_years, (1910, 1920)),
+        ];
+        compare_with_year_labels(
+            MeasureY::Types,
+            samples,
+            sample_years,
+            &targets,
+            permutations,
+            None,
+        )
+    }
+
+    #[test]
+    fn year_labels_strong_structure() {
+        // Each period uses its own vocabulary, so mixing periods gives more types.
+        let samples = [
+            sample(&[0, 1]),
+            sample(&[0, 1]),
+            sample(&[0, 1]),
+            sample(&[2, 3]),
+            sample(&[2, 3]),
+            sample(&[2, 3]),
+        ];
+        let sample_years = [1900, 1901, 1902, 1910, 1911, 1912].map(Some);
+        let results = compare(
+            &samples,
+            &sample_years,
+            Permutations::Random {
+                iter: ITER,
+                seed: 0,
+            },
+        );
+        for pr in results {
+            assert_eq!(pr.iter, ITER);
+            assert_eq!(pr.above, 0);
+            // Of the 20 possible ways to choose the samples of a period,
+            // all but 2 mix the vocabularies and give more types.
+            let expected = ITER as f64 * 18.0 / 20.0;
+            assert!((pr.below as f64 - expected).abs() < 0.05 * ITER as f64);
+        }
+    }
+
+    #[test]
+    fn year_labels_no_structure() {
+        // All samples are identical, so the years do not matter.
+        let samples = (0..6).map(|_| sample(&[0, 1, 2])).collect_vec();
+        let sample_years = [1900, 1901, 1902, 1910, 1911, 1912].map(Some);
+        let results = compare(
+            &samples,
+            &sample_years,
+            Permutations::Random {
+                iter: ITER,
+                seed: 0,
+            },
+        );
+        for pr in results {
+            assert_eq!((pr.above, pr.below, pr.iter), (0, 0, ITER));
+        }
+    }
+
+    #[test]
+    fn year_labels_fixed() {
+        let samples = [sample(&[0]), sample(&[0]), sample(&[1]), sample(&[2])];
+        let sample_years = [Some(1900), None, Some(1910), Some(1911)];
+        let permutations = [vec![0, 1, 2], vec![1, 0, 2], vec![2, 1, 0]];
+        let results = compare(&samples, &sample_years, Permutations::Fixed(&permutations));
+        // Observed: 1900s has 1 type in 1 token, 1910s has 2 types in 2 tokens.
+        // 1. Identity: both are ties.
+        // 2. 1900s gets type 1 (a tie); 1910s gets types 0 and 2 (a tie).
+        // 3. 1900s gets type 2 (a tie); 1910s gets types 1 and 0 (a tie).
+        for pr in &results {
+            assert_eq!((pr.above, pr.below, pr.iter), (0, 0, 3));
+        }
+        // Now samples 0 and 2 are identical, and the 1910s have one type fewer if they get both.
+        let samples = [sample(&[0]), sample(&[0]), sample(&[0]), sample(&[1])];
+        let results = compare(&samples, &sample_years, Permutations::Fixed(&permutations));
+        assert_eq!((results[0].above, results[0].below), (0, 0));
+        assert_eq!((results[1].above, results[1].below), (1, 0));
+    }
+
+    #[test]
+    fn year_labels_empty() {
+        let samples = [sample(&[0])];
+        let results = compare_with_year_labels(
+            MeasureY::Types,
+            &samples,
+            &[Some(1900)],
+            &[],
+            Permutations::Random {
+                iter: ITER,
+                seed: 0,
+            },
+            None,
+        );
+        assert!(results.is_empty());
+    }
+}
@@ -1,18 +1,53 @@
-#[derive(PartialEq, Eq, Debug)]
+//! Compact representation of samples used in the calculations.
+
+use serde::{Deserialize, Serialize};
+
+/// One type in a sample.
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct SToken {
+    /// Type identifier.
     pub id: usize,
+    /// Number of tokens of this type.
     pub count: u64,
+    /// Number of marked tokens of this type.
     pub marked_count: u64,
+    /// Additional markings of this type: bit `i` is set if at least one token of this type
+    /// is marked with the `i`th marking of [crate::driver::DriverArgs::also_mark_tokens].
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub also_marked: u64,
+    /// Is this type in the reference vocabulary of [crate::driver::DriverArgs::vocabulary]?
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub known: bool,
+}
+
+fn is_zero(x: &u64) -> bool {
+    *x == 0
 }
 
-#[derive(PartialEq, Eq, Debug)]
+fn is_false(x: &bool) -> bool {
+    !*x
+}
+
+/// One sample.
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Sample {
+    /// Size of this sample, in units of [crate::output::MeasureX].
     pub x: u64,
+    /// Total number of tokens.
     pub token_count: u64,
+    /// Types in this sample, sorted by [SToken::id].
     pub tokens: Vec<SToken>,
+    /// Identifier of the source of this sample, if known.
+    /// This is only relevant for [crate::output::MeasureY::Sources];
+    /// see [crate::driver::DriverArgs::source_key].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<usize>,
 }
 
 impl Sample {
+    /// Check that the sample is well-formed; panics otherwise.
     pub fn verify(&self) {
         let mut tc = 0;
         let mut prev_id = None;
@@ -29,12 +64,52 @@ impl Sample {
     }
 }
 
+/// Check that all samples are well-formed; panics otherwise.
 pub fn verify_samples(samples: &[Sample]) {
     for s in samples {
         s.verify();
     }
 }
 
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv(h: u64, v: u64) -> u64 {
+    v.to_le_bytes()
+        .iter()
+        .fold(h, |h, b| (h ^ *b as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Stable hash of a list of samples.
+///
+/// This is the 64-bit FNV-1a hash of all fields of all samples, in order.
+/// Unlike [std::hash::Hash], this does not depend on the platform or the Rust version,
+/// so it can be stored on disk; see [crate::cache::Cache].
+pub fn hash_samples(samples: &[Sample]) -> u64 {
+    let mut h = fnv(FNV_OFFSET, samples.len() as u64);
+    for s in samples {
+        h = fnv(h, s.x);
+        h = fnv(h, s.token_count);
+        h = fnv(h, s.source.map_or(0, |v| v as u64 + 1));
+        h = fnv(h, s.tokens.len() as u64);
+        for t in &s.tokens {
+            h = fnv(h, t.id as u64);
+            h = fnv(h, t.count);
+            h = fnv(h, t.marked_count);
+            h = fnv(h, t.also_marked);
+            h = fnv(h, t.known as u64);
+        }
+    }
+    h
+}
+
+/// Stable hash of a list of values, in the same way as [hash_samples].
+pub fn hash_values(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(fnv(FNV_OFFSET, values.len() as u64), |h, v| fnv(h, *v))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -48,7 +123,10 @@ mod test {
                 id: 0,
                 count: 10,
                 marked_count: 0,
+                also_marked: 0,
+                known: false,
             }],
+            source: None,
         }
         .verify();
     }
@@ -63,13 +141,18 @@ mod test {
                     id: 0,
                     count: 10,
                     marked_count: 5,
+                    also_marked: 0,
+                    known: false,
                 },
                 SToken {
                     id: 1,
                     count: 2,
                     marked_count: 0,
+                    also_marked: 0,
+                    known: false,
                 },
             ],
+            source: None,
         }
         .verify();
     }
@@ -84,7 +167,10 @@ mod test {
                 id: 0,
                 count: 10,
                 marked_count: 0,
+                also_marked: 0,
+                known: false,
             }],
+            source: None,
         }
         .verify();
     }
@@ -99,7 +185,10 @@ mod test {
                 id: 0,
                 count: 10,
                 marked_count: 20,
+                also_marked: 0,
+                known: false,
             }],
+            source: None,
         }
         .verify();
     }
@@ -115,13 +204,18 @@ mod test {
                     id: 0,
                     count: 0,
                     marked_count: 0,
+                    also_marked: 0,
+                    known: false,
                 },
                 SToken {
                     id: 1,
                     count: 2,
                     marked_count: 0,
+                    also_marked: 0,
+                    known: false,
                 },
             ],
+            source: None,
         }
         .verify();
     }
@@ -137,14 +231,51 @@ mod test {
                     id: 1,
                     count: 2,
                     marked_count: 0,
+                    also_marked: 0,
+                    known: false,
                 },
                 SToken {
                     id: 0,
                     count: 10,
                     marked_count: 5,
+                    also_marked: 0,
+                    known: false,
                 },
             ],
+            source: None,
         }
         .verify();
     }
+
+    fn hash_sample(x: u64, id: usize, source: Option<usize>) -> u64 {
+        hash_samples(&[Sample {
+            x,
+            token_count: 10,
+            tokens: vec![SToken {
+                id,
+                count: 10,
+                marked_count: 0,
+                also_marked: 0,
+                known: false,
+            }],
+            source,
+        }])
+    }
+
+    #[test]
+    fn hash_samples_stable() {
+        assert_eq!(hash_samples(&[]), 0xa8c7f832281a39c5);
+        assert_eq!(hash_sample(10, 0, None), hash_sample(10, 0, None));
+        assert_ne!(hash_sample(10, 0, None), hash_sample(11, 0, None));
+        assert_ne!(hash_sample(10, 0, None), hash_sample(10, 1, None));
+        assert_ne!(hash_sample(10, 0, None), hash_sample(10, 0, Some(0)));
+        assert_ne!(hash_sample(10, 0, Some(0)), hash_sample(10, 0, Some(1)));
+    }
+
+    #[test]
+    fn hash_values_stable() {
+        assert_eq!(hash_values(&[]), hash_samples(&[]));
+        assert_ne!(hash_values(&[1, 2]), hash_values(&[2, 1]));
+        assert_ne!(hash_values(&[0]), hash_values(&[0, 0]));
+    }
 }
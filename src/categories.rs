@@ -15,6 +15,14 @@ pub fn owned_cat(category: Category) -> OCategory {
     category.map(|(k, v)| (k.to_owned(), v.to_owned()))
 }
 
+/// Converts a list of key-value pairs to owned strings.
+pub fn owned_pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+        .collect_vec()
+}
+
 /// Checks if `metadata` contains a key-value pair that matches `category`.
 ///
 /// If `category` is `None`, this always returns `true`.
@@ -48,20 +56,34 @@ pub fn matches(category: Category, metadata: &HashMap<String, String>) -> bool {
 /// assert_eq!(parse_restriction(&Some("a b=c d".to_owned())).unwrap(), Some(("a b", "c d")));
 /// assert!(parse_restriction(&Some("a=b=c".to_owned())).is_err());
 /// ```
-pub fn parse_restriction(arg: &Option<String>) -> Result<Category> {
+pub fn parse_restriction(arg: &Option<String>) -> Result<Category<'_>> {
     match arg {
         None => Ok(None),
-        Some(r) => {
-            let parts = r.split('=').collect_vec();
-            if parts.len() != 2 {
-                return Err(errors::invalid_argument(format!(
-                    "restriction should be of the form 'key=value', got '{r}'"
-                )));
-            }
-            let category = Some((parts[0], parts[1]));
-            Ok(category)
-        }
+        Some(r) => Ok(Some(parse_pair(r)?)),
+    }
+}
+
+/// Parse a list of key-value pairs, each of the form `key=value`; see [parse_restriction].
+///
+/// # Examples
+/// ```
+/// use types3::categories::parse_restrictions;
+/// let args = ["a=b".to_owned(), "c=d".to_owned()];
+/// assert_eq!(parse_restrictions(&args).unwrap(), [("a", "b"), ("c", "d")]);
+/// assert!(parse_restrictions(&["a".to_owned()]).is_err());
+/// ```
+pub fn parse_restrictions(args: &[String]) -> Result<Vec<(&str, &str)>> {
+    args.iter().map(|r| parse_pair(r)).collect()
+}
+
+fn parse_pair(r: &str) -> Result<(&str, &str)> {
+    let parts = r.split('=').collect_vec();
+    if parts.len() != 2 {
+        return Err(errors::invalid_argument(format!(
+            "restriction should be of the form 'key=value', got '{r}'"
+        )));
     }
+    Ok((parts[0], parts[1]))
 }
 
 #[cfg(test)]
@@ -1,8 +1,20 @@
 use crate::{
     calculation::{SToken, Sample},
-    output::MeasureY,
+    output::{
+        MeasureY, RoundMode, ENTROPY_ROUND, ENTROPY_SCALE, GUIRAUD_R_ROUND, GUIRAUD_R_SCALE,
+        HAPAX_TOKEN_RATIO_ROUND, HAPAX_TOKEN_RATIO_SCALE, HERDAN_C_ROUND, HERDAN_C_SCALE,
+        MEAN_FREQUENCY_ROUND, MEAN_FREQUENCY_SCALE, TYPE_TOKEN_RATIO_ROUND, TYPE_TOKEN_RATIO_SCALE,
+        YULE_K_ROUND, YULE_K_SCALE, ZIPF_SLOPE_ROUND, ZIPF_SLOPE_SCALE,
+    },
 };
+use std::collections::BTreeMap;
 
+/// State of a counter after a sample.
+///
+/// Here `y` is the value after the sample, and `low_y` and `high_y` bound the value
+/// at any point in the middle of the sample, including just before it. Measures do not
+/// need to be monotone: e.g. [TypeTokenRatioCounter] usually decreases, and for
+/// comparisons and averages we only rely on these bounds.
 pub struct CounterState {
     pub x: u64,
     pub y: u64,
@@ -12,6 +24,14 @@ pub struct CounterState {
 
 pub trait Counter {
     fn new(total_types: usize) -> Self;
+    /// Counter for `measure_y`; this is only needed for measures with parameters,
+    /// and otherwise it is the same as [Counter::new].
+    fn for_measure(_measure_y: MeasureY, total_types: usize) -> Self
+    where
+        Self: Sized,
+    {
+        Self::new(total_types)
+    }
     fn reset(&mut self);
     fn feed_sample(&mut self, sample: &Sample) -> CounterState;
 }
@@ -142,6 +162,79 @@ impl Counter for HapaxCounter {
     }
 }
 
+/// Counter for [MeasureY::Legomena]: types with exactly `n` tokens so far.
+///
+/// This generalizes [HapaxCounter], which is the same as `n = 1`. In the middle of a sample,
+/// a type may gain this status if its count before the sample is below `n` and its count
+/// after the sample is at least `n`, and it may lose it if its count before the sample
+/// is at most `n` and its count after the sample is above `n`.
+pub struct NLegomenaCounter {
+    x: u64,
+    n: u64,
+    legomena: u64,
+    /// Number of tokens of each type, saturating at `n + 1`.
+    seen: Vec<u64>,
+}
+
+impl NLegomenaCounter {
+    /// Counter for types with exactly `n` tokens; [Counter::new] uses `n = 1`.
+    pub fn with_n(total_types: usize, n: u64) -> NLegomenaCounter {
+        NLegomenaCounter {
+            x: 0,
+            n,
+            legomena: 0,
+            seen: vec![0; total_types],
+        }
+    }
+}
+
+impl Counter for NLegomenaCounter {
+    fn new(total_types: usize) -> NLegomenaCounter {
+        NLegomenaCounter::with_n(total_types, 1)
+    }
+
+    fn for_measure(measure_y: MeasureY, total_types: usize) -> NLegomenaCounter {
+        match measure_y {
+            MeasureY::Legomena { n } => NLegomenaCounter::with_n(total_types, n),
+            _ => NLegomenaCounter::new(total_types),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.x = 0;
+        self.legomena = 0;
+        for e in self.seen.iter_mut() {
+            *e = 0;
+        }
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let n = self.n;
+        let mut gain = 0;
+        let mut lose = 0;
+        for t in &sample.tokens {
+            let old = self.seen[t.id];
+            let new = (old + t.count).min(n + 1);
+            if old < n && new >= n {
+                gain += 1;
+            }
+            if old <= n && new > n {
+                lose += 1;
+            }
+            self.seen[t.id] = new;
+        }
+        self.x += sample.x;
+        let prev_y = self.legomena;
+        self.legomena = prev_y + gain - lose;
+        CounterState {
+            x: self.x,
+            y: self.legomena,
+            low_y: prev_y.saturating_sub(lose),
+            high_y: prev_y + gain,
+        }
+    }
+}
+
 pub struct TypeRatioCounter {
     types: u64,
     types_marked: u64,
@@ -197,6 +290,66 @@ impl Counter for TypeRatioCounter {
     }
 }
 
+/// Like [TypeRatioCounter], but for several markings at once.
+///
+/// Marking 0 is [SToken::marked_count], and marking `i + 1` is bit `i` of [SToken::also_marked].
+/// For each marking, we keep track of the number of marked types before and after
+/// the latest sample; see [TypeRatiosCounter::marked].
+pub struct TypeRatiosCounter {
+    types: u64,
+    prev_marked: Vec<u64>,
+    marked: Vec<u64>,
+    /// Markings of each type seen so far, in the same order as the markings.
+    seen_marked: Vec<u64>,
+    seen: Vec<bool>,
+}
+
+impl TypeRatiosCounter {
+    pub fn new(total_types: usize, markings: usize) -> TypeRatiosCounter {
+        assert!(markings <= u64::BITS as usize);
+        TypeRatiosCounter {
+            types: 0,
+            prev_marked: vec![0; markings],
+            marked: vec![0; markings],
+            seen_marked: vec![0; total_types],
+            seen: vec![false; total_types],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.types = 0;
+        self.prev_marked.fill(0);
+        self.marked.fill(0);
+        self.seen_marked.fill(0);
+        self.seen.fill(false);
+    }
+
+    /// Returns the number of types seen so far.
+    pub fn feed_sample(&mut self, sample: &Sample) -> u64 {
+        self.prev_marked.copy_from_slice(&self.marked);
+        for t in &sample.tokens {
+            if !self.seen[t.id] {
+                self.types += 1;
+                self.seen[t.id] = true;
+            }
+            let markings = (t.also_marked << 1) | u64::from(t.marked_count > 0);
+            let new = markings & !self.seen_marked[t.id];
+            if new != 0 {
+                self.seen_marked[t.id] |= new;
+                for (i, m) in self.marked.iter_mut().enumerate() {
+                    *m += (new >> i) & 1;
+                }
+            }
+        }
+        self.types
+    }
+
+    /// Number of marked types for marking `i`, before and after the latest sample.
+    pub fn marked(&self, i: usize) -> (u64, u64) {
+        (self.prev_marked[i], self.marked[i])
+    }
+}
+
 pub struct TokenCounter {
     x: u64,
     tokens: u64,
@@ -225,6 +378,82 @@ impl Counter for TokenCounter {
     }
 }
 
+pub struct MarkedTokenCounter {
+    x: u64,
+    tokens: u64,
+}
+
+impl Counter for MarkedTokenCounter {
+    fn new(_total_types: usize) -> MarkedTokenCounter {
+        MarkedTokenCounter { x: 0, tokens: 0 }
+    }
+
+    fn reset(&mut self) {
+        self.x = 0;
+        self.tokens = 0;
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let prev_tokens = self.tokens;
+        self.x += sample.x;
+        self.tokens += sample.tokens.iter().map(|t| t.marked_count).sum::<u64>();
+        CounterState {
+            x: self.x,
+            y: self.tokens,
+            low_y: prev_tokens,
+            high_y: self.tokens,
+        }
+    }
+}
+
+/// Counts the types that are in the reference vocabulary; see [crate::calculation::SToken::known].
+pub struct KnownTypeCounter {
+    x: u64,
+    types: u64,
+    seen: Vec<bool>,
+}
+
+impl KnownTypeCounter {
+    fn feed_token(&mut self, t: &SToken) {
+        if t.known && !self.seen[t.id] {
+            self.types += 1;
+            self.seen[t.id] = true;
+        }
+    }
+}
+
+impl Counter for KnownTypeCounter {
+    fn new(total_types: usize) -> KnownTypeCounter {
+        KnownTypeCounter {
+            x: 0,
+            types: 0,
+            seen: vec![false; total_types],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.x = 0;
+        self.types = 0;
+        for e in self.seen.iter_mut() {
+            *e = false;
+        }
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let prev_types = self.types;
+        self.x += sample.x;
+        for t in &sample.tokens {
+            self.feed_token(t);
+        }
+        CounterState {
+            x: self.x,
+            y: self.types,
+            low_y: prev_types,
+            high_y: self.types,
+        }
+    }
+}
+
 pub struct SampleCounter {
     x: u64,
     samples: u64,
@@ -253,136 +482,1650 @@ impl Counter for SampleCounter {
     }
 }
 
-pub fn count_types(samples: &[Sample]) -> usize {
-    let mut max_type = 0;
-    for sample in samples {
-        for token in &sample.tokens {
-            max_type = max_type.max(token.id);
+pub struct MeanFrequencyCounter {
+    x: u64,
+    tokens: u64,
+    types: u64,
+    seen: Vec<bool>,
+}
+
+impl MeanFrequencyCounter {
+    fn feed_token(&mut self, t: &SToken) {
+        self.tokens += t.count;
+        if !self.seen[t.id] {
+            self.types += 1;
+            self.seen[t.id] = true;
         }
     }
-    max_type + 1
 }
 
-pub fn count_xy(measure_y: MeasureY, samples: &[Sample]) -> (u64, u64) {
-    match measure_y {
-        MeasureY::Types => count_xy_variant::<TypeCounter>(samples),
-        MeasureY::Tokens => count_xy_variant::<TokenCounter>(samples),
-        MeasureY::Hapaxes => count_xy_variant::<HapaxCounter>(samples),
-        MeasureY::Samples => count_xy_variant::<SampleCounter>(samples),
-        MeasureY::MarkedTypes => count_xy_variant::<TypeRatioCounter>(samples),
+/// Relative tolerance used to recognize values that are integers up to floating-point error.
+const ROUND_EPSILON: f64 = 1e-9;
+
+/// Multiply a non-negative `value` by `scale` and round it to an integer.
+///
+/// All scaled measures should use this, so that rounding is consistent; see [RoundMode].
+pub fn scale_and_round(value: f64, scale: u64, mode: RoundMode) -> u64 {
+    debug_assert!(value >= 0.0);
+    let scaled = value * scale as f64;
+    let nearest = scaled.round();
+    if (scaled - nearest).abs() <= ROUND_EPSILON * nearest.max(1.0) {
+        return nearest as u64;
+    }
+    match mode {
+        RoundMode::Down => scaled.floor() as u64,
+        RoundMode::HalfUp => (scaled + 0.5).floor() as u64,
     }
 }
 
-fn count_xy_variant<TCounter>(samples: &[Sample]) -> (u64, u64)
-where
-    TCounter: Counter,
-{
-    let n = count_types(samples);
-    let mut counter = TCounter::new(n);
-    let mut c = None;
-    for s in samples {
-        c = Some(counter.feed_sample(s));
-    }
-    match c {
-        None => (0, 0),
-        Some(c) => (c.x, c.y),
+fn mean_frequency(tokens: u64, types: u64) -> u64 {
+    if types == 0 {
+        0
+    } else {
+        scale_and_round(
+            tokens as f64 / types as f64,
+            MEAN_FREQUENCY_SCALE,
+            MEAN_FREQUENCY_ROUND,
+        )
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+impl Counter for MeanFrequencyCounter {
+    fn new(total_types: usize) -> MeanFrequencyCounter {
+        MeanFrequencyCounter {
+            x: 0,
+            tokens: 0,
+            types: 0,
+            seen: vec![false; total_types],
+        }
+    }
 
-    fn stm(id: usize, count: u64, marked_count: u64) -> SToken {
-        SToken {
-            id,
-            count,
-            marked_count,
+    fn reset(&mut self) {
+        self.x = 0;
+        self.tokens = 0;
+        self.types = 0;
+        for e in self.seen.iter_mut() {
+            *e = false;
         }
     }
 
-    #[test]
-    fn count_xy_tokens() {
-        let samples = vec![
-            Sample {
-                x: 1234,
-                token_count: 11,
-                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
-            },
-            Sample {
-                x: 5678,
-                token_count: 5,
-                tokens: vec![stm(1, 5, 0)],
-            },
-        ];
-        assert_eq!(count_xy(MeasureY::Tokens, &samples), (1234 + 5678, 16));
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let prev_tokens = self.tokens;
+        let prev_types = self.types;
+        for t in &sample.tokens {
+            self.feed_token(t);
+        }
+        self.x += sample.x;
+        // In the middle of the sample, both tokens and types are somewhere between
+        // the previous and the current values.
+        let low_y = mean_frequency(prev_tokens, self.types);
+        let high_y = mean_frequency(self.tokens, prev_types.max(1));
+        let y = mean_frequency(self.tokens, self.types);
+        debug_assert!(low_y <= y);
+        debug_assert!(y <= high_y);
+        CounterState {
+            x: self.x,
+            y,
+            low_y,
+            high_y,
+        }
     }
+}
 
-    #[test]
-    fn count_xy_types() {
-        let samples = vec![
-            Sample {
-                x: 1234,
-                token_count: 11,
-                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
-            },
-            Sample {
-                x: 5678,
-                token_count: 5,
-                tokens: vec![stm(1, 5, 0)],
-            },
-        ];
-        assert_eq!(count_xy(MeasureY::Types, &samples), (1234 + 5678, 2));
+pub struct HapaxTokenRatioCounter {
+    hapaxes: HapaxCounter,
+    tokens: u64,
+}
+
+fn hapax_token_ratio(hapaxes: u64, tokens: u64) -> u64 {
+    if tokens == 0 {
+        0
+    } else {
+        scale_and_round(
+            hapaxes as f64 / tokens as f64,
+            HAPAX_TOKEN_RATIO_SCALE,
+            HAPAX_TOKEN_RATIO_ROUND,
+        )
     }
+}
 
-    #[test]
-    fn count_xy_samples() {
-        let samples = vec![
-            Sample {
-                x: 1234,
-                token_count: 11,
-                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
-            },
-            Sample {
-                x: 5678,
-                token_count: 5,
-                tokens: vec![stm(1, 5, 0)],
-            },
-        ];
-        assert_eq!(count_xy(MeasureY::Samples, &samples), (1234 + 5678, 2));
+impl Counter for HapaxTokenRatioCounter {
+    fn new(total_types: usize) -> HapaxTokenRatioCounter {
+        HapaxTokenRatioCounter {
+            hapaxes: HapaxCounter::new(total_types),
+            tokens: 0,
+        }
     }
 
-    #[test]
-    fn count_xy_hapaxes() {
-        let samples = vec![
-            Sample {
-                x: 1234,
-                token_count: 11,
-                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
-            },
-            Sample {
-                x: 5678,
-                token_count: 5,
-                tokens: vec![stm(1, 5, 0)],
-            },
-        ];
-        assert_eq!(count_xy(MeasureY::Hapaxes, &samples), (1234 + 5678, 0));
+    fn reset(&mut self) {
+        self.hapaxes.reset();
+        self.tokens = 0;
     }
 
-    #[test]
-    fn count_xy_marked_types() {
-        let samples = vec![
-            Sample {
-                x: 0,
-                token_count: 11,
-                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
-            },
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let prev_tokens = self.tokens;
+        let h = self.hapaxes.feed_sample(sample);
+        self.tokens += sample.token_count;
+        // In the middle of the sample, hapaxes are between h.low_y and h.high_y,
+        // and tokens are between the previous and the current values.
+        // There are never more hapaxes than tokens, so the ratio is at most 1.
+        let low_y = hapax_token_ratio(h.low_y, self.tokens);
+        let high_y = if prev_tokens == 0 {
+            HAPAX_TOKEN_RATIO_SCALE
+        } else {
+            hapax_token_ratio(h.high_y, prev_tokens).min(HAPAX_TOKEN_RATIO_SCALE)
+        };
+        let y = hapax_token_ratio(h.y, self.tokens);
+        debug_assert!(low_y <= y);
+        debug_assert!(y <= high_y);
+        CounterState {
+            x: h.x,
+            y,
+            low_y,
+            high_y,
+        }
+    }
+}
+
+pub struct TypeTokenRatioCounter {
+    types: TypeCounter,
+    tokens: u64,
+}
+
+fn type_token_ratio(types: u64, tokens: u64) -> u64 {
+    if tokens == 0 {
+        0
+    } else {
+        scale_and_round(
+            types as f64 / tokens as f64,
+            TYPE_TOKEN_RATIO_SCALE,
+            TYPE_TOKEN_RATIO_ROUND,
+        )
+    }
+}
+
+impl Counter for TypeTokenRatioCounter {
+    fn new(total_types: usize) -> TypeTokenRatioCounter {
+        TypeTokenRatioCounter {
+            types: TypeCounter::new(total_types),
+            tokens: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.types.reset();
+        self.tokens = 0;
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let prev_tokens = self.tokens;
+        let t = self.types.feed_sample(sample);
+        self.tokens += sample.token_count;
+        // In the middle of the sample, both types and tokens are somewhere between
+        // the previous and the current values. There are never more types than tokens,
+        // so the ratio is at most 1.
+        let low_y = type_token_ratio(t.low_y, self.tokens);
+        let high_y = if prev_tokens == 0 {
+            TYPE_TOKEN_RATIO_SCALE
+        } else {
+            type_token_ratio(t.high_y, prev_tokens).min(TYPE_TOKEN_RATIO_SCALE)
+        };
+        let y = type_token_ratio(t.y, self.tokens);
+        debug_assert!(low_y <= y);
+        debug_assert!(y <= high_y);
+        CounterState {
+            x: t.x,
+            y,
+            low_y,
+            high_y,
+        }
+    }
+}
+
+pub struct GuiraudRCounter {
+    types: TypeCounter,
+    tokens: u64,
+}
+
+fn guiraud_r(types: u64, tokens: u64) -> u64 {
+    if tokens == 0 {
+        0
+    } else {
+        scale_and_round(
+            types as f64 / (tokens as f64).sqrt(),
+            GUIRAUD_R_SCALE,
+            GUIRAUD_R_ROUND,
+        )
+    }
+}
+
+impl Counter for GuiraudRCounter {
+    fn new(total_types: usize) -> GuiraudRCounter {
+        GuiraudRCounter {
+            types: TypeCounter::new(total_types),
+            tokens: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.types.reset();
+        self.tokens = 0;
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let prev_tokens = self.tokens;
+        let t = self.types.feed_sample(sample);
+        self.tokens += sample.token_count;
+        // In the middle of the sample, both types and tokens are somewhere between
+        // the previous and the current values. There are never more types than tokens,
+        // so the value is at most the square root of tokens.
+        let low_y = guiraud_r(t.low_y, self.tokens);
+        let high_y = if prev_tokens == 0 {
+            guiraud_r(self.tokens, self.tokens)
+        } else {
+            guiraud_r(t.high_y, prev_tokens)
+        };
+        let y = guiraud_r(t.y, self.tokens);
+        debug_assert!(low_y <= y);
+        debug_assert!(y <= high_y);
+        CounterState {
+            x: t.x,
+            y,
+            low_y,
+            high_y,
+        }
+    }
+}
+
+pub struct HerdanCCounter {
+    types: TypeCounter,
+    tokens: u64,
+}
+
+fn herdan_c(types: u64, tokens: u64) -> u64 {
+    if tokens < 2 || types == 0 {
+        0
+    } else {
+        scale_and_round(
+            (types as f64).ln() / (tokens as f64).ln(),
+            HERDAN_C_SCALE,
+            HERDAN_C_ROUND,
+        )
+    }
+}
+
+impl Counter for HerdanCCounter {
+    fn new(total_types: usize) -> HerdanCCounter {
+        HerdanCCounter {
+            types: TypeCounter::new(total_types),
+            tokens: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.types.reset();
+        self.tokens = 0;
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let prev_tokens = self.tokens;
+        let t = self.types.feed_sample(sample);
+        self.tokens += sample.token_count;
+        // In the middle of the sample, both types and tokens are somewhere between
+        // the previous and the current values. There are never more types than tokens,
+        // so the value is at most 1. With fewer than two tokens, the value is 0.
+        let (low_y, high_y) = if prev_tokens < 2 {
+            (0, HERDAN_C_SCALE)
+        } else {
+            (
+                herdan_c(t.low_y, self.tokens),
+                herdan_c(t.high_y, prev_tokens).min(HERDAN_C_SCALE),
+            )
+        };
+        let y = herdan_c(t.y, self.tokens);
+        debug_assert!(low_y <= y);
+        debug_assert!(y <= high_y);
+        CounterState {
+            x: t.x,
+            y,
+            low_y,
+            high_y,
+        }
+    }
+}
+
+/// Counter for [MeasureY::YuleK].
+///
+/// We only need the sum Σ m² V(m) over the frequency spectrum, which is the same as the sum
+/// of squared frequencies of the types. We maintain it incrementally: when a type with
+/// frequency f gets c more tokens, the sum grows by 2fc + c². Hence each sample takes time
+/// proportional to the number of distinct tokens in it, and we never need to build the
+/// spectrum itself.
+pub struct YuleKCounter {
+    x: u64,
+    freq: Vec<u64>,
+    tokens: u64,
+    /// Sum of f(f − 1) over the frequencies f of the types, i.e., Σ m² V(m) − N.
+    pairs: u64,
+}
+
+fn yule_k(pairs: u64, tokens: u64) -> u64 {
+    if tokens == 0 {
+        0
+    } else {
+        let n = tokens as f64;
+        scale_and_round(1e4 * pairs as f64 / (n * n), YULE_K_SCALE, YULE_K_ROUND)
+    }
+}
+
+impl Counter for YuleKCounter {
+    fn new(total_types: usize) -> YuleKCounter {
+        YuleKCounter {
+            x: 0,
+            freq: vec![0; total_types],
+            tokens: 0,
+            pairs: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        for e in self.freq.iter_mut() {
+            *e = 0;
+        }
+        self.x = 0;
+        self.tokens = 0;
+        self.pairs = 0;
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let prev_tokens = self.tokens;
+        let prev_pairs = self.pairs;
+        for t in &sample.tokens {
+            let f = self.freq[t.id];
+            // (f + c)(f + c − 1) − f(f − 1) = c(2f + c − 1).
+            self.pairs += t.count * (2 * f + t.count - 1);
+            self.freq[t.id] = f + t.count;
+        }
+        self.x += sample.x;
+        self.tokens += sample.token_count;
+        // In the middle of the sample, the sum of f(f − 1) is between the previous and
+        // the current values, and so is the number of tokens. The sum is always less
+        // than N², so K is less than 10⁴.
+        let max = 10000 * YULE_K_SCALE;
+        let low_y = yule_k(prev_pairs, self.tokens);
+        let high_y = if prev_tokens == 0 {
+            max
+        } else {
+            yule_k(self.pairs, prev_tokens).min(max)
+        };
+        let y = yule_k(self.pairs, self.tokens);
+        debug_assert!(low_y <= y);
+        debug_assert!(y <= high_y);
+        CounterState {
+            x: self.x,
+            y,
+            low_y,
+            high_y,
+        }
+    }
+}
+
+/// Counter for [MeasureY::Entropy].
+///
+/// The entropy of frequencies f with total N is log₂ N − (Σ f log₂ f) / N. We maintain
+/// the sum Σ f log₂ f incrementally, updating only the terms of the types in each sample,
+/// so each sample takes time proportional to the number of distinct tokens in it.
+pub struct EntropyCounter {
+    x: u64,
+    types: u64,
+    tokens: u64,
+    freq: Vec<u64>,
+    /// Sum of f log₂ f over the frequencies f of the types.
+    sum: f64,
+}
+
+fn f_log_f(f: u64) -> f64 {
+    if f == 0 {
+        0.0
+    } else {
+        let f = f as f64;
+        f * f.log2()
+    }
+}
+
+impl EntropyCounter {
+    fn entropy(&self) -> u64 {
+        if self.tokens == 0 {
+            return 0;
+        }
+        let n = self.tokens as f64;
+        let h = n.log2() - self.sum / n;
+        scale_and_round(h.max(0.0), ENTROPY_SCALE, ENTROPY_ROUND)
+    }
+}
+
+impl Counter for EntropyCounter {
+    fn new(total_types: usize) -> EntropyCounter {
+        EntropyCounter {
+            x: 0,
+            types: 0,
+            tokens: 0,
+            freq: vec![0; total_types],
+            sum: 0.0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.x = 0;
+        self.types = 0;
+        self.tokens = 0;
+        for e in self.freq.iter_mut() {
+            *e = 0;
+        }
+        self.sum = 0.0;
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        for t in &sample.tokens {
+            let old = self.freq[t.id];
+            let new = old + t.count;
+            if old == 0 {
+                self.types += 1;
+            }
+            self.sum += f_log_f(new) - f_log_f(old);
+            self.freq[t.id] = new;
+            self.tokens += t.count;
+        }
+        self.x += sample.x;
+        let y = self.entropy();
+        // The entropy is not monotone, and in the middle of the sample it can be anything
+        // from 0 to log₂ of the number of types so far, which is at most the number
+        // of types after the sample.
+        let low_y = 0;
+        let high_y = if self.types == 0 {
+            0
+        } else {
+            scale_and_round((self.types as f64).log2(), ENTROPY_SCALE, ENTROPY_ROUND)
+        };
+        debug_assert!(y <= high_y);
+        CounterState {
+            x: self.x,
+            y,
+            low_y,
+            high_y,
+        }
+    }
+}
+
+pub struct SourceCounter {
+    x: u64,
+    sources: u64,
+    seen: Vec<bool>,
+}
+
+impl Counter for SourceCounter {
+    fn new(_total_types: usize) -> SourceCounter {
+        SourceCounter {
+            x: 0,
+            sources: 0,
+            seen: vec![],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.x = 0;
+        self.sources = 0;
+        for e in self.seen.iter_mut() {
+            *e = false;
+        }
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let prev_sources = self.sources;
+        if let Some(id) = sample.source {
+            // We do not know the number of sources in advance.
+            if id >= self.seen.len() {
+                self.seen.resize(id + 1, false);
+            }
+            if !self.seen[id] {
+                self.sources += 1;
+                self.seen[id] = true;
+            }
+        }
+        self.x += sample.x;
+        CounterState {
+            x: self.x,
+            y: self.sources,
+            low_y: prev_sources,
+            high_y: self.sources,
+        }
+    }
+}
+
+/// Counter for [MeasureY::FrequentTypes].
+///
+/// A type is frequent if its number of tokens is at least `per_million` per million
+/// of all tokens so far. As the total grows, types can drop below the threshold,
+/// so the measure is not monotone. We maintain the frequency spectrum (number of types
+/// with each frequency) incrementally, and each sample takes time proportional to
+/// the number of its tokens plus the number of distinct frequencies above the threshold.
+///
+/// In the middle of a sample, a type is certainly frequent if its count before the sample
+/// reaches the threshold after the sample, and possibly frequent if its count after
+/// the sample reaches the threshold before the sample.
+pub struct FrequentTypesCounter {
+    x: u64,
+    tokens: u64,
+    per_million: u64,
+    freq: Vec<u64>,
+    spectrum: BTreeMap<u64, u64>,
+}
+
+impl FrequentTypesCounter {
+    /// Counter with the threshold `per_million`; [Counter::new] uses threshold 0,
+    /// so that all types are frequent.
+    pub fn with_threshold(total_types: usize, per_million: u64) -> FrequentTypesCounter {
+        FrequentTypesCounter {
+            x: 0,
+            tokens: 0,
+            per_million,
+            freq: vec![0; total_types],
+            spectrum: BTreeMap::new(),
+        }
+    }
+
+    /// Smallest number of tokens for a frequent type, if there are `tokens` tokens in total.
+    fn threshold(&self, tokens: u64) -> u64 {
+        let t = (self.per_million as u128 * tokens as u128).div_ceil(1_000_000);
+        t.max(1) as u64
+    }
+
+    /// Number of types with at least `threshold` tokens.
+    fn count_at_least(&self, threshold: u64) -> u64 {
+        self.spectrum.range(threshold..).map(|(_, &m)| m).sum()
+    }
+}
+
+impl Counter for FrequentTypesCounter {
+    fn new(total_types: usize) -> FrequentTypesCounter {
+        FrequentTypesCounter::with_threshold(total_types, 0)
+    }
+
+    fn for_measure(measure_y: MeasureY, total_types: usize) -> FrequentTypesCounter {
+        match measure_y {
+            MeasureY::FrequentTypes { per_million } => {
+                FrequentTypesCounter::with_threshold(total_types, per_million)
+            }
+            _ => FrequentTypesCounter::new(total_types),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.x = 0;
+        self.tokens = 0;
+        for e in self.freq.iter_mut() {
+            *e = 0;
+        }
+        self.spectrum.clear();
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        let prev_threshold = self.threshold(self.tokens);
+        for t in &sample.tokens {
+            let old = self.freq[t.id];
+            let new = old + t.count;
+            if old > 0 {
+                let e = self.spectrum.get_mut(&old).unwrap();
+                *e -= 1;
+                if *e == 0 {
+                    self.spectrum.remove(&old);
+                }
+            }
+            *self.spectrum.entry(new).or_insert(0) += 1;
+            self.freq[t.id] = new;
+        }
+        self.tokens += sample.token_count;
+        self.x += sample.x;
+        let threshold = self.threshold(self.tokens);
+        let y = self.count_at_least(threshold);
+        // Types of this sample that reach the threshold only because of this sample.
+        let new_types = sample
+            .tokens
+            .iter()
+            .filter(|t| {
+                let f = self.freq[t.id];
+                f >= threshold && f - t.count < threshold
+            })
+            .count() as u64;
+        let low_y = y - new_types;
+        let high_y = self.count_at_least(prev_threshold);
+        debug_assert!(low_y <= y);
+        debug_assert!(y <= high_y);
+        CounterState {
+            x: self.x,
+            y,
+            low_y,
+            high_y,
+        }
+    }
+}
+
+/// Counter for [MeasureY::ZipfSlope].
+///
+/// We maintain the frequency of each type and the frequency spectrum (number of types
+/// with each frequency) incrementally. The least-squares fit is done over the
+/// frequency spectrum, using precomputed prefix sums of log ranks, so each query takes
+/// time proportional to the number of distinct frequencies, which is at most
+/// about the square root of twice the number of tokens. This is paid once per sample,
+/// in addition to the time proportional to the number of tokens in the sample.
+pub struct ZipfSlopeCounter {
+    x: u64,
+    tokens: u64,
+    freq: Vec<u64>,
+    spectrum: BTreeMap<u64, u64>,
+    /// Sums of log(r) for ranks r = 1, ..., k.
+    log_sum: Vec<f64>,
+    /// Sums of log(r)² for ranks r = 1, ..., k.
+    log_sq_sum: Vec<f64>,
+}
+
+impl ZipfSlopeCounter {
+    fn feed_token(&mut self, t: &SToken) {
+        let old = self.freq[t.id];
+        let new = old + t.count;
+        if old > 0 {
+            let e = self.spectrum.get_mut(&old).unwrap();
+            *e -= 1;
+            if *e == 0 {
+                self.spectrum.remove(&old);
+            }
+        }
+        *self.spectrum.entry(new).or_insert(0) += 1;
+        self.freq[t.id] = new;
+        self.tokens += t.count;
+    }
+
+    /// Least-squares slope of log(frequency) against log(rank), negated and scaled.
+    fn zipf_slope(&self) -> u64 {
+        let mut n = 0;
+        let mut sx = 0.0;
+        let mut sy = 0.0;
+        let mut sxx = 0.0;
+        let mut sxy = 0.0;
+        // Types with the same frequency occupy consecutive ranks n+1, ..., n+m.
+        for (&f, &m) in self.spectrum.iter().rev() {
+            let m = m as usize;
+            let y = (f as f64).ln();
+            let x = self.log_sum[n + m] - self.log_sum[n];
+            sx += x;
+            sy += m as f64 * y;
+            sxx += self.log_sq_sum[n + m] - self.log_sq_sum[n];
+            sxy += x * y;
+            n += m;
+        }
+        let n = n as f64;
+        let d = n * sxx - sx * sx;
+        if n < 2.0 || d <= 0.0 {
+            return 0;
+        }
+        let slope = (n * sxy - sx * sy) / d;
+        scale_and_round((-slope).max(0.0), ZIPF_SLOPE_SCALE, ZIPF_SLOPE_ROUND)
+    }
+}
+
+impl Counter for ZipfSlopeCounter {
+    fn new(total_types: usize) -> ZipfSlopeCounter {
+        let mut log_sum = Vec::with_capacity(total_types + 1);
+        let mut log_sq_sum = Vec::with_capacity(total_types + 1);
+        log_sum.push(0.0);
+        log_sq_sum.push(0.0);
+        for r in 1..=total_types {
+            let l = (r as f64).ln();
+            log_sum.push(log_sum[r - 1] + l);
+            log_sq_sum.push(log_sq_sum[r - 1] + l * l);
+        }
+        ZipfSlopeCounter {
+            x: 0,
+            tokens: 0,
+            freq: vec![0; total_types],
+            spectrum: BTreeMap::new(),
+            log_sum,
+            log_sq_sum,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.x = 0;
+        self.tokens = 0;
+        for e in self.freq.iter_mut() {
+            *e = 0;
+        }
+        self.spectrum.clear();
+    }
+
+    fn feed_sample(&mut self, sample: &Sample) -> CounterState {
+        for t in &sample.tokens {
+            self.feed_token(t);
+        }
+        self.x += sample.x;
+        let y = self.zipf_slope();
+        // The slope is not monotone, and it is too expensive to follow it token by token,
+        // so in the middle of the sample we only use bounds that hold for any frequencies.
+        // By Cauchy–Schwarz, the slope is at most the ratio of the standard deviations of
+        // log frequency and log rank. Log frequencies are between 0 and ln N, where N is
+        // the number of tokens after the sample, and the standard deviation of log ranks
+        // is smallest for two types, so the slope is at most (ln N / 2) / (ln 2 / 2) = log₂ N.
+        let low_y = 0;
+        let high_y = if self.tokens == 0 {
+            0
+        } else {
+            scale_and_round(
+                (self.tokens as f64).log2(),
+                ZIPF_SLOPE_SCALE,
+                ZIPF_SLOPE_ROUND,
+            )
+        };
+        debug_assert!(y <= high_y);
+        CounterState {
+            x: self.x,
+            y,
+            low_y,
+            high_y,
+        }
+    }
+}
+
+pub fn count_types(samples: &[Sample]) -> usize {
+    let mut max_type = 0;
+    for sample in samples {
+        for token in &sample.tokens {
+            max_type = max_type.max(token.id);
+        }
+    }
+    max_type + 1
+}
+
+pub fn count_xy(measure_y: MeasureY, samples: &[Sample]) -> (u64, u64) {
+    count_xy_of(measure_y, samples.iter())
+}
+
+/// Like [count_xy], but for any sequence of samples; the same sample may appear several times.
+pub fn count_xy_of<'a, I>(measure_y: MeasureY, samples: I) -> (u64, u64)
+where
+    I: Iterator<Item = &'a Sample> + Clone,
+{
+    match measure_y {
+        MeasureY::Types => count_xy_variant::<TypeCounter, _>(measure_y, samples),
+        MeasureY::Tokens => count_xy_variant::<TokenCounter, _>(measure_y, samples),
+        MeasureY::Hapaxes => count_xy_variant::<HapaxCounter, _>(measure_y, samples),
+        MeasureY::Samples => count_xy_variant::<SampleCounter, _>(measure_y, samples),
+        MeasureY::MarkedTypes => count_xy_variant::<TypeRatioCounter, _>(measure_y, samples),
+        MeasureY::MeanFrequency => count_xy_variant::<MeanFrequencyCounter, _>(measure_y, samples),
+        MeasureY::HapaxTokenRatio => {
+            count_xy_variant::<HapaxTokenRatioCounter, _>(measure_y, samples)
+        }
+        MeasureY::Sources => count_xy_variant::<SourceCounter, _>(measure_y, samples),
+        MeasureY::MarkedTokens => count_xy_variant::<MarkedTokenCounter, _>(measure_y, samples),
+        MeasureY::ZipfSlope => count_xy_variant::<ZipfSlopeCounter, _>(measure_y, samples),
+        MeasureY::FrequentTypes { .. } => {
+            count_xy_variant::<FrequentTypesCounter, _>(measure_y, samples)
+        }
+        MeasureY::KnownTypes => count_xy_variant::<KnownTypeCounter, _>(measure_y, samples),
+        MeasureY::Legomena { .. } => count_xy_variant::<NLegomenaCounter, _>(measure_y, samples),
+        MeasureY::TypeTokenRatio => {
+            count_xy_variant::<TypeTokenRatioCounter, _>(measure_y, samples)
+        }
+        MeasureY::YuleK => count_xy_variant::<YuleKCounter, _>(measure_y, samples),
+        MeasureY::Entropy => count_xy_variant::<EntropyCounter, _>(measure_y, samples),
+        MeasureY::GuiraudR => count_xy_variant::<GuiraudRCounter, _>(measure_y, samples),
+        MeasureY::HerdanC => count_xy_variant::<HerdanCCounter, _>(measure_y, samples),
+    }
+}
+
+fn count_xy_variant<'a, TCounter, I>(measure_y: MeasureY, samples: I) -> (u64, u64)
+where
+    TCounter: Counter,
+    I: Iterator<Item = &'a Sample> + Clone,
+{
+    let n = samples
+        .clone()
+        .flat_map(|s| s.tokens.iter().map(|t| t.id + 1))
+        .max()
+        .unwrap_or(1);
+    let mut counter = TCounter::for_measure(measure_y, n);
+    let mut c = None;
+    for s in samples {
+        c = Some(counter.feed_sample(s));
+    }
+    match c {
+        None => (0, 0),
+        Some(c) => (c.x, c.y),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stm(id: usize, count: u64, marked_count: u64) -> SToken {
+        SToken {
+            id,
+            count,
+            marked_count,
+            also_marked: 0,
+            known: false,
+        }
+    }
+
+    #[test]
+    fn count_xy_tokens() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 11,
+                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![stm(1, 5, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(count_xy(MeasureY::Tokens, &samples), (1234 + 5678, 16));
+    }
+
+    #[test]
+    fn count_xy_marked_tokens() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 11,
+                tokens: vec![stm(0, 10, 2), stm(1, 1, 1)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![stm(1, 5, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 7,
+                tokens: vec![stm(0, 3, 3), stm(2, 4, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(
+            count_xy(MeasureY::MarkedTokens, &samples),
+            (1234 + 5678 + 1, 6)
+        );
+        assert_eq!(count_xy(MeasureY::MarkedTokens, &samples[1..2]), (5678, 0));
+    }
+
+    #[test]
+    fn count_xy_types() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 11,
+                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![stm(1, 5, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(count_xy(MeasureY::Types, &samples), (1234 + 5678, 2));
+    }
+
+    #[test]
+    fn count_xy_samples() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 11,
+                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![stm(1, 5, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(count_xy(MeasureY::Samples, &samples), (1234 + 5678, 2));
+    }
+
+    #[test]
+    fn count_xy_hapaxes() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 11,
+                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![stm(1, 5, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(count_xy(MeasureY::Hapaxes, &samples), (1234 + 5678, 0));
+    }
+
+    #[test]
+    fn count_xy_known_types() {
+        let stk = |id, known| SToken {
+            known,
+            ..stm(id, 1, 0)
+        };
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 3,
+                tokens: vec![stk(0, true), stk(1, false), stk(2, true)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 3,
+                tokens: vec![stk(0, true), stk(3, true), stk(4, false)],
+                source: None,
+            },
+        ];
+        assert_eq!(count_xy(MeasureY::KnownTypes, &samples), (1234 + 5678, 3));
+        assert_eq!(count_xy(MeasureY::KnownTypes, &samples[1..]), (5678, 2));
+        assert_eq!(count_xy(MeasureY::Types, &samples), (1234 + 5678, 5));
+    }
+
+    #[test]
+    fn count_xy_marked_types() {
+        let samples = vec![
+            Sample {
+                x: 0,
+                token_count: 11,
+                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
+                source: None,
+            },
             Sample {
                 x: 0,
                 token_count: 5,
                 tokens: vec![stm(1, 5, 0)],
+                source: None,
             },
         ];
         assert_eq!(count_xy(MeasureY::MarkedTypes, &samples), (2, 1));
     }
+
+    #[test]
+    fn type_ratios_counter() {
+        let sta = |id, count, marked_count, also_marked| SToken {
+            id,
+            count,
+            marked_count,
+            also_marked,
+            known: false,
+        };
+        let samples = [
+            Sample {
+                x: 0,
+                token_count: 11,
+                tokens: vec![sta(0, 10, 2, 0b01), sta(1, 1, 0, 0b10)],
+                source: None,
+            },
+            Sample {
+                x: 0,
+                token_count: 8,
+                tokens: vec![sta(1, 5, 1, 0b11), sta(2, 3, 0, 0b10)],
+                source: None,
+            },
+        ];
+        let mut counter = TypeRatiosCounter::new(3, 3);
+        for _ in 0..2 {
+            counter.reset();
+            assert_eq!(counter.feed_sample(&samples[0]), 2);
+            assert_eq!(counter.marked(0), (0, 1));
+            assert_eq!(counter.marked(1), (0, 1));
+            assert_eq!(counter.marked(2), (0, 1));
+            assert_eq!(counter.feed_sample(&samples[1]), 3);
+            assert_eq!(counter.marked(0), (1, 2));
+            assert_eq!(counter.marked(1), (1, 2));
+            assert_eq!(counter.marked(2), (1, 2));
+        }
+        // Marking 0 agrees with TypeRatioCounter.
+        let mut single = TypeRatioCounter::new(3);
+        counter.reset();
+        for s in &samples {
+            let c = single.feed_sample(s);
+            assert_eq!(counter.feed_sample(s), c.x);
+            assert_eq!(counter.marked(0), (c.low_y, c.high_y));
+        }
+    }
+
+    #[test]
+    fn count_xy_mean_frequency() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 2,
+                tokens: vec![stm(0, 1, 0), stm(1, 1, 0)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 1,
+                tokens: vec![stm(2, 1, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(
+            count_xy(MeasureY::MeanFrequency, &samples),
+            (1234 + 5678, MEAN_FREQUENCY_SCALE)
+        );
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 11,
+                tokens: vec![stm(0, 10, 2), stm(1, 1, 0)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 5,
+                tokens: vec![stm(1, 5, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(
+            count_xy(MeasureY::MeanFrequency, &samples),
+            (1234 + 5678, 8 * MEAN_FREQUENCY_SCALE)
+        );
+        assert_eq!(count_xy(MeasureY::MeanFrequency, &[]), (0, 0));
+    }
+
+    #[test]
+    fn mean_frequency_envelope() {
+        let mut counter = MeanFrequencyCounter::new(3);
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 3,
+            tokens: vec![stm(0, 3, 0)],
+            source: None,
+        });
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 3000, 3000));
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 2,
+            tokens: vec![stm(1, 1, 0), stm(2, 1, 0)],
+            source: None,
+        });
+        assert_eq!((c.low_y, c.y, c.high_y), (1000, 1666, 5000));
+    }
+
+    #[test]
+    fn count_xy_hapax_token_ratio() {
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 5,
+                tokens: vec![stm(0, 1, 0), stm(1, 1, 0), stm(2, 3, 0)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 3,
+                tokens: vec![stm(1, 1, 0), stm(3, 1, 0), stm(4, 1, 0)],
+                source: None,
+            },
+        ];
+        // Hapaxes: 0, 3, 4; tokens: 8.
+        assert_eq!(
+            count_xy(MeasureY::HapaxTokenRatio, &samples),
+            (1234 + 5678, 3 * HAPAX_TOKEN_RATIO_SCALE / 8)
+        );
+        assert_eq!(
+            count_xy(MeasureY::HapaxTokenRatio, &samples[..1]),
+            (1234, 2 * HAPAX_TOKEN_RATIO_SCALE / 5)
+        );
+        assert_eq!(count_xy(MeasureY::HapaxTokenRatio, &[]), (0, 0));
+    }
+
+    #[test]
+    fn count_xy_sources() {
+        let sample = |x, source| Sample {
+            x,
+            token_count: 1,
+            tokens: vec![stm(0, 1, 0)],
+            source,
+        };
+        // Overlapping sources.
+        let samples = vec![sample(1, Some(0)), sample(2, Some(1)), sample(4, Some(0))];
+        assert_eq!(count_xy(MeasureY::Sources, &samples), (7, 2));
+        // Distinct sources.
+        let samples = vec![sample(1, Some(0)), sample(2, Some(1)), sample(4, Some(2))];
+        assert_eq!(count_xy(MeasureY::Sources, &samples), (7, 3));
+        // Unknown sources.
+        let samples = vec![sample(1, None), sample(2, Some(3)), sample(4, None)];
+        assert_eq!(count_xy(MeasureY::Sources, &samples), (7, 1));
+        assert_eq!(count_xy(MeasureY::Sources, &[]), (0, 0));
+    }
+
+    #[test]
+    fn source_counter_reset() {
+        let mut counter = SourceCounter::new(1);
+        let sample = Sample {
+            x: 1,
+            token_count: 0,
+            tokens: vec![],
+            source: Some(5),
+        };
+        let c = counter.feed_sample(&sample);
+        assert_eq!((c.x, c.low_y, c.y, c.high_y), (1, 0, 1, 1));
+        let c = counter.feed_sample(&sample);
+        assert_eq!((c.x, c.low_y, c.y, c.high_y), (2, 1, 1, 1));
+        counter.reset();
+        let c = counter.feed_sample(&sample);
+        assert_eq!((c.x, c.low_y, c.y, c.high_y), (1, 0, 1, 1));
+    }
+
+    #[test]
+    fn hapax_token_ratio_envelope() {
+        let mut counter = HapaxTokenRatioCounter::new(4);
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 4,
+            tokens: vec![stm(0, 1, 0), stm(1, 3, 0)],
+            source: None,
+        });
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 250, 1000));
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 4,
+            tokens: vec![stm(0, 1, 0), stm(2, 1, 0), stm(3, 2, 0)],
+            source: None,
+        });
+        // Hapaxes: 1 before, 1 after, between 0 and 3 in the middle.
+        // Tokens: 4 before, 8 after.
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 125, 750));
+    }
+
+    #[test]
+    fn count_xy_type_token_ratio() {
+        let samples = [
+            Sample {
+                x: 1,
+                token_count: 4,
+                tokens: vec![stm(0, 1, 0), stm(1, 3, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 4,
+                tokens: vec![stm(0, 2, 0), stm(2, 2, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(count_xy(MeasureY::TypeTokenRatio, &samples[..1]), (1, 5000));
+        assert_eq!(count_xy(MeasureY::TypeTokenRatio, &samples), (2, 3750));
+        assert_eq!(count_xy(MeasureY::TypeTokenRatio, &[]), (0, 0));
+    }
+
+    #[test]
+    fn type_token_ratio_envelope() {
+        let mut counter = TypeTokenRatioCounter::new(4);
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 4,
+            tokens: vec![stm(0, 1, 0), stm(1, 3, 0)],
+            source: None,
+        });
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 5000, 10000));
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 4,
+            tokens: vec![stm(0, 1, 0), stm(2, 1, 0), stm(3, 2, 0)],
+            source: None,
+        });
+        // Types: 2 before, 4 after. Tokens: 4 before, 8 after.
+        // The ratio is 5000 before and after, but it can be up to 4/4 in the middle.
+        assert_eq!((c.low_y, c.y, c.high_y), (2500, 5000, 10000));
+    }
+
+    #[test]
+    fn count_xy_yule_k() {
+        let samples = [
+            Sample {
+                x: 1,
+                token_count: 4,
+                tokens: vec![stm(0, 1, 0), stm(1, 3, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 4,
+                tokens: vec![stm(0, 2, 0), stm(2, 2, 0)],
+                source: None,
+            },
+        ];
+        // Frequencies 1, 3: K = 10⁴ · (1 + 9 − 4) / 4² = 3750.
+        assert_eq!(count_xy(MeasureY::YuleK, &samples[..1]), (1, 375000));
+        // Frequencies 3, 3, 2: K = 10⁴ · (9 + 9 + 4 − 8) / 8² = 2187.5.
+        assert_eq!(count_xy(MeasureY::YuleK, &samples), (2, 218750));
+        assert_eq!(count_xy(MeasureY::YuleK, &[]), (0, 0));
+        let mut counter = YuleKCounter::new(3);
+        let c = counter.feed_sample(&samples[0]);
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 375000, 1000000));
+        let c = counter.feed_sample(&samples[1]);
+        // Σ f(f − 1): 6 before, 14 after. Tokens: 4 before, 8 after.
+        assert_eq!((c.low_y, c.y, c.high_y), (93750, 218750, 875000));
+        counter.reset();
+        let c = counter.feed_sample(&samples[1]);
+        assert_eq!(c.y, 250000);
+    }
+
+    #[test]
+    fn count_xy_entropy() {
+        let samples = [
+            Sample {
+                x: 1,
+                token_count: 4,
+                tokens: vec![stm(0, 2, 0), stm(1, 2, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 4,
+                tokens: vec![stm(2, 2, 0), stm(3, 2, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 8,
+                tokens: vec![stm(0, 8, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(count_xy(MeasureY::Entropy, &samples[..1]), (1, 1000));
+        assert_eq!(count_xy(MeasureY::Entropy, &samples[..2]), (2, 2000));
+        // Probabilities 5/8, 1/8, 1/8, 1/8: 1.549 bits.
+        assert_eq!(count_xy(MeasureY::Entropy, &samples), (3, 1549));
+        assert_eq!(count_xy(MeasureY::Entropy, &samples[2..]), (1, 0));
+        assert_eq!(count_xy(MeasureY::Entropy, &[]), (0, 0));
+        let mut counter = EntropyCounter::new(4);
+        counter.feed_sample(&samples[0]);
+        counter.feed_sample(&samples[1]);
+        let c = counter.feed_sample(&samples[2]);
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 1549, 2000));
+    }
+
+    #[test]
+    fn entropy_envelope() {
+        // In the middle of the second sample, the entropy is 1 bit, which is higher than
+        // the entropy before or after it.
+        let samples = [
+            Sample {
+                x: 1,
+                token_count: 1,
+                tokens: vec![stm(0, 1, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 101,
+                tokens: vec![stm(1, 1, 0), stm(0, 100, 0)],
+                source: None,
+            },
+        ];
+        let mut counter = EntropyCounter::new(2);
+        let c = counter.feed_sample(&samples[0]);
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 0, 0));
+        let c = counter.feed_sample(&samples[1]);
+        assert!(c.y < 1000);
+        assert_eq!((c.low_y, c.high_y), (0, 1000));
+        check_envelope::<EntropyCounter>(MeasureY::Entropy, &samples);
+    }
+
+    #[test]
+    fn count_xy_guiraud_r_herdan_c() {
+        let samples = [
+            Sample {
+                x: 1,
+                token_count: 4,
+                tokens: vec![stm(0, 1, 0), stm(1, 3, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 12,
+                tokens: vec![stm(0, 10, 0), stm(2, 2, 0)],
+                source: None,
+            },
+        ];
+        // 2 types, 4 tokens: R = 2 / 2, C = log 2 / log 4.
+        assert_eq!(count_xy(MeasureY::GuiraudR, &samples[..1]), (1, 1000));
+        assert_eq!(count_xy(MeasureY::HerdanC, &samples[..1]), (1, 5000));
+        // 3 types, 16 tokens: R = 3 / 4, C = log 3 / log 16.
+        assert_eq!(count_xy(MeasureY::GuiraudR, &samples), (2, 750));
+        assert_eq!(count_xy(MeasureY::HerdanC, &samples), (2, 3962));
+        assert_eq!(count_xy(MeasureY::GuiraudR, &[]), (0, 0));
+        assert_eq!(count_xy(MeasureY::HerdanC, &[]), (0, 0));
+    }
+
+    /// Sample with the first `k` tokens of `sample`, in order.
+    fn prefix(sample: &Sample, k: u64) -> Sample {
+        let mut tokens = vec![];
+        let mut left = k;
+        for t in &sample.tokens {
+            if left == 0 {
+                break;
+            }
+            let count = t.count.min(left);
+            tokens.push(stm(t.id, count, 0));
+            left -= count;
+        }
+        Sample {
+            x: 1,
+            token_count: k,
+            tokens,
+            source: None,
+        }
+    }
+
+    /// Check that the envelope of each sample contains the values at all
+    /// intermediate points, as assumed in [crate::calc_point].
+    fn check_envelope<TCounter: Counter>(measure_y: MeasureY, samples: &[Sample]) {
+        let mut counter = TCounter::for_measure(measure_y, count_types(samples));
+        for i in 0..samples.len() {
+            let c = counter.feed_sample(&samples[i]);
+            for k in 0..samples[i].token_count {
+                let last = prefix(&samples[i], k);
+                let (_, y) = count_xy_of(measure_y, samples[..i].iter().chain([&last]));
+                assert!(c.low_y <= y && y <= c.high_y, "{measure_y} {i} {k}");
+            }
+        }
+    }
+
+    #[test]
+    fn derived_measures_envelope() {
+        let samples = [
+            Sample {
+                x: 1,
+                token_count: 3,
+                tokens: vec![stm(0, 1, 0), stm(1, 2, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 6,
+                tokens: vec![stm(2, 1, 0), stm(3, 1, 0), stm(0, 4, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 5,
+                tokens: vec![stm(1, 3, 0), stm(4, 1, 0), stm(5, 1, 0)],
+                source: None,
+            },
+        ];
+        check_envelope::<GuiraudRCounter>(MeasureY::GuiraudR, &samples);
+        check_envelope::<HerdanCCounter>(MeasureY::HerdanC, &samples);
+        check_envelope::<TypeTokenRatioCounter>(MeasureY::TypeTokenRatio, &samples);
+        check_envelope::<HapaxTokenRatioCounter>(MeasureY::HapaxTokenRatio, &samples);
+        check_envelope::<YuleKCounter>(MeasureY::YuleK, &samples);
+        check_envelope::<ZipfSlopeCounter>(MeasureY::ZipfSlope, &samples);
+        check_envelope::<EntropyCounter>(MeasureY::Entropy, &samples);
+    }
+
+    fn frequent_types_samples() -> Vec<Sample> {
+        vec![
+            Sample {
+                x: 1,
+                token_count: 4,
+                tokens: vec![stm(0, 3, 0), stm(1, 1, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 4,
+                tokens: vec![stm(2, 4, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 8,
+                tokens: vec![stm(3, 8, 0)],
+                source: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn count_xy_frequent_types() {
+        let samples = frequent_types_samples();
+        let my = |per_million| MeasureY::FrequentTypes { per_million };
+        // 16 tokens: types 2 and 3 have at least 4 tokens.
+        assert_eq!(count_xy(my(250_000), &samples), (3, 2));
+        // Only type 3 has at least 8 tokens.
+        assert_eq!(count_xy(my(500_000), &samples), (3, 1));
+        assert_eq!(count_xy(my(1_000_000), &samples), (3, 0));
+        // A low threshold counts all types.
+        assert_eq!(count_xy(my(1), &samples), (3, 4));
+        assert_eq!(count_xy(my(250_000), &samples[..1]), (1, 2));
+        assert_eq!(count_xy(my(250_000), &[]), (0, 0));
+    }
+
+    #[test]
+    fn frequent_types_envelope() {
+        let samples = frequent_types_samples();
+        let mut counter = FrequentTypesCounter::for_measure(
+            MeasureY::FrequentTypes {
+                per_million: 250_000,
+            },
+            4,
+        );
+        let states = samples
+            .iter()
+            .map(|s| {
+                let c = counter.feed_sample(s);
+                (c.low_y, c.y, c.high_y)
+            })
+            .collect::<Vec<_>>();
+        // Threshold 1 token after the first sample, 2 tokens after the second sample,
+        // and 4 tokens after the third sample. Type 0 drops out in the third sample,
+        // but type 3 comes in.
+        assert_eq!(states, [(0, 2, 2), (1, 2, 3), (1, 2, 3)]);
+        counter.reset();
+        let c = counter.feed_sample(&samples[2]);
+        assert_eq!((c.x, c.low_y, c.y, c.high_y), (1, 0, 1, 1));
+    }
+
+    #[test]
+    fn count_xy_legomena() {
+        let samples = frequent_types_samples();
+        let my = |n| MeasureY::Legomena { n };
+        // Tokens per type: 3, 1, 4, 8.
+        assert_eq!(count_xy(my(1), &samples), (3, 1));
+        assert_eq!(count_xy(my(3), &samples), (3, 1));
+        assert_eq!(count_xy(my(2), &samples), (3, 0));
+        assert_eq!(count_xy(my(8), &samples), (3, 1));
+        assert_eq!(count_xy(my(2), &[]), (0, 0));
+    }
+
+    #[test]
+    fn legomena_envelope() {
+        let mut counter = NLegomenaCounter::for_measure(MeasureY::Legomena { n: 2 }, 4);
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 4,
+            tokens: vec![stm(0, 1, 0), stm(1, 3, 0)],
+            source: None,
+        });
+        // Type 1 has 2 tokens in the middle of the sample.
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 0, 1));
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 4,
+            tokens: vec![stm(0, 2, 0), stm(2, 2, 0)],
+            source: None,
+        });
+        // Type 0 passes 2 tokens on its way to 3, and type 2 stays at 2.
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 1, 2));
+        counter.reset();
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 2,
+            tokens: vec![stm(3, 2, 0)],
+            source: None,
+        });
+        assert_eq!((c.x, c.low_y, c.y, c.high_y), (1, 0, 1, 1));
+    }
+
+    #[test]
+    fn legomena_same_as_hapaxes() {
+        let samples = (0..20)
+            .map(|i| Sample {
+                x: 1,
+                token_count: 6,
+                tokens: vec![
+                    stm(i % 3, 1 + i as u64 % 2, 0),
+                    stm(3 + i % 7, 5 - i as u64 % 2, 0),
+                ],
+                source: None,
+            })
+            .collect::<Vec<_>>();
+        let mut hapaxes = HapaxCounter::new(10);
+        let mut legomena = NLegomenaCounter::for_measure(MeasureY::Legomena { n: 1 }, 10);
+        for s in &samples {
+            let a = hapaxes.feed_sample(s);
+            let b = legomena.feed_sample(s);
+            assert_eq!((a.x, a.low_y, a.y, a.high_y), (b.x, b.low_y, b.y, b.high_y));
+        }
+    }
+
+    #[test]
+    fn count_xy_zipf_slope() {
+        // Exactly Zipfian: frequency of rank r is 720 / r.
+        let samples = vec![
+            Sample {
+                x: 1234,
+                token_count: 720 + 240 + 144,
+                tokens: vec![stm(0, 720, 0), stm(2, 240, 0), stm(4, 144, 0)],
+                source: None,
+            },
+            Sample {
+                x: 5678,
+                token_count: 360 + 180 + 120,
+                tokens: vec![stm(1, 360, 0), stm(3, 180, 0), stm(5, 120, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(
+            count_xy(MeasureY::ZipfSlope, &samples),
+            (1234 + 5678, ZIPF_SLOPE_SCALE)
+        );
+        // Frequency of rank r is 3600 / r², split across samples.
+        let samples = vec![
+            Sample {
+                x: 1,
+                token_count: 3600 + 900,
+                tokens: vec![stm(0, 3600, 0), stm(1, 450, 0)],
+                source: None,
+            },
+            Sample {
+                x: 1,
+                token_count: 400 + 450 + 225,
+                tokens: vec![stm(1, 450, 0), stm(2, 400, 0), stm(3, 225, 0)],
+                source: None,
+            },
+        ];
+        assert_eq!(
+            count_xy(MeasureY::ZipfSlope, &samples),
+            (2, 2 * ZIPF_SLOPE_SCALE)
+        );
+        // All types equally frequent.
+        let samples = vec![Sample {
+            x: 1,
+            token_count: 6,
+            tokens: vec![stm(0, 2, 0), stm(1, 2, 0), stm(2, 2, 0)],
+            source: None,
+        }];
+        assert_eq!(count_xy(MeasureY::ZipfSlope, &samples), (1, 0));
+        // Only one type.
+        let samples = vec![Sample {
+            x: 1,
+            token_count: 5,
+            tokens: vec![stm(0, 5, 0)],
+            source: None,
+        }];
+        assert_eq!(count_xy(MeasureY::ZipfSlope, &samples), (1, 0));
+        assert_eq!(count_xy(MeasureY::ZipfSlope, &[]), (0, 0));
+    }
+
+    #[test]
+    fn zipf_slope_envelope() {
+        let mut counter = ZipfSlopeCounter::new(3);
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 6,
+            tokens: vec![stm(0, 4, 0), stm(1, 2, 0)],
+            source: None,
+        });
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 1000, 2584));
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 2,
+            tokens: vec![stm(1, 2, 0)],
+            source: None,
+        });
+        assert_eq!((c.low_y, c.y, c.high_y), (0, 0, 3000));
+        counter.reset();
+        let c = counter.feed_sample(&Sample {
+            x: 1,
+            token_count: 3,
+            tokens: vec![stm(2, 3, 0)],
+            source: None,
+        });
+        assert_eq!((c.x, c.low_y, c.y, c.high_y), (1, 0, 0, 1584));
+    }
+
+    #[test]
+    fn scale_and_round_down() {
+        assert_eq!(scale_and_round(0.0, 1000, RoundMode::Down), 0);
+        assert_eq!(scale_and_round(1.0, 1000, RoundMode::Down), 1000);
+        assert_eq!(scale_and_round(5.0 / 3.0, 1000, RoundMode::Down), 1666);
+        assert_eq!(scale_and_round(1.2345, 1000, RoundMode::Down), 1234);
+        assert_eq!(scale_and_round(1.2349, 1000, RoundMode::Down), 1234);
+        assert_eq!(scale_and_round(0.0005, 1000, RoundMode::Down), 0);
+        assert_eq!(scale_and_round(0.9999, 1000, RoundMode::Down), 999);
+    }
+
+    #[test]
+    fn scale_and_round_half_up() {
+        assert_eq!(scale_and_round(0.0, 1000, RoundMode::HalfUp), 0);
+        assert_eq!(scale_and_round(1.0, 1000, RoundMode::HalfUp), 1000);
+        assert_eq!(scale_and_round(5.0 / 3.0, 1000, RoundMode::HalfUp), 1667);
+        assert_eq!(scale_and_round(1.2345, 1000, RoundMode::HalfUp), 1235);
+        assert_eq!(scale_and_round(1.2344, 1000, RoundMode::HalfUp), 1234);
+        assert_eq!(scale_and_round(0.0005, 1000, RoundMode::HalfUp), 1);
+        assert_eq!(scale_and_round(0.9999, 1000, RoundMode::HalfUp), 1000);
+    }
+
+    #[test]
+    fn scale_and_round_exact() {
+        // 0.29 * 100.0 is slightly below 29 in floating point.
+        for mode in [RoundMode::Down, RoundMode::HalfUp] {
+            assert_eq!(scale_and_round(0.29, 100, mode), 29);
+            assert_eq!(scale_and_round(0.57, 100, mode), 57);
+            assert_eq!(scale_and_round(29.0 / 10.0, 1000, mode), 2900);
+        }
+    }
 }
@@ -0,0 +1,26 @@
+//! Lexical diversity statistics of random subcorpora.
+
+use crate::calc_avg;
+use crate::calculation::Sample;
+use crate::output::{AvgResult, MeasureY};
+
+/// Yule's K in random subcorpora with `limit` many things, over `iter` random permutations
+/// of `samples`.
+///
+/// This is the same as [calc_avg::average_at_limit] with [MeasureY::YuleK], using a fixed seed
+/// so that the result is reproducible. The values are scaled by
+/// [crate::output::YULE_K_SCALE], and they are sums over the iterations, as usual.
+///
+/// # Examples
+/// ```
+/// use types3::calculation::{SToken, Sample};
+/// use types3::diversity;
+/// let st = |id, count| SToken { id, count, marked_count: 0, also_marked: 0, known: false };
+/// let samples = [Sample { x: 4, token_count: 4, tokens: vec![st(0, 2), st(1, 2)], source: None }];
+/// let r = diversity::yules_k(&samples, 4, 10);
+/// // Σ m² V(m) = 8 and N = 4, so K = 10⁴ · 4 / 16 = 2500.
+/// assert_eq!((r.low, r.high, r.iter), (250000 * 10, 250000 * 10, 10));
+/// ```
+pub fn yules_k(samples: &[Sample], limit: u64, iter: u64) -> AvgResult {
+    calc_avg::average_at_limit(MeasureY::YuleK, samples, iter, limit, 0, None)
+}
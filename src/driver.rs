@@ -1,18 +1,63 @@
 //! Main entry point for calculating everything.
 
-use crate::calc_avg;
+use crate::cache::{AvgKey, Cache, PointKey};
+use crate::calc_avg::{self, AvgExtras};
+use crate::calc_boot;
 use crate::calc_point::{self, Point};
+use crate::calc_year::{self, YearTarget};
+use crate::calculation;
 use crate::categories::{self, Category};
+use crate::counter;
 use crate::errors::{self, Result};
+use crate::exact;
 use crate::information;
-use crate::input::{Input, Year};
-use crate::output::{self, MeasureX, MeasureY, OCurve, OResult, Output, PointResult, Years};
-use crate::samples;
-use crate::subsets::{self, Subset, SubsetKey};
+use crate::input::{self, Input, Year};
+use crate::output::{
+    self, AvgResult, MeasureX, MeasureY, NullModel, OBasis, OCheckpoint, OComparisonN, OCurve,
+    OLimit, OLimitUnits, OPartition, OPartitions, ORepeats, OResult, OSamples, OTopResult,
+    OWeightedComparison, Output, PointResult, TestSide, Years, FULL_CURVE_STEPS,
+    REGION_WEIGHT_STEPS,
+};
+use crate::parallelism;
+use crate::samples::{self, CSample};
+use crate::shuffle::{self, Permutations};
+use crate::subsets::{self, SampleMode, Subset, SubsetKey};
 use itertools::Itertools;
-use log::{debug, info};
+use log::{debug, info, warn};
+use std::cell::RefCell;
 use std::collections::hash_map::Entry::{Occupied, Vacant};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::AtomicBool;
+
+/// How to assign samples to time periods?
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// Each time period contains all samples with years in its window.
+    /// If the window is longer than the step, consecutive periods overlap,
+    /// and the same sample is included in several periods.
+    Overlap,
+    /// Each sample belongs to exactly one time period: the one with the latest
+    /// starting year that is not after the year of the sample.
+    /// The last time period also contains all samples after its window.
+    /// In effect, the window length only affects period labels,
+    /// and samples are assigned as if the window length was equal to the step.
+    Disjoint,
+}
+
+/// Which samples does a time period contain?
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowMode {
+    /// The samples with years in the window of the period, as determined by [Boundary].
+    Fixed,
+    /// The samples from the most recent years up to the end of the period,
+    /// as determined by [Boundary]. Period labels do not change.
+    TrailingRecent {
+        /// Number of years. If this is larger than [DriverArgs::step], consecutive periods
+        /// share samples, in the same way as with overlapping windows, and if it is smaller,
+        /// some samples do not belong to any period.
+        years: Year,
+    },
+}
 
 /// What to calculate?
 pub struct DriverArgs<'a> {
@@ -21,6 +66,21 @@ pub struct DriverArgs<'a> {
     /// If not specified, calculate just one curve for all data.
     pub category: Option<&'a str>,
 
+    /// Maximum number of categories.
+    /// If specified, it is an error if [DriverArgs::category] has more distinct values than this.
+    /// This is a safeguard against accidentally calculating hundreds of curves.
+    pub max_categories: Option<usize>,
+
+    /// Sample metadata key used for grouping samples, instead of years.
+    /// If specified, the value of this key in [crate::input::ISample::metadata] has to be an integer,
+    /// and it is used in place of [crate::input::ISample::year] everywhere:
+    /// [DriverArgs::offset], [DriverArgs::start], [DriverArgs::end], [DriverArgs::window],
+    /// and [DriverArgs::step] then refer to values of this key, so that e.g. [DriverArgs::window]
+    /// is the bin width. Samples without this key are ignored.
+    /// This is independent of [DriverArgs::category]: we still calculate one curve per category,
+    /// but each curve consists of bins of this attribute instead of time periods.
+    pub group_by: Option<&'a str>,
+
     /// What to calculate.
     /// In the visualizations, this corresponds to what will be put in the y axis.
     pub measure_y: MeasureY,
@@ -55,9 +115,48 @@ pub struct DriverArgs<'a> {
     /// Step size.
     pub step: Year,
 
+    /// Do we ignore time?
+    /// If true, use exactly one period that covers the whole range of years,
+    /// so that each curve has only one point, and [DriverArgs::window] and [DriverArgs::step] are ignored.
+    pub no_time: bool,
+
     /// Minimum size for subsets.
     pub minimum_size: u64,
 
+    /// Fixed size limit.
+    /// By default, the size limit [Output::limit] is the size of the smallest subset.
+    /// If specified, this is used as the size limit instead, and subsets smaller than this
+    /// are skipped with a warning, in the same way as with [DriverArgs::minimum_size].
+    pub fixed_limit: Option<u64>,
+
+    /// Minimum number of tokens in a sample.
+    /// If specified, samples with fewer tokens (after [DriverArgs::restrict_tokens]) are discarded
+    /// before anything else is calculated; see [samples::drop_short].
+    /// This is applied before [DriverArgs::minimum_size]: subsets are formed from the remaining
+    /// samples, and a subset is then skipped if it is too small.
+    pub min_sample_tokens: Option<u64>,
+
+    /// Fraction of samples to use.
+    /// If specified, we keep only a random subsample of this fraction of the samples
+    /// (after [DriverArgs::restrict_samples] and [DriverArgs::restrict_tokens]), and everything
+    /// else is calculated as if the other samples did not exist; see [samples::subsample].
+    /// The subsample is determined by [DriverArgs::seed], so it is reproducible.
+    /// This is meant for quick experiments with large corpora:
+    /// all results are then estimates based on the subsample only.
+    /// The fraction has to be in range (0, 1].
+    pub subsample: Option<f64>,
+
+    /// Threshold for a skewed size limit.
+    /// If specified, we warn if the size limit is below this fraction of the median size
+    /// of the subsets in the curves. This usually means that one tiny subset determines
+    /// the size limit for everything, and the curves are uninformative.
+    /// This is not checked if [DriverArgs::fixed_limit] is set.
+    pub skew_threshold: Option<f64>,
+
+    /// Is a skewed size limit an error?
+    /// If true, the warning of [DriverArgs::skew_threshold] is an error instead.
+    pub strict: bool,
+
     /// Sample-level restriction.
     /// Can be either a key-value pair (which refers to [crate::input::ISample::metadata]),
     /// or `None` if there is no need to restrict based on sample metadata.
@@ -71,15 +170,304 @@ pub struct DriverArgs<'a> {
     /// Which tokens are marked.
     /// Can be either a key-value pair (which refers to [crate::input::IToken::metadata]),
     /// or `None` if there is no need to mark tokens.
-    /// Marking is relevant if [DriverArgs::measure_y] is set to [MeasureY::MarkedTypes].
+    /// Marking is relevant if [DriverArgs::measure_y] is set to [MeasureY::MarkedTypes]
+    /// or [MeasureY::MarkedTokens].
+    /// Tokens that do not have the key at all are not marked, either;
+    /// see [DriverArgs::require_mark_key].
     pub mark_tokens: Category<'a>,
 
+    /// Additional token markings, as key-value pairs.
+    /// If non-empty, fill in [OResult::marked_types] with the average number of marked types
+    /// for [DriverArgs::mark_tokens] and for each of these markings, using the same random
+    /// subcorpora; see [calc_avg::marked_types_at_limit]. This way we can follow e.g.
+    /// several competing variants in one pass. Requires [MeasureY::MarkedTypes]; at most 63.
+    pub also_mark_tokens: &'a [(&'a str, &'a str)],
+
     /// Do we split samples?
     /// If false, we use the original samples as is when forming random subcorpora.
     /// If true, we interpret each token as a "micro-sample" and ignore the sample structure.
+    /// This cannot be combined with [MeasureX::Words], [MeasureX::Chars], or [MeasureX::Samples].
     pub split_samples: bool,
+
+    /// Do we only consider the presence or absence of each type in each sample?
+    /// If true, all tokens of the same type in a sample are counted as one token,
+    /// so the number of tokens in a sample is the number of distinct types in it,
+    /// and a type is marked in a sample if any of its tokens is marked.
+    /// Measures of tokens (e.g. [MeasureX::Tokens], [MeasureY::Tokens], [MeasureY::Hapaxes])
+    /// then count samples in which each type occurs, while [MeasureY::Types] is not affected,
+    /// and [MeasureX::Words] and [MeasureX::Chars] still use [crate::input::ISample::words]
+    /// and [crate::input::ISample::chars].
+    /// Unlike [DriverArgs::split_samples], this keeps the sample structure,
+    /// and the two cannot be combined.
+    pub presence_absence: bool,
+
+    /// Sample metadata key that identifies the units of accumulation, e.g. the author.
+    /// If specified, all samples of a subset with the same value of this key in
+    /// [crate::input::ISample::metadata] are merged into one sample, so that random subcorpora
+    /// are formed by adding one author at a time, with all of their samples together.
+    /// This way each author is equally likely to be included next, regardless of how many
+    /// samples they have, so authors with many samples do not dominate the random subcorpora.
+    /// Samples without this key are kept as separate samples.
+    /// This cannot be combined with [DriverArgs::split_samples] or with [NullModel::YearLabel].
+    pub accumulate_by: Option<&'a str>,
+
+    /// Length of lemma n-grams that are used as types.
+    /// If this is 1 (the default), types are lemmas. Otherwise each run of this many
+    /// consecutive tokens within a sample is counted as one token, and types are distinct
+    /// n-grams of lemmas; see [Input::ngrams]. This requires that the tokens of each sample
+    /// are listed in the original text order and are not pre-aggregated.
+    /// N-grams are formed before anything else, so [DriverArgs::restrict_tokens] and
+    /// [DriverArgs::mark_tokens] apply to an n-gram if they apply to all of its tokens,
+    /// and [DriverArgs::split_samples] and [DriverArgs::presence_absence] work with n-grams.
+    pub ngram: usize,
+
+    /// Do we report the Monte Carlo standard error of the significance estimates?
+    /// If true, fill in [OResult::vs_time_se] and [OResult::vs_categories_se],
+    /// and warn about results that are too close to a significance level
+    /// to be classified reliably with this number of iterations.
+    pub standard_error: bool,
+
+    /// Do we scale the number of iterations by subset size?
+    /// If true, each subset uses [DriverArgs::iter] multiplied by the ratio of its size
+    /// and the size of the largest subset, so [DriverArgs::iter] becomes the maximum.
+    /// The number of iterations actually used is reported in each result.
+    pub scale_iter: bool,
+
+    /// Group results into buckets of this many years for display purposes.
+    /// If specified, fill in [OResult::size] and [OCurve::buckets];
+    /// see [output::bucket_results].
+    pub display_bucket: Option<Year>,
+
+    /// Weighting exponent for comparing each subset with other time periods at several sizes.
+    /// If specified, fill in [OResult::weighted_vs_time]: each subset is compared with
+    /// the same reference as in [OResult::vs_time] at [output::REGION_WEIGHT_STEPS] sizes up to
+    /// its own size, and the results are combined with weights proportional to the size raised
+    /// to this power; see [output::weighted_comparison]. At smaller sizes, the value of the subset
+    /// is the rounded average of its own random subcorpora. Requires [NullModel::SampleOrder].
+    pub region_weight: Option<f64>,
+
+    /// Do we report all comparison results?
+    /// If true, fill in [Output::toplevel] with the results of comparing every point
+    /// with every subset that it was compared with.
+    pub dump_toplevel: bool,
+
+    /// Do we report the internal representation of samples?
+    /// If true, fill in [Output::samples] with the post-filter samples of each subset.
+    /// This can be large.
+    pub dump_samples: bool,
+
+    /// How to assign samples to time periods if windows overlap.
+    pub boundary: Boundary,
+
+    /// Which samples each time period contains; see [WindowMode].
+    /// The period that covers all data always contains all samples.
+    pub window_mode: WindowMode,
+
+    /// Do we leave out the subset itself from the reference distribution?
+    /// By default, a subset is compared with random subcorpora drawn from its parent
+    /// (e.g. all time periods together), which includes the subset itself.
+    /// A large subset then pulls the reference distribution towards itself,
+    /// making it harder to see that the subset is different from the rest of the data.
+    /// If true, each subset is instead compared with random subcorpora drawn from its parent
+    /// with the samples of the subset itself removed. This is more expensive, as we need
+    /// separate reference sets for each subset.
+    /// If the rest of a parent is smaller than the subset itself, the subset is not compared
+    /// with it: [OResult::vs_time] or [OResult::vs_categories] is `None`, with a warning.
+    pub leave_out_self: bool,
+
+    /// Do we report which subset determined the size limit?
+    /// If true, fill in [Output::limit_subset].
+    /// There is no such subset if [DriverArgs::fixed_limit] is set.
+    pub explain_limit: bool,
+
+    /// Do we report the size limit in all units?
+    /// If true, fill in [Output::limit_units] with the size of the subset that determined
+    /// the size limit in words, tokens, and types. One of these is [Output::limit] itself.
+    /// The others are specific to this subset and only indicate roughly
+    /// what the size limit corresponds to.
+    /// There is no such subset if [DriverArgs::fixed_limit] is set.
+    pub limit_units: bool,
+
+    /// Do we report comparison results also in an explicit, direction-aware form?
+    /// If true, fill in [OResult::vs_time_explicit] and [OResult::vs_categories_explicit].
+    pub explicit: bool,
+
+    /// Do we skip significance testing?
+    /// If true, only calculate [OResult::average_at_limit], and leave [OResult::vs_time]
+    /// and [OResult::vs_categories] empty. This is much faster.
+    /// Everything that depends on the comparisons is then left empty, too:
+    /// [DriverArgs::standard_error], [DriverArgs::region_weight], [DriverArgs::explicit],
+    /// and [DriverArgs::leave_out_self] have no effect,
+    /// and [DriverArgs::dump_toplevel] reports no results.
+    pub no_significance: bool,
+
+    /// Fractions of iterations at which we report intermediate results.
+    /// If specified, fill in [OResult::convergence] with the results after the first
+    /// fraction `f` of the iterations, for each `f` in this list, so that we can see how
+    /// the Monte Carlo estimates stabilize. The fractions have to be in range (0, 1];
+    /// they are sorted, and 1 is added if it is missing, so that the last checkpoint
+    /// is always the same as the final result.
+    pub convergence: Option<&'a [f64]>,
+
+    /// Sample metadata key that identifies the source of each sample, e.g. the author.
+    /// Required if [DriverArgs::measure_y] is set to [MeasureY::Sources]:
+    /// we then count the number of distinct values of this key in [crate::input::ISample::metadata].
+    /// Samples without this key do not contribute to the count.
+    pub source_key: Option<&'a str>,
+
+    /// Reference vocabulary, as a set of lemmas.
+    /// Required if [DriverArgs::measure_y] is set to [MeasureY::KnownTypes]:
+    /// we then count the number of distinct lemmas that are in this set.
+    pub vocabulary: Option<&'a HashSet<String>>,
+
+    /// Random seed.
+    /// The random permutations, and hence all results, are determined by the seed.
+    pub seed: u64,
+
+    /// Number of independent repetitions.
+    /// If specified, [OResult::average_at_limit] is calculated this many times,
+    /// with seeds [DriverArgs::seed], [DriverArgs::seed] + 1, etc.,
+    /// and [OResult::repeats] reports the spread of the averages across the repetitions;
+    /// see [output::repeat_bands].
+    /// All other results come from the first repetition; significance tests are not repeated.
+    pub repeats: Option<u64>,
+
+    /// Do we require that all tokens have the key of [DriverArgs::mark_tokens]?
+    /// If true, it is an error if a token that passes [DriverArgs::restrict_tokens]
+    /// in a sample that passes [DriverArgs::restrict_samples] does not have this key at all.
+    /// A token with the key but a different value is fine: it is simply not marked.
+    /// This helps to catch missing annotations, which would otherwise be silently
+    /// treated as unmarked tokens. Requires [DriverArgs::mark_tokens].
+    pub require_mark_key: bool,
+
+    /// Alternative hypothesis of the significance tests.
+    /// If specified, fill in [OResult::vs_time_p] and [OResult::vs_categories_p]
+    /// with the p-values of [OResult::vs_time] and [OResult::vs_categories] for this test;
+    /// see [PointResult::p_value]. This does not change the other results,
+    /// which report both directions.
+    pub test_side: Option<TestSide>,
+
+    /// Null model of the comparisons with other time periods.
+    /// With [NullModel::SampleOrder], [OResult::vs_time] compares each subset with random
+    /// subcorpora formed from all samples of the same category, at the size of the subset.
+    /// With [NullModel::YearLabel], the years of all samples of the same category are instead
+    /// randomly reassigned, and each subset is compared with the samples that get years in
+    /// its time period; this tests whether the time structure matters at all.
+    /// See [calc_year::compare_with_year_labels] for the details.
+    /// This does not affect [OResult::vs_categories]. [NullModel::YearLabel] requires a monotone
+    /// measure (see [MeasureY::is_monotone]), as the random subsets are not of the same size as
+    /// the subset; it cannot be combined with [DriverArgs::leave_out_self] or
+    /// [DriverArgs::convergence], its results are not cached, and it does not fill in
+    /// [OResult::vs_time_z].
+    pub null_model: NullModel,
+
+    /// Cancellation flag.
+    /// If specified, we check this flag regularly during the calculation,
+    /// and if it is set (e.g. by another thread), we stop as soon as possible
+    /// and return [errors::Cancelled] as the error.
+    pub cancel: Option<&'a AtomicBool>,
+
+    /// Cache for results.
+    /// If specified, results for subsets and parameters that are already in the cache are
+    /// reused instead of recomputing, and new results are added to the cache.
+    /// The results are the same as without the cache, up to extremely unlikely
+    /// collisions of the hashes that identify the subsets; see [Cache].
+    pub cache: Option<&'a Cache>,
+
+    /// Do we report the exact expected number of types?
+    /// If true, fill in [OResult::expected_types]; see [exact::expected_types_at_limit].
+    /// This is the exact value that [OResult::average_at_limit] estimates
+    /// if [DriverArgs::split_samples] is also set, so it can be used to validate the results.
+    /// Requires [MeasureY::Types] and [MeasureX::Tokens].
+    pub expected_types: bool,
+
+    /// Do we report the sizes of the reference sets of the comparisons?
+    /// If true, fill in [OResult::comparison_n].
+    /// This has no effect if [DriverArgs::no_significance] is set.
+    pub comparison_n: bool,
+
+    /// Do we report fingerprints?
+    /// If true, fill in [OResult::fingerprint]. This is needed for reusing the results with [extend].
+    pub fingerprints: bool,
+
+    /// Do we report the number of samples in random subcorpora?
+    /// If true, fill in [OResult::avg_samples_at_limit] and [AvgResult::samples],
+    /// so that we can see how many samples a random subcorpus with [Output::limit] many things
+    /// consists of on average.
+    pub samples_at_limit: bool,
+
+    /// Do we report the contribution of the last sample in random subcorpora?
+    /// If true, fill in [OResult::avg_marginal] and [AvgResult::marginal].
+    /// This shows how much the bounds of [OResult::average_at_limit] depend on
+    /// the sample that reaches the limit.
+    pub marginal: bool,
+
+    /// Do we report the extremes of random subcorpora?
+    /// If true, fill in [AvgResult::min_y] and [AvgResult::max_y] of
+    /// [OResult::average_at_limit], i.e., the smallest and largest value of [Output::measure_y]
+    /// in any of the random subcorpora with [Output::limit] many things.
+    /// If the limit falls in the middle of a sample, we use the lower and upper bound.
+    pub extremes: bool,
+
+    /// Do we report z-scores?
+    /// If true, fill in [OResult::vs_time_z], [OResult::vs_categories_z], and
+    /// [PointResult::moments]. The z-scores are based on the normal approximation
+    /// of the random values at the size of the observed point; see [PointResult::z_score].
+    pub z_score: bool,
+
+    /// Do we report the number of distinct orderings of samples?
+    /// If true, fill in [OResult::distinct_orderings], and warn about subsets
+    /// with so few samples that the number of iterations is at least
+    /// [ORDERINGS_WARNING_FACTOR] times the number of distinct orderings.
+    pub distinct_orderings: bool,
+
+    /// Do we report how much the categories differ from each other in each time period?
+    /// If true, fill in [Output::category_spread]. Requires [DriverArgs::category].
+    pub category_spread: bool,
+
+    /// Do we annotate the results with what they are based on?
+    /// If true, fill in [Output::basis].
+    pub basis: bool,
+
+    /// If specified, also report the size at which random subcorpora first reach this value
+    /// of [DriverArgs::measure_y], e.g. the number of tokens needed to see this many types.
+    /// Fill in [OResult::x_at_target]; see [calc_avg::x_at_target].
+    pub type_target: Option<u64>,
+
+    /// Do we report bootstrap confidence intervals for the observed values?
+    /// If true, fill in [OResult::observed_ci]; see [calc_boot::observed_ci].
+    /// This resamples the samples of each subset, and hence tells how much the observed value
+    /// depends on which samples happen to be in the corpus.
+    pub observed_ci: bool,
+
+    /// If specified, also report the expected number of types at sizes up to this many tokens,
+    /// interpolated below and extrapolated above the size of each subset.
+    /// Fill in [OResult::extrapolation]; see [exact::extrapolated_types] for the model.
+    /// Requires [MeasureY::Types] and [MeasureX::Tokens].
+    pub extrapolate: Option<u64>,
+
+    /// Do we report the mean accumulation curve of random subcorpora, e.g. for plotting?
+    /// If true, fill in [OResult::mean_curve] with the averages at evenly spaced sizes
+    /// up to the size limit; see [calc_avg::mean_curve].
+    pub mean_curve: bool,
+
+    /// Do we report the full accumulation curve of each subset?
+    /// If true, fill in [OResult::curve] with the averages at evenly spaced sizes
+    /// up to the total size of the subset, so that unlike [DriverArgs::mean_curve],
+    /// this also covers sizes above the size limit; see [calc_avg::average_at_limits].
+    pub full_curve: bool,
+
+    /// If specified, fill in [AvgResult::percentiles] of [OResult::average_at_limit]
+    /// with these quantiles, e.g. `(0.025, 0.975)` for a 95% band of the values
+    /// in the random subcorpora with [Output::limit] many things. The lower quantile is
+    /// of the lower bounds and the upper quantile is of the upper bounds, in the same way
+    /// as for [DriverArgs::extremes].
+    pub percentiles: Option<(f64, f64)>,
 }
 
+/// See [DriverArgs::distinct_orderings].
+pub const ORDERINGS_WARNING_FACTOR: u64 = 10;
+
 struct Curve<'a> {
     category: Category<'a>,
     keys: Vec<SubsetKey<'a>>,
@@ -104,11 +492,30 @@ pub fn get_periods(offset: Year, window: Year, step: Year, years: &Years) -> Vec
 }
 
 fn get_periods_wrapper(args: &DriverArgs, years: &Years) -> Vec<Years> {
-    let periods = get_periods(args.offset, args.window, args.step, years);
+    let periods = if args.no_time {
+        vec![*years]
+    } else {
+        get_periods(args.offset, args.window, args.step, years)
+    };
     info!(target: "types3", "periods: {}", output::pretty_periods(&periods));
     periods
 }
 
+/// Years of the samples that belong to the subset `key`.
+fn sample_years(args: &DriverArgs, years: Years, key: &SubsetKey) -> Years {
+    let window = match args.boundary {
+        Boundary::Overlap => key.period,
+        Boundary::Disjoint if key.period == years => years,
+        Boundary::Disjoint if key.period.1 >= years.1 => (key.period.0, years.1),
+        Boundary::Disjoint => (key.period.0, key.period.0 + args.step),
+    };
+    match args.window_mode {
+        WindowMode::Fixed => window,
+        WindowMode::TrailingRecent { .. } if key.period == years => years,
+        WindowMode::TrailingRecent { years: k } => (window.1.saturating_sub(k), window.1),
+    }
+}
+
 fn build_curve<'a>(category: Category<'a>, periods: &[Years]) -> Curve<'a> {
     let keys = periods
         .iter()
@@ -124,13 +531,178 @@ fn build_curves<'a>(categories: &[Category<'a>], periods: &[Years]) -> Vec<Curve
         .collect_vec()
 }
 
-type TopResults<'a> = HashMap<(SubsetKey<'a>, Point), PointResult>;
+/// Number of iterations for a subset of size `total_x` if the largest subset has size `max_x`.
+///
+/// This is `iter` scaled by `total_x / max_x`, rounded up, and at least 1 unless `iter` is 0.
+pub fn scaled_iter(iter: u64, total_x: u64, max_x: u64) -> u64 {
+    if iter == 0 || max_x == 0 {
+        return iter;
+    }
+    let scaled = (iter as u128 * total_x as u128).div_ceil(max_x as u128) as u64;
+    scaled.clamp(1, iter)
+}
+
+/// Results at each checkpoint; the last one covers all iterations.
+type TopResults<'a> = HashMap<(SubsetKey<'a>, Point), Vec<PointResult>>;
+/// Results at each checkpoint; the last one covers all iterations.
+type RefResults<'a> = HashMap<(SubsetKey<'a>, SubsetKey<'a>), Vec<PointResult>>;
+/// Results of [NullModel::YearLabel], with only one checkpoint.
+type YearResults<'a> = HashMap<SubsetKey<'a>, Vec<PointResult>>;
+/// Subsets to compare with [NullModel::YearLabel], indexed by the parent whose years are reassigned.
+type YearTargets<'a> = HashMap<SubsetKey<'a>, Vec<(SubsetKey<'a>, YearTarget)>>;
+
+fn get_checkpoints(convergence: Option<&[f64]>) -> Result<Vec<f64>> {
+    let mut checkpoints = convergence.unwrap_or_default().to_vec();
+    if checkpoints.iter().any(|f| !(*f > 0.0 && *f <= 1.0)) {
+        return Err(errors::invalid_argument_ref(
+            "convergence points must be in range (0, 1]",
+        ));
+    }
+    checkpoints.sort_by(f64::total_cmp);
+    checkpoints.dedup();
+    if checkpoints.last() != Some(&1.0) {
+        checkpoints.push(1.0);
+    }
+    Ok(checkpoints)
+}
+
+/// Collects warnings for [Output::warnings]; each warning is also logged.
+#[derive(Default)]
+struct Warnings(RefCell<Vec<String>>);
+
+impl Warnings {
+    fn warn(&self, msg: String) {
+        warn!(target: "types3", "{}", msg);
+        self.0.borrow_mut().push(msg);
+    }
+
+    fn into_inner(self) -> Vec<String> {
+        self.0.into_inner()
+    }
+}
 
 /// Calculate everything.
 ///
 /// This is the main entry point for the library.
 pub fn calc(args: &DriverArgs, input: &Input) -> Result<Output> {
-    Calc::new(args, input)?.calc()
+    let ngrams;
+    let input = match args.ngram {
+        1 => input,
+        n => {
+            ngrams = input.ngrams(n)?;
+            &ngrams
+        }
+    };
+    Calc::new(args, input, None)?.calc()
+}
+
+/// Calculate everything, using samples that have already been filtered and converted.
+///
+/// Here `prepared` is the result of [samples::prepare] for `input`, with the same
+/// [DriverArgs::restrict_samples], [DriverArgs::restrict_tokens], and [DriverArgs::mark_tokens]
+/// as in `args`. The result is the same as what [calc] would return, but the same prepared
+/// samples can be used in any number of calculations, e.g. with different measures or categories.
+/// The prepared samples borrow from `input`, which therefore has to outlive them.
+///
+/// Additional token markings and n-grams change the samples themselves,
+/// so [DriverArgs::also_mark_tokens] and [DriverArgs::ngram] cannot be used here.
+pub fn calc_prepared<'a>(
+    args: &'a DriverArgs,
+    input: &'a Input,
+    prepared: &[CSample<'a>],
+) -> Result<Output> {
+    if !args.also_mark_tokens.is_empty() {
+        return Err(errors::invalid_argument_ref(
+            "prepared samples cannot be used with additional token markings",
+        ));
+    }
+    if args.ngram != 1 {
+        return Err(errors::invalid_argument_ref(
+            "prepared samples cannot be used with n-grams",
+        ));
+    }
+    Calc::new(args, input, Some(prepared))?.calc()
+}
+
+/// Calculate everything, reusing the results of a previous calculation where possible.
+///
+/// Here `prev` is the output of [calc] (or [extend]) with the same `args`, and `input` is the
+/// new input, usually the previous input with some new samples added.
+/// The result is the same as what [calc] would return for `input`, but results whose
+/// [OResult::fingerprint] did not change are copied from `prev` instead of recomputing.
+///
+/// We assume that `args` are the same as in the previous calculation, and that
+/// [DriverArgs::fingerprints] is set. Parameters that are recorded in [Output] are checked,
+/// and if they differ, or if the size limit changes, nothing is reused.
+/// Note that adding a sample in one time period affects the comparisons of all time periods
+/// of the same category (see [OResult::vs_time]), so the results that can be reused
+/// are those of categories without new samples.
+/// [Output::toplevel] only contains the comparisons that were recomputed.
+pub fn extend(prev: &Output, input: &Input, args: &DriverArgs) -> Result<Output> {
+    if !args.fingerprints {
+        return Err(errors::invalid_argument_ref(
+            "fingerprints are required for reusing results",
+        ));
+    }
+    let ngrams;
+    let input = match args.ngram {
+        1 => input,
+        n => {
+            ngrams = input.ngrams(n)?;
+            &ngrams
+        }
+    };
+    let mut calc = Calc::new(args, input, None)?;
+    calc.reuse(prev)?;
+    calc.calc()
+}
+
+/// Calculate everything separately for each value of sample metadata key `key`.
+///
+/// Samples are partitioned by the value of `key` before anything else is calculated,
+/// exactly as if [DriverArgs::restrict_samples] was set to each value in turn;
+/// samples without this key are ignored. Hence this cannot be combined with
+/// [DriverArgs::restrict_samples].
+///
+/// Each partition is calculated independently: in particular, each partition has its own
+/// size limit [Output::limit], determined by the smallest subset of that partition.
+/// If the limits differ, the averages of different partitions are not directly comparable;
+/// [Output::limit] of each partition tells whether this is the case.
+pub fn calc_partitions(args: &DriverArgs, input: &Input, key: &str) -> Result<OPartitions> {
+    if args.restrict_samples.is_some() {
+        return Err(errors::invalid_argument_ref(
+            "cannot combine partitioning with a sample restriction",
+        ));
+    }
+    let values = input
+        .samples
+        .iter()
+        .filter_map(|s| s.metadata.get(key))
+        .unique()
+        .sorted()
+        .collect_vec();
+    if values.is_empty() {
+        return Err(errors::invalid_input(format!(
+            "there are no samples with metadata key {}",
+            key
+        )));
+    }
+    let mut partitions = vec![];
+    for value in values {
+        info!(target: "types3", "partition: {} = {}", key, value);
+        let args = DriverArgs {
+            restrict_samples: Some((key, value)),
+            ..*args
+        };
+        partitions.push(OPartition {
+            value: value.to_owned(),
+            output: calc(&args, input)?,
+        });
+    }
+    Ok(OPartitions {
+        key: key.to_owned(),
+        partitions,
+    })
 }
 
 struct Calc<'a> {
@@ -138,39 +710,322 @@ struct Calc<'a> {
     periods: Vec<Years>,
     curves: Vec<Curve<'a>>,
     subset_map: HashMap<SubsetKey<'a>, Subset<'a>>,
+    /// Reference sets for [DriverArgs::leave_out_self], indexed by (subset, parent).
+    references: HashMap<(SubsetKey<'a>, SubsetKey<'a>), Subset<'a>>,
+    year_targets: YearTargets<'a>,
     iter: u64,
     measure_y: MeasureY,
     measure_x: MeasureX,
     restrict_samples: Category<'a>,
     restrict_tokens: Category<'a>,
     mark_tokens: Category<'a>,
+    also_mark_tokens: &'a [(&'a str, &'a str)],
     split_samples: bool,
+    presence_absence: bool,
+    accumulate_by: Option<&'a str>,
+    ngram: Option<usize>,
+    min_sample_tokens: Option<u64>,
+    subsample: Option<f64>,
+    fixed_limit: Option<u64>,
+    skew_threshold: Option<f64>,
+    strict: bool,
+    standard_error: bool,
+    scale_iter: bool,
+    max_x: u64,
+    display_bucket: Option<Year>,
+    region_weight: Option<f64>,
+    dump_toplevel: bool,
+    dump_samples: bool,
+    group_by: Option<&'a str>,
+    explicit: bool,
+    leave_out_self: bool,
+    explain_limit: bool,
+    limit_units: bool,
+    no_significance: bool,
+    convergence: bool,
+    checkpoints: Vec<f64>,
+    seed: u64,
+    repeats: Option<u64>,
+    test_side: Option<TestSide>,
+    null_model: NullModel,
+    cancel: Option<&'a AtomicBool>,
+    cache: Option<&'a Cache>,
+    expected_types: bool,
+    comparison_n: bool,
+    fingerprints: bool,
+    samples_at_limit: bool,
+    marginal: bool,
+    extremes: bool,
+    z_score: bool,
+    distinct_orderings: bool,
+    category_spread: bool,
+    basis: bool,
+    type_target: Option<u64>,
+    observed_ci: bool,
+    extrapolate: Option<u64>,
+    mean_curve: bool,
+    full_curve: bool,
+    percentiles: Option<(f64, f64)>,
+    /// Results reused from a previous calculation; see [extend].
+    reuse: HashMap<SubsetKey<'a>, OResult>,
+    warnings: Warnings,
 }
 
 impl<'a> Calc<'a> {
-    fn new(args: &'a DriverArgs, input: &'a Input) -> Result<Calc<'a>> {
-        information::statistics(&input.samples);
+    fn new(
+        args: &'a DriverArgs,
+        input: &'a Input,
+        prepared: Option<&[CSample<'a>]>,
+    ) -> Result<Calc<'a>> {
+        let time = !args.no_time && args.group_by.is_none();
+        input::validate_for(input, args.measure_x, args.measure_y, time)?;
+        information::statistics(input);
         let restrict_years = (args.start, args.end + 1);
-        let samples = samples::get_samples(
-            &restrict_years,
-            args.restrict_samples,
-            args.restrict_tokens,
-            args.mark_tokens,
-            &input.samples,
-        );
+        // If we group by an attribute, restrict by attribute values instead of years.
+        let input_years = match args.group_by {
+            None => restrict_years,
+            Some(_) => (Year::MIN, Year::MAX),
+        };
+        if args
+            .skew_threshold
+            .is_some_and(|t| !(0.0..=1.0).contains(&t))
+        {
+            return Err(errors::invalid_argument_ref(
+                "skew threshold must be between 0 and 1",
+            ));
+        }
+        if args.strict && args.skew_threshold.is_none() {
+            return Err(errors::invalid_argument_ref(
+                "strict mode needs a skew threshold",
+            ));
+        }
+        if args.null_model == NullModel::YearLabel {
+            if !args.measure_y.is_monotone() {
+                return Err(errors::invalid_argument(format!(
+                    "the year-label null model needs a monotone measure, not {}",
+                    args.measure_y
+                )));
+            }
+            if args.leave_out_self {
+                return Err(errors::invalid_argument_ref(
+                    "the year-label null model cannot be combined with leaving out the subset itself",
+                ));
+            }
+            if args.convergence.is_some() {
+                return Err(errors::invalid_argument_ref(
+                    "the year-label null model cannot be combined with convergence checkpoints",
+                ));
+            }
+            if args.region_weight.is_some() {
+                return Err(errors::invalid_argument_ref(
+                    "the year-label null model cannot be combined with region weights",
+                ));
+            }
+        }
+        if matches!(args.window_mode, WindowMode::TrailingRecent { years } if years <= 0) {
+            return Err(errors::invalid_argument_ref(
+                "number of trailing years must be positive",
+            ));
+        }
+        if args.category_spread && args.category.is_none() {
+            return Err(errors::invalid_argument_ref(
+                "category spread requires a category",
+            ));
+        }
+        if args.max_categories == Some(0) {
+            return Err(errors::invalid_argument_ref(
+                "maximum number of categories must be positive",
+            ));
+        }
+        if args.subsample.is_some_and(|f| !(f > 0.0 && f <= 1.0)) {
+            return Err(errors::invalid_argument_ref(
+                "subsample fraction must be greater than 0 and at most 1",
+            ));
+        }
+        if args.fixed_limit == Some(0) {
+            return Err(errors::invalid_argument_ref("fixed limit must be positive"));
+        }
+        if args.type_target == Some(0) {
+            return Err(errors::invalid_argument_ref("type target must be positive"));
+        }
+        if args.repeats == Some(0) {
+            return Err(errors::invalid_argument_ref(
+                "number of repetitions must be positive",
+            ));
+        }
+        if args.measure_y == (MeasureY::FrequentTypes { per_million: 0 }) {
+            return Err(errors::invalid_argument_ref(
+                "frequency threshold must be positive",
+            ));
+        }
+        if let Some((low, high)) = args.percentiles {
+            if !(0.0 <= low && low <= high && high <= 1.0) {
+                return Err(errors::invalid_argument_ref(
+                    "percentile levels must be in range [0, 1], the lower first",
+                ));
+            }
+        }
+        if args.measure_y == (MeasureY::Legomena { n: 0 }) {
+            return Err(errors::invalid_argument_ref(
+                "number of tokens for legomena must be positive",
+            ));
+        }
+        if args.measure_y == MeasureY::Sources && args.source_key.is_none() {
+            return Err(errors::invalid_argument_ref(
+                "source key is required for counting sources",
+            ));
+        }
+        if args.measure_y == MeasureY::KnownTypes && args.vocabulary.is_none() {
+            return Err(errors::invalid_argument_ref(
+                "vocabulary is required for counting known types",
+            ));
+        }
+        if args.expected_types
+            && (args.measure_y != MeasureY::Types || args.measure_x != MeasureX::Tokens)
+        {
+            return Err(errors::invalid_argument_ref(
+                "expected number of types can only be calculated for types vs. tokens",
+            ));
+        }
+        if let Some(to_x) = args.extrapolate {
+            if args.measure_y != MeasureY::Types || args.measure_x != MeasureX::Tokens {
+                return Err(errors::invalid_argument_ref(
+                    "extrapolation can only be calculated for types vs. tokens",
+                ));
+            }
+            if to_x == 0 {
+                return Err(errors::invalid_argument_ref(
+                    "extrapolation target must be positive",
+                ));
+            }
+        }
+        if !args.also_mark_tokens.is_empty() {
+            if args.measure_y != MeasureY::MarkedTypes {
+                return Err(errors::invalid_argument_ref(
+                    "additional token markings can only be used with marked types",
+                ));
+            }
+            if args.also_mark_tokens.len() >= u64::BITS as usize {
+                return Err(errors::invalid_argument(format!(
+                    "at most {} additional token markings are supported",
+                    u64::BITS - 1
+                )));
+            }
+        }
+        let mode = match (args.split_samples, args.presence_absence) {
+            (false, false) => SampleMode::Whole,
+            (true, false) => SampleMode::Split,
+            (false, true) => SampleMode::PresenceAbsence,
+            (true, true) => {
+                return Err(errors::invalid_argument_ref(
+                    "cannot both split samples and count presence/absence",
+                ))
+            }
+        };
+        if args.accumulate_by.is_some() {
+            if mode == SampleMode::Split {
+                return Err(errors::invalid_argument_ref(
+                    "cannot both split samples and accumulate by a metadata key",
+                ));
+            }
+            if args.null_model == NullModel::YearLabel {
+                return Err(errors::invalid_argument_ref(
+                    "year-label null model cannot be combined with accumulating by a metadata key",
+                ));
+            }
+        }
+        if args.require_mark_key {
+            if args.mark_tokens.is_none() {
+                return Err(errors::invalid_argument_ref(
+                    "requiring the mark key needs a token marking",
+                ));
+            }
+            samples::require_mark_key(
+                &input_years,
+                args.restrict_samples,
+                args.restrict_tokens,
+                args.mark_tokens,
+                &input.samples,
+            )?;
+        }
+        let mut samples = match prepared {
+            None => samples::get_samples(
+                &input_years,
+                args.restrict_samples,
+                args.restrict_tokens,
+                args.mark_tokens,
+                args.also_mark_tokens,
+                &input.samples,
+            ),
+            Some(prepared) => samples::select_years(&input_years, prepared),
+        };
+        if let Some(fraction) = args.subsample {
+            let before = samples.len();
+            let kept = samples::subsample(fraction, args.seed, &mut samples);
+            info!(target: "types3", "subsample: kept {} of {} samples", kept, before);
+        }
+        let warnings = Warnings::default();
+        if let Some(min_tokens) = args.min_sample_tokens {
+            let dropped = samples::drop_short(min_tokens, &mut samples);
+            if dropped > 0 {
+                warnings.warn(format!(
+                    "{} samples with fewer than {} tokens are ignored",
+                    dropped, min_tokens
+                ));
+            }
+        }
+        if let Some(key) = args.group_by {
+            let missing = samples::count_missing_key(key, &samples);
+            if missing > 0 {
+                warnings.warn(format!(
+                    "{} samples without metadata key {} are ignored",
+                    missing, key
+                ));
+            }
+        }
+        let mut samples = match args.group_by {
+            None => samples,
+            Some(key) => samples::group_by(key, &restrict_years, samples)?,
+        };
+        if let Some(key) = args.source_key {
+            samples::set_sources(key, &mut samples);
+        }
         information::post_statistics(&samples);
         if samples.is_empty() {
             return Err(errors::invalid_input_ref("no samples found"));
         }
         let categories = match &args.category {
             None => vec![None],
-            Some(key) => samples::get_categories(key, &samples)?,
+            Some(key) => {
+                let categories = samples::get_categories(key, &samples)?;
+                if let Some(max) = args.max_categories.filter(|&m| categories.len() > m) {
+                    return Err(errors::invalid_input(format!(
+                        "metadata key {} has {} distinct values, more than the maximum number of categories {}",
+                        key,
+                        categories.len(),
+                        max
+                    )));
+                }
+                categories
+            }
         };
-        let years = samples::get_years(&samples);
+        if let Some(key) = args.category {
+            let missing = samples::count_missing_key(key, &samples);
+            if missing > 0 {
+                warnings.warn(format!(
+                    "{} samples without metadata key {} are not in any category",
+                    missing, key
+                ));
+            }
+        }
+        let years = samples::get_years(&samples)
+            .ok_or_else(|| errors::invalid_input_ref("no dated samples found"))?;
         info!(target: "types3", "years in input data: {}", output::pretty_period(&years));
+        info!(target: "types3", "random seed: {}", args.seed);
         let periods = get_periods_wrapper(args, &years);
         let curves = build_curves(&categories, &periods);
         let mut subset_map = HashMap::new();
+        let mut references = HashMap::new();
         for curve in &curves {
             for key in &curve.keys {
                 let subset = subsets::build_subset(
@@ -178,14 +1033,68 @@ impl<'a> Calc<'a> {
                     args.measure_y,
                     &samples,
                     *key,
-                    args.split_samples,
-                );
+                    sample_years(args, years, key),
+                    false,
+                    mode,
+                    args.vocabulary,
+                    args.accumulate_by,
+                )?;
                 if subset.total_x < args.minimum_size {
+                    warnings.warn(format!(
+                        "{}: skipped, size {} {} is below the minimum size {}",
+                        key.pretty(),
+                        subset.total_x,
+                        args.measure_x,
+                        args.minimum_size
+                    ));
+                    continue;
+                }
+                if let Some(limit) = args.fixed_limit.filter(|&l| subset.total_x < l) {
+                    warnings.warn(format!(
+                        "{}: skipped, size {} {} is below the fixed limit {}",
+                        key.pretty(),
+                        subset.total_x,
+                        args.measure_x,
+                        limit
+                    ));
                     continue;
                 }
                 let point = subset.get_point();
                 let parents = subset.get_parents(years);
+                let time_parent = subset.get_parent_period(years);
                 subset_map.insert(*key, subset);
+                if args.leave_out_self {
+                    let own_years = sample_years(args, years, key);
+                    for parent in &parents {
+                        let parent_years = sample_years(args, years, parent);
+                        let all_time = parent.period == years;
+                        let filter = |s: &CSample| {
+                            subsets::contains(parent.category, parent_years, all_time, s)
+                                && !subsets::contains(key.category, own_years, false, s)
+                        };
+                        let mut reference = subsets::build_subset_with(
+                            args.measure_x,
+                            args.measure_y,
+                            &samples,
+                            *parent,
+                            filter,
+                            mode,
+                            args.vocabulary,
+                            args.accumulate_by,
+                        )?;
+                        if reference.total_x < point.x {
+                            warnings.warn(format!(
+                                "{}: not compared with {}, as the rest of it is smaller than the subset itself",
+                                key.pretty(),
+                                parent.pretty()
+                            ));
+                        } else {
+                            reference.points.insert(point);
+                        }
+                        references.insert((*key, *parent), reference);
+                    }
+                    continue;
+                }
                 for parent in &parents {
                     let x = match subset_map.entry(*parent) {
                         Occupied(e) => e.into_mut(),
@@ -194,36 +1103,125 @@ impl<'a> Calc<'a> {
                             args.measure_y,
                             &samples,
                             *parent,
-                            args.split_samples,
-                        )),
+                            sample_years(args, years, parent),
+                            parent.period == years,
+                            mode,
+                            args.vocabulary,
+                            args.accumulate_by,
+                        )?),
                     };
-                    x.points.insert(point);
+                    // With the year-label null model, the comparisons with other time
+                    // periods do not use the points of the parent.
+                    if args.null_model == NullModel::SampleOrder || *parent != time_parent {
+                        x.points.insert(point);
+                    }
                 }
             }
         }
+        let mut year_targets = HashMap::new();
+        if args.null_model == NullModel::YearLabel && !args.no_significance {
+            for curve in &curves {
+                let targets = curve
+                    .keys
+                    .iter()
+                    .filter_map(|key| {
+                        let subset = subset_map.get(key)?;
+                        let target = YearTarget {
+                            period: sample_years(args, years, key),
+                            point: subset.get_point(),
+                        };
+                        Some((*key, target))
+                    })
+                    .collect_vec();
+                if !targets.is_empty() {
+                    let parent = SubsetKey {
+                        category: curve.category,
+                        period: years,
+                    };
+                    year_targets.insert(parent, targets);
+                }
+            }
+        }
+        let max_x = subset_map
+            .values()
+            .chain(references.values())
+            .map(|s| s.total_x)
+            .max()
+            .unwrap_or(0);
         Ok(Calc {
             years,
             periods,
             curves,
             subset_map,
+            references,
+            year_targets,
             iter: args.iter,
             measure_y: args.measure_y,
             measure_x: args.measure_x,
             restrict_samples: args.restrict_samples,
             restrict_tokens: args.restrict_tokens,
             mark_tokens: args.mark_tokens,
+            also_mark_tokens: args.also_mark_tokens,
             split_samples: args.split_samples,
+            presence_absence: args.presence_absence,
+            accumulate_by: args.accumulate_by,
+            ngram: (args.ngram > 1).then_some(args.ngram),
+            min_sample_tokens: args.min_sample_tokens,
+            subsample: args.subsample,
+            fixed_limit: args.fixed_limit,
+            skew_threshold: args.skew_threshold,
+            strict: args.strict,
+            standard_error: args.standard_error,
+            scale_iter: args.scale_iter,
+            max_x,
+            display_bucket: args.display_bucket,
+            region_weight: args.region_weight,
+            dump_toplevel: args.dump_toplevel,
+            dump_samples: args.dump_samples,
+            group_by: args.group_by,
+            explicit: args.explicit,
+            leave_out_self: args.leave_out_self,
+            explain_limit: args.explain_limit,
+            limit_units: args.limit_units,
+            no_significance: args.no_significance,
+            convergence: args.convergence.is_some(),
+            checkpoints: get_checkpoints(args.convergence)?,
+            seed: args.seed,
+            repeats: args.repeats,
+            test_side: args.test_side.filter(|_| !args.no_significance),
+            null_model: args.null_model,
+            cancel: args.cancel,
+            cache: args.cache,
+            expected_types: args.expected_types,
+            comparison_n: args.comparison_n && !args.no_significance,
+            fingerprints: args.fingerprints,
+            samples_at_limit: args.samples_at_limit,
+            marginal: args.marginal,
+            extremes: args.extremes,
+            z_score: args.z_score,
+            distinct_orderings: args.distinct_orderings,
+            category_spread: args.category_spread,
+            basis: args.basis,
+            type_target: args.type_target,
+            observed_ci: args.observed_ci,
+            extrapolate: args.extrapolate,
+            mean_curve: args.mean_curve,
+            full_curve: args.full_curve,
+            percentiles: args.percentiles,
+            reuse: HashMap::new(),
+            warnings,
         })
     }
 
-    fn size_limit(&self) -> Result<u64> {
-        let mut limit: Option<u64> = None;
+    /// The smallest subset in the curves; its size is the size limit.
+    fn size_limit(&self) -> Result<&Subset<'a>> {
+        let mut limit: Option<&Subset> = None;
         for c in &self.curves {
             for key in &c.keys {
                 if let Some(s) = self.subset_map.get(key) {
                     limit = match limit {
-                        Some(x) => Some(x.min(s.total_x)),
-                        None => Some(s.total_x),
+                        Some(x) if x.total_x <= s.total_x => Some(x),
+                        _ => Some(s),
                     }
                 }
             }
@@ -234,18 +1232,258 @@ impl<'a> Calc<'a> {
         }
     }
 
+    /// The size limit, together with the subset that determined it.
+    ///
+    /// If [DriverArgs::fixed_limit] is set, this is the fixed limit, and there is no such subset.
+    fn limit(&self) -> Result<(u64, Option<&Subset<'a>>)> {
+        let limiting = self.size_limit()?;
+        Ok(match self.fixed_limit {
+            Some(limit) => (limit, None),
+            None => (limiting.total_x, Some(limiting)),
+        })
+    }
+
+    /// Warn about a size limit that is much smaller than the typical subset size.
+    ///
+    /// See [DriverArgs::skew_threshold]; with [DriverArgs::strict] this is an error.
+    fn check_skew(&self, limiting: &Subset) -> Result<()> {
+        let Some(threshold) = self.skew_threshold else {
+            return Ok(());
+        };
+        let mut sizes = self
+            .curves
+            .iter()
+            .flat_map(|c| c.keys.iter())
+            .filter_map(|key| self.subset_map.get(key))
+            .map(|s| s.total_x)
+            .collect_vec();
+        sizes.sort();
+        let median = sizes[sizes.len() / 2];
+        if limiting.total_x as f64 >= threshold * median as f64 {
+            return Ok(());
+        }
+        let msg = format!(
+            "size limit {} {} is below {}% of the median subset size {} {}; \
+            it is determined by {}, consider increasing the minimum size or leaving out this subset",
+            limiting.total_x,
+            self.measure_x,
+            threshold * 100.0,
+            median,
+            self.measure_x,
+            limiting.pretty()
+        );
+        if self.strict {
+            return Err(errors::invalid_input(msg));
+        }
+        self.warnings.warn(msg);
+        Ok(())
+    }
+
+    /// Null model as recorded in [Output::null_model].
+    fn null_model_output(&self) -> Option<NullModel> {
+        Some(self.null_model).filter(|&m| m != NullModel::SampleOrder)
+    }
+
+    /// Can we reuse results from `prev` at all?
+    fn compatible(&self, prev: &Output, limit: u64) -> bool {
+        prev.limit == limit
+            && prev.iter == self.iter
+            && prev.measure_y == self.measure_y
+            && prev.measure_x == self.measure_x
+            && prev.split_samples == self.split_samples
+            && prev.presence_absence == self.presence_absence
+            && prev.accumulate_by.as_deref() == self.accumulate_by
+            && prev.ngram == self.ngram
+            && prev.min_sample_tokens == self.min_sample_tokens
+            && prev.subsample == self.subsample
+            && prev.years == self.years
+            && prev.periods == self.periods
+            && prev.group_by.as_deref() == self.group_by
+            && prev.restrict_samples == categories::owned_cat(self.restrict_samples)
+            && prev.restrict_tokens == categories::owned_cat(self.restrict_tokens)
+            && prev.mark_tokens == categories::owned_cat(self.mark_tokens)
+            && prev.also_mark_tokens == categories::owned_pairs(self.also_mark_tokens)
+            && prev.test_side == self.test_side
+            && prev.null_model == self.null_model_output()
+    }
+
+    /// Take results with unchanged fingerprints from `prev`,
+    /// and drop the comparisons that are only needed for them.
+    fn reuse(&mut self, prev: &Output) -> Result<()> {
+        let (limit, _) = self.limit()?;
+        if !self.compatible(prev, limit) {
+            info!(target: "types3", "previous results cannot be reused");
+            return Ok(());
+        }
+        let prev_results: HashMap<_, _> = prev
+            .curves
+            .iter()
+            .flat_map(|c| c.results.iter().map(|r| ((&c.category, r.period), r)))
+            .collect();
+        let mut total = 0;
+        for curve in &self.curves {
+            for key in &curve.keys {
+                let Some(subset) = self.subset_map.get(key) else {
+                    continue;
+                };
+                total += 1;
+                let category = categories::owned_cat(key.category);
+                if let Some(r) = prev_results.get(&(&category, key.period)) {
+                    if r.fingerprint.as_ref() == Some(&self.fingerprint(subset)) {
+                        self.reuse.insert(*key, (*r).clone());
+                    }
+                }
+            }
+        }
+        info!(target: "types3", "reused {} of {} results", self.reuse.len(), total);
+        let mut needed = HashSet::new();
+        for curve in &self.curves {
+            for key in &curve.keys {
+                if self.reuse.contains_key(key) {
+                    continue;
+                }
+                if let Some(subset) = self.subset_map.get(key) {
+                    for parent in subset.get_parents(self.years) {
+                        needed.insert((parent, subset.get_point()));
+                    }
+                }
+            }
+        }
+        for (key, subset) in self.subset_map.iter_mut() {
+            subset.points.retain(|p| needed.contains(&(*key, *p)));
+        }
+        let reuse = &self.reuse;
+        self.references
+            .retain(|(key, _), _| !reuse.contains_key(key));
+        Ok(())
+    }
+
+    /// The subset that `subset` is compared with, when the parent is `parent`.
+    fn reference(&self, subset: &Subset<'a>, parent: SubsetKey<'a>) -> &Subset<'a> {
+        if self.leave_out_self {
+            let key = SubsetKey {
+                category: subset.category,
+                period: subset.period,
+            };
+            &self.references[&(key, parent)]
+        } else {
+            &self.subset_map[&parent]
+        }
+    }
+
+    /// See [OResult::comparison_n].
+    fn comparison_n(&self, subset: &Subset<'a>) -> OComparisonN {
+        let n = |parent| self.reference(subset, parent).samples.len() as u64;
+        OComparisonN {
+            vs_time: n(subset.get_parent_period(self.years)),
+            vs_categories: subset.category.map(|_| n(subset.get_parent_category())),
+        }
+    }
+
+    /// See [OResult::fingerprint].
+    fn fingerprint(&self, subset: &Subset<'a>) -> String {
+        let mut values = vec![
+            calculation::hash_samples(&subset.samples),
+            self.subset_iter(subset),
+        ];
+        if !self.no_significance {
+            for parent in subset.get_parents(self.years) {
+                let reference = self.reference(subset, parent);
+                values.push(calculation::hash_samples(&reference.samples));
+                values.push(self.subset_iter(reference));
+            }
+        }
+        format!("{:016x}", calculation::hash_values(&values))
+    }
+
     fn calc(self) -> Result<Output> {
+        self.check_cancelled()?;
         let mut top_results = HashMap::new();
-        for subset in self.subset_map.values() {
-            self.calc_top(subset, &mut top_results);
+        let mut ref_results = HashMap::new();
+        let mut year_results = HashMap::new();
+        if !self.no_significance {
+            for subset in self.subset_map.values() {
+                self.calc_top(subset, &mut top_results)?;
+            }
+            for (key, reference) in &self.references {
+                self.calc_reference(*key, reference, &mut ref_results)?;
+            }
+            for (parent, targets) in &self.year_targets {
+                self.calc_year_labels(*parent, targets, &mut year_results)?;
+            }
         }
-        let limit = self.size_limit()?;
+        let null_model = self.null_model_output();
+        let (limit, limiting) = self.limit()?;
         debug!(target: "types3", "size limit: {} {}", limit, self.measure_x);
+        match limiting {
+            Some(limiting) => {
+                info!(target: "types3", "size limit determined by: {}", limiting.pretty());
+                self.check_skew(limiting)?;
+            }
+            None => info!(target: "types3", "size limit is fixed"),
+        }
+        let limit_subset = limiting
+            .filter(|_| self.explain_limit)
+            .map(|limiting| OLimit {
+                category: categories::owned_cat(limiting.category),
+                period: limiting.period,
+                size: limit,
+            });
+        let limit_units = limiting
+            .filter(|_| self.limit_units)
+            .map(|limiting| OLimitUnits {
+                words: limiting.total_words,
+                tokens: counter::count_xy(MeasureY::Tokens, &limiting.samples).1,
+                types: counter::count_xy(MeasureY::Types, &limiting.samples).1,
+            });
         let curves = self
             .curves
             .iter()
-            .map(|c| self.calc_curve(c, limit, &top_results))
-            .collect_vec();
+            .map(|c| self.calc_curve(c, limit, &top_results, &ref_results, &year_results))
+            .collect::<Result<Vec<_>>>()?;
+        let toplevel = if self.dump_toplevel {
+            Some(
+                top_results
+                    .iter()
+                    .sorted_by_key(|((k, p), _)| (*k, *p))
+                    .map(|((k, p), pr)| OTopResult {
+                        category: categories::owned_cat(k.category),
+                        period: k.period,
+                        x: p.x,
+                        y: p.y,
+                        result: *pr.last().unwrap(),
+                    })
+                    .collect_vec(),
+            )
+        } else {
+            None
+        };
+        let category_spread = self
+            .category_spread
+            .then(|| output::category_spread(&curves, &self.periods));
+        let basis = self.basis.then_some(OBasis {
+            measure_y: self.measure_y,
+            measure_x: self.measure_x,
+            limit,
+            split_samples: self.split_samples,
+        });
+        let samples = if self.dump_samples {
+            Some(
+                self.subset_map
+                    .into_values()
+                    .sorted_by_key(|s| (s.category, s.period))
+                    .map(|s| OSamples {
+                        category: categories::owned_cat(s.category),
+                        period: s.period,
+                        total_x: s.total_x,
+                        total_y: s.total_y,
+                        samples: s.samples,
+                    })
+                    .collect_vec(),
+            )
+        } else {
+            None
+        };
         Ok(Output {
             curves,
             years: self.years,
@@ -257,80 +1495,536 @@ impl<'a> Calc<'a> {
             restrict_tokens: categories::owned_cat(self.restrict_tokens),
             restrict_samples: categories::owned_cat(self.restrict_samples),
             mark_tokens: categories::owned_cat(self.mark_tokens),
+            also_mark_tokens: categories::owned_pairs(self.also_mark_tokens),
             split_samples: self.split_samples,
+            presence_absence: self.presence_absence,
+            accumulate_by: self.accumulate_by.map(|k| k.to_owned()),
+            ngram: self.ngram,
+            min_sample_tokens: self.min_sample_tokens,
+            subsample: self.subsample,
+            toplevel,
+            group_by: self.group_by.map(|k| k.to_owned()),
+            limit_subset,
+            limit_units,
+            samples,
+            test_side: self.test_side,
+            null_model,
+            category_spread,
+            basis,
+            warnings: self.warnings.into_inner(),
         })
     }
 
-    fn calc_top(&self, subset: &'a Subset, top_results: &mut TopResults<'a>) {
-        if subset.points.is_empty() {
-            return;
+    fn subset_iter(&self, subset: &Subset) -> u64 {
+        if self.scale_iter {
+            scaled_iter(self.iter, subset.total_x, self.max_x)
+        } else {
+            self.iter
         }
+    }
+
+    fn calc_top(&self, subset: &'a Subset, top_results: &mut TopResults<'a>) -> Result<()> {
         let mut points = subset.points.iter().copied().collect_vec();
         let key = subset.key();
         points.sort();
-        let results =
-            calc_point::compare_with_points(self.measure_y, &subset.samples, self.iter, &points);
-        for (i, p) in points.into_iter().enumerate() {
-            top_results.insert((key, p), results[i]);
+        let results = self.compare_with_points(subset, &points);
+        self.check_cancelled()?;
+        for (i, p) in points.iter().enumerate() {
+            top_results.insert((key, *p), results.iter().map(|r| r[i]).collect_vec());
         }
-        debug!(target: "types3", "{}: calculated {} points", subset.pretty(), results.len());
+        debug!(target: "types3", "{}: calculated {} points", subset.pretty(), points.len());
+        Ok(())
     }
 
-    fn calc_curve(&self, curve: &Curve, limit: u64, top_results: &TopResults) -> OCurve {
-        OCurve {
-            category: categories::owned_cat(curve.category),
-            results: curve
-                .keys
-                .iter()
-                .filter_map(|k| {
-                    self.subset_map
-                        .get(k)
-                        .as_ref()
-                        .map(|s| self.calc_relevant(s, limit, top_results))
-                })
-                .collect_vec(),
+    fn calc_reference(
+        &self,
+        key: (SubsetKey<'a>, SubsetKey<'a>),
+        reference: &Subset,
+        ref_results: &mut RefResults<'a>,
+    ) -> Result<()> {
+        let points = reference.points.iter().copied().collect_vec();
+        if points.is_empty() {
+            return Ok(());
+        }
+        let results = self.compare_with_points(reference, &points);
+        self.check_cancelled()?;
+        ref_results.insert(key, results.iter().map(|r| r[0]).collect_vec());
+        debug!(target: "types3", "{} without {}: calculated", key.1.pretty(), key.0.pretty());
+        Ok(())
+    }
+
+    fn calc_year_labels(
+        &self,
+        parent: SubsetKey<'a>,
+        targets: &[(SubsetKey<'a>, YearTarget)],
+        year_results: &mut YearResults<'a>,
+    ) -> Result<()> {
+        let subset = &self.subset_map[&parent];
+        let permutations = Permutations::Random {
+            iter: self.subset_iter(subset),
+            seed: self.seed,
+        };
+        let results = calc_year::compare_with_year_labels(
+            self.measure_y,
+            &subset.samples,
+            &subset.sample_years,
+            &targets.iter().map(|(_, t)| *t).collect_vec(),
+            permutations,
+            self.cancel,
+        );
+        self.check_cancelled()?;
+        for ((key, _), pr) in targets.iter().zip(results) {
+            year_results.insert(*key, vec![pr]);
+        }
+        debug!(target: "types3", "{}: calculated {} year-label comparisons", parent.pretty(), targets.len());
+        Ok(())
+    }
+
+    /// See [calc_point::compare_with_points_checkpoints]; results are cached in [DriverArgs::cache].
+    fn compare_with_points(&self, subset: &Subset, points: &[Point]) -> Vec<Vec<PointResult>> {
+        let iter = self.subset_iter(subset);
+        let compute = || {
+            calc_point::compare_with_points_checkpoints(
+                self.measure_y,
+                &subset.samples,
+                iter,
+                points,
+                &self.checkpoints,
+                self.z_score,
+                self.seed,
+                self.cancel,
+            )
+        };
+        match self.cache {
+            None => compute(),
+            Some(cache) => {
+                let key = PointKey::new(
+                    self.measure_y,
+                    &subset.samples,
+                    iter,
+                    points,
+                    &self.checkpoints,
+                    self.z_score,
+                    self.seed,
+                );
+                cache.points(key, self.cancel, compute)
+            }
+        }
+    }
+
+    /// See [calc_avg::average_at_limit_checkpoints]; results are cached in [DriverArgs::cache].
+    /// If `convergence` is false, there is only one checkpoint, 1.
+    fn average_at_limit(
+        &self,
+        subset: &Subset,
+        limit: u64,
+        seed: u64,
+        convergence: bool,
+    ) -> Vec<AvgResult> {
+        let iter = self.subset_iter(subset);
+        let checkpoints: &[f64] = if convergence {
+            &self.checkpoints
+        } else {
+            &[1.0]
+        };
+        let extras = AvgExtras {
+            samples: self.samples_at_limit,
+            marginal: self.marginal,
+            extremes: self.extremes,
+        };
+        let compute = || {
+            calc_avg::average_at_limit_checkpoints(
+                self.measure_y,
+                &subset.samples,
+                iter,
+                limit,
+                checkpoints,
+                self.percentiles,
+                extras,
+                seed,
+                self.cancel,
+            )
+        };
+        match self.cache {
+            None => compute(),
+            Some(cache) => {
+                let key = AvgKey::new(
+                    self.measure_y,
+                    &subset.samples,
+                    iter,
+                    limit,
+                    checkpoints,
+                    self.percentiles,
+                    extras,
+                    seed,
+                );
+                cache.averages(key, self.cancel, compute)
+            }
+        }
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if parallelism::is_cancelled(self.cancel) {
+            info!(target: "types3", "cancelled");
+            return Err(errors::cancelled());
+        }
+        Ok(())
+    }
+
+    /// Results of comparing `subset` with `parent`, at each checkpoint.
+    /// This is `None` if the rest of `parent` was too small for [DriverArgs::leave_out_self].
+    fn parent_results<'b, 'c>(
+        &self,
+        subset: &Subset<'c>,
+        parent: SubsetKey<'c>,
+        top_results: &'b TopResults<'c>,
+        ref_results: &'b RefResults<'c>,
+    ) -> Option<&'b [PointResult]> {
+        if self.leave_out_self {
+            let key = SubsetKey {
+                category: subset.category,
+                period: subset.period,
+            };
+            ref_results.get(&(key, parent)).map(|prs| prs.as_slice())
+        } else {
+            Some(&top_results[&(parent, subset.get_point())])
         }
     }
 
-    fn calc_relevant(&self, subset: &Subset, limit: u64, top_results: &TopResults) -> OResult {
+    fn calc_curve(
+        &self,
+        curve: &Curve,
+        limit: u64,
+        top_results: &TopResults,
+        ref_results: &RefResults,
+        year_results: &YearResults,
+    ) -> Result<OCurve> {
+        let subsets = curve
+            .keys
+            .iter()
+            .filter_map(|k| self.subset_map.get(k))
+            .collect_vec();
+        let results = subsets
+            .iter()
+            .map(|s| self.calc_relevant(s, limit, top_results, ref_results, year_results))
+            .collect::<Result<Vec<_>>>()?;
+        let buckets = self
+            .display_bucket
+            .map(|width| output::bucket_results(&results, width));
+        Ok(OCurve {
+            category: categories::owned_cat(curve.category),
+            results,
+            buckets,
+        })
+    }
+
+    fn calc_relevant(
+        &self,
+        subset: &Subset,
+        limit: u64,
+        top_results: &TopResults,
+        ref_results: &RefResults,
+        year_results: &YearResults,
+    ) -> Result<OResult> {
+        if let Some(r) = self.reuse.get(&subset.key()) {
+            debug!(target: "types3", "{}: reused", subset.pretty());
+            return Ok(r.clone());
+        }
         let mut msg = format!("{}: ", subset.pretty());
-        let average_at_limit =
-            calc_avg::average_at_limit(self.measure_y, &subset.samples, self.iter, limit);
+        let averages = self.average_at_limit(subset, limit, self.seed, self.convergence);
+        self.check_cancelled()?;
+        let average_at_limit = *averages.last().unwrap();
+        let repeats = match self.repeats {
+            None => None,
+            Some(repeats) => Some(self.calc_repeats(subset, limit, repeats, &average_at_limit)?),
+        };
+        let expected_types = self
+            .expected_types
+            .then(|| exact::expected_types_at_limit(&subset.samples, limit));
+        let extrapolation = self
+            .extrapolate
+            .map(|to_x| exact::extrapolated_types(&subset.samples, to_x));
+        let distinct_orderings = self.distinct_orderings.then(|| {
+            let iter = average_at_limit.iter;
+            let n = subset.samples.len();
+            let orderings = shuffle::distinct_orderings(n, iter);
+            if iter / ORDERINGS_WARNING_FACTOR >= orderings {
+                self.warnings.warn(format!(
+                    "{}: only {} distinct orderings of {} samples, but {} iterations",
+                    subset.pretty(),
+                    orderings,
+                    n,
+                    iter
+                ));
+            }
+            orderings
+        });
+        let x_at_target = match self.type_target {
+            None => None,
+            Some(target) => {
+                let r = calc_avg::x_at_target(
+                    self.measure_y,
+                    &subset.samples,
+                    self.subset_iter(subset),
+                    target,
+                    self.seed,
+                    self.cancel,
+                );
+                self.check_cancelled()?;
+                Some(r)
+            }
+        };
+        let mean_curve = if self.mean_curve {
+            let r = calc_avg::mean_curve(
+                self.measure_y,
+                &subset.samples,
+                self.subset_iter(subset),
+                limit,
+                self.seed,
+                self.cancel,
+            );
+            self.check_cancelled()?;
+            Some(r)
+        } else {
+            None
+        };
+        let curve = if self.full_curve {
+            let xs = output::size_grid(subset.total_x, FULL_CURVE_STEPS);
+            let r = calc_avg::average_at_limits(
+                self.measure_y,
+                &subset.samples,
+                self.subset_iter(subset),
+                &xs,
+                self.seed,
+                self.cancel,
+            );
+            self.check_cancelled()?;
+            Some(xs.into_iter().zip(r).collect_vec())
+        } else {
+            None
+        };
+        let observed_ci = if self.observed_ci {
+            let r = calc_boot::observed_ci(
+                self.measure_y,
+                &subset.samples,
+                self.subset_iter(subset),
+                self.seed,
+                self.cancel,
+            );
+            self.check_cancelled()?;
+            Some(r)
+        } else {
+            None
+        };
+        let marked_types = if self.also_mark_tokens.is_empty() {
+            None
+        } else {
+            let marked_types = calc_avg::marked_types_at_limit(
+                &subset.samples,
+                self.also_mark_tokens.len(),
+                self.subset_iter(subset),
+                limit,
+                self.seed,
+                self.cancel,
+            );
+            self.check_cancelled()?;
+            Some(marked_types)
+        };
         msg.push_str(&format!(
             "{} {} / {} {}",
-            output::avg_string(&average_at_limit),
+            output::avg_string_for(self.measure_y, &average_at_limit),
             self.measure_y,
             limit,
             self.measure_x
         ));
-        let p = subset.get_point();
-        let vs_time = {
-            let k = subset.get_parent_period(self.years);
-            let pr = top_results[&(k, p)];
-            msg.push_str(&format!(
-                ", {} vs. other time points",
-                output::point_string(&pr)
-            ));
-            pr
+        let vs_time_all = if self.no_significance {
+            None
+        } else {
+            let prs = match self.null_model {
+                NullModel::SampleOrder => {
+                    let k = subset.get_parent_period(self.years);
+                    self.parent_results(subset, k, top_results, ref_results)
+                }
+                NullModel::YearLabel => Some(year_results[&subset.key()].as_slice()),
+            };
+            if let Some(prs) = prs {
+                msg.push_str(&format!(
+                    ", {} vs. other time points",
+                    output::point_string(prs.last().unwrap())
+                ));
+            }
+            prs
         };
-        let vs_categories = match subset.category {
+        let vs_categories_all = match subset.category {
             None => None,
+            Some(_) if self.no_significance => None,
             Some(_) => {
                 let k = subset.get_parent_category();
-                let pr = top_results[&(k, p)];
-                msg.push_str(&format!(
-                    ", {} vs. other categories",
-                    output::point_string(&pr)
-                ));
-                Some(pr)
+                let prs = self.parent_results(subset, k, top_results, ref_results);
+                if let Some(prs) = prs {
+                    msg.push_str(&format!(
+                        ", {} vs. other categories",
+                        output::point_string(prs.last().unwrap())
+                    ));
+                }
+                prs
             }
         };
+        let observed_y = subset.get_point().y;
+        let vs_time = vs_time_all.map(|prs| *prs.last().unwrap());
+        let vs_categories = vs_categories_all.map(|prs| *prs.last().unwrap());
         debug!(target: "types3", "{msg}");
-        OResult {
+        let weighted_vs_time = match (self.region_weight, vs_time) {
+            (Some(exponent), Some(pr)) => {
+                let r = self.calc_weighted(subset, exponent, pr);
+                self.check_cancelled()?;
+                Some(r)
+            }
+            _ => None,
+        };
+        let (vs_time_se, vs_categories_se) = if self.standard_error {
+            if let Some(pr) = &vs_time {
+                self.check_standard_error(subset, "other time points", pr);
+            }
+            if let Some(pr) = &vs_categories {
+                self.check_standard_error(subset, "other categories", pr);
+            }
+            (
+                vs_time.map(|pr| pr.standard_error()),
+                vs_categories.map(|pr| pr.standard_error()),
+            )
+        } else {
+            (None, None)
+        };
+        let convergence = self.convergence.then(|| {
+            self.checkpoints
+                .iter()
+                .enumerate()
+                .map(|(i, &fraction)| OCheckpoint {
+                    fraction,
+                    average_at_limit: averages[i],
+                    vs_time: vs_time_all.map(|prs| prs[i]),
+                    vs_categories: vs_categories_all.map(|prs| prs[i]),
+                })
+                .collect_vec()
+        });
+        Ok(OResult {
             period: subset.period,
             average_at_limit,
             vs_time,
             vs_categories,
+            vs_time_se,
+            vs_categories_se,
+            vs_time_p: self
+                .test_side
+                .and_then(|side| vs_time.map(|pr| pr.p_value(side))),
+            vs_categories_p: self
+                .test_side
+                .and_then(|side| vs_categories.map(|pr| pr.p_value(side))),
+            vs_time_z: vs_time.and_then(|pr| pr.z_score(observed_y)),
+            vs_categories_z: vs_categories.and_then(|pr| pr.z_score(observed_y)),
+            size: self.display_bucket.map(|_| subset.total_x),
+            vs_time_explicit: vs_time.filter(|_| self.explicit).map(|pr| pr.explicit()),
+            vs_categories_explicit: vs_categories
+                .filter(|_| self.explicit)
+                .map(|pr| pr.explicit()),
+            weighted_vs_time,
+            convergence,
+            repeats,
+            expected_types,
+            comparison_n: self.comparison_n.then(|| self.comparison_n(subset)),
+            avg_samples_at_limit: average_at_limit
+                .samples
+                .map(|s| s as f64 / average_at_limit.iter as f64),
+            avg_marginal: average_at_limit
+                .marginal
+                .map(|m| m as f64 / average_at_limit.iter as f64),
+            marked_types,
+            distinct_orderings,
+            x_at_target,
+            observed_ci,
+            extrapolation,
+            mean_curve,
+            curve,
+            fingerprint: self.fingerprints.then(|| self.fingerprint(subset)),
+        })
+    }
+
+    /// Compare `subset` with other time periods at several sizes; see [DriverArgs::region_weight].
+    /// Here `at_total` is the result at the size of the whole subset, i.e., [OResult::vs_time].
+    fn calc_weighted(
+        &self,
+        subset: &Subset<'a>,
+        exponent: f64,
+        at_total: PointResult,
+    ) -> OWeightedComparison {
+        let xs = output::size_grid(subset.total_x, REGION_WEIGHT_STEPS)
+            .into_iter()
+            .filter(|&x| 0 < x && x < subset.total_x)
+            .collect_vec();
+        let averages = calc_avg::average_at_limits(
+            self.measure_y,
+            &subset.samples,
+            self.subset_iter(subset),
+            &xs,
+            self.seed,
+            self.cancel,
+        );
+        let points = xs
+            .iter()
+            .zip(&averages)
+            .filter(|(_, ar)| ar.iter > 0)
+            .map(|(&x, ar)| Point {
+                x,
+                y: (ar.low + ar.high + ar.iter) / (2 * ar.iter),
+            })
+            .collect_vec();
+        let reference = self.reference(subset, subset.get_parent_period(self.years));
+        let mut weighted = vec![(subset.total_x, at_total)];
+        if !points.is_empty() {
+            let results = self.compare_with_points(reference, &points);
+            let results = results.last().unwrap();
+            weighted.extend(points.iter().map(|p| p.x).zip(results.iter().copied()));
+        }
+        output::weighted_comparison(&weighted, exponent)
+    }
+
+    /// Repeat the calculation of [OResult::average_at_limit] with different seeds.
+    /// The first repetition uses [DriverArgs::seed], and its result is `first`.
+    fn calc_repeats(
+        &self,
+        subset: &Subset,
+        limit: u64,
+        repeats: u64,
+        first: &AvgResult,
+    ) -> Result<ORepeats> {
+        let averages = (0..repeats)
+            .map(|i| {
+                let ar = if i == 0 {
+                    *first
+                } else {
+                    self.average_at_limit(subset, limit, self.seed.wrapping_add(i), false)
+                        .pop()
+                        .unwrap()
+                };
+                self.check_cancelled()?;
+                Ok((ar.low + ar.high) as f64 / (2 * ar.iter) as f64)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        debug!(target: "types3", "{}: {} repetitions", subset.pretty(), repeats);
+        Ok(output::repeat_bands(&averages))
+    }
+
+    fn check_standard_error(&self, subset: &Subset, what: &str, pr: &PointResult) {
+        let p = pr.tail_fraction();
+        let se = pr.standard_error();
+        for alpha in output::SIGNIFICANCE_LEVELS {
+            if (p - alpha).abs() < 2.0 * se {
+                self.warnings.warn(format!(
+                    "{}: vs. {}: p = {:.5} ± {:.5} is too close to significance level {} to classify reliably, try more iterations",
+                    subset.pretty(), what, p, se, alpha
+                ));
+            }
         }
     }
 }
@@ -342,6 +2036,8 @@ mod test {
     fn build_args<'a>(window: Year, step: Year, offset: Year) -> DriverArgs<'a> {
         DriverArgs {
             category: None,
+            max_categories: None,
+            group_by: None,
             measure_y: MeasureY::Types,
             measure_x: MeasureX::Tokens,
             iter: 0,
@@ -350,14 +2046,99 @@ mod test {
             end: 9999,
             window,
             step,
+            no_time: false,
             restrict_samples: None,
             restrict_tokens: None,
             mark_tokens: None,
+            also_mark_tokens: &[],
             split_samples: false,
+            presence_absence: false,
+            accumulate_by: None,
+            ngram: 1,
             minimum_size: 1,
+            fixed_limit: None,
+            min_sample_tokens: None,
+            subsample: None,
+            skew_threshold: None,
+            strict: false,
+            standard_error: false,
+            scale_iter: false,
+            display_bucket: None,
+            region_weight: None,
+            dump_toplevel: false,
+            dump_samples: false,
+            boundary: Boundary::Overlap,
+            window_mode: WindowMode::Fixed,
+            leave_out_self: false,
+            explain_limit: false,
+            limit_units: false,
+            explicit: false,
+            no_significance: false,
+            convergence: None,
+            source_key: None,
+            vocabulary: None,
+            seed: 0,
+            repeats: None,
+            require_mark_key: false,
+            test_side: None,
+            null_model: NullModel::SampleOrder,
+            cancel: None,
+            cache: None,
+            expected_types: false,
+            comparison_n: false,
+            fingerprints: false,
+            samples_at_limit: false,
+            marginal: false,
+            extremes: false,
+            z_score: false,
+            distinct_orderings: false,
+            category_spread: false,
+            basis: false,
+            type_target: None,
+            observed_ci: false,
+            extrapolate: None,
+            mean_curve: false,
+            full_curve: false,
+            percentiles: None,
         }
     }
 
+    #[test]
+    fn scaled_iter_basic() {
+        assert_eq!(scaled_iter(1000, 100, 100), 1000);
+        assert_eq!(scaled_iter(1000, 50, 100), 500);
+        assert_eq!(scaled_iter(1000, 1, 3), 334);
+        assert_eq!(scaled_iter(1000, 0, 100), 1);
+        assert_eq!(scaled_iter(1000, 0, 0), 1000);
+        assert_eq!(scaled_iter(0, 50, 100), 0);
+        assert_eq!(scaled_iter(0, 0, 100), 0);
+        assert!(scaled_iter(1000, 20, 100) < scaled_iter(1000, 30, 100));
+    }
+
+    #[test]
+    fn sample_years_window_mode() {
+        let years = (1905, 1975);
+        let key = |period| SubsetKey {
+            category: None,
+            period,
+        };
+        let mut args = build_args(20, 10, 0);
+        assert_eq!(sample_years(&args, years, &key((1920, 1940))), (1920, 1940));
+        assert_eq!(sample_years(&args, years, &key(years)), years);
+        args.window_mode = WindowMode::TrailingRecent { years: 5 };
+        assert_eq!(sample_years(&args, years, &key((1920, 1940))), (1935, 1940));
+        assert_eq!(sample_years(&args, years, &key(years)), years);
+        args.window_mode = WindowMode::TrailingRecent { years: 30 };
+        assert_eq!(sample_years(&args, years, &key((1920, 1940))), (1910, 1940));
+        // With disjoint periods, the window ends at the start of the next period,
+        // and the last period extends to the end of the data.
+        args.boundary = Boundary::Disjoint;
+        assert_eq!(sample_years(&args, years, &key((1920, 1940))), (1900, 1930));
+        assert_eq!(sample_years(&args, years, &key((1960, 1980))), (1945, 1975));
+        args.window_mode = WindowMode::Fixed;
+        assert_eq!(sample_years(&args, years, &key((1920, 1940))), (1920, 1930));
+    }
+
     #[test]
     fn get_periods_10_10() {
         let args = build_args(10, 10, 0);
@@ -485,4 +2266,17 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn checkpoints() {
+        assert_eq!(get_checkpoints(None).unwrap(), [1.0]);
+        assert_eq!(
+            get_checkpoints(Some(&[0.5, 0.1, 0.5])).unwrap(),
+            [0.1, 0.5, 1.0]
+        );
+        assert_eq!(get_checkpoints(Some(&[1.0, 0.25])).unwrap(), [0.25, 1.0]);
+        assert!(get_checkpoints(Some(&[0.0])).is_err());
+        assert!(get_checkpoints(Some(&[1.5])).is_err());
+        assert!(get_checkpoints(Some(&[f64::NAN])).is_err());
+    }
 }
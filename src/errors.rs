@@ -13,6 +13,10 @@ pub struct InvalidInput(pub String);
 #[derive(Debug)]
 pub struct InvalidArgument(pub String);
 
+/// The calculation was cancelled.
+#[derive(Debug)]
+pub struct Cancelled;
+
 impl fmt::Display for InvalidInput {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "invalid input: {}", self.0)
@@ -25,10 +29,18 @@ impl fmt::Display for InvalidArgument {
     }
 }
 
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "calculation cancelled")
+    }
+}
+
 impl error::Error for InvalidInput {}
 
 impl error::Error for InvalidArgument {}
 
+impl error::Error for Cancelled {}
+
 /// A helper for constructing [InvalidInput].
 pub fn invalid_input(s: String) -> Box<dyn error::Error> {
     InvalidInput(s).into()
@@ -48,3 +60,8 @@ pub fn invalid_argument(s: String) -> Box<dyn error::Error> {
 pub fn invalid_argument_ref(s: &str) -> Box<dyn error::Error> {
     InvalidArgument(s.to_owned()).into()
 }
+
+/// A helper for constructing [Cancelled].
+pub fn cancelled() -> Box<dyn error::Error> {
+    Cancelled.into()
+}
@@ -0,0 +1,347 @@
+//! Exact expected values, for validating the Monte Carlo estimates.
+
+use crate::calculation::Sample;
+use crate::output::{self, OExtrapolated};
+use std::collections::{BTreeMap, HashMap};
+
+/// Number of steps between 0 and the target size in [extrapolated_types].
+pub const EXTRAPOLATION_STEPS: u64 = 20;
+
+/// Multiplier of the standard error for the confidence band of [extrapolated_types] (95%).
+const EXTRAPOLATION_Z: f64 = 1.96;
+
+/// Expected number of types in a random subcorpus with `limit` tokens.
+///
+/// We pick `limit` tokens uniformly at random without replacement from all tokens in `samples`.
+/// A type with `k` tokens out of `n` tokens in total is then absent with probability
+/// `C(n - k, limit) / C(n, limit)`, and the result is the sum of `1 - P(type absent)` over all types.
+///
+/// This is exactly the expected value of the average calculated with [crate::output::MeasureY::Types]
+/// and [crate::output::MeasureX::Tokens] if each sample consists of one token
+/// (see [crate::driver::DriverArgs::split_samples]). Otherwise random subcorpora consist of whole samples,
+/// and tokens of the same type are usually clustered in the same samples, so the Monte Carlo
+/// average is typically somewhat lower.
+///
+/// Panics if `limit` is larger than the total number of tokens.
+///
+/// # Examples
+/// ```
+/// use types3::calculation::{SToken, Sample};
+/// use types3::exact;
+/// let sample = |id| Sample { x: 1, token_count: 1, tokens: vec![SToken { id, count: 1, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(0), sample(0), sample(1)];
+/// // Type 1 is absent with probability 1/3, type 0 is always present.
+/// assert!((exact::expected_types_at_limit(&samples, 2) - 5.0 / 3.0).abs() < 1e-12);
+/// ```
+pub fn expected_types_at_limit(samples: &[Sample], limit: u64) -> f64 {
+    let mut counts = HashMap::new();
+    for s in samples {
+        for t in &s.tokens {
+            *counts.entry(t.id).or_insert(0) += t.count;
+        }
+    }
+    let n: u64 = counts.values().sum();
+    assert!(limit <= n);
+    counts
+        .values()
+        .map(|&k| 1.0 - absent_probability(n, k, limit))
+        .sum()
+}
+
+/// Expected number of types in random subcorpora of up to `to_x` tokens,
+/// interpolated below and extrapolated above the total number of tokens in `samples`.
+///
+/// This is the rarefaction and extrapolation model of Colwell et al. (2012) and
+/// Chao et al. (2014), with tokens as individuals. Let `n` be the total number of tokens,
+/// `f_k` the number of types with exactly `k` tokens, and `S` the number of types.
+///
+/// - At `m <= n` tokens, the value is [expected_types_at_limit]: we pick `m` tokens
+///   at random without replacement.
+/// - At `n + m` tokens, the value is `S + f_0 (1 - (1 - f_1 / (n f_0 + f_1))^m)`, where
+///   `f_0 = (n - 1) / n * f_1^2 / (2 f_2)` (or `(n - 1) / n * f_1 (f_1 - 1) / 2` if `f_2 = 0`)
+///   is the Chao1 estimate of the number of unseen types. This assumes that the samples are
+///   a random sample of tokens from a larger population with a fixed distribution of types;
+///   as with [expected_types_at_limit], clustering of types in samples is ignored. The
+///   extrapolation is only reliable up to about twice the observed size.
+///
+/// The confidence band is `±1.96` standard errors, where the variance is the unconditional
+/// variance `sum_k d_k^2 f_k - (sum_k d_k f_k)^2 / (S + f_0)` obtained with the delta method,
+/// `d_k` being the derivative of the value with respect to `f_k` (with `n` fixed).
+/// The lower end is at least 0.
+///
+/// There is one point at every [EXTRAPOLATION_STEPS]th of `to_x`, starting from 0,
+/// plus one point at `n` if it is below `to_x`.
+///
+/// # Examples
+/// ```
+/// use types3::calculation::{SToken, Sample};
+/// use types3::exact;
+/// let sample = |id| Sample { x: 1, token_count: 1, tokens: vec![SToken { id, count: 1, marked_count: 0, also_marked: 0, known: false }], source: None };
+/// let samples = [sample(0), sample(0), sample(1), sample(2)];
+/// let curve = exact::extrapolated_types(&samples, 8);
+/// let observed = curve.iter().find(|p| p.x == 4).unwrap();
+/// assert!((observed.expected - 3.0).abs() < 1e-9);
+/// assert!(curve.last().unwrap().expected > 3.0);
+/// ```
+pub fn extrapolated_types(samples: &[Sample], to_x: u64) -> Vec<OExtrapolated> {
+    let mut counts = HashMap::new();
+    for s in samples {
+        for t in &s.tokens {
+            *counts.entry(t.id).or_insert(0) += t.count;
+        }
+    }
+    let n: u64 = counts.values().sum();
+    let mut freq = BTreeMap::new();
+    for &k in counts.values() {
+        *freq.entry(k).or_insert(0.0) += 1.0;
+    }
+    let mut xs = output::size_grid(to_x, EXTRAPOLATION_STEPS);
+    if n < to_x {
+        xs.push(n);
+    }
+    xs.sort();
+    xs.dedup();
+    let s_est = counts.len() as f64 + unseen(n, &freq);
+    xs.into_iter()
+        .map(|x| {
+            let (expected, derivatives) = if x <= n {
+                interpolate(n, &freq, x)
+            } else {
+                extrapolate(n, &freq, x - n)
+            };
+            let weighted = derivatives.iter().map(|(d, f)| d * f).sum::<f64>();
+            let variance = if s_est > 0.0 {
+                derivatives.iter().map(|(d, f)| d * d * f).sum::<f64>()
+                    - weighted * weighted / s_est
+            } else {
+                0.0
+            };
+            let se = variance.max(0.0).sqrt();
+            OExtrapolated {
+                x,
+                expected,
+                low: (expected - EXTRAPOLATION_Z * se).max(0.0),
+                high: expected + EXTRAPOLATION_Z * se,
+                extrapolated: x > n,
+            }
+        })
+        .collect()
+}
+
+/// Chao1 estimate of the number of unseen types; see [extrapolated_types].
+fn unseen(n: u64, freq: &BTreeMap<u64, f64>) -> f64 {
+    let f1 = freq.get(&1).copied().unwrap_or(0.0);
+    let f2 = freq.get(&2).copied().unwrap_or(0.0);
+    unseen_with(n, f1, f2)
+}
+
+fn unseen_with(n: u64, f1: f64, f2: f64) -> f64 {
+    if n == 0 {
+        return 0.0;
+    }
+    let c = (n - 1) as f64 / n as f64;
+    if f2 > 0.0 {
+        c * f1 * f1 / (2.0 * f2)
+    } else {
+        c * f1 * (f1 - 1.0).max(0.0) / 2.0
+    }
+}
+
+/// Expected number of types at `m <= n` tokens, and the derivative and value of each `f_k`.
+fn interpolate(n: u64, freq: &BTreeMap<u64, f64>, m: u64) -> (f64, Vec<(f64, f64)>) {
+    let derivatives = freq
+        .iter()
+        .map(|(&k, &f)| (1.0 - absent_probability(n, k, m), f))
+        .collect::<Vec<_>>();
+    let expected = derivatives.iter().map(|(d, f)| d * f).sum();
+    (expected, derivatives)
+}
+
+/// Expected number of types at `n + m` tokens, and the derivative and value of each `f_k`.
+fn extrapolate(n: u64, freq: &BTreeMap<u64, f64>, m: u64) -> (f64, Vec<(f64, f64)>) {
+    let observed = freq.values().sum::<f64>();
+    let f1 = freq.get(&1).copied().unwrap_or(0.0);
+    let f2 = freq.get(&2).copied().unwrap_or(0.0);
+    // Expected number of types that are unseen in the observed tokens but seen later.
+    let new = |f1: f64, f2: f64| {
+        let f0 = unseen_with(n, f1, f2);
+        if f0 <= 0.0 {
+            return 0.0;
+        }
+        f0 * (1.0 - (1.0 - f1 / (n as f64 * f0 + f1)).powf(m as f64))
+    };
+    let h = 1e-4;
+    let d1 = 1.0 + (new(f1 + h, f2) - new((f1 - h).max(0.0), f2)) / (f1 + h - (f1 - h).max(0.0));
+    let d2 = 1.0 + (new(f1, f2 + h) - new(f1, (f2 - h).max(0.0))) / (f2 + h - (f2 - h).max(0.0));
+    let derivatives = freq
+        .iter()
+        .map(|(&k, &f)| match k {
+            1 => (d1, f),
+            2 => (d2, f),
+            _ => (1.0, f),
+        })
+        .collect::<Vec<_>>();
+    (observed + new(f1, f2), derivatives)
+}
+
+/// Probability that none of `k` marked balls is picked if we pick `m` out of `n` balls.
+///
+/// This is `C(n - k, m) / C(n, m)`, calculated as the product of `(n - m - i) / (n - i)`
+/// for `i = 0, 1, ..., k - 1`, which avoids large intermediate values.
+fn absent_probability(n: u64, k: u64, m: u64) -> f64 {
+    if k + m > n {
+        return 0.0;
+    }
+    (0..k)
+        .map(|i| (n - m - i) as f64 / (n - i) as f64)
+        .product()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::calculation::SToken;
+    use itertools::Itertools;
+
+    fn sample(tokens: &[(usize, u64)]) -> Sample {
+        let tokens = tokens
+            .iter()
+            .map(|&(id, count)| SToken {
+                id,
+                count,
+                marked_count: 0,
+                also_marked: 0,
+                known: false,
+            })
+            .collect_vec();
+        let token_count = tokens.iter().map(|t| t.count).sum();
+        Sample {
+            x: token_count,
+            token_count,
+            tokens,
+            source: None,
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn absent_probability_basic() {
+        assert_eq!(absent_probability(10, 0, 5), 1.0);
+        assert_eq!(absent_probability(10, 3, 0), 1.0);
+        assert_eq!(absent_probability(10, 3, 8), 0.0);
+        assert_eq!(absent_probability(10, 10, 1), 0.0);
+        // C(7, 2) / C(10, 2) = 21 / 45.
+        assert!(close(absent_probability(10, 3, 2), 21.0 / 45.0));
+    }
+
+    #[test]
+    fn expected_types_edge_cases() {
+        let samples = [sample(&[(0, 3), (1, 2)]), sample(&[(1, 1), (2, 4)])];
+        assert_eq!(expected_types_at_limit(&samples, 0), 0.0);
+        assert!(close(expected_types_at_limit(&samples, 10), 3.0));
+        assert!(close(expected_types_at_limit(&samples, 1), 1.0));
+    }
+
+    #[test]
+    fn expected_types_distinct() {
+        let samples = [sample(&[(0, 1), (1, 1), (2, 1)]), sample(&[(3, 1), (4, 1)])];
+        for limit in 0..=5 {
+            assert!(close(
+                expected_types_at_limit(&samples, limit),
+                limit as f64
+            ));
+        }
+    }
+
+    #[test]
+    fn expected_types_same() {
+        let samples = [sample(&[(0, 4)]), sample(&[(0, 6)])];
+        for limit in 1..=10 {
+            assert!(close(expected_types_at_limit(&samples, limit), 1.0));
+        }
+    }
+
+    #[test]
+    fn expected_types_split_counts() {
+        // The same type in different samples is counted together.
+        let a = [sample(&[(0, 2), (1, 1)]), sample(&[(0, 1), (2, 1)])];
+        let b = [sample(&[(0, 3), (1, 1), (2, 1)])];
+        for limit in 0..=5 {
+            assert!(close(
+                expected_types_at_limit(&a, limit),
+                expected_types_at_limit(&b, limit)
+            ));
+        }
+    }
+
+    /// Samples of single tokens, with a mix of rare and common types.
+    fn single_tokens() -> Vec<Sample> {
+        (0..30)
+            .flat_map(|id| {
+                let count = [1, 1, 1, 2, 2, 3, 5, 8][id % 8];
+                (0..count).map(move |_| sample(&[(id, 1)]))
+            })
+            .collect_vec()
+    }
+
+    #[test]
+    fn extrapolated_types_monotone() {
+        let samples = single_tokens();
+        let n = samples.len() as u64;
+        let curve = extrapolated_types(&samples, 3 * n);
+        assert_eq!(curve[0].x, 0);
+        assert_eq!(curve[0].expected, 0.0);
+        assert_eq!(curve.last().unwrap().x, 3 * n);
+        let observed = curve.iter().find(|p| p.x == n).unwrap();
+        assert!(!observed.extrapolated);
+        assert!(close(observed.expected, 30.0));
+        for (a, b) in curve.iter().tuple_windows() {
+            assert!(a.x < b.x);
+            assert!(a.expected < b.expected, "{a:?} {b:?}");
+            assert_eq!(b.extrapolated, b.x > n);
+        }
+        for p in &curve {
+            assert!(p.low <= p.expected && p.expected <= p.high, "{p:?}");
+        }
+        // The extrapolation approaches the Chao1 estimate, and the band widens.
+        let last = curve.last().unwrap();
+        assert!(last.expected < 30.0 + unseen(n, &BTreeMap::from([(1, 12.0), (2, 8.0)])));
+        assert!(last.high - last.low > observed.high - observed.low);
+    }
+
+    #[test]
+    fn extrapolated_types_no_singletons() {
+        // Without types seen once, there are no unseen types, and the curve is flat.
+        let samples = [sample(&[(0, 2), (1, 3)])];
+        let curve = extrapolated_types(&samples, 10);
+        for p in curve.iter().filter(|p| p.extrapolated) {
+            assert!(close(p.expected, 2.0));
+        }
+    }
+
+    #[test]
+    fn extrapolated_types_vs_monte_carlo() {
+        use crate::calc_avg;
+        use crate::output::MeasureY;
+        let samples = single_tokens();
+        let n = samples.len() as u64;
+        let iter = 10000;
+        for p in extrapolated_types(&samples, n).into_iter().skip(1) {
+            let ar = calc_avg::average_at_limit(MeasureY::Types, &samples, iter, p.x, 0, None);
+            let average = (ar.low + ar.high) as f64 / (2 * ar.iter) as f64;
+            assert!(
+                (average - p.expected).abs() < 0.02 * p.expected,
+                "{p:?} {average}"
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "assert")]
+    fn expected_types_too_large() {
+        expected_types_at_limit(&[sample(&[(0, 2)])], 3);
+    }
+}
@@ -0,0 +1,347 @@
+//! Import input data from CSV or TSV files.
+//!
+//! The data is in long format, with one row per token. Columns `sample_id`, `year`,
+//! `words`, and `lemma` are required, and columns `chars` and `count` are optional.
+//! Columns of the form `sample.key` are sample metadata, and columns of the form
+//! `token.key` are token metadata. For example:
+//!
+//! ```csv
+//! sample_id,year,words,sample.gender,lemma,token.variant
+//! 7009,1848,107744,M,seek,be-going-to-verb
+//! 7009,1848,107744,M,write,gonna
+//! 2997,1917,55962,F,last,be-going-to-verb
+//! ```
+//!
+//! Rows with the same `sample_id` belong to the same sample, and they have to agree
+//! on all sample-level columns. The samples are in the order of their first rows.
+//! A row with an empty `lemma` does not represent any token, so a sample without
+//! tokens can be given as a single row with an empty `lemma`. An empty `year` means
+//! that the sample is undated. Metadata values are kept as they are, even if empty.
+//!
+//! Column `count` is the number of occurrences that the row represents (see
+//! [IToken::count]), so pre-aggregated data does not need to repeat identical rows;
+//! an empty `count` means a single occurrence.
+
+use crate::errors::{self, Result};
+use crate::input::{ISample, IToken, Input, Year};
+use std::collections::HashMap;
+
+/// Prefix of the columns for sample metadata.
+pub const SAMPLE_PREFIX: &str = "sample.";
+/// Prefix of the columns for token metadata.
+pub const TOKEN_PREFIX: &str = "token.";
+
+/// Meaning of a column.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Column {
+    SampleId,
+    Year,
+    Words,
+    Chars,
+    Lemma,
+    Count,
+    Sample(String),
+    Token(String),
+}
+
+impl Column {
+    fn parse(name: &str) -> Result<Column> {
+        let column = match name {
+            "sample_id" => Column::SampleId,
+            "year" => Column::Year,
+            "words" => Column::Words,
+            "chars" => Column::Chars,
+            "lemma" => Column::Lemma,
+            "count" => Column::Count,
+            _ => {
+                if let Some(key) = name.strip_prefix(SAMPLE_PREFIX) {
+                    Column::Sample(key.to_owned())
+                } else if let Some(key) = name.strip_prefix(TOKEN_PREFIX) {
+                    Column::Token(key.to_owned())
+                } else {
+                    return Err(errors::invalid_input(format!(
+                        "unknown column {name}: expected sample_id, year, words, chars, lemma, count, or a column starting with {SAMPLE_PREFIX} or {TOKEN_PREFIX}"
+                    )));
+                }
+            }
+        };
+        match column {
+            Column::Sample(ref key) | Column::Token(ref key) if key.is_empty() => Err(
+                errors::invalid_input(format!("column {name} does not have a metadata key")),
+            ),
+            _ => Ok(column),
+        }
+    }
+
+    fn is_sample_level(&self) -> bool {
+        !matches!(self, Column::Lemma | Column::Count | Column::Token(_))
+    }
+}
+
+/// Split CSV or TSV data into records, each of which is a list of fields.
+///
+/// The data is parsed with the [csv] crate, and `delimiter` has to be an ASCII character.
+/// Fields can be quoted with `"`, and quotes inside quoted fields are written as `""`;
+/// quoted fields may contain delimiters and line breaks, and a quoted field that is not
+/// closed extends to the end of the data.
+/// Both `\n` and `\r\n` line breaks are accepted, a leading byte order mark
+/// is ignored, and so are empty lines.
+///
+/// # Examples
+/// ```
+/// use types3::import::parse_records;
+/// let records = parse_records("a,b\n\"x, \"\"y\"\"\",\r\n", ',').unwrap();
+/// assert_eq!(records, vec![vec!["a", "b"], vec!["x, \"y\"", ""]]);
+/// ```
+pub fn parse_records(data: &str, delimiter: char) -> Result<Vec<Vec<String>>> {
+    let delimiter = u8::try_from(delimiter)
+        .ok()
+        .filter(u8::is_ascii)
+        .ok_or_else(|| {
+            errors::invalid_argument(format!("delimiter {delimiter:?} is not an ASCII character"))
+        })?;
+    let data = data.strip_prefix('\u{feff}').unwrap_or(data);
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data.as_bytes());
+    let mut records = vec![];
+    for record in reader.records() {
+        records.push(record?.iter().map(|f| f.to_owned()).collect());
+    }
+    Ok(records)
+}
+
+/// Sample-level values of the first row of a sample, for checking the other rows.
+struct SeenSample {
+    index: usize,
+    values: Vec<String>,
+}
+
+/// Convert CSV or TSV data in long format into [Input]; see the [module-level documentation](self).
+///
+/// Use `delimiter` `','` for CSV and `'\t'` for TSV.
+pub fn import(data: &str, delimiter: char) -> Result<Input> {
+    let mut records = parse_records(data, delimiter)?.into_iter();
+    let Some(header) = records.next() else {
+        return Err(errors::invalid_input_ref("no header row"));
+    };
+    let columns: Vec<Column> = header
+        .iter()
+        .map(|h| Column::parse(h))
+        .collect::<Result<_>>()?;
+    for (i, h) in header.iter().enumerate() {
+        if header[..i].contains(h) {
+            return Err(errors::invalid_input(format!("duplicate column {h}")));
+        }
+    }
+    let find = |c: &Column| columns.iter().position(|x| x == c);
+    for (name, c) in [
+        ("sample_id", Column::SampleId),
+        ("year", Column::Year),
+        ("words", Column::Words),
+        ("lemma", Column::Lemma),
+    ] {
+        if find(&c).is_none() {
+            let got = header.join(", ");
+            return Err(errors::invalid_input(format!(
+                "I was expecting to see column {name}, but I only got these columns: {got}"
+            )));
+        }
+    }
+    let id_col = find(&Column::SampleId).expect("required column");
+    let lemma_col = find(&Column::Lemma).expect("required column");
+    let count_col = find(&Column::Count);
+    let sample_cols = (0..columns.len())
+        .filter(|&i| columns[i].is_sample_level())
+        .collect::<Vec<_>>();
+
+    let mut samples: Vec<ISample> = vec![];
+    let mut seen: HashMap<String, SeenSample> = HashMap::new();
+    for (i, record) in records.enumerate() {
+        // The header is row 1.
+        let row = i + 2;
+        if record.len() != columns.len() {
+            return Err(errors::invalid_input(format!(
+                "row {row}: expected {} fields, got {}",
+                columns.len(),
+                record.len()
+            )));
+        }
+        let id = &record[id_col];
+        let values = sample_cols
+            .iter()
+            .map(|&j| record[j].clone())
+            .collect::<Vec<_>>();
+        let index = match seen.get(id) {
+            Some(s) => {
+                if let Some(k) = (0..values.len()).find(|&k| values[k] != s.values[k]) {
+                    return Err(errors::invalid_input(format!(
+                        "row {row}: sample {id} has a different value in column {} than in its earlier rows",
+                        header[sample_cols[k]]
+                    )));
+                }
+                s.index
+            }
+            None => {
+                samples.push(new_sample(&header, &columns, &record, row)?);
+                let index = samples.len() - 1;
+                seen.insert(id.clone(), SeenSample { index, values });
+                index
+            }
+        };
+        let lemma = &record[lemma_col];
+        if lemma.is_empty() {
+            continue;
+        }
+        let count = match count_col.map(|j| record[j].trim()) {
+            None | Some("") => None,
+            Some(v) => Some(v.parse::<u64>().map_err(|_| {
+                errors::invalid_input(format!("row {row}: invalid value in column count: {v}"))
+            })?),
+        };
+        let metadata = columns
+            .iter()
+            .zip(&record)
+            .filter_map(|(c, v)| match c {
+                Column::Token(key) => Some((key.clone(), v.clone())),
+                _ => None,
+            })
+            .collect();
+        samples[index].tokens.push(IToken {
+            lemma: lemma.clone(),
+            descr: None,
+            metadata,
+            count,
+        });
+    }
+    Ok(Input { samples })
+}
+
+fn new_sample(
+    header: &[String],
+    columns: &[Column],
+    record: &[String],
+    row: usize,
+) -> Result<ISample> {
+    let number = |name: &str, v: &str| {
+        v.trim().parse::<u64>().map_err(|_| {
+            errors::invalid_input(format!("row {row}: invalid value in column {name}: {v}"))
+        })
+    };
+    let mut sample = ISample {
+        id: String::new(),
+        year: None,
+        descr: None,
+        metadata: HashMap::new(),
+        words: 0,
+        chars: 0,
+        tokens: vec![],
+        numeric: HashMap::new(),
+    };
+    for ((c, h), v) in columns.iter().zip(header).zip(record) {
+        match c {
+            Column::SampleId => sample.id = v.clone(),
+            Column::Year if v.trim().is_empty() => sample.year = None,
+            Column::Year => {
+                sample.year =
+                    Some(v.trim().parse::<Year>().map_err(|_| {
+                        errors::invalid_input(format!("row {row}: invalid year: {v}"))
+                    })?)
+            }
+            Column::Words => sample.words = number(h, v)?,
+            Column::Chars => sample.chars = number(h, v)?,
+            Column::Sample(key) => {
+                sample.metadata.insert(key.clone(), v.clone());
+            }
+            Column::Lemma | Column::Count | Column::Token(_) => (),
+        }
+    }
+    Ok(sample)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_records_quoting() {
+        let data = "\u{feff}a,b,c\r\n\"1,2\",\"x\ny\",\"say \"\"hi\"\"\"\n\n,,\n";
+        let records = parse_records(data, ',').unwrap();
+        assert_eq!(
+            records,
+            vec![
+                vec!["a", "b", "c"],
+                vec!["1,2", "x\ny", "say \"hi\""],
+                vec!["", "", ""],
+            ]
+        );
+        let records = parse_records("a\tb,c\n1\t2", '\t').unwrap();
+        assert_eq!(records, vec![vec!["a", "b,c"], vec!["1", "2"]]);
+        let records = parse_records("a,\"b\n", ',').unwrap();
+        assert_eq!(records, vec![vec!["a", "b\n"]]);
+        assert!(parse_records("a\u{a7}b", '\u{a7}').is_err());
+    }
+
+    #[test]
+    fn import_basic() {
+        let data = "sample_id,year,words,chars,sample.gender,lemma,token.variant\n\
+                    s1,1700,10,50,F,be,ity\n\
+                    s2,,5,20,M,,\n\
+                    s1,1700,10,50,F,do,ness\n";
+        let input = import(data, ',').unwrap();
+        assert_eq!(input.samples.len(), 2);
+        let s1 = &input.samples[0];
+        assert_eq!(s1.id, "s1");
+        assert_eq!(s1.year, Some(1700));
+        assert_eq!((s1.words, s1.chars), (10, 50));
+        assert_eq!(s1.metadata["gender"], "F");
+        assert_eq!(s1.tokens.len(), 2);
+        assert_eq!(s1.tokens[1].lemma, "do");
+        assert_eq!(s1.tokens[1].metadata["variant"], "ness");
+        assert_eq!(s1.tokens[1].count, None);
+        let s2 = &input.samples[1];
+        assert_eq!(s2.year, None);
+        assert!(s2.tokens.is_empty());
+    }
+
+    #[test]
+    fn import_count() {
+        let data = "sample_id,year,words,lemma,count\n\
+                    s1,1700,10,be,3\n\
+                    s1,1700,10,do,\n\
+                    s2,1800,5,be,0\n";
+        let input = import(data, ',').unwrap();
+        let counts = |s: &ISample| s.tokens.iter().map(|t| t.count).collect::<Vec<_>>();
+        assert_eq!(counts(&input.samples[0]), vec![Some(3), None]);
+        assert_eq!(counts(&input.samples[1]), vec![Some(0)]);
+    }
+
+    #[test]
+    fn import_invalid() {
+        let check = |data: &str, expected: &str| {
+            let e = import(data, ',').err().unwrap().to_string();
+            assert!(e.contains(expected), "{e}");
+        };
+        check("", "no header row");
+        check("sample_id,year,words", "column lemma");
+        check("sample_id,year,words,lemma,gender", "unknown column gender");
+        check("sample_id,year,words,lemma,token.", "metadata key");
+        check("sample_id,year,words,lemma,lemma", "duplicate column lemma");
+        check(
+            "sample_id,year,words,lemma\na,1700,1",
+            "row 2: expected 4 fields",
+        );
+        check("sample_id,year,words,lemma\na,x,1,b", "row 2: invalid year");
+        check("sample_id,year,words,lemma\na,1700,-1,b", "column words");
+        check(
+            "sample_id,year,words,lemma,count\na,1700,1,b,x",
+            "row 2: invalid value in column count",
+        );
+        check(
+            "sample_id,year,words,lemma,sample.g\na,1700,1,b,F\na,1700,1,c,M",
+            "row 3: sample a has a different value in column sample.g",
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::input::ISample;
+use crate::input::{self, Input};
 use crate::samples::CSample;
 use itertools::Itertools;
 use log::info;
@@ -16,26 +16,23 @@ fn explain_metadata(metadata: &HashMap<&str, HashSet<&str>>) -> String {
         .join("; ")
 }
 
-pub fn statistics(samples: &[ISample]) {
-    let mut lemmas = HashSet::new();
+pub fn statistics(input: &Input) {
     let mut token_metadata: HashMap<&str, HashSet<&str>> = HashMap::new();
     let mut sample_metadata: HashMap<&str, HashSet<&str>> = HashMap::new();
-    let mut tokencount = 0;
-    for s in samples {
+    for s in &input.samples {
         for (k, v) in s.metadata.iter() {
             sample_metadata.entry(k).or_default().insert(v);
         }
         for t in &s.tokens {
-            tokencount += 1;
             for (k, v) in t.metadata.iter() {
                 token_metadata.entry(k).or_default().insert(v);
             }
-            lemmas.insert(&t.lemma);
         }
     }
-    info!(target: "types3", "before filtering: samples: {}", samples.len());
-    info!(target: "types3", "before filtering: tokens: {}", tokencount);
-    info!(target: "types3", "before filtering: distinct lemmas: {}", lemmas.len());
+    let summary = input::summary(input);
+    info!(target: "types3", "before filtering: samples: {}", summary.samples);
+    info!(target: "types3", "before filtering: tokens: {}", summary.tokens);
+    info!(target: "types3", "before filtering: distinct lemmas: {}", summary.types);
     info!(target: "types3",
         "token metadata categories: {}",
         explain_metadata(&token_metadata)
@@ -53,10 +50,10 @@ pub fn post_statistics(samples: &[CSample]) {
     let mut marked_tokencount = 0;
     for s in samples {
         for t in &s.tokens {
-            tokencount += 1;
+            tokencount += t.count;
             lemmas.insert(t.token);
             if t.marked {
-                marked_tokencount += 1;
+                marked_tokencount += t.count;
                 marked_lemmas.insert(t.token);
             }
         }
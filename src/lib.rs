@@ -5,17 +5,30 @@
 #![warn(missing_docs)]
 #![forbid(unsafe_code)]
 
-mod calc_avg;
-mod calc_point;
-mod calculation;
+pub mod cache;
+pub mod calc_avg;
+pub mod calc_boot;
+pub mod calc_point;
+pub mod calc_year;
+pub mod calculation;
 pub mod categories;
 mod counter;
+pub mod diversity;
 pub mod driver;
 pub mod errors;
+pub mod exact;
+pub mod import;
 mod information;
 pub mod input;
+pub mod logging;
 pub mod output;
 mod parallelism;
+#[cfg(feature = "parquet")]
+pub mod parquet;
 pub mod samples;
-mod shuffle;
+#[cfg(feature = "schemars")]
+pub mod schema;
+pub mod shuffle;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 mod subsets;
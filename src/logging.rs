@@ -0,0 +1,66 @@
+//! Logging setup for the command-line tools.
+
+use crate::errors::Result;
+use log::{LevelFilter, Log, Metadata, Record};
+use pretty_env_logger::env_logger::Logger;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+struct TeeLogger {
+    inner: Logger,
+    file: Mutex<io::LineWriter<fs::File>>,
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        self.inner.log(record);
+        if record.target() == "types3" {
+            let mut file = self.file.lock().unwrap();
+            // Logging must not fail, so errors are ignored here.
+            let _ = writeln!(
+                file,
+                "{:<5} {} > {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+        let _ = self.file.lock().unwrap().flush();
+    }
+}
+
+/// Set up logging to stderr with the given verbosity.
+///
+/// If `log_file` is given, messages with target `types3` are also written to this file,
+/// so that they can be kept separate from the logs of other applications.
+pub fn init(level: LevelFilter, log_file: Option<&str>) -> Result<()> {
+    let mut builder = pretty_env_logger::formatted_timed_builder();
+    builder.filter_level(level);
+    match log_file {
+        None => builder.init(),
+        Some(filename) => {
+            let file = fs::File::create(filename)?;
+            let inner = builder.build();
+            let max_level = inner.filter();
+            let logger = TeeLogger {
+                inner,
+                file: Mutex::new(io::LineWriter::new(file)),
+            };
+            log::set_boxed_logger(Box::new(logger))?;
+            log::set_max_level(max_level);
+        }
+    }
+    Ok(())
+}
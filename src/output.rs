@@ -1,14 +1,19 @@
 //! Data structures for representing the output.
 
+use crate::calculation::Sample;
+use crate::errors::{self, Result};
 use crate::input::Year;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
+use std::io::{self, Write};
 
 /// What to calculate.
 ///
 /// In the visualizations, this corresponds to what will be put in the y axis.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum MeasureY {
     /// Number of distinct lemmas.
@@ -21,6 +26,150 @@ pub enum MeasureY {
     Samples,
     /// Number of distinct lemmas in marked tokens.
     MarkedTypes,
+    /// Mean frequency of lemmas: tokens per type, multiplied by [MEAN_FREQUENCY_SCALE]
+    /// and rounded with [MEAN_FREQUENCY_ROUND]; 0 if there are no types.
+    MeanFrequency,
+    /// Proportion of tokens that are hapax legomena (V1/N), multiplied by
+    /// [HAPAX_TOKEN_RATIO_SCALE] and rounded with [HAPAX_TOKEN_RATIO_ROUND]; 0 if there are no tokens.
+    /// Each hapax legomenon has exactly one token, so the numerator V1 is the same as
+    /// the number of hapaxes in [MeasureY::Hapaxes]; only the denominator differs.
+    HapaxTokenRatio,
+    /// Number of distinct sources of samples, i.e., distinct values of a sample metadata key.
+    /// See [crate::driver::DriverArgs::source_key].
+    Sources,
+    /// Number of marked tokens.
+    MarkedTokens,
+    /// Slope of the Zipf curve: the least-squares slope of log frequency against log rank
+    /// of the types, negated, multiplied by [ZIPF_SLOPE_SCALE] and rounded with
+    /// [ZIPF_SLOPE_ROUND]; 0 if there are fewer than two types.
+    /// This is an expensive measure; see [crate::counter::ZipfSlopeCounter].
+    ZipfSlope,
+    /// Number of frequent types, i.e., types with at least `per_million` tokens per million
+    /// tokens. This is not monotone, as types become less frequent as the number of tokens grows;
+    /// see [crate::counter::FrequentTypesCounter].
+    FrequentTypes {
+        /// Threshold for relative frequency, in tokens per million tokens.
+        per_million: u64,
+    },
+    /// Number of distinct lemmas that are in the reference vocabulary.
+    /// See [crate::driver::DriverArgs::vocabulary].
+    KnownTypes,
+    /// Number of types with exactly `n` tokens, e.g. dis legomena for `n = 2`.
+    /// For `n = 1`, this is the same as [MeasureY::Hapaxes]; see [crate::counter::NLegomenaCounter].
+    Legomena {
+        /// Number of tokens.
+        n: u64,
+    },
+    /// Type-token ratio: types per token, multiplied by [TYPE_TOKEN_RATIO_SCALE]
+    /// and rounded with [TYPE_TOKEN_RATIO_ROUND]; 0 if there are no tokens.
+    /// This is not monotone, and it usually decreases as the subcorpus grows.
+    TypeTokenRatio,
+    /// Yule's characteristic constant K = 10⁴ (Σ m² V(m) − N) / N², where V(m) is the number
+    /// of types with m tokens and N is the number of tokens, multiplied by [YULE_K_SCALE]
+    /// and rounded with [YULE_K_ROUND]; 0 if there are no tokens.
+    /// This is not monotone; see [crate::counter::YuleKCounter] and [crate::diversity].
+    YuleK,
+    /// Shannon entropy of the frequency distribution of lemmas, in bits, multiplied by
+    /// [ENTROPY_SCALE] (i.e., in millibits) and rounded with [ENTROPY_ROUND]; 0 if there are
+    /// no tokens. This is not monotone; see [crate::counter::EntropyCounter].
+    Entropy,
+    /// Guiraud's R: types divided by the square root of tokens, multiplied by
+    /// [GUIRAUD_R_SCALE] and rounded with [GUIRAUD_R_ROUND]; 0 if there are no tokens.
+    GuiraudR,
+    /// Herdan's C: log of types divided by log of tokens, multiplied by [HERDAN_C_SCALE]
+    /// and rounded with [HERDAN_C_ROUND]; 0 if there are fewer than two tokens.
+    HerdanC,
+}
+
+/// Scaling factor for [MeasureY::MeanFrequency], so that we can use integers.
+pub const MEAN_FREQUENCY_SCALE: u64 = 1000;
+
+/// Rounding mode for [MeasureY::MeanFrequency].
+pub const MEAN_FREQUENCY_ROUND: RoundMode = RoundMode::Down;
+
+/// Scaling factor for [MeasureY::HapaxTokenRatio], so that we can use integers.
+pub const HAPAX_TOKEN_RATIO_SCALE: u64 = 1000;
+
+/// Rounding mode for [MeasureY::HapaxTokenRatio].
+pub const HAPAX_TOKEN_RATIO_ROUND: RoundMode = RoundMode::Down;
+
+/// Scaling factor for [MeasureY::TypeTokenRatio], so that we can use integers.
+/// The ratio is at most 1, so this gives four decimal places.
+pub const TYPE_TOKEN_RATIO_SCALE: u64 = 10000;
+
+/// Rounding mode for [MeasureY::TypeTokenRatio].
+pub const TYPE_TOKEN_RATIO_ROUND: RoundMode = RoundMode::Down;
+
+/// Scaling factor for [MeasureY::YuleK], so that we can use integers.
+/// Yule's K is less than 10⁴, so this gives two decimal places.
+pub const YULE_K_SCALE: u64 = 100;
+
+/// Rounding mode for [MeasureY::YuleK].
+pub const YULE_K_ROUND: RoundMode = RoundMode::Down;
+
+/// Scaling factor for [MeasureY::Entropy], so that we can use integers.
+pub const ENTROPY_SCALE: u64 = 1000;
+
+/// Rounding mode for [MeasureY::Entropy].
+pub const ENTROPY_ROUND: RoundMode = RoundMode::HalfUp;
+
+/// Scaling factor for [MeasureY::GuiraudR], so that we can use integers.
+pub const GUIRAUD_R_SCALE: u64 = 1000;
+
+/// Rounding mode for [MeasureY::GuiraudR].
+pub const GUIRAUD_R_ROUND: RoundMode = RoundMode::Down;
+
+/// Scaling factor for [MeasureY::HerdanC], so that we can use integers.
+/// Herdan's C is at most 1, so this gives four decimal places.
+pub const HERDAN_C_SCALE: u64 = 10000;
+
+/// Rounding mode for [MeasureY::HerdanC].
+pub const HERDAN_C_ROUND: RoundMode = RoundMode::Down;
+
+/// Scaling factor for [MeasureY::ZipfSlope], so that we can use integers.
+pub const ZIPF_SLOPE_SCALE: u64 = 1000;
+
+/// Rounding mode for [MeasureY::ZipfSlope].
+pub const ZIPF_SLOPE_ROUND: RoundMode = RoundMode::Down;
+
+/// How to round scaled floating-point measures to integers.
+///
+/// Measures such as [MeasureY::MeanFrequency] are naturally fractional;
+/// we multiply them by a fixed scaling factor and round them to integers.
+/// Values that are within floating-point error of an integer are treated as that integer
+/// in all modes, so that e.g. 2.9 × 1000 is always 2900.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundMode {
+    /// Round towards zero (truncate).
+    Down,
+    /// Round to the nearest integer, with ties rounded up.
+    HalfUp,
+}
+
+impl MeasureY {
+    /// Does the measure never decrease as samples are added to the subcorpus?
+    pub fn is_monotone(self) -> bool {
+        match self {
+            MeasureY::Types
+            | MeasureY::Tokens
+            | MeasureY::Samples
+            | MeasureY::MarkedTypes
+            | MeasureY::Sources
+            | MeasureY::MarkedTokens
+            | MeasureY::KnownTypes => true,
+            MeasureY::Hapaxes
+            | MeasureY::MeanFrequency
+            | MeasureY::HapaxTokenRatio
+            | MeasureY::ZipfSlope
+            | MeasureY::FrequentTypes { .. }
+            | MeasureY::Legomena { .. }
+            | MeasureY::TypeTokenRatio
+            | MeasureY::YuleK
+            | MeasureY::Entropy
+            | MeasureY::GuiraudR
+            | MeasureY::HerdanC => false,
+        }
+    }
 }
 
 impl fmt::Display for MeasureY {
@@ -31,6 +180,21 @@ impl fmt::Display for MeasureY {
             MeasureY::Hapaxes => write!(f, "hapaxes"),
             MeasureY::Samples => write!(f, "samples"),
             MeasureY::MarkedTypes => write!(f, "marked types"),
+            MeasureY::MeanFrequency => write!(f, "mean frequency"),
+            MeasureY::HapaxTokenRatio => write!(f, "hapax-token ratio"),
+            MeasureY::Sources => write!(f, "sources"),
+            MeasureY::MarkedTokens => write!(f, "marked tokens"),
+            MeasureY::ZipfSlope => write!(f, "Zipf slope"),
+            MeasureY::FrequentTypes { per_million } => {
+                write!(f, "types with at least {per_million} per million")
+            }
+            MeasureY::KnownTypes => write!(f, "known types"),
+            MeasureY::Legomena { n } => write!(f, "types with exactly {n} tokens"),
+            MeasureY::TypeTokenRatio => write!(f, "type-token ratio"),
+            MeasureY::YuleK => write!(f, "Yule's K"),
+            MeasureY::Entropy => write!(f, "entropy"),
+            MeasureY::GuiraudR => write!(f, "Guiraud's R"),
+            MeasureY::HerdanC => write!(f, "Herdan's C"),
         }
     }
 }
@@ -39,6 +203,7 @@ impl fmt::Display for MeasureY {
 ///
 /// We will accumulate samples until they have the same size according to this measure.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum MeasureX {
     /// Number of running words.
@@ -47,6 +212,10 @@ pub enum MeasureX {
     Tokens,
     /// Number of distinct lemmas.
     Types,
+    /// Number of samples.
+    Samples,
+    /// Number of characters.
+    Chars,
 }
 
 impl fmt::Display for MeasureX {
@@ -55,6 +224,8 @@ impl fmt::Display for MeasureX {
             MeasureX::Words => write!(f, "words"),
             MeasureX::Tokens => write!(f, "tokens"),
             MeasureX::Types => write!(f, "types"),
+            MeasureX::Samples => write!(f, "samples"),
+            MeasureX::Chars => write!(f, "characters"),
         }
     }
 }
@@ -73,6 +244,7 @@ pub type OCategory = Option<(String, String)>;
 /// in random subcorpora with [Output::limit] many things of type
 /// [Output::measure_x].
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct AvgResult {
     /// Lower bound for the sum. Divide by `iter` to get the lower bound for the average.
     pub low: u64,
@@ -80,6 +252,30 @@ pub struct AvgResult {
     pub high: u64,
     /// Number of random samples accumulated.
     pub iter: u64,
+    /// Sum of the number of samples in each random subcorpus, if reported.
+    /// Divide by `iter` to get the average; see [OResult::avg_samples_at_limit].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<u64>,
+    /// Sum of the contribution of the last sample in each random subcorpus, if reported:
+    /// the difference between the upper and lower bound of [Output::measure_y] for the sample
+    /// that reaches the limit. Divide by `iter` to get the average;
+    /// see [OResult::avg_marginal].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub marginal: Option<i64>,
+    /// Smallest lower bound of [Output::measure_y] in any random subcorpus, if reported.
+    /// See [crate::driver::DriverArgs::extremes].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_y: Option<u64>,
+    /// Largest upper bound of [Output::measure_y] in any random subcorpus, if reported.
+    /// See [crate::driver::DriverArgs::extremes].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_y: Option<u64>,
+    /// Percentiles of [Output::measure_y] in the random subcorpora, if reported:
+    /// a low quantile of the lower bounds and a high quantile of the upper bounds.
+    /// Unlike the other fields, these are values, not sums over the iterations.
+    /// See [crate::driver::DriverArgs::percentiles].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percentiles: Option<(u64, u64)>,
 }
 
 /// Representation for statistical significance.
@@ -93,6 +289,7 @@ pub struct AvgResult {
 /// - above/iter ≈ 0.999…
 /// - (iter - above) / iter ≈ 0.000…
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct PointResult {
     /// How many times we are above what is observed in a random subcorpus.
     pub above: u64,
@@ -100,10 +297,339 @@ pub struct PointResult {
     pub below: u64,
     /// Number of random samples accumulated.
     pub iter: u64,
+    /// Sums of the random values at the size of the observed point, if reported.
+    /// See [PointResult::z_score].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moments: Option<PointMoments>,
+}
+
+/// Sums of the random values at the size of the observed point.
+///
+/// The random value of a random subcorpus is the midpoint between the lower and
+/// upper bounds of [Output::measure_y] at this size. To keep everything in integers,
+/// we add up twice the midpoint, i.e., the sum of the bounds.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct PointMoments {
+    /// Sum of `low + high`. Divide by `2 * iter` to get the mean.
+    pub sum: u64,
+    /// Sum of `(low + high)²`.
+    pub sum_sq: u128,
+}
+
+impl PointResult {
+    /// Fraction of random subcorpora that are at least as extreme as what we observed,
+    /// in whichever direction is more extreme.
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::PointResult;
+    /// let x = PointResult { above: 9995, below: 3, iter: 10000, moments: None };
+    /// assert_eq!(x.tail_fraction(), 0.0005);
+    /// let x = PointResult { above: 10, below: 9980, iter: 10000, moments: None };
+    /// assert_eq!(x.tail_fraction(), 0.002);
+    /// ```
+    pub fn tail_fraction(&self) -> f64 {
+        let tail = (self.iter - self.above).min(self.iter - self.below);
+        tail as f64 / self.iter as f64
+    }
+
+    /// Monte Carlo standard error of [PointResult::tail_fraction].
+    ///
+    /// The counts are binomial estimates, so the standard error is `sqrt(p (1 - p) / iter)`.
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::PointResult;
+    /// let x = PointResult { above: 9995, below: 3, iter: 10000, moments: None };
+    /// assert!((x.standard_error() - 0.000224).abs() < 0.000001);
+    /// let x = PointResult { above: 5000, below: 5000, iter: 10000, moments: None };
+    /// assert!((x.standard_error() - 0.005).abs() < 0.000001);
+    /// let x = PointResult { above: 0, below: 0, iter: 10000, moments: None };
+    /// assert_eq!(x.standard_error(), 0.0);
+    /// ```
+    pub fn standard_error(&self) -> f64 {
+        let p = self.tail_fraction();
+        (p * (1.0 - p) / self.iter as f64).sqrt()
+    }
+
+    /// Is this significant at level `alpha`, in either direction?
+    ///
+    /// This uses the same convention as [point_string]:
+    /// a result is significant if [PointResult::tail_fraction] is less than `alpha`.
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::PointResult;
+    /// let x = PointResult { above: 9995, below: 3, iter: 10000, moments: None };
+    /// assert!(x.is_significant(0.001));
+    /// assert!(!x.is_significant(0.0001));
+    /// ```
+    pub fn is_significant(&self, alpha: f64) -> bool {
+        self.tail_fraction() < alpha
+    }
+
+    /// Fraction of random subcorpora in which the observed value is strictly above the random one.
+    ///
+    /// Values close to 1 mean that the observed value is unusually high.
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::PointResult;
+    /// let x = PointResult { above: 9995, below: 3, iter: 10000, moments: None };
+    /// assert_eq!(x.fraction_above(), 0.9995);
+    /// ```
+    pub fn fraction_above(&self) -> f64 {
+        self.above as f64 / self.iter as f64
+    }
+
+    /// Fraction of random subcorpora in which the observed value is strictly below the random one.
+    ///
+    /// Values close to 1 mean that the observed value is unusually low.
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::PointResult;
+    /// let x = PointResult { above: 9995, below: 3, iter: 10000, moments: None };
+    /// assert_eq!(x.fraction_below(), 0.0003);
+    /// ```
+    pub fn fraction_below(&self) -> f64 {
+        self.below as f64 / self.iter as f64
+    }
+
+    /// Direction of the deviation, using the weakest level in [SIGNIFICANCE_LEVELS].
+    ///
+    /// This agrees with [point_string]: [Direction::Above] corresponds to `+`,
+    /// and [Direction::Below] corresponds to `-`.
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::{Direction, PointResult};
+    /// let x = PointResult { above: 9995, below: 3, iter: 10000, moments: None };
+    /// assert_eq!(x.direction(), Direction::Above);
+    /// let x = PointResult { above: 10, below: 9980, iter: 10000, moments: None };
+    /// assert_eq!(x.direction(), Direction::Below);
+    /// let x = PointResult { above: 5000, below: 4000, iter: 10000, moments: None };
+    /// assert_eq!(x.direction(), Direction::Neither);
+    /// ```
+    pub fn direction(&self) -> Direction {
+        let alpha = SIGNIFICANCE_LEVELS[SIGNIFICANCE_LEVELS.len() - 1];
+        let above = (self.iter - self.above) as f64 / self.iter as f64;
+        let below = (self.iter - self.below) as f64 / self.iter as f64;
+        if above < alpha {
+            Direction::Above
+        } else if below < alpha {
+            Direction::Below
+        } else {
+            Direction::Neither
+        }
+    }
+
+    /// P-value of the test with the alternative hypothesis `side`.
+    ///
+    /// For [TestSide::GreaterOnly] this is the fraction of random subcorpora that are
+    /// at least as high as what we observed, and for [TestSide::LessOnly] the fraction
+    /// that are at least as low. For [TestSide::TwoSided] this is twice the smaller
+    /// of these two (at most 1). Note that [PointResult::tail_fraction],
+    /// [PointResult::is_significant], and [point_string] do not double the tail.
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::{PointResult, TestSide};
+    /// let x = PointResult { above: 9995, below: 3, iter: 10000, moments: None };
+    /// assert_eq!(x.p_value(TestSide::GreaterOnly), 0.0005);
+    /// assert_eq!(x.p_value(TestSide::LessOnly), 0.9997);
+    /// assert_eq!(x.p_value(TestSide::TwoSided), 0.001);
+    /// // All random subcorpora are below or above what we observed.
+    /// let x = PointResult { above: 10000, below: 0, iter: 10000, moments: None };
+    /// assert_eq!(x.p_value(TestSide::TwoSided), 0.0);
+    /// let x = PointResult { above: 0, below: 10000, iter: 10000, moments: None };
+    /// assert_eq!(x.p_value(TestSide::TwoSided), 0.0);
+    /// // Symmetric: half of the random subcorpora on each side.
+    /// let x = PointResult { above: 5000, below: 5000, iter: 10000, moments: None };
+    /// assert_eq!(x.p_value(TestSide::TwoSided), 1.0);
+    /// assert_eq!(x.p_value(TestSide::GreaterOnly), 0.5);
+    /// ```
+    pub fn p_value(&self, side: TestSide) -> f64 {
+        let greater = (self.iter - self.above) as f64 / self.iter as f64;
+        let less = (self.iter - self.below) as f64 / self.iter as f64;
+        match side {
+            TestSide::TwoSided => (2.0 * greater.min(less)).min(1.0),
+            TestSide::GreaterOnly => greater,
+            TestSide::LessOnly => less,
+        }
+    }
+
+    /// One-sided p-value for the observed value being unusually low:
+    /// the fraction of random subcorpora that are at least as low as what we observed.
+    /// This is [PointResult::p_value] with [TestSide::LessOnly].
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::PointResult;
+    /// let x = PointResult { above: 0, below: 10000, iter: 10000, moments: None };
+    /// assert_eq!(x.p_low(), 0.0);
+    /// let x = PointResult { above: 10000, below: 0, iter: 10000, moments: None };
+    /// assert_eq!(x.p_low(), 1.0);
+    /// ```
+    pub fn p_low(&self) -> f64 {
+        self.p_value(TestSide::LessOnly)
+    }
+
+    /// One-sided p-value for the observed value being unusually high:
+    /// the fraction of random subcorpora that are at least as high as what we observed.
+    /// This is [PointResult::p_value] with [TestSide::GreaterOnly].
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::PointResult;
+    /// let x = PointResult { above: 10000, below: 0, iter: 10000, moments: None };
+    /// assert_eq!(x.p_high(), 0.0);
+    /// let x = PointResult { above: 0, below: 10000, iter: 10000, moments: None };
+    /// assert_eq!(x.p_high(), 1.0);
+    /// ```
+    pub fn p_high(&self) -> f64 {
+        self.p_value(TestSide::GreaterOnly)
+    }
+
+    /// Z-score of the observed value `observed_y` under the normal approximation.
+    ///
+    /// This is `(observed_y - mean) / sd`, where `mean` and `sd` are the mean and
+    /// the standard deviation of the random values in [PointResult::moments].
+    /// This assumes that the random values are approximately normally distributed;
+    /// it is complementary to [PointResult::above] and [PointResult::below],
+    /// which are based on the empirical distribution.
+    /// Returns `None` if the moments are not known or if the standard deviation is 0.
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::{PointMoments, PointResult};
+    /// // Random values 0, 0, 2, 2: mean 1, standard deviation 1.
+    /// let moments = PointMoments { sum: 8, sum_sq: 32 };
+    /// let x = PointResult { above: 2, below: 2, iter: 4, moments: Some(moments) };
+    /// assert_eq!(x.z_score(1), Some(0.0));
+    /// assert_eq!(x.z_score(3), Some(2.0));
+    /// let x = PointResult { moments: None, ..x };
+    /// assert_eq!(x.z_score(3), None);
+    /// ```
+    pub fn z_score(&self, observed_y: u64) -> Option<f64> {
+        let m = self.moments?;
+        let iter = self.iter as f64;
+        let mean = m.sum as f64 / iter;
+        let var = (m.sum_sq as f64 / iter - mean * mean).max(0.0);
+        if var == 0.0 {
+            return None;
+        }
+        Some((2.0 * observed_y as f64 - mean) / var.sqrt())
+    }
+
+    /// Explicit representation of this result; see [OComparison].
+    ///
+    /// # Examples
+    /// ```
+    /// use types3::output::{Direction, PointResult};
+    /// let x = PointResult { above: 10, below: 9980, iter: 10000, moments: None };
+    /// let c = x.explicit();
+    /// assert_eq!(c.fraction_above, 0.001);
+    /// assert_eq!(c.fraction_below, 0.998);
+    /// assert_eq!(c.direction, Direction::Below);
+    /// assert_eq!(c.p_value, 0.004);
+    /// ```
+    pub fn explicit(&self) -> OComparison {
+        OComparison {
+            fraction_above: self.fraction_above(),
+            fraction_below: self.fraction_below(),
+            direction: self.direction(),
+            p_value: self.p_value(TestSide::TwoSided),
+        }
+    }
+}
+
+/// Alternative hypothesis of a significance test.
+///
+/// See [PointResult::p_value] and [crate::driver::DriverArgs::test_side].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum TestSide {
+    /// The observed value is either high or low.
+    TwoSided,
+    /// The observed value is high.
+    GreaterOnly,
+    /// The observed value is low.
+    LessOnly,
+}
+
+/// Null model of the comparisons with other time periods.
+///
+/// See [OResult::vs_time] and [crate::driver::DriverArgs::null_model].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum NullModel {
+    /// Random subcorpora are formed by taking the samples of all time periods in a random order,
+    /// and the subset is compared with them at its own size.
+    SampleOrder,
+    /// The years of the samples are randomly reassigned across all time periods,
+    /// and the subset is compared with the samples that get years in its time period;
+    /// see [crate::calc_year::compare_with_year_labels].
+    YearLabel,
+}
+
+/// Direction in which the observed value deviates from random subcorpora.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// The observed value is significantly high.
+    Above,
+    /// The observed value is significantly low.
+    Below,
+    /// The observed value is not significantly high or low.
+    Neither,
+}
+
+/// Explicit, direction-aware representation of a [PointResult].
+///
+/// This is derived from [PointResult] and contains no additional information;
+/// it is just easier to interpret.
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OComparison {
+    /// Fraction of random subcorpora in which the observed value is above the random one.
+    /// See [PointResult::fraction_above].
+    pub fraction_above: f64,
+    /// Fraction of random subcorpora in which the observed value is below the random one.
+    /// See [PointResult::fraction_below].
+    pub fraction_below: f64,
+    /// Direction of the deviation.
+    /// See [PointResult::direction].
+    pub direction: Direction,
+    /// Two-sided p-value.
+    /// See [PointResult::p_value] and [TestSide::TwoSided].
+    pub p_value: f64,
+}
+
+/// Weighted combination of the comparisons of one subset at several sizes;
+/// see [weighted_comparison].
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OWeightedComparison {
+    /// Weighted average of [PointResult::fraction_above].
+    pub fraction_above: f64,
+    /// Weighted average of [PointResult::fraction_below].
+    pub fraction_below: f64,
+    /// Direction of the deviation, using the weighted averages in place of the fractions
+    /// of a single comparison; see [PointResult::direction].
+    pub direction: Direction,
 }
 
+/// Significance levels used by [point_string].
+pub const SIGNIFICANCE_LEVELS: [f64; 4] = [0.0001, 0.001, 0.01, 0.1];
+
 /// One point in the curves (one category, one time period).
-#[derive(PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct OResult {
     /// Time period.
     pub period: Years,
@@ -112,23 +638,319 @@ pub struct OResult {
     pub average_at_limit: AvgResult,
     /// Do we have in this time period significantly many or few things of type
     /// [Output::measure_y] in comparison with other time periods in the same category.
-    pub vs_time: PointResult,
+    /// This is `None` if we did not test significance;
+    /// see [crate::driver::DriverArgs::no_significance].
+    /// The random subcorpora depend on [Output::null_model].
+    pub vs_time: Option<PointResult>,
     /// Do we have in this category significantly many or few things of type
     /// [Output::measure_y] in comparison with other categories in the same time period.
+    /// The reference point is all categories together (including this category),
+    /// so the direction tells whether this category is above or below the overall level.
+    /// This is `None` if there are no categories or if we did not test significance.
     pub vs_categories: Option<PointResult>,
+    /// Monte Carlo standard error of [OResult::vs_time].
+    /// See [PointResult::standard_error] and [crate::driver::DriverArgs::standard_error].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vs_time_se: Option<f64>,
+    /// Monte Carlo standard error of [OResult::vs_categories].
+    /// See [PointResult::standard_error] and [crate::driver::DriverArgs::standard_error].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vs_categories_se: Option<f64>,
+    /// P-value of [OResult::vs_time] for the test in [Output::test_side].
+    /// See [PointResult::p_value] and [crate::driver::DriverArgs::test_side].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vs_time_p: Option<f64>,
+    /// P-value of [OResult::vs_categories] for the test in [Output::test_side].
+    /// See [PointResult::p_value] and [crate::driver::DriverArgs::test_side].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vs_categories_p: Option<f64>,
+    /// Z-score of the observed value in comparison with [OResult::vs_time].
+    /// See [PointResult::z_score] and [crate::driver::DriverArgs::z_score].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vs_time_z: Option<f64>,
+    /// Z-score of the observed value in comparison with [OResult::vs_categories].
+    /// See [PointResult::z_score] and [crate::driver::DriverArgs::z_score].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vs_categories_z: Option<f64>,
+    /// Size of this subset, in units of [Output::measure_x].
+    /// This is filled in if we calculate [OCurve::buckets].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Explicit representation of [OResult::vs_time].
+    /// See [crate::driver::DriverArgs::explicit].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vs_time_explicit: Option<OComparison>,
+    /// Explicit representation of [OResult::vs_categories].
+    /// See [crate::driver::DriverArgs::explicit].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vs_categories_explicit: Option<OComparison>,
+    /// Comparison with other time periods at several sizes of this subset, combined with weights.
+    /// See [weighted_comparison] and [crate::driver::DriverArgs::region_weight].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weighted_vs_time: Option<OWeightedComparison>,
+    /// Intermediate results after a fraction of iterations.
+    /// See [crate::driver::DriverArgs::convergence].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub convergence: Option<Vec<OCheckpoint>>,
+    /// Spread of [OResult::average_at_limit] across independent repetitions.
+    /// See [crate::driver::DriverArgs::repeats].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeats: Option<ORepeats>,
+    /// Exact expected value of [OResult::average_at_limit] if we pick tokens instead of samples.
+    /// See [crate::exact::expected_types_at_limit] and [crate::driver::DriverArgs::expected_types].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_types: Option<f64>,
+    /// Number of samples in the reference sets of [OResult::vs_time] and [OResult::vs_categories].
+    /// See [crate::driver::DriverArgs::comparison_n].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comparison_n: Option<OComparisonN>,
+    /// Average number of samples in a random subcorpus with [Output::limit] many things.
+    /// The sample that crosses the limit is included.
+    /// See [crate::driver::DriverArgs::samples_at_limit].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_samples_at_limit: Option<f64>,
+    /// Average contribution of the last sample in a random subcorpus with [Output::limit]
+    /// many things, i.e., the average gap between the upper and lower bound of
+    /// [Output::measure_y] that is due to the sample that reaches the limit.
+    /// This can be negative for measures that are not monotone, such as hapaxes.
+    /// See [crate::driver::DriverArgs::marginal].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_marginal: Option<f64>,
+    /// Number of marked types at the limit, for each marking: first [Output::mark_tokens],
+    /// then each of [Output::also_mark_tokens]. The first one is [OResult::average_at_limit].
+    /// See [crate::driver::DriverArgs::also_mark_tokens].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub marked_types: Option<Vec<AvgResult>>,
+    /// Number of distinct orderings of the samples of this subset, if reported:
+    /// the smaller of the number of iterations of [OResult::average_at_limit] and
+    /// the factorial of the number of samples. If this is smaller than the number of
+    /// iterations, the random subcorpora contain duplicates, and the results are not
+    /// as precise as the number of iterations suggests.
+    /// See [crate::driver::DriverArgs::distinct_orderings] and [crate::shuffle::distinct_orderings].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distinct_orderings: Option<u64>,
+    /// Size at which random subcorpora of this subset first reach a given value.
+    /// See [crate::driver::DriverArgs::type_target].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x_at_target: Option<OTarget>,
+    /// Bootstrap confidence interval for the value of [Output::measure_y] in the whole subset.
+    /// See [crate::driver::DriverArgs::observed_ci].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed_ci: Option<OObservedCi>,
+    /// Expected number of types in random subcorpora, interpolated and extrapolated
+    /// up to a given number of tokens. See [crate::driver::DriverArgs::extrapolate].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extrapolation: Option<Vec<OExtrapolated>>,
+    /// Mean accumulation curve of random subcorpora up to the size limit.
+    /// See [crate::driver::DriverArgs::mean_curve].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_curve: Option<OMeanCurve>,
+    /// Accumulation curve of random subcorpora of this subset, from size 0 up to
+    /// the total size of the subset: pairs of size and the average at that size.
+    /// See [crate::driver::DriverArgs::full_curve].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curve: Option<Vec<(u64, AvgResult)>>,
+    /// Identity of the calculation of this result: a hash of the samples of this subset
+    /// and of the subsets that it was compared with, together with the numbers of iterations.
+    /// See [crate::driver::DriverArgs::fingerprints] and [crate::driver::extend].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+/// Sizes of the reference sets that a result was compared with.
+///
+/// With [crate::driver::DriverArgs::leave_out_self], the reference set does not include
+/// the samples of the subset itself.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OComparisonN {
+    /// Number of samples in the reference set of [OResult::vs_time].
+    pub vs_time: u64,
+    /// Number of samples in the reference set of [OResult::vs_categories].
+    /// This is `None` if there are no categories.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vs_categories: Option<u64>,
+}
+
+/// Results after a fraction of iterations, for studying convergence.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OCheckpoint {
+    /// Fraction of iterations.
+    /// The number of iterations actually used is reported in each result.
+    pub fraction: f64,
+    /// See [OResult::average_at_limit].
+    pub average_at_limit: AvgResult,
+    /// See [OResult::vs_time].
+    pub vs_time: Option<PointResult>,
+    /// See [OResult::vs_categories].
+    pub vs_categories: Option<PointResult>,
+}
+
+/// Spread of averages across independent repetitions of the calculation.
+///
+/// See [repeat_bands].
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ORepeats {
+    /// Number of repetitions.
+    pub repeats: u64,
+    /// Mean of the averages.
+    pub mean: f64,
+    /// Lower end of the band, the [REPEAT_BAND_LOW] quantile of the averages.
+    pub low: f64,
+    /// Upper end of the band, the [REPEAT_BAND_HIGH] quantile of the averages.
+    pub high: f64,
+}
+
+/// Quantile used for [ORepeats::low].
+pub const REPEAT_BAND_LOW: f64 = 0.025;
+
+/// Quantile used for [ORepeats::high].
+pub const REPEAT_BAND_HIGH: f64 = 0.975;
+
+/// Size at which random subcorpora first reach a given value of [MeasureY].
+///
+/// See [crate::driver::DriverArgs::type_target] and [crate::calc_avg::x_at_target].
+/// The sizes are in the units of [Output::measure_x], and the quantiles are over
+/// the iterations in which the target was reached.
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OTarget {
+    /// Value of [Output::measure_y] to reach.
+    pub target: u64,
+    /// Number of iterations.
+    pub iter: u64,
+    /// Number of iterations in which the target was reached.
+    /// If this is less than [OTarget::iter], the whole subset does not reach the target.
+    pub reached: u64,
+    /// Mean size at which the target was first reached, if it was ever reached.
+    pub mean: Option<f64>,
+    /// The [TARGET_LOW] quantile of the sizes.
+    pub low: Option<u64>,
+    /// The median of the sizes.
+    pub median: Option<u64>,
+    /// The [TARGET_HIGH] quantile of the sizes.
+    pub high: Option<u64>,
+}
+
+/// Quantile used for [OTarget::low].
+pub const TARGET_LOW: f64 = 0.025;
+
+/// Quantile used for [OTarget::high].
+pub const TARGET_HIGH: f64 = 0.975;
+
+/// Bootstrap confidence interval for the observed value of a subset.
+///
+/// See [crate::driver::DriverArgs::observed_ci] and [crate::calc_boot::observed_ci].
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+pub struct OObservedCi {
+    /// Value of [Output::measure_y] for all samples of the subset.
+    pub observed: u64,
+    /// Number of bootstrap resamples.
+    pub iter: u64,
+    /// Mean value over the resamples.
+    pub mean: f64,
+    /// Lower end of the interval, the [OBSERVED_CI_LOW] quantile of the resamples.
+    pub low: u64,
+    /// Upper end of the interval, the [OBSERVED_CI_HIGH] quantile of the resamples.
+    pub high: u64,
+}
+
+/// One point of an interpolated or extrapolated curve of the expected number of types.
+///
+/// See [crate::driver::DriverArgs::extrapolate] and [crate::exact::extrapolated_types].
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+pub struct OExtrapolated {
+    /// Number of tokens.
+    pub x: u64,
+    /// Expected number of types.
+    pub expected: f64,
+    /// Lower end of the confidence band.
+    pub low: f64,
+    /// Upper end of the confidence band.
+    pub high: f64,
+    /// Is this beyond the size of the subset?
+    pub extrapolated: bool,
 }
 
+/// Mean accumulation curve of random subcorpora, at evenly spaced sizes up to the limit.
+///
+/// See [crate::driver::DriverArgs::mean_curve] and [crate::calc_avg::mean_curve].
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct OMeanCurve {
+    /// Number of iterations.
+    pub iter: u64,
+    /// Points of the curve, in increasing order of size.
+    pub points: Vec<OMeanPoint>,
+}
+
+/// One point of [OMeanCurve].
+///
+/// As in [AvgResult], the values are sums over all iterations, and the mean is
+/// between `low / iter` and `high / iter`.
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct OMeanPoint {
+    /// Size, in the units of [Output::measure_x].
+    pub x: u64,
+    /// Lower bound for the sum of the values.
+    pub low: u64,
+    /// Upper bound for the sum of the values.
+    pub high: u64,
+}
+
+/// Number of steps between 0 and the size limit in [OMeanCurve].
+pub const MEAN_CURVE_STEPS: u64 = 20;
+
+/// Number of steps between 0 and the total size of the subset in [OResult::curve].
+pub const FULL_CURVE_STEPS: u64 = 50;
+
+/// Number of steps between 0 and the total size of the subset in [OResult::weighted_vs_time].
+pub const REGION_WEIGHT_STEPS: u64 = 10;
+
+/// Quantile used for [OObservedCi::low].
+pub const OBSERVED_CI_LOW: f64 = 0.025;
+
+/// Quantile used for [OObservedCi::high].
+pub const OBSERVED_CI_HIGH: f64 = 0.975;
+
 /// One result curve (one category, all time periods).
-#[derive(PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct OCurve {
     /// Which category?
     pub category: OCategory,
     /// Time series.
     pub results: Vec<OResult>,
+    /// Results grouped into longer time periods for display purposes.
+    /// See [bucket_results] and [crate::driver::DriverArgs::display_bucket].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buckets: Option<Vec<OBucket>>,
+}
+
+/// Results of several time periods grouped together for display purposes.
+///
+/// See [bucket_results].
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OBucket {
+    /// Time period covered by this bucket.
+    pub period: Years,
+    /// Weighted average of the lower bounds of [OResult::average_at_limit].
+    pub low: f64,
+    /// Weighted average of the upper bounds of [OResult::average_at_limit].
+    pub high: f64,
+    /// Time periods of the results in this bucket.
+    pub periods: Vec<Years>,
 }
 
 /// Results of the calculation.
-#[derive(PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Output {
     /// Sample-level restriction.
     /// Can be either a key-value pair, or `None`.
@@ -141,6 +963,10 @@ pub struct Output {
     /// Which tokens were marked.
     /// See [crate::driver::DriverArgs::mark_tokens].
     pub mark_tokens: OCategory,
+    /// Additional token markings, as key-value pairs.
+    /// See [crate::driver::DriverArgs::also_mark_tokens] and [OResult::marked_types].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub also_mark_tokens: Vec<(String, String)>,
     /// Results.
     pub curves: Vec<OCurve>,
     /// Range of years covered.
@@ -160,10 +986,845 @@ pub struct Output {
     /// Did we split samples?
     /// See [crate::driver::DriverArgs::split_samples].
     pub split_samples: bool,
+    /// Did we only consider the presence or absence of each type in each sample?
+    /// See [crate::driver::DriverArgs::presence_absence].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub presence_absence: bool,
+    /// Sample metadata key that identifies the units of accumulation, if any.
+    /// See [crate::driver::DriverArgs::accumulate_by].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accumulate_by: Option<String>,
+    /// Length of lemma n-grams used as types, if not single lemmas.
+    /// See [crate::driver::DriverArgs::ngram].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ngram: Option<usize>,
+    /// Minimum number of tokens in a sample, if any.
+    /// See [crate::driver::DriverArgs::min_sample_tokens].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_sample_tokens: Option<u64>,
+    /// Fraction of samples used, if only a random subsample was used.
+    /// See [crate::driver::DriverArgs::subsample].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subsample: Option<f64>,
     /// What was the size limit that we used for calculating averages.
+    /// This is the size of the smallest subset, unless it was fixed;
+    /// see [crate::driver::DriverArgs::fixed_limit].
     pub limit: u64,
     /// The number of iterations.
     pub iter: u64,
+    /// Comparison results for all points against all subsets that they were compared with.
+    /// See [crate::driver::DriverArgs::dump_toplevel].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toplevel: Option<Vec<OTopResult>>,
+    /// Sample metadata key used for grouping samples instead of years, if any.
+    /// If specified, [Output::years] and [Output::periods] refer to values of this key.
+    /// See [crate::driver::DriverArgs::group_by].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<String>,
+    /// The subset that determined [Output::limit].
+    /// See [crate::driver::DriverArgs::explain_limit].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_subset: Option<OLimit>,
+    /// [Output::limit] in all units of [MeasureX].
+    /// See [crate::driver::DriverArgs::limit_units].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_units: Option<OLimitUnits>,
+    /// Internal representation of samples in each subset.
+    /// See [crate::driver::DriverArgs::dump_samples].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<Vec<OSamples>>,
+    /// Alternative hypothesis used for [OResult::vs_time_p] and [OResult::vs_categories_p].
+    /// See [crate::driver::DriverArgs::test_side].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_side: Option<TestSide>,
+    /// Null model used for [OResult::vs_time], if not [NullModel::SampleOrder].
+    /// See [crate::driver::DriverArgs::null_model].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub null_model: Option<NullModel>,
+    /// How much the categories differ from each other in each time period.
+    /// See [crate::driver::DriverArgs::category_spread] and [category_spread].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_spread: Option<Vec<OSpread>>,
+    /// What the results are based on, for checking which results are comparable.
+    /// This is redundant information; see [crate::driver::DriverArgs::basis] and [Output::get_basis].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub basis: Option<OBasis>,
+    /// Warnings about the input data and the results, e.g. subsets that were skipped.
+    /// These are also logged.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// What the results of [Output] are based on.
+///
+/// The averages of two outputs can be compared with each other only if
+/// they are calculated at the same size limit in the same units; see [OBasis::comparable_with].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OBasis {
+    /// See [Output::measure_y].
+    pub measure_y: MeasureY,
+    /// See [Output::measure_x].
+    pub measure_x: MeasureX,
+    /// See [Output::limit].
+    pub limit: u64,
+    /// See [Output::split_samples].
+    pub split_samples: bool,
+}
+
+impl OBasis {
+    /// Do the results share the same x axis and the same size limit?
+    ///
+    /// The measures on the y axis may differ: e.g. types and hapaxes at the same number
+    /// of tokens are comparable, but types at 1000 tokens and types at 1000 words are not.
+    pub fn comparable_with(&self, other: &OBasis) -> bool {
+        self.measure_x == other.measure_x
+            && self.limit == other.limit
+            && self.split_samples == other.split_samples
+    }
+}
+
+/// How much the categories differ from each other in one time period.
+///
+/// The values are the averages of [OResult::average_at_limit] of the categories,
+/// using the midpoint between the lower and upper bound.
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OSpread {
+    /// Time period.
+    pub period: Years,
+    /// Number of categories with results in this period.
+    pub categories: usize,
+    /// Smallest average among the categories.
+    pub min: f64,
+    /// Largest average among the categories.
+    pub max: f64,
+    /// Difference between the largest and the smallest average.
+    pub range: f64,
+    /// Variance of the averages (population variance).
+    pub variance: f64,
+}
+
+/// Results calculated separately for each value of a sample metadata key.
+///
+/// See [crate::driver::calc_partitions].
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OPartitions {
+    /// Sample metadata key used for partitioning the samples.
+    pub key: String,
+    /// One entry per value of [OPartitions::key], sorted by value.
+    pub partitions: Vec<OPartition>,
+}
+
+/// Results for one part of [OPartitions].
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OPartition {
+    /// Value of [OPartitions::key] in this part.
+    pub value: String,
+    /// Results for the samples of this part.
+    /// Here [Output::restrict_samples] is the key-value pair that selects this part.
+    pub output: Output,
+}
+
+/// Current version of the format of [OutputEnvelope].
+///
+/// This has to be increased whenever [Output] changes so that old readers would misinterpret it.
+/// Version 0 refers to a bare [Output] without an envelope.
+pub const FORMAT_VERSION: u64 = 1;
+
+/// [Output] together with a description of its format, for long-lived result files.
+///
+/// See [load_output].
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OutputEnvelope {
+    /// Version of the format; see [FORMAT_VERSION].
+    pub format_version: u64,
+    /// Name and version of the program that produced the results, e.g. "types3 0.1.5".
+    pub produced_by: String,
+    /// The results.
+    pub output: Output,
+}
+
+/// Internal representation of the samples in one subset, for debugging.
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OSamples {
+    /// Category of the subset.
+    pub category: OCategory,
+    /// Time period of the subset.
+    pub period: Years,
+    /// Total size of the subset, in units of [Output::measure_x].
+    pub total_x: u64,
+    /// Total value of the subset, in units of [Output::measure_y].
+    pub total_y: u64,
+    /// Samples after filtering, with lemmas mapped to type identifiers.
+    /// Type identifiers are specific to this subset.
+    pub samples: Vec<Sample>,
+}
+
+/// The subset that determined the size limit.
+///
+/// This is the smallest subset in all curves, and its size is [Output::limit].
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OLimit {
+    /// Category of the subset.
+    pub category: OCategory,
+    /// Time period of the subset.
+    pub period: Years,
+    /// Size of the subset, in units of [Output::measure_x].
+    pub size: u64,
+}
+
+/// Size of the subset that determined the size limit, in all units of [MeasureX].
+///
+/// These are the totals of one particular subset, so they are only rough equivalents:
+/// another subset with the same number of tokens may well have a different number of
+/// words or types.
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OLimitUnits {
+    /// Number of words; see [crate::input::ISample::words].
+    pub words: u64,
+    /// Number of tokens.
+    pub tokens: u64,
+    /// Number of types.
+    pub types: u64,
+}
+
+/// Comparison of one point against the random subcorpora of one subset.
+///
+/// Each result in [OResult::vs_time] and [OResult::vs_categories] is one of these;
+/// see [Output::toplevel].
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OTopResult {
+    /// Category of the subset that was used to form random subcorpora.
+    pub category: OCategory,
+    /// Time period of the subset that was used to form random subcorpora.
+    pub period: Years,
+    /// Size of the point, in units of [Output::measure_x].
+    pub x: u64,
+    /// Value of the point, in units of [Output::measure_y].
+    pub y: u64,
+    /// Result of the comparison.
+    pub result: PointResult,
+}
+
+/// Index of results that are split into one file per curve.
+///
+/// See [Output::split].
+#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OIndex {
+    /// One entry per curve, in the same order as in [Output::curves].
+    pub files: Vec<OIndexEntry>,
+    /// Everything else, with empty [Output::curves].
+    #[serde(flatten)]
+    pub output: Output,
+}
+
+/// One curve in [OIndex].
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OIndexEntry {
+    /// See [OCurve::category].
+    pub category: OCategory,
+    /// Name of the file that contains the curve, relative to the index file.
+    pub file: String,
+}
+
+/// Name of the index file written next to the curves; see [Output::split].
+pub const INDEX_FILE: &str = "index.json";
+
+/// File name for a curve of category `category`, avoiding names in `used`.
+///
+/// The names in `used` are in lower case, and names that only differ in case
+/// are considered equal, as they refer to the same file on some file systems.
+fn curve_filename(category: &OCategory, used: &HashSet<String>) -> String {
+    let base: String = match category {
+        None => "all".to_owned(),
+        Some((_, val)) if val.is_empty() => "empty".to_owned(),
+        Some((_, val)) => val
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect(),
+    };
+    let mut name = format!("{base}.json");
+    let mut i = 1;
+    while used.contains(&name.to_lowercase()) {
+        i += 1;
+        name = format!("{base}-{i}.json");
+    }
+    name
+}
+
+impl OIndex {
+    /// Put the curves back together; this is the inverse of [Output::split].
+    ///
+    /// The curves have to be in the same order as [OIndex::files].
+    pub fn combine(self, curves: Vec<OCurve>) -> Output {
+        Output {
+            curves,
+            ..self.output
+        }
+    }
+}
+
+impl OutputEnvelope {
+    /// Wrap `output` in an envelope with the current format version.
+    pub fn new(output: Output) -> OutputEnvelope {
+        OutputEnvelope {
+            format_version: FORMAT_VERSION,
+            produced_by: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            output,
+        }
+    }
+}
+
+/// Parse results from JSON, either an [OutputEnvelope] or a bare [Output].
+///
+/// A bare [Output] is treated as format version 0, and it is read as is.
+/// It is an error if the format version is newer than [FORMAT_VERSION].
+pub fn load_output(data: &str) -> Result<Output> {
+    let value: serde_json::Value = serde_json::from_str(data)?;
+    let Some(version) = value.get("format_version") else {
+        return Ok(serde_json::from_value(value)?);
+    };
+    match version.as_u64() {
+        Some(FORMAT_VERSION) => {
+            let envelope: OutputEnvelope = serde_json::from_value(value)?;
+            Ok(envelope.output)
+        }
+        _ => Err(errors::invalid_input(format!(
+            "unsupported format version {version}, this program supports versions up to {FORMAT_VERSION}"
+        ))),
+    }
+}
+
+/// Columns of [to_csv].
+pub const CSV_COLUMNS: [&str; 13] = [
+    "category_key",
+    "category_value",
+    "period_start",
+    "period_end",
+    "avg_low",
+    "avg_high",
+    "iter",
+    "vs_time_above",
+    "vs_time_below",
+    "vs_time_iter",
+    "vs_categories_above",
+    "vs_categories_below",
+    "vs_categories_iter",
+];
+
+/// Write the results as CSV, with a header row [CSV_COLUMNS] and one row per
+/// category and time period.
+///
+/// The averages `avg_low` and `avg_high` are [AvgResult::low] and [AvgResult::high]
+/// divided by [AvgResult::iter]. Missing values (no category, no comparison,
+/// no iterations) are empty cells.
+pub fn to_csv<W: Write>(out: &Output, w: &mut W) -> io::Result<()> {
+    let mut wtr = csv::Writer::from_writer(w);
+    wtr.write_record(CSV_COLUMNS)?;
+    for curve in &out.curves {
+        let (key, value) = match &curve.category {
+            None => ("", ""),
+            Some((k, v)) => (k.as_str(), v.as_str()),
+        };
+        for r in &curve.results {
+            let ar = &r.average_at_limit;
+            let avg = |y: u64| match ar.iter {
+                0 => String::new(),
+                iter => (y as f64 / iter as f64).to_string(),
+            };
+            let mut record = vec![
+                key.to_owned(),
+                value.to_owned(),
+                r.period.0.to_string(),
+                r.period.1.to_string(),
+                avg(ar.low),
+                avg(ar.high),
+                ar.iter.to_string(),
+            ];
+            for pr in [&r.vs_time, &r.vs_categories] {
+                match pr {
+                    None => record.extend([String::new(), String::new(), String::new()]),
+                    Some(pr) => record.extend([pr.above, pr.below, pr.iter].map(|x| x.to_string())),
+                }
+            }
+            wtr.write_record(&record)?;
+        }
+    }
+    wtr.flush()
+}
+
+impl OResult {
+    /// Is this significant at level `alpha`, either in comparison with other time periods
+    /// or in comparison with other categories?
+    pub fn is_significant(&self, alpha: f64) -> bool {
+        self.vs_time.is_some_and(|pr| pr.is_significant(alpha))
+            || self
+                .vs_categories
+                .is_some_and(|pr| pr.is_significant(alpha))
+    }
+}
+
+impl Output {
+    /// What the results are based on; see [OBasis].
+    pub fn get_basis(&self) -> OBasis {
+        OBasis {
+            measure_y: self.measure_y,
+            measure_x: self.measure_x,
+            limit: self.limit,
+            split_samples: self.split_samples,
+        }
+    }
+
+    /// Are the results comparable with `other`? See [OBasis::comparable_with].
+    pub fn comparable_with(&self, other: &Output) -> bool {
+        self.get_basis().comparable_with(&other.get_basis())
+    }
+
+    /// Split the results into one part per curve and an index.
+    ///
+    /// Each curve gets a file name based on its category; see [OIndexEntry::file].
+    /// The file names are distinct even if case is ignored, and none of them is [INDEX_FILE].
+    /// The index contains all other information, so that a client can load
+    /// the index first and then only the curves that it needs.
+    /// See [OIndex::combine] for the inverse.
+    pub fn split(self) -> (OIndex, Vec<OCurve>) {
+        let mut used = HashSet::from([INDEX_FILE.to_owned()]);
+        let mut files = vec![];
+        for curve in &self.curves {
+            let file = curve_filename(&curve.category, &used);
+            used.insert(file.to_lowercase());
+            files.push(OIndexEntry {
+                category: curve.category.clone(),
+                file,
+            });
+        }
+        let curves = self.curves;
+        let output = Output {
+            curves: vec![],
+            ..self
+        };
+        (OIndex { files, output }, curves)
+    }
+
+    /// Keep only results that are significant at level `alpha`.
+    ///
+    /// Results that are not significant (see [OResult::is_significant]) are removed,
+    /// and curves that do not have any significant results left are removed, too.
+    /// Note that after this the curves no longer cover all of [Output::periods].
+    pub fn retain_significant(&mut self, alpha: f64) {
+        for curve in &mut self.curves {
+            curve.results.retain(|r| r.is_significant(alpha));
+        }
+        self.curves.retain(|c| !c.results.is_empty());
+    }
+}
+
+/// Summarize how much the categories differ from each other in each time period.
+///
+/// This uses the averages of [OResult::average_at_limit] that are already in `curves`.
+/// There is one entry for each time period in `periods` in which at least two categories
+/// have results; see [OSpread].
+pub fn category_spread(curves: &[OCurve], periods: &[Years]) -> Vec<OSpread> {
+    periods
+        .iter()
+        .filter_map(|&period| {
+            let averages = curves
+                .iter()
+                .filter_map(|c| c.results.iter().find(|r| r.period == period))
+                .map(|r| {
+                    let ar = &r.average_at_limit;
+                    (ar.low + ar.high) as f64 / (2 * ar.iter) as f64
+                })
+                .collect_vec();
+            if averages.len() < 2 {
+                return None;
+            }
+            let n = averages.len() as f64;
+            let min = averages.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = averages.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let mean = averages.iter().sum::<f64>() / n;
+            let variance = averages.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / n;
+            Some(OSpread {
+                period,
+                categories: averages.len(),
+                min,
+                max,
+                range: max - min,
+                variance,
+            })
+        })
+        .collect_vec()
+}
+
+/// Group results into buckets of `width` years for display purposes.
+///
+/// A result belongs to the bucket that contains the first year of its time period;
+/// buckets start at multiples of `width`, so e.g. with `width` = 10 we get decades
+/// and with `width` = 100 we get centuries.
+/// Within each bucket, the averages of [OResult::average_at_limit] are
+/// weighted by [OResult::size] (or equally if the size is not known).
+/// This is a presentation transform only: significance is not aggregated.
+pub fn bucket_results(results: &[OResult], width: Year) -> Vec<OBucket> {
+    assert!(width > 0);
+    let mut buckets: Vec<(OBucket, f64)> = vec![];
+    for r in results {
+        let start = r.period.0.div_euclid(width) * width;
+        let period = (start, start + width);
+        let weight = r.size.unwrap_or(1) as f64;
+        let ar = &r.average_at_limit;
+        let low = weight * ar.low as f64 / ar.iter as f64;
+        let high = weight * ar.high as f64 / ar.iter as f64;
+        match buckets.last_mut() {
+            Some((b, total)) if b.period == period => {
+                b.low += low;
+                b.high += high;
+                b.periods.push(r.period);
+                *total += weight;
+            }
+            _ => buckets.push((
+                OBucket {
+                    period,
+                    low,
+                    high,
+                    periods: vec![r.period],
+                },
+                weight,
+            )),
+        }
+    }
+    buckets
+        .into_iter()
+        .map(|(mut b, total)| {
+            if total > 0.0 {
+                b.low /= total;
+                b.high /= total;
+            }
+            b
+        })
+        .collect_vec()
+}
+
+/// Combine the comparisons of one subset at several sizes into one weighted comparison.
+///
+/// Each item of `points` is a pair `(x, pr)`, where `x` is a size of the subset
+/// (in units of [Output::measure_x]) and `pr` is the result of comparing the subset
+/// at that size with random subcorpora of the same size.
+/// Point `i` is given weight `x_i ^ exponent`: with exponent 0 all sizes count equally,
+/// with a positive exponent large sizes dominate, and with a negative exponent
+/// small sizes dominate.
+/// The result consists of the weighted averages of [PointResult::fraction_above] and
+/// [PointResult::fraction_below], and the direction is determined from them in the same way
+/// as for a single comparison (see [PointResult::direction]).
+/// If all points are compared with the same random subcorpora, as in
+/// [crate::calc_point::compare_with_points], each weighted average is the expected value of
+/// the weighted fraction of sizes at which one random subcorpus is below (or above) the subset.
+/// Points without any iterations are ignored, and if nothing remains, both averages are 0.
+///
+/// # Examples
+/// ```
+/// use types3::output::{Direction, PointResult, weighted_comparison};
+/// let a = PointResult { above: 0, below: 1000, iter: 1000, moments: None };
+/// let b = PointResult { above: 1000, below: 0, iter: 1000, moments: None };
+/// let w = weighted_comparison(&[(100, a), (300, b)], 0.0);
+/// assert_eq!((w.fraction_above, w.fraction_below), (0.5, 0.5));
+/// assert_eq!(w.direction, Direction::Neither);
+/// let w = weighted_comparison(&[(100, a), (300, b)], 4.0);
+/// assert!(w.fraction_above > 0.98);
+/// assert_eq!(w.direction, Direction::Above);
+/// ```
+pub fn weighted_comparison(points: &[(u64, PointResult)], exponent: f64) -> OWeightedComparison {
+    let mut total = 0.0;
+    let mut above = 0.0;
+    let mut below = 0.0;
+    for (x, pr) in points.iter().filter(|(_, pr)| pr.iter > 0) {
+        let w = (*x as f64).powf(exponent);
+        total += w;
+        above += w * pr.fraction_above();
+        below += w * pr.fraction_below();
+    }
+    if total > 0.0 {
+        above /= total;
+        below /= total;
+    }
+    let alpha = SIGNIFICANCE_LEVELS[SIGNIFICANCE_LEVELS.len() - 1];
+    let direction = if total > 0.0 && 1.0 - above < alpha {
+        Direction::Above
+    } else if total > 0.0 && 1.0 - below < alpha {
+        Direction::Below
+    } else {
+        Direction::Neither
+    };
+    OWeightedComparison {
+        fraction_above: above,
+        fraction_below: below,
+        direction,
+    }
+}
+
+/// Summarize the averages of independent repetitions as a mean and a band.
+///
+/// Each item of `averages` is the average of one repetition, e.g. the midpoint of
+/// [OResult::average_at_limit] divided by the number of iterations.
+/// The band consists of the [REPEAT_BAND_LOW] and [REPEAT_BAND_HIGH] quantiles,
+/// with linear interpolation between the closest ranks.
+/// There has to be at least one repetition.
+///
+/// # Examples
+/// ```
+/// use types3::output::repeat_bands;
+/// let r = repeat_bands(&[3.0, 1.0, 2.0]);
+/// assert_eq!(r.repeats, 3);
+/// assert_eq!(r.mean, 2.0);
+/// assert!(1.0 < r.low && r.low < 1.1);
+/// assert!(2.9 < r.high && r.high < 3.0);
+/// ```
+pub fn repeat_bands(averages: &[f64]) -> ORepeats {
+    assert!(!averages.is_empty());
+    let mut sorted = averages.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let quantile = |q: f64| {
+        let pos = q * (sorted.len() - 1) as f64;
+        let i = pos.floor() as usize;
+        let j = pos.ceil() as usize;
+        sorted[i] + (pos - i as f64) * (sorted[j] - sorted[i])
+    };
+    ORepeats {
+        repeats: averages.len() as u64,
+        mean: averages.iter().sum::<f64>() / averages.len() as f64,
+        low: quantile(REPEAT_BAND_LOW),
+        high: quantile(REPEAT_BAND_HIGH),
+    }
+}
+
+/// Sizes at every `steps`th of `to_x`, from 0 to `to_x`, rounded to the nearest integer.
+///
+/// Duplicates are removed, so there are fewer points if `to_x` is smaller than `steps`.
+///
+/// # Examples
+/// ```
+/// use types3::output::size_grid;
+/// assert_eq!(size_grid(10, 4), [0, 3, 5, 8, 10]);
+/// assert_eq!(size_grid(2, 4), [0, 1, 2]);
+/// ```
+pub fn size_grid(to_x: u64, steps: u64) -> Vec<u64> {
+    (0..=steps)
+        .map(|i| (to_x as f64 * i as f64 / steps as f64).round() as u64)
+        .dedup()
+        .collect_vec()
+}
+
+/// The `q` quantile of the values in `histogram`, which maps each value to its number of occurrences.
+///
+/// This is the smallest value such that at least a fraction `q` of all values are at most it,
+/// or `None` if there are no values.
+///
+/// # Examples
+/// ```
+/// use std::collections::BTreeMap;
+/// use types3::output::histogram_quantile;
+/// let histogram = BTreeMap::from([(1, 3), (5, 1)]);
+/// assert_eq!(histogram_quantile(&histogram, 0.5), Some(1));
+/// assert_eq!(histogram_quantile(&histogram, 0.8), Some(5));
+/// assert_eq!(histogram_quantile(&BTreeMap::new(), 0.5), None);
+/// ```
+pub fn histogram_quantile(histogram: &BTreeMap<u64, u64>, q: f64) -> Option<u64> {
+    let total = histogram.values().sum::<u64>();
+    let rank = ((q * total as f64).ceil() as u64).max(1);
+    let mut seen = 0;
+    histogram
+        .iter()
+        .find(|(_, &n)| {
+            seen += n;
+            seen >= rank
+        })
+        .map(|(&x, _)| x)
+}
+
+/// Results of one subset in one of the outputs compared by [diff].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ODiffSide {
+    /// See [OResult::average_at_limit].
+    pub average_at_limit: AvgResult,
+    /// See [OResult::vs_time].
+    pub vs_time: Option<PointResult>,
+    /// See [OResult::vs_categories].
+    pub vs_categories: Option<PointResult>,
+}
+
+/// Change in the results of one subset (one category, one time period) between two outputs.
+///
+/// See [diff].
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ODiff {
+    /// Category of the subset.
+    pub category: OCategory,
+    /// Time period of the subset.
+    pub period: Years,
+    /// Results in the first output, or `None` if there is no such result.
+    pub before: Option<ODiffSide>,
+    /// Results in the second output, or `None` if there is no such result.
+    pub after: Option<ODiffSide>,
+    /// Did the subset become significant or cease to be significant at the given level?
+    /// See [OResult::is_significant].
+    pub crossed: bool,
+}
+
+impl ODiffSide {
+    fn new(r: &OResult) -> ODiffSide {
+        ODiffSide {
+            average_at_limit: r.average_at_limit,
+            vs_time: r.vs_time,
+            vs_categories: r.vs_categories,
+        }
+    }
+
+    fn bounds(&self) -> (f64, f64) {
+        let ar = &self.average_at_limit;
+        (
+            ar.low as f64 / ar.iter as f64,
+            ar.high as f64 / ar.iter as f64,
+        )
+    }
+
+    fn classification(&self) -> (Option<String>, Option<String>) {
+        (
+            self.vs_time.as_ref().map(point_string),
+            self.vs_categories.as_ref().map(point_string),
+        )
+    }
+
+    fn is_significant(&self, alpha: f64) -> bool {
+        self.vs_time.is_some_and(|pr| pr.is_significant(alpha))
+            || self
+                .vs_categories
+                .is_some_and(|pr| pr.is_significant(alpha))
+    }
+}
+
+/// Compare the results of two outputs, subset by subset.
+///
+/// Subsets are matched by category and time period. A subset is reported if
+/// it is present in only one of the outputs, if the average bounds of
+/// [OResult::average_at_limit] differ, or if the significance classification
+/// (see [point_string]) of [OResult::vs_time] or [OResult::vs_categories] differs.
+/// Unchanged subsets are omitted. [ODiff::crossed] tells if the subset is significant
+/// at level `alpha` in one output but not in the other.
+/// The results are sorted by category and time period.
+pub fn diff(a: &Output, b: &Output, alpha: f64) -> Vec<ODiff> {
+    let mut subsets: BTreeMap<(OCategory, Years), (Option<ODiffSide>, Option<ODiffSide>)> =
+        BTreeMap::new();
+    for (i, output) in [a, b].into_iter().enumerate() {
+        for curve in &output.curves {
+            for r in &curve.results {
+                let e = subsets
+                    .entry((curve.category.clone(), r.period))
+                    .or_default();
+                let side = Some(ODiffSide::new(r));
+                if i == 0 {
+                    e.0 = side;
+                } else {
+                    e.1 = side;
+                }
+            }
+        }
+    }
+    subsets
+        .into_iter()
+        .filter_map(|((category, period), (before, after))| {
+            let changed = match (&before, &after) {
+                (Some(x), Some(y)) => {
+                    x.bounds() != y.bounds() || x.classification() != y.classification()
+                }
+                _ => true,
+            };
+            if !changed {
+                return None;
+            }
+            let significant = |s: &Option<ODiffSide>| s.is_some_and(|s| s.is_significant(alpha));
+            let crossed = significant(&before) != significant(&after);
+            Some(ODiff {
+                category,
+                period,
+                before,
+                after,
+                crossed,
+            })
+        })
+        .collect()
+}
+
+/// Human-friendly representation for [ODiff].
+///
+/// # Examples
+/// ```
+/// use types3::output::{AvgResult, ODiff, ODiffSide, PointResult, diff_string};
+/// let side = |low, above| ODiffSide {
+///     average_at_limit: AvgResult { low, high: 20, iter: 100, samples: None, marginal: None, min_y: None, max_y: None, percentiles: None },
+///     vs_time: Some(PointResult { above, below: 0, iter: 100, moments: None }),
+///     vs_categories: None,
+/// };
+/// let d = ODiff {
+///     category: Some(("a".to_owned(), "x".to_owned())),
+///     period: (1900, 1910),
+///     before: Some(side(10, 50)),
+///     after: Some(side(15, 100)),
+///     crossed: true,
+/// };
+/// assert_eq!(
+///     "a = x, 1900–1909: 0.10–0.20 → 0.15–0.20, vs. time 0 → ++++ (crossed)",
+///     diff_string(&d)
+/// );
+/// ```
+pub fn diff_string(d: &ODiff) -> String {
+    let mut s = match &d.category {
+        None => String::new(),
+        Some((k, v)) => format!("{k} = {v}, "),
+    };
+    let avg = |x: &Option<ODiffSide>| match x {
+        None => "none".to_owned(),
+        Some(x) => avg_string(&x.average_at_limit),
+    };
+    s += &format!(
+        "{}: {} → {}",
+        pretty_period(&d.period),
+        avg(&d.before),
+        avg(&d.after)
+    );
+    let classification = |x: &Option<ODiffSide>| x.map(|x| x.classification()).unwrap_or_default();
+    let (time0, cat0) = classification(&d.before);
+    let (time1, cat1) = classification(&d.after);
+    for (label, x, y) in [("vs. time", time0, time1), ("vs. categories", cat0, cat1)] {
+        if x.is_some() || y.is_some() {
+            let none = || "none".to_owned();
+            s += &format!(
+                ", {label} {} → {}",
+                x.unwrap_or_else(none),
+                y.unwrap_or_else(none)
+            );
+        }
+    }
+    if d.crossed {
+        s += " (crossed)";
+    }
+    s
 }
 
 /// Structure for saving errors in a machine-readable form.
@@ -172,6 +1833,7 @@ pub struct Output {
 /// types3-calc can save errors as a JSON serialization of `OError`,
 /// and types3-ui can read it.
 #[derive(Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct OError {
     /// Human-readable error message.
     pub error: String,
@@ -182,7 +1844,7 @@ pub struct OError {
 /// # Examples
 /// ```
 /// use types3::output::{AvgResult, avg_string};
-/// let x = AvgResult { low: 10, high: 20, iter: 100 };
+/// let x = AvgResult { low: 10, high: 20, iter: 100, samples: None, marginal: None, min_y: None, max_y: None, percentiles: None };
 /// assert_eq!("0.10–0.20", avg_string(&x));
 /// ```
 pub fn avg_string(ar: &AvgResult) -> String {
@@ -191,12 +1853,45 @@ pub fn avg_string(ar: &AvgResult) -> String {
     format!("{:.2}–{:.2}", low, high)
 }
 
+/// Like [avg_string], but for an average of `measure_y`.
+///
+/// Values of [MeasureY::TypeTokenRatio] are shown as ratios, i.e., divided by
+/// [TYPE_TOKEN_RATIO_SCALE], values of [MeasureY::YuleK] are divided by [YULE_K_SCALE],
+/// and values of [MeasureY::Entropy] are shown in bits; all other values are shown as is.
+///
+/// # Examples
+/// ```
+/// use types3::output::{AvgResult, MeasureY, avg_string_for};
+/// let x = AvgResult { low: 2500, high: 5000, iter: 1, samples: None, marginal: None, min_y: None, max_y: None, percentiles: None };
+/// assert_eq!("0.2500–0.5000", avg_string_for(MeasureY::TypeTokenRatio, &x));
+/// assert_eq!("25.00–50.00", avg_string_for(MeasureY::YuleK, &x));
+/// assert_eq!("2.500–5.000", avg_string_for(MeasureY::Entropy, &x));
+/// assert_eq!("2500.00–5000.00", avg_string_for(MeasureY::Types, &x));
+/// ```
+pub fn avg_string_for(measure_y: MeasureY, ar: &AvgResult) -> String {
+    match measure_y {
+        MeasureY::TypeTokenRatio => {
+            let scale = (ar.iter * TYPE_TOKEN_RATIO_SCALE) as f64;
+            format!("{:.4}–{:.4}", ar.low as f64 / scale, ar.high as f64 / scale)
+        }
+        MeasureY::YuleK => {
+            let scale = (ar.iter * YULE_K_SCALE) as f64;
+            format!("{:.2}–{:.2}", ar.low as f64 / scale, ar.high as f64 / scale)
+        }
+        MeasureY::Entropy => {
+            let scale = (ar.iter * ENTROPY_SCALE) as f64;
+            format!("{:.3}–{:.3}", ar.low as f64 / scale, ar.high as f64 / scale)
+        }
+        _ => avg_string(ar),
+    }
+}
+
 /// Human-friendly representation for [PointResult].
 ///
 /// # Examples
 /// ```
 /// use types3::output::{PointResult, point_string};
-/// let x = PointResult { above: 9995, below: 3, iter: 10000 };
+/// let x = PointResult { above: 9995, below: 3, iter: 10000, moments: None };
 /// assert_eq!("+++", point_string(&x));
 /// ```
 pub fn point_string(pr: &PointResult) -> String {
@@ -262,6 +1957,593 @@ pub fn pretty_periods(periods: &[Years]) -> String {
 mod test {
     use super::*;
 
+    fn result(period: Years, above: u64, below: u64) -> OResult {
+        OResult {
+            period,
+            average_at_limit: AvgResult {
+                low: 10,
+                high: 20,
+                iter: 1000,
+                samples: None,
+                marginal: None,
+                min_y: None,
+                max_y: None,
+                percentiles: None,
+            },
+            vs_time: Some(PointResult {
+                above,
+                below,
+                iter: 1000,
+                moments: None,
+            }),
+            vs_categories: None,
+            vs_time_se: None,
+            vs_categories_se: None,
+            vs_time_p: None,
+            vs_categories_p: None,
+            vs_time_z: None,
+            vs_categories_z: None,
+            size: None,
+            vs_time_explicit: None,
+            vs_categories_explicit: None,
+            weighted_vs_time: None,
+            convergence: None,
+            repeats: None,
+            expected_types: None,
+            comparison_n: None,
+            avg_samples_at_limit: None,
+            avg_marginal: None,
+            marked_types: None,
+            distinct_orderings: None,
+            x_at_target: None,
+            observed_ci: None,
+            extrapolation: None,
+            mean_curve: None,
+            curve: None,
+            fingerprint: None,
+        }
+    }
+
+    fn output(curves: Vec<OCurve>) -> Output {
+        Output {
+            restrict_samples: None,
+            restrict_tokens: None,
+            mark_tokens: None,
+            also_mark_tokens: vec![],
+            curves,
+            years: (1900, 1930),
+            periods: vec![(1900, 1910), (1910, 1920), (1920, 1930)],
+            measure_y: MeasureY::Types,
+            measure_x: MeasureX::Tokens,
+            split_samples: false,
+            presence_absence: false,
+            accumulate_by: None,
+            ngram: None,
+            min_sample_tokens: None,
+            subsample: None,
+            limit: 100,
+            iter: 1000,
+            toplevel: None,
+            group_by: None,
+            limit_subset: None,
+            limit_units: None,
+            samples: None,
+            test_side: None,
+            null_model: None,
+            category_spread: None,
+            basis: None,
+            warnings: vec![],
+        }
+    }
+
+    #[test]
+    fn retain_significant_basic() {
+        let cat = |v: &str| Some(("a".to_owned(), v.to_owned()));
+        let mut o = output(vec![
+            OCurve {
+                category: cat("x"),
+                results: vec![
+                    result((1900, 1910), 500, 400),
+                    result((1910, 1920), 999, 0),
+                    result((1920, 1930), 0, 995),
+                ],
+                buckets: None,
+            },
+            OCurve {
+                category: cat("y"),
+                results: vec![
+                    result((1900, 1910), 950, 40),
+                    result((1910, 1920), 500, 500),
+                ],
+                buckets: None,
+            },
+        ]);
+        o.curves[1].results[1].vs_categories = Some(PointResult {
+            above: 1000,
+            below: 0,
+            iter: 1000,
+            moments: None,
+        });
+        o.retain_significant(0.01);
+        assert_eq!(
+            o,
+            output(vec![
+                OCurve {
+                    category: cat("x"),
+                    results: vec![result((1910, 1920), 999, 0), result((1920, 1930), 0, 995),],
+                    buckets: None,
+                },
+                OCurve {
+                    category: cat("y"),
+                    results: vec![OResult {
+                        vs_categories: Some(PointResult {
+                            above: 1000,
+                            below: 0,
+                            iter: 1000,
+                            moments: None,
+                        }),
+                        ..result((1910, 1920), 500, 500)
+                    }],
+                    buckets: None,
+                },
+            ])
+        );
+        o.retain_significant(0.0001);
+        assert_eq!(o.curves.len(), 1);
+        assert_eq!(o.curves[0].category, cat("y"));
+    }
+
+    #[test]
+    fn to_csv_basic() {
+        let o = output(vec![
+            OCurve {
+                category: None,
+                results: vec![result((1900, 1910), 500, 400)],
+                buckets: None,
+            },
+            OCurve {
+                category: Some(("a, b".to_owned(), "say \"x\"".to_owned())),
+                results: vec![OResult {
+                    vs_time: None,
+                    vs_categories: Some(PointResult {
+                        above: 3,
+                        below: 990,
+                        iter: 1000,
+                        moments: None,
+                    }),
+                    ..result((1910, 1920), 0, 0)
+                }],
+                buckets: None,
+            },
+        ]);
+        let mut csv = Vec::new();
+        to_csv(&o, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "category_key,category_value,period_start,period_end,avg_low,avg_high,iter,\
+             vs_time_above,vs_time_below,vs_time_iter,\
+             vs_categories_above,vs_categories_below,vs_categories_iter\n\
+             ,,1900,1910,0.01,0.02,1000,500,400,1000,,,\n\
+             \"a, b\",\"say \"\"x\"\"\",1910,1920,0.01,0.02,1000,,,,3,990,1000\n"
+        );
+    }
+
+    #[test]
+    fn to_csv_no_iter() {
+        let mut r = result((1900, 1910), 0, 0);
+        r.average_at_limit.iter = 0;
+        r.vs_time = None;
+        let o = output(vec![OCurve {
+            category: Some(("a".to_owned(), "line\nbreak".to_owned())),
+            results: vec![r],
+            buckets: None,
+        }]);
+        let mut csv = Vec::new();
+        to_csv(&o, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(!csv.contains("NaN"), "{csv}");
+        assert!(
+            csv.ends_with("a,\"line\nbreak\",1900,1910,,,0,,,,,,\n"),
+            "{csv}"
+        );
+    }
+
+    #[test]
+    fn diff_perturbed() {
+        let cat = |v: &str| Some(("a".to_owned(), v.to_owned()));
+        let curves = || {
+            vec![
+                OCurve {
+                    category: cat("x"),
+                    results: vec![
+                        result((1900, 1910), 500, 400),
+                        result((1910, 1920), 999, 0),
+                        result((1920, 1930), 0, 995),
+                    ],
+                    buckets: None,
+                },
+                OCurve {
+                    category: cat("y"),
+                    results: vec![result((1900, 1910), 950, 40)],
+                    buckets: None,
+                },
+            ]
+        };
+        let a = output(curves());
+        assert_eq!(diff(&a, &output(curves()), 0.01), vec![]);
+        let mut b = output(curves());
+        // Significance changes, average stays the same.
+        b.curves[0].results[0] = result((1900, 1910), 995, 0);
+        // Average changes, significance stays the same.
+        b.curves[0].results[1].average_at_limit.high = 25;
+        // Classification changes, but not at level 0.01.
+        b.curves[1].results[0] = result((1900, 1910), 850, 40);
+        // Result disappears.
+        b.curves[0].results.pop();
+        let d = diff(&a, &b, 0.01);
+        assert_eq!(
+            d.iter()
+                .map(|d| (d.category.clone(), d.period, d.crossed))
+                .collect_vec(),
+            [
+                (cat("x"), (1900, 1910), true),
+                (cat("x"), (1910, 1920), false),
+                (cat("x"), (1920, 1930), true),
+                (cat("y"), (1900, 1910), false),
+            ]
+        );
+        assert_eq!(d[2].after, None);
+        assert_eq!(
+            diff_string(&d[0]),
+            "a = x, 1900–1909: 0.01–0.02 → 0.01–0.02, vs. time 0 → ++ (crossed)"
+        );
+        assert_eq!(
+            diff_string(&d[1]),
+            "a = x, 1910–1919: 0.01–0.02 → 0.01–0.03, vs. time ++ → ++"
+        );
+        assert_eq!(
+            diff_string(&d[2]),
+            "a = x, 1920–1929: 0.01–0.02 → none, vs. time -- → none (crossed)"
+        );
+        assert_eq!(diff(&b, &a, 0.01).len(), 4);
+    }
+
+    #[test]
+    fn bucket_results_decades() {
+        let r = |start: Year, low: u64, high: u64, size: u64| OResult {
+            period: (start, start + 5),
+            average_at_limit: AvgResult {
+                low,
+                high,
+                iter: 100,
+                samples: None,
+                marginal: None,
+                min_y: None,
+                max_y: None,
+                percentiles: None,
+            },
+            size: Some(size),
+            vs_time_explicit: None,
+            vs_categories_explicit: None,
+            ..result((start, start + 5), 0, 0)
+        };
+        let results = [
+            r(1900, 100, 200, 1),
+            r(1905, 400, 500, 2),
+            r(1910, 1000, 1000, 5),
+            r(1915, 2000, 2000, 5),
+            r(1920, 300, 300, 7),
+        ];
+        let buckets = bucket_results(&results, 10);
+        assert_eq!(
+            buckets,
+            vec![
+                OBucket {
+                    period: (1900, 1910),
+                    low: 3.0,
+                    high: 4.0,
+                    periods: vec![(1900, 1905), (1905, 1910)],
+                },
+                OBucket {
+                    period: (1910, 1920),
+                    low: 15.0,
+                    high: 15.0,
+                    periods: vec![(1910, 1915), (1915, 1920)],
+                },
+                OBucket {
+                    period: (1920, 1930),
+                    low: 3.0,
+                    high: 3.0,
+                    periods: vec![(1920, 1925)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bucket_results_centuries() {
+        let results = [
+            result((1680, 1700), 0, 0),
+            result((1700, 1720), 0, 0),
+            result((1720, 1740), 0, 0),
+            result((1780, 1800), 0, 0),
+            result((1800, 1820), 0, 0),
+        ];
+        let buckets = bucket_results(&results, 100);
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(buckets[0].period, (1600, 1700));
+        assert_eq!(buckets[0].periods, vec![(1680, 1700)]);
+        assert_eq!(buckets[1].period, (1700, 1800));
+        assert_eq!(
+            buckets[1].periods,
+            vec![(1700, 1720), (1720, 1740), (1780, 1800)]
+        );
+        assert_eq!(buckets[1].low, 0.01);
+        assert_eq!(buckets[1].high, 0.02);
+        assert_eq!(buckets[2].period, (1800, 1900));
+    }
+
+    #[test]
+    fn p_value_sides() {
+        let high = PointResult {
+            above: 1000,
+            below: 0,
+            iter: 1000,
+            moments: None,
+        };
+        assert_eq!(high.p_value(TestSide::TwoSided), 0.0);
+        assert_eq!(high.p_value(TestSide::GreaterOnly), 0.0);
+        assert_eq!(high.p_value(TestSide::LessOnly), 1.0);
+        let low = PointResult {
+            above: 10,
+            below: 980,
+            iter: 1000,
+            moments: None,
+        };
+        assert_eq!(low.p_value(TestSide::TwoSided), 0.04);
+        assert_eq!(low.p_value(TestSide::GreaterOnly), 0.99);
+        assert_eq!(low.p_value(TestSide::LessOnly), 0.02);
+        let equal = PointResult {
+            above: 0,
+            below: 0,
+            iter: 1000,
+            moments: None,
+        };
+        assert_eq!(equal.p_value(TestSide::TwoSided), 1.0);
+        assert_eq!(equal.p_value(TestSide::GreaterOnly), 1.0);
+        assert_eq!(equal.p_value(TestSide::LessOnly), 1.0);
+    }
+
+    #[test]
+    fn load_output_bare() {
+        let o = output(vec![OCurve {
+            category: None,
+            results: vec![result((1900, 1910), 500, 400)],
+            buckets: None,
+        }]);
+        let json = serde_json::to_string(&o).unwrap();
+        assert_eq!(load_output(&json).unwrap(), o);
+    }
+
+    #[test]
+    fn load_output_envelope() {
+        let o = output(vec![]);
+        let envelope = OutputEnvelope::new(output(vec![]));
+        assert_eq!(envelope.format_version, FORMAT_VERSION);
+        assert!(envelope.produced_by.starts_with("types3 "));
+        let json = serde_json::to_string(&envelope).unwrap();
+        assert_eq!(
+            serde_json::from_str::<OutputEnvelope>(&json).unwrap(),
+            envelope
+        );
+        assert_eq!(load_output(&json).unwrap(), o);
+        let newer = OutputEnvelope {
+            format_version: FORMAT_VERSION + 1,
+            ..envelope
+        };
+        let json = serde_json::to_string(&newer).unwrap();
+        let Err(e) = load_output(&json) else {
+            panic!("newer format accepted");
+        };
+        assert!(e.to_string().contains("unsupported format version"));
+        assert!(load_output(r#"{"format_version": "x", "output": {}}"#).is_err());
+    }
+
+    #[test]
+    fn comparable_with() {
+        let base = output(vec![]);
+        let same = |f: fn(&mut Output)| {
+            let mut o = output(vec![]);
+            f(&mut o);
+            base.comparable_with(&o)
+        };
+        assert!(same(|_| {}));
+        assert!(same(|o| o.measure_y = MeasureY::Hapaxes));
+        assert!(same(|o| o.iter = 1));
+        assert!(!same(|o| o.measure_x = MeasureX::Words));
+        assert!(!same(|o| o.limit += 1));
+        assert!(!same(|o| o.split_samples = !o.split_samples));
+        let basis = base.get_basis();
+        assert_eq!(basis.limit, base.limit);
+        assert!(basis.comparable_with(&basis));
+    }
+
+    #[test]
+    fn category_spread_basic() {
+        let cat = |v: &str| Some(("a".to_owned(), v.to_owned()));
+        let curve = |v, lows: &[u64]| OCurve {
+            category: cat(v),
+            results: lows
+                .iter()
+                .enumerate()
+                .map(|(i, &low)| {
+                    let start = 1900 + 10 * i as Year;
+                    let mut r = result((start, start + 10), 0, 0);
+                    r.average_at_limit = AvgResult {
+                        low,
+                        high: low + 1000,
+                        iter: 1000,
+                        samples: None,
+                        marginal: None,
+                        min_y: None,
+                        max_y: None,
+                        percentiles: None,
+                    };
+                    r
+                })
+                .collect_vec(),
+            buckets: None,
+        };
+        let o = output(vec![]);
+        // Identical in the first period, different in the second, only one in the third.
+        let curves = [
+            curve("x", &[5000, 1000]),
+            curve("y", &[5000, 3000]),
+            curve("z", &[5000, 8000, 1000]),
+        ];
+        let spread = category_spread(&curves, &o.periods);
+        assert_eq!(spread.len(), 2);
+        assert_eq!(spread[0].period, (1900, 1910));
+        assert_eq!(spread[0].categories, 3);
+        assert_eq!((spread[0].range, spread[0].variance), (0.0, 0.0));
+        assert_eq!(spread[1].period, (1910, 1920));
+        assert_eq!((spread[1].min, spread[1].max), (1.5, 8.5));
+        assert_eq!(spread[1].range, 7.0);
+        // Averages 1.5, 3.5, 8.5 with mean 4.5.
+        assert!((spread[1].variance - 26.0 / 3.0).abs() < 1e-9);
+        assert!(category_spread(&curves[..1], &o.periods).is_empty());
+    }
+
+    #[test]
+    fn curve_filenames() {
+        let mut used = HashSet::new();
+        let cat = |v: &str| Some(("k".to_owned(), v.to_owned()));
+        assert_eq!(curve_filename(&None, &used), "all.json");
+        assert_eq!(
+            curve_filename(&cat("soc. mob. U"), &used),
+            "soc__mob__U.json"
+        );
+        used.insert("soc__mob__u.json".to_owned());
+        assert_eq!(
+            curve_filename(&cat("soc. mob. U"), &used),
+            "soc__mob__U-2.json"
+        );
+        assert_eq!(
+            curve_filename(&cat("soc_ mob. U"), &used),
+            "soc__mob__U-2.json"
+        );
+        assert_eq!(curve_filename(&cat("female"), &used), "female.json");
+        used.insert("female.json".to_owned());
+        assert_eq!(curve_filename(&cat("Female"), &used), "Female-2.json");
+        assert_eq!(curve_filename(&cat(""), &used), "empty.json");
+    }
+
+    #[test]
+    fn split_file_names() {
+        let cat = |v: &str| Some(("k".to_owned(), v.to_owned()));
+        let curve = |category| OCurve {
+            category,
+            results: vec![],
+            buckets: None,
+        };
+        let o = output(vec![
+            curve(cat("index")),
+            curve(cat("Index")),
+            curve(cat("a")),
+            curve(cat("A")),
+            curve(cat("")),
+            curve(None),
+        ]);
+        let (index, curves) = o.split();
+        assert_eq!(curves.len(), 6);
+        let files = index.files.iter().map(|f| &f.file as &str).collect_vec();
+        assert_eq!(
+            files,
+            [
+                "index-2.json",
+                "Index-3.json",
+                "a.json",
+                "A-2.json",
+                "empty.json",
+                "all.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn repeat_bands_single() {
+        let r = repeat_bands(&[1.5]);
+        assert_eq!(
+            r,
+            ORepeats {
+                repeats: 1,
+                mean: 1.5,
+                low: 1.5,
+                high: 1.5
+            }
+        );
+    }
+
+    #[test]
+    fn repeat_bands_quantiles() {
+        let averages = (0..=40).rev().map(|x| x as f64).collect::<Vec<_>>();
+        let r = repeat_bands(&averages);
+        assert_eq!(r.repeats, 41);
+        assert_eq!(r.mean, 20.0);
+        assert_eq!(r.low, 1.0);
+        assert_eq!(r.high, 39.0);
+    }
+
+    #[test]
+    fn weighted_comparison_flip() {
+        let pr = |above, below| PointResult {
+            above,
+            below,
+            iter: 10000,
+            moments: None,
+        };
+        // The same subset at increasing sizes: clearly low when small, high when large.
+        let points = [
+            (100, pr(0, 9999)),
+            (200, pr(0, 9999)),
+            (300, pr(9500, 400)),
+            (400, pr(9990, 5)),
+        ];
+        let even = weighted_comparison(&points, 0.0);
+        assert!((even.fraction_above - 0.48725).abs() < 1e-9);
+        assert!((even.fraction_below - 0.510075).abs() < 1e-9);
+        assert_eq!(even.direction, Direction::Neither);
+        let large = weighted_comparison(&points, 8.0);
+        assert!(large.fraction_above > 0.9);
+        assert_eq!(large.direction, Direction::Above);
+        let small = weighted_comparison(&points, -8.0);
+        assert_eq!(small.direction, Direction::Below);
+    }
+
+    #[test]
+    fn weighted_comparison_no_iter() {
+        let empty = PointResult {
+            above: 0,
+            below: 0,
+            iter: 0,
+            moments: None,
+        };
+        let full = PointResult {
+            above: 10,
+            below: 0,
+            iter: 10,
+            moments: None,
+        };
+        let w = weighted_comparison(&[(100, empty)], 1.0);
+        assert_eq!((w.fraction_above, w.fraction_below), (0.0, 0.0));
+        assert_eq!(w.direction, Direction::Neither);
+        let w = weighted_comparison(&[(100, empty), (100, full)], 1.0);
+        assert_eq!((w.fraction_above, w.fraction_below), (1.0, 0.0));
+        assert_eq!(w.direction, Direction::Above);
+        let w = weighted_comparison(&[], 1.0);
+        assert_eq!((w.fraction_above, w.fraction_below), (0.0, 0.0));
+    }
+
     #[test]
     fn pretty_period_basic() {
         assert_eq!(pretty_period(&(1990, 2000)), "1990–1999");
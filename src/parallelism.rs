@@ -1,77 +1,153 @@
 use crossbeam_channel::TryRecvError;
+use itertools::Itertools;
 use log::trace;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
 /// Number of parallel tasks.
 const RANDOM_JOBS: u64 = 1000;
 
+/// Default random seed.
+pub const DEFAULT_SEED: u64 = 0;
+
 #[derive(Clone, Copy)]
 pub struct Job {
-    pub job_id: u64,
     pub iter_per_job: u64,
+    /// Seed for the random number generator of this job.
+    pub seed: u64,
+}
+
+/// Seed for job `job_id` if the base seed is `seed`.
+///
+/// Different base seeds use disjoint sets of job seeds, and with [DEFAULT_SEED]
+/// the seed of each job is simply its identifier.
+fn job_seed(seed: u64, job_id: u64) -> u64 {
+    seed.wrapping_mul(RANDOM_JOBS).wrapping_add(job_id)
 }
 
+/// Partial results of parallel jobs.
+///
+/// Each job has its own partial result, and [compute_parallel] merges them with `add`
+/// in the order of job identifiers, regardless of the order in which threads finish.
+/// Hence `add` does not need to be commutative or associative (e.g. floating-point sums
+/// are fine), and the results are still deterministic.
 pub trait ParResult {
     fn add(&mut self, other: Self);
 }
 
+/// Number of jobs that we run before checkpoint `fraction`, out of `jobs` jobs.
+fn checkpoint_jobs(fraction: f64, jobs: u64) -> u64 {
+    ((fraction * jobs as f64).ceil() as u64).clamp(1, jobs)
+}
+
+/// Run `iter` iterations in parallel and merge the partial results.
+///
+/// If `iter` is smaller than the number of parallel tasks, we run exactly `iter`
+/// jobs of one iteration each. In particular, with `iter` = 1
+/// we get exactly one random permutation, and with the default seed its seed is 0.
+///
+/// We also report the running total at each checkpoint in `checkpoints`:
+/// checkpoint `f` covers the first `f` fraction of the jobs, i.e.,
+/// approximately the first `f` fraction of the iterations.
+/// Jobs are deterministic, and partial results are merged in the order of job identifiers,
+/// so neither the checkpoints nor the final result depend on the order in which jobs finish.
+/// The checkpoints have to be increasing, and the last one has to be 1,
+/// so that the last result covers all iterations.
+/// The random permutations are determined by `seed`.
+/// Returns the results and the number of iterations at each checkpoint.
+///
+/// If `cancel` is set, we stop starting new jobs, and the results are incomplete;
+/// the caller has to check `cancel` and discard the results.
 pub fn compute_parallel<TParResult, TBuilder, TRunner>(
     builder: TBuilder,
     runner: TRunner,
     iter: u64,
-) -> (TParResult, u64)
+    checkpoints: &[f64],
+    seed: u64,
+    cancel: Option<&AtomicBool>,
+) -> Vec<(TParResult, u64)>
 where
-    TParResult: ParResult + Send,
+    TParResult: ParResult + Send + Clone,
     TBuilder: Fn() -> TParResult + Send + Copy,
     TRunner: Fn(Job, &mut TParResult) + Send + Copy,
 {
+    assert!(checkpoints.iter().tuple_windows().all(|(a, b)| a < b));
+    assert_eq!(checkpoints.last(), Some(&1.0));
+    let njobs = if 0 < iter && iter < RANDOM_JOBS {
+        iter
+    } else {
+        RANDOM_JOBS
+    };
+    let bounds = checkpoints
+        .iter()
+        .map(|&f| checkpoint_jobs(f, njobs))
+        .collect_vec();
     let (s1, r1) = crossbeam_channel::unbounded();
-    for job in 0..RANDOM_JOBS {
+    for job in 0..njobs {
         s1.send(job).unwrap();
     }
-    let iter_per_job = iter.div_ceil(RANDOM_JOBS);
-    let iter = iter_per_job * RANDOM_JOBS;
+    let iter_per_job = iter.div_ceil(njobs);
     drop(s1);
-    let nthreads = num_cpus::get();
-    let mut total = builder();
-    trace!("randomized, {RANDOM_JOBS} jobs, {nthreads} threads");
+    let nthreads = num_cpus::get().min(njobs as usize);
+    trace!("randomized, {njobs} jobs, {nthreads} threads");
+    // Partial results of each job, indexed by job identifier.
+    let mut partial: Vec<Option<TParResult>> = (0..njobs).map(|_| None).collect_vec();
     thread::scope(|scope| {
         let (s2, r2) = crossbeam_channel::unbounded();
         for _ in 0..nthreads {
             let r1 = r1.clone();
             let s2 = s2.clone();
-            scope.spawn(move || {
-                let mut thread_total = builder();
-                loop {
-                    match r1.try_recv() {
-                        Ok(job_id) => {
-                            runner(
-                                Job {
-                                    job_id,
-                                    iter_per_job,
-                                },
-                                &mut thread_total,
-                            );
-                        }
-                        Err(TryRecvError::Empty) => unreachable!(),
-                        Err(TryRecvError::Disconnected) => break,
+            scope.spawn(move || loop {
+                if is_cancelled(cancel) {
+                    break;
+                }
+                match r1.try_recv() {
+                    Ok(job_id) => {
+                        let mut result = builder();
+                        runner(
+                            Job {
+                                iter_per_job,
+                                seed: job_seed(seed, job_id),
+                            },
+                            &mut result,
+                        );
+                        s2.send((job_id, result)).unwrap();
                     }
+                    Err(TryRecvError::Empty) => unreachable!(),
+                    Err(TryRecvError::Disconnected) => break,
                 }
-                s2.send(thread_total).unwrap();
             });
         }
         drop(s2);
-        while let Ok(thread_total) = r2.recv() {
-            total.add(thread_total);
+        while let Ok((job_id, result)) = r2.recv() {
+            partial[job_id as usize] = Some(result);
         }
     });
-    (total, iter)
+    // If cancelled, some jobs are missing; the results are incomplete anyway.
+    let mut partial = partial.into_iter();
+    let mut total = builder();
+    let mut results = vec![];
+    let mut merged = 0;
+    for jobs in bounds {
+        for x in partial.by_ref().take((jobs - merged) as usize).flatten() {
+            total.add(x);
+        }
+        merged = jobs;
+        results.push((total.clone(), jobs * iter_per_job));
+    }
+    results
+}
+
+/// Has the calculation been cancelled?
+pub fn is_cancelled(cancel: Option<&AtomicBool>) -> bool {
+    cancel.is_some_and(|c| c.load(Ordering::Relaxed))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[derive(Clone)]
     struct Adder {
         x: u64,
         y: u64,
@@ -89,33 +165,205 @@ mod test {
         let (r, iter) = compute_parallel(
             || Adder { x: 0, y: 0 },
             |job, adder| {
-                assert!(job.job_id < RANDOM_JOBS);
+                assert!(job.seed < RANDOM_JOBS);
                 assert_eq!(job.iter_per_job, 100);
                 adder.x += 1;
-                adder.y += job.job_id;
+                adder.y += job.seed;
             },
             100 * RANDOM_JOBS,
-        );
+            &[1.0],
+            DEFAULT_SEED,
+            None,
+        )
+        .pop()
+        .unwrap();
         assert_eq!(iter, 100 * RANDOM_JOBS);
         assert_eq!(r.x, RANDOM_JOBS);
         assert_eq!(r.y, RANDOM_JOBS * (RANDOM_JOBS - 1) / 2);
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn compute_parallel_small() {
         assert!(5 < RANDOM_JOBS);
         let (r, iter) = compute_parallel(
             || Adder { x: 0, y: 0 },
             |job, adder| {
-                assert!(job.job_id < RANDOM_JOBS);
+                assert!(job.seed < RANDOM_JOBS);
                 assert_eq!(job.iter_per_job, 1);
                 adder.x += 1;
-                adder.y += job.job_id;
+                adder.y += job.seed;
             },
             5,
+            &[1.0],
+            DEFAULT_SEED,
+            None,
+        )
+        .pop()
+        .unwrap();
+        assert_eq!(iter, 5);
+        assert_eq!(r.x, 5);
+        assert_eq!(r.y, 5 * 4 / 2);
+    }
+
+    #[test]
+    fn compute_parallel_single() {
+        let results = compute_parallel(
+            || Adder { x: 0, y: 0 },
+            |job, adder| {
+                assert_eq!(job.iter_per_job, 1);
+                adder.x += 1;
+                adder.y += job.seed;
+            },
+            1,
+            &[0.1, 1.0],
+            7,
+            None,
         );
-        assert_eq!(iter, RANDOM_JOBS);
-        assert_eq!(r.x, RANDOM_JOBS);
+        assert_eq!(results.len(), 2);
+        for (r, iter) in results {
+            assert_eq!(iter, 1);
+            assert_eq!(r.x, 1);
+            assert_eq!(r.y, 7 * RANDOM_JOBS);
+        }
+    }
+
+    #[test]
+    fn compute_parallel_almost_full() {
+        let iter = RANDOM_JOBS - 1;
+        let results = compute_parallel(
+            || Adder { x: 0, y: 0 },
+            |job, adder| {
+                assert_eq!(job.iter_per_job, 1);
+                adder.x += 1;
+                adder.y += job.seed;
+            },
+            iter,
+            &[0.5, 1.0],
+            DEFAULT_SEED,
+            None,
+        );
+        assert_eq!(results[0].1, iter.div_ceil(2));
+        assert_eq!(results[0].0.x, iter.div_ceil(2));
+        assert_eq!(results[1].1, iter);
+        assert_eq!(results[1].0.x, iter);
+        assert_eq!(results[1].0.y, iter * (iter - 1) / 2);
+    }
+
+    #[test]
+    fn compute_parallel_checkpoints() {
+        let results = compute_parallel(
+            || Adder { x: 0, y: 0 },
+            |job, adder| {
+                adder.x += job.iter_per_job;
+                adder.y += job.seed;
+            },
+            10 * RANDOM_JOBS,
+            &[0.1, 0.25, 0.5, 1.0],
+            DEFAULT_SEED,
+            None,
+        );
+        assert_eq!(results.len(), 4);
+        for ((r, iter), f) in results.iter().zip([0.1, 0.25, 0.5, 1.0]) {
+            let jobs = (f * RANDOM_JOBS as f64) as u64;
+            assert_eq!(*iter, 10 * jobs);
+            assert_eq!(r.x, 10 * jobs);
+            assert_eq!(r.y, jobs * (jobs - 1) / 2);
+        }
+    }
+
+    #[test]
+    fn compute_parallel_seed() {
+        let sum = |seed| {
+            compute_parallel(
+                || Adder { x: 0, y: 0 },
+                |job, adder| {
+                    adder.x += 1;
+                    adder.y += job.seed;
+                },
+                RANDOM_JOBS,
+                &[1.0],
+                seed,
+                None,
+            )
+            .pop()
+            .unwrap()
+            .0
+        };
+        let r = sum(DEFAULT_SEED);
         assert_eq!(r.y, RANDOM_JOBS * (RANDOM_JOBS - 1) / 2);
+        let r = sum(1);
+        assert_eq!(r.x, RANDOM_JOBS);
+        assert_eq!(
+            r.y,
+            RANDOM_JOBS * RANDOM_JOBS + RANDOM_JOBS * (RANDOM_JOBS - 1) / 2
+        );
+    }
+
+    #[derive(Clone)]
+    struct FloatSum {
+        sum: f64,
+    }
+
+    impl ParResult for FloatSum {
+        fn add(&mut self, other: Self) {
+            self.sum += other.sum;
+        }
+    }
+
+    /// Values of very different magnitudes, so that the sum depends on the order of additions.
+    fn float_value(seed: u64) -> f64 {
+        (seed as f64).sin() * 10f64.powi((seed % 32) as i32)
+    }
+
+    #[test]
+    fn compute_parallel_float_deterministic() {
+        let sum = || {
+            compute_parallel(
+                || FloatSum { sum: 0.0 },
+                |job, fs| fs.sum += float_value(job.seed),
+                RANDOM_JOBS,
+                &[0.5, 1.0],
+                DEFAULT_SEED,
+                None,
+            )
+            .into_iter()
+            .map(|(fs, _)| fs.sum.to_bits())
+            .collect_vec()
+        };
+        let mut expected = vec![];
+        let mut total = 0.0;
+        for job_id in 0..RANDOM_JOBS {
+            total += float_value(job_id);
+            if job_id + 1 == RANDOM_JOBS / 2 || job_id + 1 == RANDOM_JOBS {
+                expected.push(total.to_bits());
+            }
+        }
+        for _ in 0..10 {
+            assert_eq!(sum(), expected);
+        }
+    }
+
+    #[test]
+    fn compute_parallel_cancel() {
+        for iter in [10 * RANDOM_JOBS, 10] {
+            let cancel = AtomicBool::new(false);
+            let (r, _) = compute_parallel(
+                || Adder { x: 0, y: 0 },
+                |_, adder| {
+                    adder.x += 1;
+                    cancel.store(true, Ordering::Relaxed);
+                },
+                iter,
+                &[1.0],
+                DEFAULT_SEED,
+                Some(&cancel),
+            )
+            .pop()
+            .unwrap();
+            assert!(is_cancelled(Some(&cancel)));
+            assert!(r.x >= 1);
+            assert!(r.x <= num_cpus::get() as u64);
+        }
     }
 }
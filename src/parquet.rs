@@ -0,0 +1,129 @@
+//! Export results to a Parquet file.
+//!
+//! This is only available with feature `parquet`.
+//! The file has one row per category and time period, in the same order as in [Output::curves],
+//! with the same columns as the `results` table of the SQLite export, plus the optional
+//! numeric fields of [crate::output::OResult] that are not nested, and the extremes
+//! [crate::output::AvgResult::min_y] and [crate::output::AvgResult::max_y]. Missing values are nulls.
+//! The p-values are for [Output::test_side], or two-sided if it is not specified.
+
+use crate::errors::Result;
+use crate::output::{AvgResult, OResult, Output, PointResult, TestSide};
+use arrow_array::{ArrayRef, Float64Array, Int16Array, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Columns of the file, and whether they are nullable.
+const COLUMNS: &[(&str, DataType, bool)] = &[
+    ("category_key", DataType::Utf8, true),
+    ("category_value", DataType::Utf8, true),
+    ("period_start", DataType::Int16, false),
+    ("period_end", DataType::Int16, false),
+    ("average", DataType::Float64, false),
+    ("average_low", DataType::Float64, false),
+    ("average_high", DataType::Float64, false),
+    ("iter", DataType::UInt64, false),
+    ("vs_time_above", DataType::UInt64, true),
+    ("vs_time_below", DataType::UInt64, true),
+    ("vs_time_iter", DataType::UInt64, true),
+    ("vs_time_p", DataType::Float64, true),
+    ("vs_categories_above", DataType::UInt64, true),
+    ("vs_categories_below", DataType::UInt64, true),
+    ("vs_categories_iter", DataType::UInt64, true),
+    ("vs_categories_p", DataType::Float64, true),
+    ("vs_time_se", DataType::Float64, true),
+    ("vs_categories_se", DataType::Float64, true),
+    ("vs_time_z", DataType::Float64, true),
+    ("vs_categories_z", DataType::Float64, true),
+    ("size", DataType::UInt64, true),
+    ("expected_types", DataType::Float64, true),
+    ("avg_samples_at_limit", DataType::Float64, true),
+    ("avg_marginal", DataType::Float64, true),
+    ("min_at_limit", DataType::UInt64, true),
+    ("max_at_limit", DataType::UInt64, true),
+    ("repeats", DataType::UInt64, true),
+    ("repeats_mean", DataType::Float64, true),
+    ("repeats_low", DataType::Float64, true),
+    ("repeats_high", DataType::Float64, true),
+];
+
+/// Write the results of `output` to `writer` as a Parquet file.
+pub fn write_output<W: Write + Send>(writer: W, output: &Output) -> Result<()> {
+    let schema = Arc::new(Schema::new(
+        COLUMNS
+            .iter()
+            .map(|(name, t, nullable)| Field::new(*name, t.clone(), *nullable))
+            .collect::<Vec<_>>(),
+    ));
+    let side = output.test_side.unwrap_or(TestSide::TwoSided);
+    let rows = output
+        .curves
+        .iter()
+        .flat_map(|c| c.results.iter().map(move |r| (&c.category, r)))
+        .collect::<Vec<_>>();
+    let results = || rows.iter().map(|&(_, r)| r);
+    let string = |f: fn(&(String, String)) -> &str| -> ArrayRef {
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|(c, _)| c.as_ref().map(f)),
+        ))
+    };
+    let int16 = |f: fn(&OResult) -> i16| -> ArrayRef {
+        Arc::new(Int16Array::from_iter_values(results().map(f)))
+    };
+    let float = |f: &dyn Fn(&OResult) -> Option<f64>| -> ArrayRef {
+        Arc::new(Float64Array::from_iter(results().map(f)))
+    };
+    let uint = |f: &dyn Fn(&OResult) -> Option<u64>| -> ArrayRef {
+        Arc::new(UInt64Array::from_iter(results().map(f)))
+    };
+    let point = |f: fn(&OResult) -> &Option<PointResult>| {
+        [
+            uint(&|r| f(r).map(|pr| pr.above)),
+            uint(&|r| f(r).map(|pr| pr.below)),
+            uint(&|r| f(r).map(|pr| pr.iter)),
+            float(&|r| f(r).map(|pr| pr.p_value(side))),
+        ]
+    };
+    let mut columns = vec![
+        string(|(k, _)| k),
+        string(|(_, v)| v),
+        int16(|r| r.period.0),
+        int16(|r| r.period.1),
+        float(&|r| Some(averages(&r.average_at_limit).0)),
+        float(&|r| Some(averages(&r.average_at_limit).1)),
+        float(&|r| Some(averages(&r.average_at_limit).2)),
+        uint(&|r| Some(r.average_at_limit.iter)),
+    ];
+    columns.extend(point(|r| &r.vs_time));
+    columns.extend(point(|r| &r.vs_categories));
+    columns.extend([
+        float(&|r| r.vs_time_se),
+        float(&|r| r.vs_categories_se),
+        float(&|r| r.vs_time_z),
+        float(&|r| r.vs_categories_z),
+        uint(&|r| r.size),
+        float(&|r| r.expected_types),
+        float(&|r| r.avg_samples_at_limit),
+        float(&|r| r.avg_marginal),
+        uint(&|r| r.average_at_limit.min_y),
+        uint(&|r| r.average_at_limit.max_y),
+        uint(&|r| r.repeats.as_ref().map(|x| x.repeats)),
+        float(&|r| r.repeats.as_ref().map(|x| x.mean)),
+        float(&|r| r.repeats.as_ref().map(|x| x.low)),
+        float(&|r| r.repeats.as_ref().map(|x| x.high)),
+    ]);
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut w = ArrowWriter::try_new(writer, schema, None)?;
+    w.write(&batch)?;
+    w.close()?;
+    Ok(())
+}
+
+fn averages(ar: &AvgResult) -> (f64, f64, f64) {
+    let iter = ar.iter as f64;
+    let low = ar.low as f64 / iter;
+    let high = ar.high as f64 / iter;
+    ((low + high) / 2.0, low, high)
+}
@@ -2,99 +2,348 @@
 
 use crate::categories::{self, Category};
 use crate::errors::{self, Result};
-use crate::input::{ISample, Year};
+use crate::input::{ISample, IToken, Input, Year};
 use crate::output::Years;
 use itertools::Itertools;
 use log::info;
+use rand::seq::index;
+use rand_xoshiro::rand_core::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
 use std::collections::{HashMap, HashSet};
 
 /// Internal representation of tokens,
+#[derive(Clone)]
 pub struct CToken<'a> {
     /// Lemma.
     /// See [crate::input::IToken::lemma].
     pub token: &'a str,
+    /// Number of occurrences.
+    /// See [crate::input::IToken::count].
+    pub count: u64,
     /// Is this marked as relevant?
     /// See [crate::driver::DriverArgs::mark_tokens].
     pub marked: bool,
+    /// Additional markings: bit `i` is set if this matches the `i`th additional marking.
+    /// See [crate::driver::DriverArgs::also_mark_tokens].
+    pub also_marked: u64,
 }
 
 /// Internal representation of samples.
+#[derive(Clone)]
 pub struct CSample<'a> {
-    /// Year.
+    /// Year, or `None` if the sample is undated.
     /// See [crate::input::ISample::year].
-    pub year: Year,
+    pub year: Option<Year>,
     /// Metadata related to this sample.
     /// See [crate::input::ISample::metadata].
     pub metadata: &'a HashMap<String, String>,
     /// The number of words in this sample.
     /// See [crate::input::ISample::words].
     pub words: u64,
+    /// The number of characters in this sample.
+    /// See [crate::input::ISample::chars].
+    pub chars: u64,
     /// Tokens of this sample.
     pub tokens: Vec<CToken<'a>>,
+    /// Source of this sample.
+    /// See [crate::driver::DriverArgs::source_key] and [set_sources].
+    pub source: Option<&'a str>,
 }
 
-fn get_sample<'a>(restrict_tokens: Category, mark_tokens: Category, s: &'a ISample) -> CSample<'a> {
+/// Maximum number of sample identifiers listed in error messages.
+const MAX_LISTED: usize = 10;
+
+fn token_in_scope(restrict_tokens: Category, t: &IToken) -> bool {
+    categories::matches(restrict_tokens, &t.metadata)
+}
+
+fn sample_in_scope(years: &Years, restrict_samples: Category, s: &ISample) -> bool {
+    in_range(years, s.year) && categories::matches(restrict_samples, &s.metadata)
+}
+
+fn get_sample<'a>(
+    restrict_tokens: Category,
+    mark_tokens: Category,
+    also_mark_tokens: &[(&str, &str)],
+    s: &'a ISample,
+) -> CSample<'a> {
     CSample {
         year: s.year,
         metadata: &s.metadata,
         words: s.words,
+        chars: s.chars,
         tokens: s
             .tokens
             .iter()
-            .filter_map(|t| {
-                if categories::matches(restrict_tokens, &t.metadata) {
-                    Some(CToken {
-                        token: &t.lemma as &str,
-                        marked: categories::matches(mark_tokens, &t.metadata),
-                    })
-                } else {
-                    None
-                }
+            .filter(|t| token_in_scope(restrict_tokens, t))
+            .map(|t| CToken {
+                token: &t.lemma as &str,
+                count: t.occurrences(),
+                marked: categories::matches(mark_tokens, &t.metadata),
+                also_marked: also_mark_tokens
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &m)| categories::matches(Some(m), &t.metadata))
+                    .fold(0, |bits, (i, _)| bits | 1 << i),
             })
             .collect_vec(),
+        source: None,
     }
 }
 
 /// Filter and convert samples.
 ///
 /// Turn a list of [crate::input::ISample] objects into [crate::input::CSample] objects.
-/// Only samples with year in range `years` are kept.
+/// Only samples with year in range `years` are kept; undated samples are always kept.
 /// Only samples that match `restrict_samples` are kept.
 /// Only tokens that match `restrict_tokens` are kept.
-/// Tokens that match `mark_tokens` are marked.
+/// Tokens that match `mark_tokens` are marked, and the additional markings of tokens
+/// are set according to `also_mark_tokens` (at most 64 of them).
 /// Token metadata is then discarded.
 pub fn get_samples<'a>(
     years: &Years,
     restrict_samples: Category,
     restrict_tokens: Category,
     mark_tokens: Category,
+    also_mark_tokens: &[(&str, &str)],
     samples: &'a [ISample],
 ) -> Vec<CSample<'a>> {
+    assert!(also_mark_tokens.len() <= u64::BITS as usize);
     samples
         .iter()
-        .filter_map(|s| {
-            if years.0 <= s.year
-                && s.year < years.1
-                && categories::matches(restrict_samples, &s.metadata)
-            {
-                Some(get_sample(restrict_tokens, mark_tokens, s))
-            } else {
-                None
-            }
-        })
+        .filter(|s| sample_in_scope(years, restrict_samples, s))
+        .map(|s| get_sample(restrict_tokens, mark_tokens, also_mark_tokens, s))
+        .collect_vec()
+}
+
+/// Filter and convert the samples of `input` once, for use in several calculations.
+///
+/// This is the same as [get_samples] for all years and without additional markings.
+/// The result borrows from `input`, so `input` has to outlive it and all calculations
+/// that use it; see [crate::driver::calc_prepared] and [select_years].
+pub fn prepare<'a>(
+    input: &'a Input,
+    restrict_samples: Category,
+    restrict_tokens: Category,
+    mark_tokens: Category,
+) -> Vec<CSample<'a>> {
+    get_samples(
+        &(Year::MIN, Year::MAX),
+        restrict_samples,
+        restrict_tokens,
+        mark_tokens,
+        &[],
+        &input.samples,
+    )
+}
+
+/// Copy the samples of `prepared` with year in range `years`.
+///
+/// Undated samples are always kept, in the same way as in [get_samples].
+pub fn select_years<'a>(years: &Years, prepared: &[CSample<'a>]) -> Vec<CSample<'a>> {
+    prepared
+        .iter()
+        .filter(|s| in_range(years, s.year))
+        .cloned()
         .collect_vec()
 }
 
+/// Check that all tokens have the key of `mark_tokens`.
+///
+/// The samples and tokens in scope are the same as in [get_samples].
+/// A token that has the key of `mark_tokens` with a different value is simply not marked,
+/// but a token that does not have the key at all is an error here;
+/// the error lists the identifiers of the samples that contain such tokens.
+pub fn require_mark_key(
+    years: &Years,
+    restrict_samples: Category,
+    restrict_tokens: Category,
+    mark_tokens: Category,
+    samples: &[ISample],
+) -> Result<()> {
+    let Some((key, _)) = mark_tokens else {
+        return Ok(());
+    };
+    let bad = samples
+        .iter()
+        .filter(|s| sample_in_scope(years, restrict_samples, s))
+        .filter(|s| {
+            s.tokens
+                .iter()
+                .any(|t| token_in_scope(restrict_tokens, t) && !t.metadata.contains_key(key))
+        })
+        .map(|s| &s.id)
+        .collect_vec();
+    if bad.is_empty() {
+        return Ok(());
+    }
+    Err(errors::invalid_input(format!(
+        "tokens without metadata key {} in {} samples: {}",
+        key,
+        bad.len(),
+        list_ids(&bad)
+    )))
+}
+
+/// List sample identifiers for an error message, at most [MAX_LISTED] of them.
+pub(crate) fn list_ids(ids: &[&String]) -> String {
+    let mut listed = ids.iter().take(MAX_LISTED).join(", ");
+    if ids.len() > MAX_LISTED {
+        listed.push_str(&format!(", and {} more", ids.len() - MAX_LISTED));
+    }
+    listed
+}
+
+/// Group samples by a numeric sample attribute instead of years.
+///
+/// The year of each sample is replaced by the value of metadata key `key`,
+/// which has to be an integer that fits in [Year].
+/// Samples without this metadata key are discarded.
+/// Only samples with the new value in range `years` are kept.
+pub fn group_by<'a>(
+    key: &str,
+    years: &Years,
+    samples: Vec<CSample<'a>>,
+) -> Result<Vec<CSample<'a>>> {
+    let total = samples.len();
+    let mut grouped = vec![];
+    for mut s in samples {
+        let Some(val) = s.metadata.get(key) else {
+            continue;
+        };
+        let year = val.parse().map_err(|_| {
+            errors::invalid_input(format!(
+                "metadata key {} has value {}, expected an integer",
+                key, val
+            ))
+        })?;
+        s.year = Some(year);
+        if in_range(years, s.year) {
+            grouped.push(s);
+        }
+    }
+    info!(target: "types3", "grouped by {}: kept {} of {} samples", key, grouped.len(), total);
+    Ok(grouped)
+}
+
+/// Merge samples with the same value of metadata key `key` into one sample.
+///
+/// The merged sample has all tokens and words of the original samples, and it takes
+/// its metadata and source from the first of them; its year is the common year of
+/// the original samples, or `None` if they differ.
+/// The merged samples are in the order of their first original samples, and
+/// samples without this metadata key are kept as separate samples.
+pub fn accumulate_by<'a>(key: &str, samples: &[&CSample<'a>]) -> Vec<CSample<'a>> {
+    let mut merged: Vec<CSample<'a>> = vec![];
+    let mut index = HashMap::new();
+    for &s in samples {
+        let Some(val) = s.metadata.get(key) else {
+            merged.push(s.clone());
+            continue;
+        };
+        match index.get(val.as_str()) {
+            None => {
+                index.insert(val.as_str(), merged.len());
+                merged.push(s.clone());
+            }
+            Some(&i) => {
+                let m = &mut merged[i];
+                if m.year != s.year {
+                    m.year = None;
+                }
+                m.words += s.words;
+                m.chars += s.chars;
+                m.tokens.extend(s.tokens.iter().cloned());
+            }
+        }
+    }
+    merged
+}
+
+fn in_range(years: &Years, year: Option<Year>) -> bool {
+    match year {
+        None => true,
+        Some(y) => years.0 <= y && y < years.1,
+    }
+}
+
+/// Does sample `s` belong to time period `period`?
+///
+/// Undated samples do not belong to any time period, not even to a period that covers
+/// all data; they are only included when all data is considered together.
+pub fn in_period(period: &Years, s: &CSample) -> bool {
+    year_in_period(period, s.year)
+}
+
+/// Does a sample with year `year` belong to time period `period`? See [in_period].
+pub fn year_in_period(period: &Years, year: Option<Year>) -> bool {
+    match year {
+        None => false,
+        Some(y) => period.0 <= y && y < period.1,
+    }
+}
+
+/// Discard samples with fewer than `min_tokens` tokens.
+///
+/// Tokens are counted after the token-level restriction of [get_samples],
+/// taking into account pre-aggregated counts.
+/// Returns the number of samples that were discarded.
+pub fn drop_short(min_tokens: u64, samples: &mut Vec<CSample>) -> usize {
+    let before = samples.len();
+    samples.retain(|s| s.tokens.iter().map(|t| t.count).sum::<u64>() >= min_tokens);
+    before - samples.len()
+}
+
+/// Keep only a random subsample of the samples.
+///
+/// We keep `fraction` of the samples, rounded to the nearest integer, chosen uniformly
+/// at random; the random choice is determined by `seed`, so the subsample is reproducible.
+/// The samples that we keep stay in their original order.
+/// Returns the number of samples that were kept.
+pub fn subsample(fraction: f64, seed: u64, samples: &mut Vec<CSample>) -> usize {
+    assert!(fraction > 0.0 && fraction <= 1.0);
+    let n = samples.len();
+    let k = ((fraction * n as f64).round() as usize).min(n);
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+    let mut keep = vec![false; n];
+    for i in index::sample(&mut rng, n, k) {
+        keep[i] = true;
+    }
+    let mut keep = keep.into_iter();
+    samples.retain(|_| keep.next().unwrap());
+    k
+}
+
+/// Number of samples that do not have metadata key `key`.
+pub fn count_missing_key(key: &str, samples: &[CSample]) -> usize {
+    samples
+        .iter()
+        .filter(|s| !s.metadata.contains_key(key))
+        .count()
+}
+
+/// Set the source of each sample to the value of metadata key `key`.
+///
+/// Samples without this metadata key do not have a source.
+pub fn set_sources(key: &str, samples: &mut [CSample]) {
+    for s in samples {
+        s.source = s.metadata.get(key).map(|v| v as &str);
+    }
+}
+
 /// Get the range of years represented by a list of samples.
-pub fn get_years(samples: &[CSample]) -> Years {
+/// Undated samples are ignored; returns `None` if there are no dated samples.
+pub fn get_years(samples: &[CSample]) -> Option<Years> {
     let mut years = None;
     for s in samples {
-        years = match years {
-            None => Some((s.year, s.year + 1)),
-            Some((a, b)) => Some((a.min(s.year), b.max(s.year + 1))),
-        };
+        if let Some(y) = s.year {
+            years = match years {
+                None => Some((y, y + 1)),
+                Some((a, b)) => Some((a.min(y), b.max(y + 1))),
+            };
+        }
     }
-    years.expect("there are samples")
+    years
 }
 
 /// Get all categories for a given key.
@@ -0,0 +1,21 @@
+//! JSON Schemas of the input and output files.
+//!
+//! This is only available with feature `schemars`.
+//! The schemas are generated from the definitions of [Input] and [Output],
+//! and the documentation of each field is included as its description.
+//! [Output] is the format that types3-ui reads; files written with an envelope
+//! (see [crate::output::OutputEnvelope]) are not covered.
+
+use crate::input::Input;
+use crate::output::Output;
+use schemars::schema_for;
+
+/// JSON Schema of the output file; see [Output].
+pub fn output_schema() -> serde_json::Value {
+    schema_for!(Output).to_value()
+}
+
+/// JSON Schema of the input file; see [Input].
+pub fn input_schema() -> serde_json::Value {
+    schema_for!(Input).to_value()
+}
@@ -1,9 +1,13 @@
+//! Permutations of samples.
+
 use crate::parallelism::Job;
 use rand::seq::SliceRandom;
+use rand::Rng;
 use rand_xoshiro::rand_core::SeedableRng;
 use rand_xoshiro::Xoshiro256PlusPlus;
 
-pub fn shuffle_job<TCalcOne>(mut calc_one: TCalcOne, n: usize, job: Job)
+/// Call `calc_one` for each random permutation of `0..n` in `job`.
+pub(crate) fn shuffle_job<TCalcOne>(mut calc_one: TCalcOne, n: usize, job: Job)
 where
     TCalcOne: FnMut(&[usize]),
 {
@@ -11,9 +15,127 @@ where
     for (i, v) in idx.iter_mut().enumerate() {
         *v = i;
     }
-    let mut rng = Xoshiro256PlusPlus::seed_from_u64(job.job_id);
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(job.seed);
     for _ in 0..job.iter_per_job {
         idx.shuffle(&mut rng);
         calc_one(&idx);
     }
 }
+
+/// Call `calc_one` for each bootstrap resample of `0..n` in `job`.
+///
+/// Each resample consists of `n` indexes chosen uniformly at random with replacement.
+pub(crate) fn bootstrap_job<TCalcOne>(mut calc_one: TCalcOne, n: usize, job: Job)
+where
+    TCalcOne: FnMut(&[usize]),
+{
+    let mut idx = vec![0; n];
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(job.seed);
+    for _ in 0..job.iter_per_job {
+        for v in idx.iter_mut() {
+            *v = rng.gen_range(0..n);
+        }
+        calc_one(&idx);
+    }
+}
+
+/// Source of the permutations of samples.
+#[derive(Clone, Copy, Debug)]
+pub enum Permutations<'a> {
+    /// Random permutations.
+    Random {
+        /// Number of permutations.
+        iter: u64,
+        /// Random seed; see [crate::driver::DriverArgs::seed].
+        seed: u64,
+    },
+    /// Exactly these permutations of sample indexes, in this order.
+    /// This does not use the random number generator, so the results are fully
+    /// determined by the permutations; this is mainly useful for testing.
+    Fixed(&'a [Vec<usize>]),
+}
+
+/// Call `calc_one` for each permutation in `permutations`, in order.
+///
+/// Each permutation has to contain each of `0..n` exactly once;
+/// this is checked with assertions.
+pub fn fixed_job<TCalcOne>(mut calc_one: TCalcOne, n: usize, permutations: &[Vec<usize>])
+where
+    TCalcOne: FnMut(&[usize]),
+{
+    for idx in permutations {
+        assert!(
+            is_permutation(idx, n),
+            "not a permutation of 0..{n}: {idx:?}"
+        );
+        calc_one(idx);
+    }
+}
+
+/// Number of distinct orderings of `n` samples, capped at `cap`.
+///
+/// This is `min(n!, cap)`: even if some samples are identical, we count them as distinct.
+/// With at most this many distinct random permutations, repeating them more often
+/// does not give any new information.
+///
+/// # Examples
+/// ```
+/// use types3::shuffle::distinct_orderings;
+/// assert_eq!(distinct_orderings(0, 1000), 1);
+/// assert_eq!(distinct_orderings(2, 1000), 2);
+/// assert_eq!(distinct_orderings(5, 1000), 120);
+/// assert_eq!(distinct_orderings(7, 1000), 1000);
+/// ```
+pub fn distinct_orderings(n: usize, cap: u64) -> u64 {
+    let mut orderings: u64 = 1;
+    for i in 2..=n as u64 {
+        orderings = orderings.saturating_mul(i);
+        if orderings >= cap {
+            return cap;
+        }
+    }
+    orderings.min(cap)
+}
+
+fn is_permutation(idx: &[usize], n: usize) -> bool {
+    let mut seen = vec![false; n];
+    idx.len() == n
+        && idx
+            .iter()
+            .all(|&i| i < n && !std::mem::replace(&mut seen[i], true))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fixed_job_order() {
+        let permutations = [vec![2, 0, 1], vec![0, 1, 2], vec![1, 2, 0]];
+        let mut seen = vec![];
+        fixed_job(|idx| seen.push(idx.to_vec()), 3, &permutations);
+        assert_eq!(seen, permutations);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a permutation")]
+    fn fixed_job_duplicate() {
+        fixed_job(|_| (), 3, &[vec![0, 1, 1]]);
+    }
+
+    #[test]
+    fn distinct_orderings_basic() {
+        assert_eq!(distinct_orderings(1, 10), 1);
+        assert_eq!(distinct_orderings(3, 10), 6);
+        assert_eq!(distinct_orderings(3, 6), 6);
+        assert_eq!(distinct_orderings(3, 5), 5);
+        assert_eq!(distinct_orderings(1000, u64::MAX), u64::MAX);
+        assert_eq!(distinct_orderings(20, u64::MAX), 2432902008176640000);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a permutation")]
+    fn fixed_job_short() {
+        fixed_job(|_| (), 3, &[vec![0, 1]]);
+    }
+}
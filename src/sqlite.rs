@@ -0,0 +1,155 @@
+//! Export results to an SQLite database.
+//!
+//! This is only available with feature `sqlite`.
+//! Each [Output] is stored as one run, so that the results of many calculations
+//! (e.g. with different corpora or parameters) can be queried together.
+//!
+//! There are two tables:
+//! - `runs`: one row per run, with the parameters of the calculation.
+//! - `results`: one row per category and time period, with column `run` referring to `runs.id`.
+//!   The p-values are for [Output::test_side], or two-sided if it is not specified.
+
+use crate::errors::Result;
+use crate::output::{AvgResult, OCategory, Output, PointResult, TestSide};
+use rusqlite::{params, Connection};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    args TEXT NOT NULL,
+    provenance TEXT NOT NULL,
+    measure_y TEXT NOT NULL,
+    measure_x TEXT NOT NULL,
+    size_limit INTEGER NOT NULL,
+    iter INTEGER NOT NULL,
+    split_samples INTEGER NOT NULL,
+    group_by TEXT,
+    restrict_samples TEXT,
+    restrict_tokens TEXT,
+    mark_tokens TEXT,
+    test_side TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS results (
+    run INTEGER NOT NULL REFERENCES runs(id),
+    category_key TEXT,
+    category_value TEXT,
+    period_start INTEGER NOT NULL,
+    period_end INTEGER NOT NULL,
+    average REAL NOT NULL,
+    average_low REAL NOT NULL,
+    average_high REAL NOT NULL,
+    iter INTEGER NOT NULL,
+    vs_time_above INTEGER,
+    vs_time_below INTEGER,
+    vs_time_iter INTEGER,
+    vs_time_p REAL,
+    vs_categories_above INTEGER,
+    vs_categories_below INTEGER,
+    vs_categories_iter INTEGER,
+    vs_categories_p REAL
+);
+";
+
+/// Create the tables if they do not exist yet.
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute_batch(SCHEMA)?;
+    Ok(())
+}
+
+/// Store `output` as a new run, creating the tables if needed.
+///
+/// Here `args` describes how the calculation was invoked (e.g. the command line),
+/// and `provenance` where the input came from (e.g. the name of the input file);
+/// both are stored as is. Returns the identifier of the new run.
+pub fn write_output(
+    conn: &mut Connection,
+    output: &Output,
+    args: &str,
+    provenance: &str,
+) -> Result<i64> {
+    create_tables(conn)?;
+    let side = output.test_side.unwrap_or(TestSide::TwoSided);
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO runs (args, provenance, measure_y, measure_x, size_limit, iter, split_samples, group_by, restrict_samples, restrict_tokens, mark_tokens, test_side)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            args,
+            provenance,
+            output.measure_y.to_string(),
+            output.measure_x.to_string(),
+            output.limit as i64,
+            output.iter as i64,
+            output.split_samples,
+            output.group_by,
+            category_string(&output.restrict_samples),
+            category_string(&output.restrict_tokens),
+            category_string(&output.mark_tokens),
+            serde_json::to_value(side)?.as_str(),
+        ],
+    )?;
+    let run = tx.last_insert_rowid();
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO results VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+        )?;
+        for curve in &output.curves {
+            let (key, value) = match &curve.category {
+                None => (None, None),
+                Some((k, v)) => (Some(k), Some(v)),
+            };
+            for r in &curve.results {
+                let (avg, low, high) = averages(&r.average_at_limit);
+                let (t_above, t_below, t_iter, t_p) = point_columns(&r.vs_time, side);
+                let (c_above, c_below, c_iter, c_p) = point_columns(&r.vs_categories, side);
+                stmt.execute(params![
+                    run,
+                    key,
+                    value,
+                    r.period.0,
+                    r.period.1,
+                    avg,
+                    low,
+                    high,
+                    r.average_at_limit.iter as i64,
+                    t_above,
+                    t_below,
+                    t_iter,
+                    t_p,
+                    c_above,
+                    c_below,
+                    c_iter,
+                    c_p,
+                ])?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(run)
+}
+
+fn category_string(category: &OCategory) -> Option<String> {
+    category.as_ref().map(|(k, v)| format!("{k}={v}"))
+}
+
+fn averages(ar: &AvgResult) -> (f64, f64, f64) {
+    let iter = ar.iter as f64;
+    let low = ar.low as f64 / iter;
+    let high = ar.high as f64 / iter;
+    ((low + high) / 2.0, low, high)
+}
+
+type PointColumns = (Option<i64>, Option<i64>, Option<i64>, Option<f64>);
+
+/// Columns for a comparison; the p-value is two-sided unless [Output::test_side] says otherwise.
+fn point_columns(pr: &Option<PointResult>, side: TestSide) -> PointColumns {
+    match pr {
+        None => (None, None, None, None),
+        Some(pr) => (
+            Some(pr.above as i64),
+            Some(pr.below as i64),
+            Some(pr.iter as i64),
+            Some(pr.p_value(side)),
+        ),
+    }
+}
@@ -2,8 +2,10 @@ use crate::calc_point::Point;
 use crate::calculation::{SToken, Sample};
 use crate::categories::{self, Category};
 use crate::counter;
+use crate::errors::{self, Result};
+use crate::input::Year;
 use crate::output::{self, MeasureX, MeasureY, Years};
-use crate::samples::CSample;
+use crate::samples::{self, CSample};
 use itertools::Itertools;
 use log::debug;
 use std::collections::{HashMap, HashSet};
@@ -27,8 +29,12 @@ pub struct Subset<'a> {
     pub category: Category<'a>,
     pub period: Years,
     pub samples: Vec<Sample>,
+    /// Year of each sample in `samples`; see [crate::input::ISample::year].
+    pub sample_years: Vec<Option<Year>>,
     pub total_x: u64,
     pub total_y: u64,
+    /// Total number of words in the original samples; see [crate::input::ISample::words].
+    pub total_words: u64,
     pub points: HashSet<Point>,
 }
 
@@ -37,7 +43,7 @@ impl<'a> Subset<'a> {
         self.key().pretty()
     }
 
-    pub fn key(&self) -> SubsetKey {
+    pub fn key(&self) -> SubsetKey<'_> {
         SubsetKey {
             category: self.category,
             period: self.period,
@@ -74,44 +80,136 @@ impl<'a> Subset<'a> {
     }
 }
 
+/// How to turn samples into [Sample] objects.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SampleMode {
+    /// Use the original samples as is.
+    Whole,
+    /// Each token is a separate sample; see [crate::driver::DriverArgs::split_samples].
+    Split,
+    /// Each type is counted at most once per sample;
+    /// see [crate::driver::DriverArgs::presence_absence].
+    PresenceAbsence,
+}
+
+/// Does sample `s` have the right category and year in range `period`?
+///
+/// Undated samples are included only if `all_time` is true, i.e., if the subset
+/// represents all data instead of a time period; see [samples::in_period].
+pub fn contains(category: Category, period: Years, all_time: bool, s: &CSample) -> bool {
+    (samples::in_period(&period, s) || all_time && s.year.is_none())
+        && categories::matches(category, s.metadata)
+}
+
+/// Build the subset identified by `key`.
+///
+/// The subset consists of samples of the right category with year in range `period`;
+/// usually `period` is the time period of `key`.
+/// Undated samples are included only if `all_time` is true; see [contains].
+#[allow(clippy::too_many_arguments)]
 pub fn build_subset<'a>(
     measure_x: MeasureX,
     measure_y: MeasureY,
     samples: &[CSample<'a>],
     key: SubsetKey<'a>,
-    split_samples: bool,
-) -> Subset<'a> {
+    period: Years,
+    all_time: bool,
+    mode: SampleMode,
+    vocabulary: Option<&HashSet<String>>,
+    accumulate_by: Option<&str>,
+) -> Result<Subset<'a>> {
+    let filter = |s: &CSample| contains(key.category, period, all_time, s);
+    build_subset_with(
+        measure_x,
+        measure_y,
+        samples,
+        key,
+        filter,
+        mode,
+        vocabulary,
+        accumulate_by,
+    )
+}
+
+/// Build a subset labeled with `key`, consisting of the samples that match `filter`.
+///
+/// Types whose lemma is in `vocabulary` are marked as known; see [SToken::known].
+/// If `accumulate_by` is specified, the samples with the same value of this metadata key
+/// are merged into one sample; see [samples::accumulate_by].
+#[allow(clippy::too_many_arguments)]
+pub fn build_subset_with<'a, F>(
+    measure_x: MeasureX,
+    measure_y: MeasureY,
+    samples: &[CSample<'a>],
+    key: SubsetKey<'a>,
+    filter: F,
+    mode: SampleMode,
+    vocabulary: Option<&HashSet<String>>,
+    accumulate_by: Option<&str>,
+) -> Result<Subset<'a>>
+where
+    F: Fn(&CSample) -> bool,
+{
     let category = key.category;
     let period = key.period;
-    let filter = |s: &&CSample| {
-        period.0 <= s.year && s.year < period.1 && categories::matches(category, s.metadata)
+    let samples = samples.iter().filter(|s| filter(s)).collect_vec();
+    let total_words = samples.iter().map(|s| s.words).sum();
+    let merged;
+    let samples = match accumulate_by {
+        None => samples,
+        Some(key) => {
+            merged = samples::accumulate_by(key, &samples);
+            merged.iter().collect_vec()
+        }
     };
-    let samples = samples.iter().filter(filter).collect_vec();
 
     let mut lemmas = HashSet::new();
+    let mut sources = HashSet::new();
     for s in &samples {
         for t in &s.tokens {
             lemmas.insert(t.token);
         }
+        if let Some(source) = s.source {
+            sources.insert(source);
+        }
     }
     let mut lemmas = lemmas.into_iter().collect_vec();
     lemmas.sort();
     let lemmamap: HashMap<&str, usize> = lemmas.iter().enumerate().map(|(i, &x)| (x, i)).collect();
-    let samples = if split_samples {
-        assert!(measure_x != MeasureX::Words);
+    let known = lemmas
+        .iter()
+        .map(|&x| vocabulary.is_some_and(|v| v.contains(x)))
+        .collect_vec();
+    let mut sources = sources.into_iter().collect_vec();
+    sources.sort();
+    let sourcemap: HashMap<&str, usize> =
+        sources.iter().enumerate().map(|(i, &x)| (x, i)).collect();
+    let mut sample_years = vec![];
+    let samples = if mode == SampleMode::Split {
+        assert!(!matches!(
+            measure_x,
+            MeasureX::Words | MeasureX::Chars | MeasureX::Samples
+        ));
         let mut split = vec![];
         for s in samples {
             for t in &s.tokens {
-                let token = SToken {
-                    id: lemmamap[t.token],
-                    count: 1,
-                    marked_count: if t.marked { 1 } else { 0 },
-                };
-                split.push(Sample {
-                    x: 1,
-                    token_count: 1,
-                    tokens: vec![token],
-                })
+                for _ in 0..t.count {
+                    let id = lemmamap[t.token];
+                    let token = SToken {
+                        id,
+                        count: 1,
+                        marked_count: if t.marked { 1 } else { 0 },
+                        also_marked: t.also_marked,
+                        known: known[id],
+                    };
+                    split.push(Sample {
+                        x: 1,
+                        token_count: 1,
+                        tokens: vec![token],
+                        source: s.source.map(|x| sourcemap[x]),
+                    });
+                    sample_years.push(s.year);
+                }
             }
         }
         split
@@ -120,7 +218,9 @@ pub fn build_subset<'a>(
         struct TokenCount {
             count: u64,
             marked_count: u64,
+            also_marked: u64,
         }
+        sample_years = samples.iter().map(|s| s.year).collect_vec();
         samples
             .into_iter()
             .map(|s| {
@@ -130,10 +230,18 @@ pub fn build_subset<'a>(
                     let e = tokencount.entry(id).or_insert(TokenCount {
                         count: 0,
                         marked_count: 0,
+                        also_marked: 0,
                     });
-                    e.count += 1;
+                    e.count += t.count;
+                    e.also_marked |= t.also_marked;
                     if t.marked {
-                        e.marked_count += 1;
+                        e.marked_count += t.count;
+                    }
+                }
+                if mode == SampleMode::PresenceAbsence {
+                    for x in tokencount.values_mut() {
+                        x.count = 1;
+                        x.marked_count = x.marked_count.min(1);
                     }
                 }
                 let mut tokens = tokencount
@@ -142,6 +250,8 @@ pub fn build_subset<'a>(
                         id,
                         count: x.count,
                         marked_count: x.marked_count,
+                        also_marked: x.also_marked,
+                        known: known[id],
                     })
                     .collect_vec();
                 tokens.sort_by_key(|t| t.id);
@@ -150,22 +260,28 @@ pub fn build_subset<'a>(
                     MeasureX::Tokens => token_count,
                     MeasureX::Words => s.words,
                     MeasureX::Types => 0,
+                    MeasureX::Samples => 1,
+                    MeasureX::Chars => s.chars,
                 };
                 Sample {
                     x,
                     token_count,
                     tokens,
+                    source: s.source.map(|x| sourcemap[x]),
                 }
             })
             .collect_vec()
     };
+    check_marked(&key, &lemmas, &samples)?;
     let (total_x, total_y) = counter::count_xy(measure_y, &samples);
     let s = Subset {
         category,
         period,
         samples,
+        sample_years,
         total_x,
         total_y,
+        total_words,
         points: HashSet::new(),
     };
     debug!(
@@ -178,12 +294,36 @@ pub fn build_subset<'a>(
         s.total_x,
         measure_x,
     );
-    s
+    Ok(s)
+}
+
+/// Check that no type has more marked tokens than tokens.
+///
+/// This holds if tokens are marked correctly, but we check it already here
+/// so that we can report an error instead of failing an assertion in
+/// [crate::calculation::Sample::verify].
+/// Here `lemmas` maps type identifiers back to lemmas.
+fn check_marked(key: &SubsetKey, lemmas: &[&str], samples: &[Sample]) -> Result<()> {
+    for s in samples {
+        for t in &s.tokens {
+            if t.marked_count > t.count {
+                return Err(errors::invalid_input(format!(
+                    "{}: lemma {} has {} marked tokens but only {} tokens",
+                    key.pretty(),
+                    lemmas[t.id],
+                    t.marked_count,
+                    t.count
+                )));
+            }
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::calculation;
     use crate::samples::CToken;
 
     fn st(id: usize, count: u64) -> SToken {
@@ -191,6 +331,8 @@ mod test {
             id,
             count,
             marked_count: 0,
+            also_marked: 0,
+            known: false,
         }
     }
 
@@ -199,20 +341,35 @@ mod test {
             id,
             count,
             marked_count,
+            also_marked: 0,
+            known: false,
         }
     }
 
-    fn ct(token: &str) -> CToken {
+    fn ct(token: &str) -> CToken<'_> {
         CToken {
             token,
+            count: 1,
             marked: false,
+            also_marked: 0,
         }
     }
 
-    fn ctm(token: &str) -> CToken {
+    fn ctm(token: &str) -> CToken<'_> {
         CToken {
             token,
+            count: 1,
             marked: true,
+            also_marked: 0,
+        }
+    }
+
+    fn ctn(token: &str, count: u64, marked: bool) -> CToken<'_> {
+        CToken {
+            token,
+            count,
+            marked,
+            also_marked: 0,
         }
     }
 
@@ -231,23 +388,38 @@ mod test {
         let no_metadata = HashMap::new();
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &no_metadata,
                 words: 1234,
+                chars: 0,
                 tokens: vec![],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &no_metadata,
                 words: 5678,
+                chars: 0,
                 tokens: vec![],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: None,
             period: (1500, 1600),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -255,7 +427,8 @@ mod test {
             vec![Sample {
                 x: 1234,
                 token_count: 0,
-                tokens: vec![]
+                tokens: vec![],
+                source: None
             }]
         );
         assert_eq!(r.total_x, 1234);
@@ -263,6 +436,80 @@ mod test {
         assert_eq!(r.points, HashSet::new());
     }
 
+    #[test]
+    #[should_panic(expected = "MeasureX::Samples")]
+    fn build_subsets_split_samples_fail() {
+        let no_metadata = HashMap::new();
+        let samples = vec![CSample {
+            year: Some(1555),
+            metadata: &no_metadata,
+            words: 1234,
+            chars: 0,
+            tokens: vec![ct("a"), ct("b")],
+            source: None,
+        }];
+        let key = SubsetKey {
+            category: None,
+            period: (1500, 1600),
+        };
+        let _ = build_subset(
+            MeasureX::Samples,
+            MeasureY::Types,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Split,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn build_subsets_types_chars() {
+        let my = MeasureY::Types;
+        let mx = MeasureX::Chars;
+        let no_metadata = HashMap::new();
+        let samples = vec![
+            CSample {
+                year: Some(1555),
+                metadata: &no_metadata,
+                words: 1234,
+                chars: 5555,
+                tokens: vec![],
+                source: None,
+            },
+            CSample {
+                year: Some(1556),
+                metadata: &no_metadata,
+                words: 5678,
+                chars: 6666,
+                tokens: vec![],
+                source: None,
+            },
+        ];
+        let key = SubsetKey {
+            category: None,
+            period: (1500, 1600),
+        };
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
+        let x = r.samples.iter().map(|s| s.x).collect_vec();
+        assert_eq!(x, [5555, 6666]);
+        assert_eq!(r.total_x, 5555 + 6666);
+        assert_eq!(r.total_words, 1234 + 5678);
+    }
+
     #[test]
     fn build_subsets_types_words_empty2() {
         let my = MeasureY::Types;
@@ -270,23 +517,38 @@ mod test {
         let no_metadata = HashMap::new();
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &no_metadata,
                 words: 1234,
+                chars: 0,
                 tokens: vec![],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &no_metadata,
                 words: 5678,
+                chars: 0,
                 tokens: vec![],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: None,
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -295,12 +557,14 @@ mod test {
                 Sample {
                     x: 1234,
                     token_count: 0,
-                    tokens: vec![]
+                    tokens: vec![],
+                    source: None
                 },
                 Sample {
                     x: 5678,
                     token_count: 0,
-                    tokens: vec![]
+                    tokens: vec![],
+                    source: None
                 }
             ]
         );
@@ -316,23 +580,38 @@ mod test {
         let no_metadata = HashMap::new();
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &no_metadata,
                 words: 1234,
+                chars: 0,
                 tokens: vec![ct("a"), ct("a"), ct("b")],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &no_metadata,
                 words: 5678,
+                chars: 0,
                 tokens: vec![ct("c"), ct("d")],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: None,
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -341,12 +620,14 @@ mod test {
                 Sample {
                     x: 1234,
                     token_count: 3,
-                    tokens: vec![st(0, 2), st(1, 1),]
+                    tokens: vec![st(0, 2), st(1, 1),],
+                    source: None
                 },
                 Sample {
                     x: 5678,
                     token_count: 2,
-                    tokens: vec![st(2, 1), st(3, 1),]
+                    tokens: vec![st(2, 1), st(3, 1),],
+                    source: None
                 }
             ]
         );
@@ -362,23 +643,38 @@ mod test {
         let no_metadata = HashMap::new();
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &no_metadata,
                 words: 1234,
+                chars: 0,
                 tokens: vec![ct("c"), ct("c"), ct("b")],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &no_metadata,
                 words: 5678,
+                chars: 0,
                 tokens: vec![ct("c"), ct("d")],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: None,
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -387,12 +683,14 @@ mod test {
                 Sample {
                     x: 1234,
                     token_count: 3,
-                    tokens: vec![st(0, 1), st(1, 2),]
+                    tokens: vec![st(0, 1), st(1, 2),],
+                    source: None
                 },
                 Sample {
                     x: 5678,
                     token_count: 2,
-                    tokens: vec![st(1, 1), st(2, 1),]
+                    tokens: vec![st(1, 1), st(2, 1),],
+                    source: None
                 }
             ]
         );
@@ -408,23 +706,38 @@ mod test {
         let no_metadata = HashMap::new();
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &no_metadata,
                 words: 1234,
+                chars: 0,
                 tokens: vec![ct("c"), ct("c"), ct("b")],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &no_metadata,
                 words: 5678,
+                chars: 0,
                 tokens: vec![ct("c"), ct("d")],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: None,
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -433,12 +746,14 @@ mod test {
                 Sample {
                     x: 3,
                     token_count: 3,
-                    tokens: vec![st(0, 1), st(1, 2),]
+                    tokens: vec![st(0, 1), st(1, 2),],
+                    source: None
                 },
                 Sample {
                     x: 2,
                     token_count: 2,
-                    tokens: vec![st(1, 1), st(2, 1),]
+                    tokens: vec![st(1, 1), st(2, 1),],
+                    source: None
                 }
             ]
         );
@@ -454,23 +769,38 @@ mod test {
         let no_metadata = HashMap::new();
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &no_metadata,
                 words: 1234,
+                chars: 0,
                 tokens: vec![ct("c"), ct("c"), ct("b")],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &no_metadata,
                 words: 5678,
+                chars: 0,
                 tokens: vec![ct("c"), ct("d")],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: None,
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -479,12 +809,14 @@ mod test {
                 Sample {
                     x: 1234,
                     token_count: 3,
-                    tokens: vec![st(0, 1), st(1, 2),]
+                    tokens: vec![st(0, 1), st(1, 2),],
+                    source: None
                 },
                 Sample {
                     x: 5678,
                     token_count: 2,
-                    tokens: vec![st(1, 1), st(2, 1),]
+                    tokens: vec![st(1, 1), st(2, 1),],
+                    source: None
                 }
             ]
         );
@@ -500,23 +832,38 @@ mod test {
         let no_metadata = HashMap::new();
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &no_metadata,
                 words: 1234,
+                chars: 0,
                 tokens: vec![ct("c"), ct("c"), ct("b")],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &no_metadata,
                 words: 5678,
+                chars: 0,
                 tokens: vec![ct("c"), ct("d")],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: None,
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, true);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Split,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -525,27 +872,32 @@ mod test {
                 Sample {
                     x: 1,
                     token_count: 1,
-                    tokens: vec![st(1, 1),]
+                    tokens: vec![st(1, 1),],
+                    source: None
                 },
                 Sample {
                     x: 1,
                     token_count: 1,
-                    tokens: vec![st(1, 1),]
+                    tokens: vec![st(1, 1),],
+                    source: None
                 },
                 Sample {
                     x: 1,
                     token_count: 1,
-                    tokens: vec![st(0, 1),]
+                    tokens: vec![st(0, 1),],
+                    source: None
                 },
                 Sample {
                     x: 1,
                     token_count: 1,
-                    tokens: vec![st(1, 1),]
+                    tokens: vec![st(1, 1),],
+                    source: None
                 },
                 Sample {
                     x: 1,
                     token_count: 1,
-                    tokens: vec![st(2, 1),]
+                    tokens: vec![st(2, 1),],
+                    source: None
                 },
             ]
         );
@@ -554,6 +906,71 @@ mod test {
         assert_eq!(r.points, HashSet::new());
     }
 
+    #[test]
+    fn build_subsets_years() {
+        let my = MeasureY::Samples;
+        let mx = MeasureX::Words;
+        let no_metadata = HashMap::new();
+        let samples = [Some(1500), Some(1510), Some(1520), Some(1530), None]
+            .into_iter()
+            .map(|year| CSample {
+                year,
+                metadata: &no_metadata,
+                words: 10,
+                chars: 0,
+                tokens: vec![],
+                source: None,
+            })
+            .collect_vec();
+        let key = SubsetKey {
+            category: None,
+            period: (1500, 1540),
+        };
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            true,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(r.period, (1500, 1540));
+        assert_eq!(r.total_y, 5);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(r.period, (1500, 1540));
+        assert_eq!(r.total_y, 4);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            (1500, 1520),
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(r.period, (1500, 1540));
+        assert_eq!(r.total_x, 20);
+        assert_eq!(r.total_y, 2);
+    }
+
     #[test]
     fn build_subsets_types_words_category1() {
         let my = MeasureY::Types;
@@ -562,23 +979,38 @@ mod test {
         let meta2 = meta(&[("x", "c"), ("z", "d")]);
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &meta1,
                 words: 1234,
+                chars: 0,
                 tokens: vec![ct("c"), ct("c"), ct("b")],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &meta2,
                 words: 5678,
+                chars: 0,
                 tokens: vec![ct("c"), ct("d")],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: Some(("y", "b")),
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -586,7 +1018,8 @@ mod test {
             vec![Sample {
                 x: 1234,
                 token_count: 3,
-                tokens: vec![st(0, 1), st(1, 2),]
+                tokens: vec![st(0, 1), st(1, 2),],
+                source: None
             },]
         );
         assert_eq!(r.total_x, 1234);
@@ -602,23 +1035,38 @@ mod test {
         let meta2 = meta(&[("x", "c"), ("z", "d")]);
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &meta1,
                 words: 1234,
+                chars: 0,
                 tokens: vec![ct("c"), ct("c"), ct("b")],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &meta2,
                 words: 5678,
+                chars: 0,
                 tokens: vec![ct("c"), ct("d")],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: Some(("x", "a")),
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -626,7 +1074,8 @@ mod test {
             vec![Sample {
                 x: 1234,
                 token_count: 3,
-                tokens: vec![st(0, 1), st(1, 2),]
+                tokens: vec![st(0, 1), st(1, 2),],
+                source: None
             },]
         );
         assert_eq!(r.total_x, 1234);
@@ -641,23 +1090,38 @@ mod test {
         let no_metadata = HashMap::new();
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &no_metadata,
                 words: 1234,
+                chars: 0,
                 tokens: vec![ct("c"), ct("c"), ct("b")],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &no_metadata,
                 words: 5678,
+                chars: 0,
                 tokens: vec![ct("c"), ct("d")],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: None,
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -666,12 +1130,14 @@ mod test {
                 Sample {
                     x: 0,
                     token_count: 3,
-                    tokens: vec![st(0, 1), st(1, 2),]
+                    tokens: vec![st(0, 1), st(1, 2),],
+                    source: None
                 },
                 Sample {
                     x: 0,
                     token_count: 2,
-                    tokens: vec![st(1, 1), st(2, 1),]
+                    tokens: vec![st(1, 1), st(2, 1),],
+                    source: None
                 }
             ]
         );
@@ -687,23 +1153,38 @@ mod test {
         let no_metadata = HashMap::new();
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &no_metadata,
                 words: 1234,
+                chars: 0,
                 tokens: vec![ctm("c"), ctm("c"), ctm("b")],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &no_metadata,
                 words: 5678,
+                chars: 0,
                 tokens: vec![ctm("c"), ctm("d")],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: None,
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -712,12 +1193,14 @@ mod test {
                 Sample {
                     x: 0,
                     token_count: 3,
-                    tokens: vec![stm(0, 1, 1), stm(1, 2, 2),]
+                    tokens: vec![stm(0, 1, 1), stm(1, 2, 2),],
+                    source: None
                 },
                 Sample {
                     x: 0,
                     token_count: 2,
-                    tokens: vec![stm(1, 1, 1), stm(2, 1, 1),]
+                    tokens: vec![stm(1, 1, 1), stm(2, 1, 1),],
+                    source: None
                 }
             ]
         );
@@ -733,23 +1216,38 @@ mod test {
         let no_metadata = HashMap::new();
         let samples = vec![
             CSample {
-                year: 1555,
+                year: Some(1555),
                 metadata: &no_metadata,
                 words: 1234,
+                chars: 0,
                 tokens: vec![ctm("c"), ct("c"), ctm("b")],
+                source: None,
             },
             CSample {
-                year: 1666,
+                year: Some(1666),
                 metadata: &no_metadata,
                 words: 5678,
+                chars: 0,
                 tokens: vec![ctm("c"), ct("d")],
+                source: None,
             },
         ];
         let key = SubsetKey {
             category: None,
             period: (1500, 1700),
         };
-        let r = build_subset(mx, my, &samples, key, false);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
         assert_eq!(r.category, key.category);
         assert_eq!(r.period, key.period);
         assert_eq!(
@@ -758,12 +1256,14 @@ mod test {
                 Sample {
                     x: 0,
                     token_count: 3,
-                    tokens: vec![stm(0, 1, 1), stm(1, 2, 1),]
+                    tokens: vec![stm(0, 1, 1), stm(1, 2, 1),],
+                    source: None
                 },
                 Sample {
                     x: 0,
                     token_count: 2,
-                    tokens: vec![stm(1, 1, 1), stm(2, 1, 0),]
+                    tokens: vec![stm(1, 1, 1), stm(2, 1, 0),],
+                    source: None
                 }
             ]
         );
@@ -771,4 +1271,375 @@ mod test {
         assert_eq!(r.total_y, 2);
         assert_eq!(r.points, HashSet::new());
     }
+
+    #[test]
+    fn build_subsets_marked_tokens_some() {
+        let my = MeasureY::MarkedTokens;
+        let mx = MeasureX::Tokens;
+        let no_metadata = HashMap::new();
+        let samples = vec![
+            CSample {
+                year: Some(1555),
+                metadata: &no_metadata,
+                words: 1234,
+                chars: 0,
+                tokens: vec![ctm("c"), ct("c"), ctm("b")],
+                source: None,
+            },
+            CSample {
+                year: Some(1666),
+                metadata: &no_metadata,
+                words: 5678,
+                chars: 0,
+                tokens: vec![ctm("c"), ct("d")],
+                source: None,
+            },
+        ];
+        let key = SubsetKey {
+            category: None,
+            period: (1500, 1700),
+        };
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(r.total_x, 5);
+        assert_eq!(r.total_y, 3);
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Split,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(r.samples.len(), 5);
+        assert_eq!(r.total_x, 5);
+        assert_eq!(r.total_y, 3);
+        let key = SubsetKey {
+            category: None,
+            period: (1600, 1700),
+        };
+        let r = build_subset(
+            mx,
+            my,
+            &samples,
+            key,
+            key.period,
+            false,
+            SampleMode::Whole,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(r.total_x, 2);
+        assert_eq!(r.total_y, 1);
+    }
+
+    #[test]
+    fn check_marked_bad() {
+        let key = SubsetKey {
+            category: None,
+            period: (1500, 1600),
+        };
+        let lemmas = ["a", "b"];
+        let sample = |tokens| Sample {
+            x: 1,
+            token_count: 10,
+            tokens,
+            source: None,
+        };
+        let good = [sample(vec![stm(0, 5, 5), stm(1, 5, 0)])];
+        check_marked(&key, &lemmas, &good).unwrap();
+        let bad = [sample(vec![stm(0, 5, 5), stm(1, 5, 6)])];
+        let e = check_marked(&key, &lemmas, &bad).unwrap_err();
+        assert!(e
+            .to_string()
+            .contains("lemma b has 6 marked tokens but only 5 tokens"));
+    }
+
+    #[test]
+    fn build_subsets_presence_absence() {
+        let no_metadata = HashMap::new();
+        let samples = vec![
+            CSample {
+                year: Some(1555),
+                metadata: &no_metadata,
+                words: 1234,
+                chars: 0,
+                tokens: vec![ctm("c"), ct("c"), ct("c"), ct("b"), ctm("b")],
+                source: None,
+            },
+            CSample {
+                year: Some(1666),
+                metadata: &no_metadata,
+                words: 5678,
+                chars: 0,
+                tokens: vec![ct("c"), ct("d"), ct("d")],
+                source: None,
+            },
+        ];
+        let key = SubsetKey {
+            category: None,
+            period: (1500, 1700),
+        };
+        let build = |mx, my, mode| {
+            build_subset(mx, my, &samples, key, key.period, false, mode, None, None).unwrap()
+        };
+        let mx = MeasureX::Tokens;
+        let r = build(mx, MeasureY::Types, SampleMode::Whole);
+        assert_eq!(
+            r.samples,
+            vec![
+                Sample {
+                    x: 5,
+                    token_count: 5,
+                    tokens: vec![stm(0, 2, 1), stm(1, 3, 1)],
+                    source: None
+                },
+                Sample {
+                    x: 3,
+                    token_count: 3,
+                    tokens: vec![st(1, 1), st(2, 2)],
+                    source: None
+                },
+            ]
+        );
+        assert_eq!((r.total_x, r.total_y), (8, 3));
+        let r = build(mx, MeasureY::Types, SampleMode::PresenceAbsence);
+        assert_eq!(
+            r.samples,
+            vec![
+                Sample {
+                    x: 2,
+                    token_count: 2,
+                    tokens: vec![stm(0, 1, 1), stm(1, 1, 1)],
+                    source: None
+                },
+                Sample {
+                    x: 2,
+                    token_count: 2,
+                    tokens: vec![st(1, 1), st(2, 1)],
+                    source: None
+                },
+            ]
+        );
+        assert_eq!((r.total_x, r.total_y), (4, 3));
+        // Tokens are now types per sample, and hapaxes are types that occur in only one sample.
+        let r = build(mx, MeasureY::Tokens, SampleMode::Whole);
+        assert_eq!(r.total_y, 8);
+        let r = build(mx, MeasureY::Tokens, SampleMode::PresenceAbsence);
+        assert_eq!(r.total_y, 4);
+        let r = build(mx, MeasureY::Hapaxes, SampleMode::Whole);
+        assert_eq!(r.total_y, 0);
+        let r = build(mx, MeasureY::Hapaxes, SampleMode::PresenceAbsence);
+        assert_eq!(r.total_y, 2);
+        let r = build(mx, MeasureY::MarkedTokens, SampleMode::Whole);
+        assert_eq!(r.total_y, 2);
+        let r = build(mx, MeasureY::MarkedTokens, SampleMode::PresenceAbsence);
+        assert_eq!(r.total_y, 2);
+        // Words are not affected.
+        let r = build(
+            MeasureX::Words,
+            MeasureY::Types,
+            SampleMode::PresenceAbsence,
+        );
+        assert_eq!(r.total_x, 1234 + 5678);
+    }
+
+    #[test]
+    fn build_subsets_pre_aggregated() {
+        let no_metadata = HashMap::new();
+        let sample = |tokens| CSample {
+            year: Some(1555),
+            metadata: &no_metadata,
+            words: 1234,
+            chars: 0,
+            tokens,
+            source: None,
+        };
+        let expanded = vec![
+            sample(vec![ctm("c"), ctm("c"), ct("c"), ct("b"), ctm("a")]),
+            sample(vec![ct("d"), ct("d"), ct("d")]),
+        ];
+        let aggregated = vec![
+            sample(vec![
+                ctn("c", 2, true),
+                ctn("c", 1, false),
+                ctn("b", 1, false),
+                ctn("a", 1, true),
+            ]),
+            sample(vec![ctn("d", 3, false)]),
+        ];
+        let key = SubsetKey {
+            category: None,
+            period: (1500, 1600),
+        };
+        for mode in [
+            SampleMode::Whole,
+            SampleMode::Split,
+            SampleMode::PresenceAbsence,
+        ] {
+            for my in [MeasureY::Types, MeasureY::Tokens, MeasureY::MarkedTokens] {
+                let build = |samples| {
+                    build_subset(
+                        MeasureX::Tokens,
+                        my,
+                        samples,
+                        key,
+                        key.period,
+                        false,
+                        mode,
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                };
+                let e = build(&expanded);
+                let a = build(&aggregated);
+                calculation::verify_samples(&a.samples);
+                assert_eq!(a.samples, e.samples);
+                assert_eq!((a.total_x, a.total_y), (e.total_x, e.total_y));
+            }
+        }
+        let r = build_subset(
+            MeasureX::Tokens,
+            MeasureY::Tokens,
+            &aggregated,
+            key,
+            key.period,
+            false,
+            SampleMode::Split,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(r.samples.len(), 8);
+        assert_eq!(r.total_y, 8);
+    }
+
+    #[test]
+    fn build_subsets_known_types() {
+        let no_metadata = HashMap::new();
+        let sample = |tokens| CSample {
+            year: Some(1555),
+            metadata: &no_metadata,
+            words: 1234,
+            chars: 0,
+            tokens,
+            source: None,
+        };
+        let samples = vec![
+            sample(vec![ct("c"), ct("c"), ct("b")]),
+            sample(vec![ct("a"), ct("d"), ct("e")]),
+        ];
+        let key = SubsetKey {
+            category: None,
+            period: (1500, 1600),
+        };
+        let vocabulary: HashSet<String> = ["b", "d", "x"].map(|x| x.to_owned()).into();
+        for mode in [SampleMode::Whole, SampleMode::Split] {
+            let r = build_subset(
+                MeasureX::Tokens,
+                MeasureY::KnownTypes,
+                &samples,
+                key,
+                key.period,
+                false,
+                mode,
+                Some(&vocabulary),
+                None,
+            )
+            .unwrap();
+            // Types are a, b, c, d, e; of these, b and d are known.
+            for s in &r.samples {
+                for t in &s.tokens {
+                    assert_eq!(t.known, t.id == 1 || t.id == 3);
+                }
+            }
+            assert_eq!((r.total_x, r.total_y), (6, 2));
+        }
+    }
+
+    #[test]
+    fn build_subsets_accumulate_by() {
+        let a = HashMap::from([("author".to_owned(), "a".to_owned())]);
+        let b = HashMap::from([("author".to_owned(), "b".to_owned())]);
+        let no_metadata = HashMap::new();
+        let sample = |metadata, year, tokens| CSample {
+            year: Some(year),
+            metadata,
+            words: 10,
+            chars: 0,
+            tokens,
+            source: None,
+        };
+        // Author a has three samples, author b has one, and one sample has no author.
+        let samples = vec![
+            sample(&a, 1501, vec![ct("c"), ct("b")]),
+            sample(&b, 1502, vec![ct("c")]),
+            sample(&a, 1501, vec![ct("c")]),
+            sample(&no_metadata, 1503, vec![ct("d")]),
+            sample(&a, 1504, vec![ctm("a")]),
+        ];
+        let key = SubsetKey {
+            category: None,
+            period: (1500, 1600),
+        };
+        let build = |accumulate_by| {
+            build_subset(
+                MeasureX::Tokens,
+                MeasureY::Types,
+                &samples,
+                key,
+                key.period,
+                false,
+                SampleMode::Whole,
+                None,
+                accumulate_by,
+            )
+            .unwrap()
+        };
+        let r = build(None);
+        assert_eq!(r.samples.len(), 5);
+        assert_eq!((r.total_x, r.total_y, r.total_words), (6, 4, 50));
+        let r = build(Some("author"));
+        assert_eq!(
+            r.samples,
+            vec![
+                Sample {
+                    x: 4,
+                    token_count: 4,
+                    tokens: vec![stm(0, 1, 1), st(1, 1), st(2, 2)],
+                    source: None
+                },
+                Sample {
+                    x: 1,
+                    token_count: 1,
+                    tokens: vec![st(2, 1)],
+                    source: None
+                },
+                Sample {
+                    x: 1,
+                    token_count: 1,
+                    tokens: vec![st(3, 1)],
+                    source: None
+                },
+            ]
+        );
+        assert_eq!(r.sample_years, [None, Some(1502), Some(1503)]);
+        assert_eq!((r.total_x, r.total_y, r.total_words), (6, 4, 50));
+    }
 }
@@ -1,8 +1,17 @@
+use std::collections::HashSet;
 use std::fs;
 use std::path::PathBuf;
-use types3::driver::{self, DriverArgs};
-use types3::input::Input;
-use types3::output::{MeasureX, MeasureY, Output};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use types3::cache::Cache;
+use types3::driver::{self, Boundary, DriverArgs, WindowMode};
+use types3::errors;
+use types3::input::{self, Input};
+use types3::output::{
+    self, AvgResult, Direction, MeasureX, MeasureY, NullModel, Output, PointResult, TestSide,
+    Years, ENTROPY_SCALE, TYPE_TOKEN_RATIO_SCALE, YULE_K_SCALE,
+};
+use types3::samples;
 
 fn init() {
     let _ = pretty_env_logger::formatted_timed_builder()
@@ -18,6 +27,76 @@ fn slurp(filename: &str) -> String {
     fs::read_to_string(path).unwrap()
 }
 
+fn base_args<'a>() -> DriverArgs<'a> {
+    DriverArgs {
+        category: None,
+        max_categories: None,
+        group_by: None,
+        measure_y: MeasureY::Types,
+        measure_x: MeasureX::Tokens,
+        iter: 10000,
+        offset: 0,
+        start: 0,
+        end: 9999,
+        window: 20,
+        step: 20,
+        no_time: false,
+        minimum_size: 1,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
+        restrict_samples: None,
+        restrict_tokens: None,
+        mark_tokens: None,
+        also_mark_tokens: &[],
+        split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
+    }
+}
+
 #[test]
 fn test_basic() {
     init();
@@ -27,6 +106,8 @@ fn test_basic() {
     let expected: Output = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: None,
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::Types,
         measure_x: MeasureX::Tokens,
         iter: 10000,
@@ -35,11 +116,60 @@ fn test_basic() {
         end: 9999,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: None,
         restrict_tokens: None,
         mark_tokens: None,
+        also_mark_tokens: &[],
         split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
     };
     let output = driver::calc(&driver_args, &input).unwrap();
     assert_eq!(output, expected);
@@ -54,6 +184,8 @@ fn test_category() {
     let expected: Output = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: Some("gender"),
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::Types,
         measure_x: MeasureX::Tokens,
         iter: 10000,
@@ -62,11 +194,60 @@ fn test_category() {
         end: 9999,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: None,
         restrict_tokens: None,
         mark_tokens: None,
+        also_mark_tokens: &[],
         split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
     };
     let output = driver::calc(&driver_args, &input).unwrap();
     assert_eq!(output, expected);
@@ -79,6 +260,8 @@ fn test_bad_category() {
     let input: Input = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: Some("nonexisting"),
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::Types,
         measure_x: MeasureX::Tokens,
         iter: 10000,
@@ -87,11 +270,60 @@ fn test_bad_category() {
         end: 9999,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: None,
         restrict_tokens: None,
         mark_tokens: None,
+        also_mark_tokens: &[],
         split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
     };
     assert!(driver::calc(&driver_args, &input).is_err());
 }
@@ -105,6 +337,8 @@ fn test_tokens_words() {
     let expected: Output = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: None,
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::Tokens,
         measure_x: MeasureX::Words,
         iter: 10000,
@@ -113,11 +347,60 @@ fn test_tokens_words() {
         end: 9999,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: None,
         restrict_tokens: None,
         mark_tokens: None,
+        also_mark_tokens: &[],
         split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
     };
     let output = driver::calc(&driver_args, &input).unwrap();
     assert_eq!(output, expected);
@@ -132,6 +415,8 @@ fn test_hapaxes_words() {
     let expected: Output = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: None,
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::Hapaxes,
         measure_x: MeasureX::Words,
         iter: 10000,
@@ -140,11 +425,60 @@ fn test_hapaxes_words() {
         end: 9999,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: None,
         restrict_tokens: None,
         mark_tokens: None,
+        also_mark_tokens: &[],
         split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
     };
     let output = driver::calc(&driver_args, &input).unwrap();
     assert_eq!(output, expected);
@@ -159,6 +493,8 @@ fn test_samples_words() {
     let expected: Output = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: None,
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::Samples,
         measure_x: MeasureX::Words,
         iter: 10000,
@@ -167,11 +503,76 @@ fn test_samples_words() {
         end: 9999,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: None,
         restrict_tokens: None,
         mark_tokens: None,
+        also_mark_tokens: &[],
         split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_types_samples() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let data = slurp("integration-test/calc-expected/ceec-types-vs-samples.json");
+    let expected: Output = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::Types,
+        measure_x: MeasureX::Samples,
+        ..base_args()
     };
     let output = driver::calc(&driver_args, &input).unwrap();
     assert_eq!(output, expected);
@@ -186,6 +587,8 @@ fn test_type_ratio() {
     let expected: Output = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: None,
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::MarkedTypes,
         measure_x: MeasureX::Types,
         iter: 10000,
@@ -194,11 +597,60 @@ fn test_type_ratio() {
         end: 9999,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: Some(("gender", "female")),
         restrict_tokens: None,
         mark_tokens: Some(("variant", "ity")),
+        also_mark_tokens: &[],
         split_samples: true,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
     };
     let output = driver::calc(&driver_args, &input).unwrap();
     assert_eq!(output, expected);
@@ -213,6 +665,8 @@ fn test_minimum() {
     let expected: Output = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: None,
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::Types,
         measure_x: MeasureX::Tokens,
         iter: 10000,
@@ -221,11 +675,60 @@ fn test_minimum() {
         end: 9999,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1000,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: None,
         restrict_tokens: None,
         mark_tokens: None,
+        also_mark_tokens: &[],
         split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
     };
     let output = driver::calc(&driver_args, &input).unwrap();
     assert_eq!(output, expected);
@@ -240,6 +743,8 @@ fn test_category_minimum() {
     let expected: Output = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: Some("gender"),
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::Types,
         measure_x: MeasureX::Tokens,
         iter: 10000,
@@ -248,11 +753,60 @@ fn test_category_minimum() {
         end: 9999,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1000,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: None,
         restrict_tokens: None,
         mark_tokens: None,
+        also_mark_tokens: &[],
         split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
     };
     let output = driver::calc(&driver_args, &input).unwrap();
     assert_eq!(output, expected);
@@ -265,6 +819,8 @@ fn test_future_start() {
     let input: Input = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: None,
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::Types,
         measure_x: MeasureX::Tokens,
         iter: 10000,
@@ -273,11 +829,60 @@ fn test_future_start() {
         end: 9999,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: None,
         restrict_tokens: None,
         mark_tokens: None,
+        also_mark_tokens: &[],
         split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
     };
     assert!(driver::calc(&driver_args, &input).is_err());
 }
@@ -289,6 +894,8 @@ fn test_past_end() {
     let input: Input = serde_json::from_str(&data).unwrap();
     let driver_args = DriverArgs {
         category: None,
+        max_categories: None,
+        group_by: None,
         measure_y: MeasureY::Types,
         measure_x: MeasureX::Tokens,
         iter: 10000,
@@ -297,11 +904,3241 @@ fn test_past_end() {
         end: 1000,
         window: 20,
         step: 20,
+        no_time: false,
         minimum_size: 1,
+        fixed_limit: None,
+        min_sample_tokens: None,
+        subsample: None,
+        skew_threshold: Some(0.1),
+        strict: false,
         restrict_samples: None,
         restrict_tokens: None,
         mark_tokens: None,
+        also_mark_tokens: &[],
         split_samples: false,
+        presence_absence: false,
+        accumulate_by: None,
+        ngram: 1,
+        standard_error: false,
+        scale_iter: false,
+        display_bucket: None,
+        region_weight: None,
+        dump_toplevel: false,
+        dump_samples: false,
+        boundary: Boundary::Overlap,
+        window_mode: WindowMode::Fixed,
+        leave_out_self: false,
+        explain_limit: false,
+        limit_units: false,
+        explicit: false,
+        no_significance: false,
+        convergence: None,
+        source_key: None,
+        vocabulary: None,
+        seed: 0,
+        repeats: None,
+        require_mark_key: false,
+        test_side: None,
+        null_model: NullModel::SampleOrder,
+        cancel: None,
+        cache: None,
+        expected_types: false,
+        comparison_n: false,
+        fingerprints: false,
+        samples_at_limit: false,
+        marginal: false,
+        extremes: false,
+        z_score: false,
+        distinct_orderings: false,
+        category_spread: false,
+        basis: false,
+        type_target: None,
+        observed_ci: false,
+        extrapolate: None,
+        mean_curve: false,
+        full_curve: false,
+        percentiles: None,
+    };
+    assert!(driver::calc(&driver_args, &input).is_err());
+}
+
+#[test]
+fn test_standard_error() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let data = slurp("integration-test/calc-expected/ceec-types-vs-tokens-gender.json");
+    let expected: Output = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        category: Some("gender"),
+        standard_error: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.curves.len(), expected.curves.len());
+    for (c, e) in output.curves.iter().zip(expected.curves.iter()) {
+        assert_eq!(c.results.len(), e.results.len());
+        for (r, e) in c.results.iter().zip(e.results.iter()) {
+            assert_eq!(r.vs_time, e.vs_time);
+            assert_eq!(r.vs_time_se, Some(e.vs_time.unwrap().standard_error()));
+            assert_eq!(
+                r.vs_categories_se,
+                e.vs_categories.map(|pr| pr.standard_error())
+            );
+            assert!(r.vs_categories_se.is_some());
+        }
+    }
+}
+
+#[test]
+fn test_scale_iter() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 20000,
+        scale_iter: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.iter, 20000);
+    let results = &output.curves[0].results;
+    assert!(results.iter().all(|r| r.average_at_limit.iter <= 20000));
+    assert!(results.iter().any(|r| r.average_at_limit.iter < 20000));
+    assert!(results.iter().all(|r| r.vs_time.unwrap().iter == 20000));
+}
+
+#[test]
+fn test_summary() {
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let summary = input::summary(&input);
+    assert_eq!(summary.samples, 446);
+    assert_eq!(summary.tokens, 10463);
+    assert_eq!(summary.types, 636);
+    assert_eq!(summary.words, 2216119);
+    assert_eq!(summary.years, Some((1680, 1781)));
+}
+
+#[test]
+fn test_display_bucket() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let data = slurp("integration-test/calc-expected/ceec-types-vs-tokens.json");
+    let expected: Output = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        display_bucket: Some(100),
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let curve = &output.curves[0];
+    for (r, e) in curve.results.iter().zip(expected.curves[0].results.iter()) {
+        assert_eq!(r.average_at_limit, e.average_at_limit);
+        assert!(r.size.unwrap() >= output.limit);
+    }
+    let buckets = curve.buckets.as_ref().unwrap();
+    assert_eq!(buckets.len(), 2);
+    assert_eq!(buckets[0].period, (1600, 1700));
+    assert_eq!(buckets[0].periods, vec![(1680, 1700)]);
+    assert_eq!(buckets[1].period, (1700, 1800));
+    assert_eq!(buckets[1].periods.len(), 5);
+}
+
+/// Samples of the 1900s that each repeat a new lemma 10 times, so that their types grow
+/// slowly but steadily, and one-token samples of the 1910s that cycle through 5 lemmas.
+fn region_weight_input() -> Input {
+    let mut samples = vec![];
+    for i in 0..20 {
+        let tokens = vec![format!(r#"{{"lemma": "s{i}", "metadata": {{}}}}"#); 10].join(", ");
+        samples.push(format!(
+            r#"{{"id": "s{i}", "year": 1900, "metadata": {{}}, "words": 10, "tokens": [{tokens}]}}"#
+        ));
+    }
+    for i in 0..1000 {
+        samples.push(format!(
+            r#"{{"id": "t{i}", "year": 1910, "metadata": {{}}, "words": 1, "tokens": [{{"lemma": "t{}", "metadata": {{}}}}]}}"#,
+            i % 5
+        ));
+    }
+    let samples = samples.join(", ");
+    serde_json::from_str(&format!(r#"{{"samples": [{samples}]}}"#)).unwrap()
+}
+
+#[test]
+fn test_region_weight() {
+    init();
+    let input = region_weight_input();
+    let run = |exponent| {
+        let driver_args = DriverArgs {
+            iter: 1000,
+            window: 10,
+            step: 10,
+            region_weight: Some(exponent),
+            ..base_args()
+        };
+        let output = driver::calc(&driver_args, &input).unwrap();
+        let r = output.curves[0].results[0].clone();
+        assert_eq!(r.period, (1900, 1910));
+        r
+    };
+    // The 1900s are below the other periods in small subcorpora and above them in large ones.
+    let even = run(0.0);
+    let w = even.weighted_vs_time.unwrap();
+    assert_eq!(w.direction, Direction::Neither, "{w:?}");
+    assert!(w.fraction_below > 0.1, "{w:?}");
+    let large = run(8.0);
+    assert_eq!(large.vs_time, even.vs_time);
+    assert_eq!(large.weighted_vs_time.unwrap().direction, Direction::Above);
+    let small = run(-8.0);
+    assert_eq!(small.weighted_vs_time.unwrap().direction, Direction::Below);
+    let driver_args = DriverArgs {
+        iter: 1000,
+        window: 10,
+        step: 10,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.curves[0].results[0].weighted_vs_time, None);
+}
+
+#[test]
+fn test_dump_toplevel() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        category: Some("gender"),
+        iter: 1000,
+        dump_toplevel: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let toplevel = output.toplevel.as_ref().unwrap();
+    let mut comparisons = 0;
+    for c in &output.curves {
+        for r in &c.results {
+            comparisons += 2;
+            assert!(toplevel.iter().any(|t| t.category == c.category
+                && t.period == output.years
+                && Some(t.result) == r.vs_time));
+            let vs_categories = r.vs_categories.unwrap();
+            assert!(toplevel.iter().any(|t| t.category.is_none()
+                && t.period == r.period
+                && t.result == vs_categories));
+        }
+    }
+    assert!(toplevel.len() <= comparisons);
+}
+
+#[test]
+fn test_boundary() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let total_size = |boundary| {
+        let driver_args = DriverArgs {
+            iter: 1000,
+            window: 40,
+            step: 20,
+            display_bucket: Some(100),
+            boundary,
+            ..base_args()
+        };
+        let output = driver::calc(&driver_args, &input).unwrap();
+        assert_eq!(output.periods[0], (1680, 1720));
+        output.curves[0]
+            .results
+            .iter()
+            .map(|r| r.size.unwrap())
+            .sum::<u64>()
+    };
+    assert_eq!(total_size(Boundary::Disjoint), 10463);
+    assert!(total_size(Boundary::Overlap) > 10463);
+}
+
+#[test]
+fn test_trailing_recent() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    // All samples are from 1680, 1700, ..., 1780.
+    let sizes = |window, window_mode| {
+        let driver_args = DriverArgs {
+            iter: 1000,
+            window,
+            step: 20,
+            display_bucket: Some(100),
+            window_mode,
+            ..base_args()
+        };
+        let output = driver::calc(&driver_args, &input).unwrap();
+        output.curves[0]
+            .results
+            .iter()
+            .map(|r| (r.period, r.size.unwrap()))
+            .collect::<Vec<_>>()
+    };
+    let fixed20 = sizes(20, WindowMode::Fixed);
+    let fixed40 = sizes(40, WindowMode::Fixed);
+    assert_eq!(fixed20.len(), 6);
+    assert_eq!(fixed40.len(), 5);
+    assert_eq!(sizes(20, WindowMode::TrailingRecent { years: 20 }), fixed20);
+    for i in 0..5 {
+        assert_eq!(fixed40[i].1, fixed20[i].1 + fixed20[i + 1].1);
+    }
+    // Labels stay the same, but only the most recent 20 years are included.
+    let trailing = sizes(40, WindowMode::TrailingRecent { years: 20 });
+    for i in 0..5 {
+        assert_eq!(trailing[i], (fixed40[i].0, fixed20[i + 1].1));
+    }
+    // Trailing windows longer than the step also include the previous period.
+    let trailing = sizes(20, WindowMode::TrailingRecent { years: 40 });
+    for i in 0..6 {
+        let previous = if i == 0 { 0 } else { fixed20[i - 1].1 };
+        assert_eq!(trailing[i], (fixed20[i].0, fixed20[i].1 + previous));
+    }
+    let driver_args = DriverArgs {
+        window_mode: WindowMode::TrailingRecent { years: 0 },
+        ..base_args()
+    };
+    assert!(driver::calc(&driver_args, &input).is_err());
+}
+
+#[test]
+fn test_group_by_year() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let mut grouped: Input = serde_json::from_str(&data).unwrap();
+    for s in &mut grouped.samples {
+        s.metadata
+            .insert("born".to_owned(), format!("{}", s.year.unwrap()));
+        s.year = Some(0);
+    }
+    let driver_args = DriverArgs {
+        iter: 1000,
+        ..base_args()
+    };
+    let expected = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        group_by: Some("born"),
+        ..base_args()
+    };
+    let mut output = driver::calc(&driver_args, &grouped).unwrap();
+    assert_eq!(output.group_by.as_deref(), Some("born"));
+    output.group_by = None;
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_group_by_bins() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let mut input: Input = serde_json::from_str(&data).unwrap();
+    for (i, s) in input.samples.iter_mut().enumerate() {
+        if i % 11 != 0 {
+            s.metadata.insert("score".to_owned(), format!("{}", i % 10));
+        }
+    }
+    let driver_args = DriverArgs {
+        iter: 1000,
+        window: 5,
+        step: 5,
+        category: Some("gender"),
+        group_by: Some("score"),
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.years, (0, 10));
+    assert_eq!(output.periods, [(0, 5), (5, 10)]);
+    assert_eq!(output.curves.len(), 2);
+}
+
+#[test]
+fn test_group_by_invalid() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let mut input: Input = serde_json::from_str(&data).unwrap();
+    input.samples[0]
+        .metadata
+        .insert("score".to_owned(), "high".to_owned());
+    let driver_args = DriverArgs {
+        iter: 1000,
+        group_by: Some("score"),
+        ..base_args()
     };
     assert!(driver::calc(&driver_args, &input).is_err());
 }
+
+#[test]
+fn test_explicit() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let expected = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        explicit: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for (c, ec) in output.curves.iter().zip(expected.curves.iter()) {
+        for (r, e) in c.results.iter().zip(ec.results.iter()) {
+            assert_eq!(r.vs_time, e.vs_time);
+            let x = r.vs_time_explicit.unwrap();
+            assert_eq!(x.fraction_above, e.vs_time.unwrap().fraction_above());
+            assert_eq!(x.fraction_below, e.vs_time.unwrap().fraction_below());
+            assert_eq!(x.direction, e.vs_time.unwrap().direction());
+            let p = e.vs_time.unwrap().p_low().min(e.vs_time.unwrap().p_high());
+            assert_eq!(x.p_value, (2.0 * p).min(1.0));
+            assert_eq!(
+                r.vs_categories_explicit,
+                e.vs_categories.map(|pr| pr.explicit())
+            );
+            assert!(r.vs_categories_explicit.is_some());
+        }
+    }
+}
+
+/// A sample with `tokens` distinct lemmas; the identifiers are unique within a test run.
+fn synthetic_sample(year: i16, tokens: usize) -> serde_json::Value {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let tokens = (0..tokens)
+        .map(|i| serde_json::json!({"lemma": format!("w{i}"), "metadata": {}}))
+        .collect::<Vec<_>>();
+    serde_json::json!({
+        "id": format!("s{year}-{}-{id}", tokens.len()),
+        "year": year,
+        "metadata": {},
+        "words": 100,
+        "tokens": tokens,
+    })
+}
+
+/// Collapse repeated tokens of each sample into one token with an explicit count.
+fn aggregate_tokens(input: &serde_json::Value) -> serde_json::Value {
+    let mut input = input.clone();
+    for sample in input["samples"].as_array_mut().unwrap() {
+        let mut tokens: Vec<(serde_json::Value, u64)> = vec![];
+        for t in sample["tokens"].as_array().unwrap() {
+            let t = serde_json::json!({"lemma": t["lemma"], "metadata": t["metadata"]});
+            match tokens.iter_mut().find(|(u, _)| *u == t) {
+                Some((_, count)) => *count += 1,
+                None => tokens.push((t, 1)),
+            }
+        }
+        sample["tokens"] = tokens
+            .into_iter()
+            .map(|(mut t, count)| {
+                t["count"] = count.into();
+                t
+            })
+            .collect();
+    }
+    input
+}
+
+#[test]
+fn test_chars() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let mut input: serde_json::Value = serde_json::from_str(&data).unwrap();
+    for sample in input["samples"].as_array_mut().unwrap() {
+        sample["chars"] = (5 * sample["words"].as_u64().unwrap()).into();
+    }
+    let input: Input = serde_json::from_value(input).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        measure_x: MeasureX::Words,
+        ..base_args()
+    };
+    let words = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        measure_x: MeasureX::Chars,
+        ..driver_args
+    };
+    let chars = driver::calc(&driver_args, &input).unwrap();
+    // Characters are proportional to words, so we get the same subcorpora.
+    assert_eq!(chars.measure_x, MeasureX::Chars);
+    assert_eq!(chars.limit, 5 * words.limit);
+    assert_eq!(chars.curves, words.curves);
+}
+
+#[test]
+fn test_pre_aggregated() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let expanded: serde_json::Value = serde_json::from_str(&data).unwrap();
+    let aggregated = aggregate_tokens(&expanded);
+    let expanded: Input = serde_json::from_value(expanded).unwrap();
+    let aggregated: Input = serde_json::from_value(aggregated).unwrap();
+    assert_eq!(input::summary(&aggregated), input::summary(&expanded));
+    assert!(
+        aggregated
+            .samples
+            .iter()
+            .map(|s| s.tokens.len())
+            .sum::<usize>()
+            < expanded
+                .samples
+                .iter()
+                .map(|s| s.tokens.len())
+                .sum::<usize>()
+    );
+    for (measure_y, split_samples) in [
+        (MeasureY::Types, false),
+        (MeasureY::Types, true),
+        (MeasureY::Hapaxes, false),
+        (MeasureY::MarkedTypes, false),
+    ] {
+        let driver_args = DriverArgs {
+            iter: 1000,
+            measure_y,
+            split_samples,
+            category: Some("gender"),
+            mark_tokens: (measure_y == MeasureY::MarkedTypes).then_some(("variant", "ity")),
+            dump_samples: true,
+            ..base_args()
+        };
+        let a = driver::calc(&driver_args, &aggregated).unwrap();
+        let e = driver::calc(&driver_args, &expanded).unwrap();
+        assert_eq!(a, e);
+    }
+}
+
+#[test]
+fn test_zero_count() {
+    init();
+    let input: Input = serde_json::from_value(serde_json::json!({"samples": [
+        {"id": "a", "year": 1700, "metadata": {}, "words": 100, "tokens": [
+            {"lemma": "x", "metadata": {}, "count": 2},
+            {"lemma": "y", "metadata": {}, "count": 0}
+        ]}
+    ]}))
+    .unwrap();
+    let e = driver::calc(&base_args(), &input).unwrap_err();
+    assert!(e.to_string().contains("count 0"));
+}
+
+#[test]
+fn test_validate_input() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let mut input: Input = serde_json::from_str(&data).unwrap();
+    input.samples[1].id = input.samples[0].id.clone();
+    input.samples[2].words = 0;
+    let driver_args = DriverArgs {
+        iter: 10,
+        ..base_args()
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err().to_string();
+    assert!(e.contains("1 duplicate sample identifiers"), "{e}");
+    assert!(!e.contains("0 words"), "{e}");
+    let driver_args = DriverArgs {
+        measure_x: MeasureX::Words,
+        ..driver_args
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err().to_string();
+    assert!(e.contains("1 duplicate sample identifiers"), "{e}");
+    assert!(
+        e.contains(&format!("0 words in 1 samples: {}", input.samples[2].id)),
+        "{e}"
+    );
+}
+
+#[test]
+fn test_validate_years() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let mut input: Input = serde_json::from_str(&data).unwrap();
+    input.samples[0].year = Some(-1);
+    let driver_args = DriverArgs {
+        iter: 10,
+        start: -100,
+        ..base_args()
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err().to_string();
+    assert!(
+        e.contains(&format!(
+            "years outside 0 to 9999 in 1 samples: {}",
+            input.samples[0].id
+        )),
+        "{e}"
+    );
+    // Without time periods, the years do not matter.
+    let driver_args = DriverArgs {
+        no_time: true,
+        ..driver_args
+    };
+    driver::calc(&driver_args, &input).unwrap();
+}
+
+#[test]
+fn test_leave_out_self() {
+    init();
+    // The first period has 50 tokens in total, the second period 49 tokens.
+    // Random halves of all data nearly always have more than 50 tokens
+    // or less than 50 tokens, but the rest of the data always has 49 tokens.
+    let mut samples = vec![];
+    for _ in 0..5 {
+        samples.push(synthetic_sample(1900, 10));
+    }
+    for n in [1, 1, 1, 1, 45] {
+        samples.push(synthetic_sample(1910, n));
+    }
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": samples })).unwrap();
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::Tokens,
+        measure_x: MeasureX::Words,
+        iter: 1000,
+        window: 10,
+        step: 10,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.periods, [(1900, 1910), (1910, 1920)]);
+    let vs_time = output.curves[0].results[0].vs_time.unwrap();
+    assert!(!vs_time.is_significant(0.1));
+    let driver_args = DriverArgs {
+        leave_out_self: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let r = &output.curves[0].results;
+    assert_eq!(output::point_string(&r[0].vs_time.unwrap()), "++++");
+    assert_eq!(output::point_string(&r[1].vs_time.unwrap()), "----");
+}
+
+#[test]
+fn test_leave_out_self_dominant_period() {
+    init();
+    // The first period has 600 words and 60 tokens, and the other two periods have
+    // 300 words each and 62 tokens in total. Random halves of all data have more than
+    // 60 tokens if they contain the sample with 57 tokens, and otherwise fewer,
+    // but the rest of the data always has 62 tokens.
+    let mut samples = vec![];
+    for _ in 0..6 {
+        samples.push(synthetic_sample(1900, 10));
+    }
+    for n in [1, 1, 57] {
+        samples.push(synthetic_sample(1910, n));
+    }
+    for n in [1, 1, 1] {
+        samples.push(synthetic_sample(1920, n));
+    }
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": samples })).unwrap();
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::Tokens,
+        measure_x: MeasureX::Words,
+        iter: 1000,
+        window: 10,
+        step: 10,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.periods, [(1900, 1910), (1910, 1920), (1920, 1930)]);
+    let vs_time = output.curves[0].results[0].vs_time.unwrap();
+    assert!(!vs_time.is_significant(0.1));
+    let driver_args = DriverArgs {
+        leave_out_self: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let vs_time = output.curves[0].results[0].vs_time.unwrap();
+    assert_eq!(output::point_string(&vs_time), "----");
+    assert!(output.warnings.is_empty());
+}
+
+#[test]
+fn test_leave_out_self_too_large() {
+    init();
+    // The first period has 500 words, and the rest of the data only 200 words.
+    let mut samples = vec![];
+    for _ in 0..5 {
+        samples.push(synthetic_sample(1900, 10));
+    }
+    for _ in 0..2 {
+        samples.push(synthetic_sample(1910, 10));
+    }
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": samples })).unwrap();
+    let driver_args = DriverArgs {
+        measure_x: MeasureX::Words,
+        iter: 1000,
+        window: 10,
+        step: 10,
+        leave_out_self: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let r = &output.curves[0].results;
+    assert_eq!(r[0].period, (1900, 1910));
+    assert_eq!(r[0].vs_time, None);
+    assert!(r[1].vs_time.is_some());
+    assert_eq!(
+        output.warnings,
+        ["1900–1909: not compared with 1900–1910, as the rest of it is smaller than the subset itself"]
+    );
+}
+
+#[test]
+fn test_skew_threshold() {
+    init();
+    // Three periods of 50 tokens and one period of 2 tokens.
+    let mut samples = vec![];
+    for year in [1900, 1910, 1920] {
+        samples.push(synthetic_sample(year, 50));
+    }
+    samples.push(synthetic_sample(1930, 2));
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": samples })).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        window: 10,
+        step: 10,
+        no_significance: true,
+        skew_threshold: None,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.limit, 2);
+    assert!(output.warnings.is_empty());
+    let skewed = |w: &String| w.contains("size limit 2 tokens") && w.contains("1930–1939");
+    let driver_args = DriverArgs {
+        skew_threshold: Some(0.1),
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.warnings.iter().filter(|w| skewed(w)).count(), 1);
+    let driver_args = DriverArgs {
+        strict: true,
+        ..driver_args
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err();
+    assert!(skewed(&e.to_string()));
+    let driver_args = DriverArgs {
+        skew_threshold: Some(0.01),
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert!(output.warnings.is_empty());
+}
+
+#[test]
+fn test_no_time() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        offset: 1680,
+        window: 101,
+        step: 101,
+        ..base_args()
+    };
+    let expected = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(expected.periods, [(1680, 1781)]);
+    let driver_args = DriverArgs {
+        offset: 0,
+        window: 20,
+        step: 20,
+        no_time: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_explain_limit() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        display_bucket: Some(100),
+        explain_limit: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let limit_subset = output.limit_subset.as_ref().unwrap();
+    assert_eq!(limit_subset.size, output.limit);
+    let (category, result) = output
+        .curves
+        .iter()
+        .flat_map(|c| c.results.iter().map(move |r| (&c.category, r)))
+        .min_by_key(|(_, r)| r.size.unwrap())
+        .unwrap();
+    assert_eq!(&limit_subset.category, category);
+    assert_eq!(limit_subset.period, result.period);
+    assert_eq!(result.size, Some(output.limit));
+}
+
+#[test]
+fn test_max_categories() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 10,
+        category: Some("gender"),
+        max_categories: Some(2),
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.curves.len(), 2);
+    let driver_args = DriverArgs {
+        max_categories: Some(1),
+        ..driver_args
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err();
+    assert!(e.is::<errors::InvalidInput>());
+    assert_eq!(
+        e.to_string(),
+        "invalid input: metadata key gender has 2 distinct values, more than the maximum number of categories 1"
+    );
+    let driver_args = DriverArgs {
+        max_categories: Some(0),
+        ..driver_args
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err();
+    assert!(e.is::<errors::InvalidArgument>());
+}
+
+#[test]
+fn test_fixed_limit() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let auto = driver::calc(&driver_args, &input).unwrap();
+    // Fixing the limit to the automatic limit gives the same results,
+    // but there is no subset that determined the limit.
+    let driver_args = DriverArgs {
+        fixed_limit: Some(auto.limit),
+        ..driver_args
+    };
+    let fixed = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(fixed.limit, auto.limit);
+    assert_eq!(fixed.curves, auto.curves);
+    let explained = driver::calc(
+        &DriverArgs {
+            explain_limit: true,
+            ..driver_args
+        },
+        &input,
+    )
+    .unwrap();
+    assert_eq!(explained.limit_subset, None);
+    // A larger limit skips the subsets that are too small.
+    let limit = 1000;
+    assert!(auto.limit < limit);
+    let driver_args = DriverArgs {
+        fixed_limit: Some(limit),
+        display_bucket: Some(100),
+        ..driver_args
+    };
+    let fixed = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(fixed.limit, limit);
+    let sizes = fixed
+        .curves
+        .iter()
+        .flat_map(|c| c.results.iter())
+        .map(|r| r.size.unwrap())
+        .collect::<Vec<_>>();
+    assert!(!sizes.is_empty());
+    assert!(sizes.iter().all(|&s| s >= limit));
+    let skipped = auto.curves.iter().map(|c| c.results.len()).sum::<usize>() - sizes.len();
+    assert!(skipped > 0);
+    let warnings = fixed
+        .warnings
+        .iter()
+        .filter(|w| w.contains("is below the fixed limit 1000"))
+        .count();
+    assert_eq!(warnings, skipped);
+}
+
+#[test]
+fn test_limit_units() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    for (measure_x, measure_y) in [
+        (MeasureX::Tokens, MeasureY::Types),
+        (MeasureX::Words, MeasureY::Tokens),
+        (MeasureX::Samples, MeasureY::Types),
+    ] {
+        let driver_args = DriverArgs {
+            iter: 1000,
+            measure_x,
+            measure_y,
+            category: Some("gender"),
+            display_bucket: Some(100),
+            explain_limit: true,
+            limit_units: true,
+            ..base_args()
+        };
+        let output = driver::calc(&driver_args, &input).unwrap();
+        let limit_subset = output.limit_subset.unwrap();
+        let units = output.limit_units.unwrap();
+        let (from, to) = limit_subset.period;
+        let (key, value) = limit_subset.category.unwrap();
+        let samples = input
+            .samples
+            .iter()
+            .filter(|s| s.year.is_some_and(|y| from <= y && y < to) && s.metadata[&key] == value)
+            .collect::<Vec<_>>();
+        let words: u64 = samples.iter().map(|s| s.words).sum();
+        let tokens = samples.iter().map(|s| s.tokens.len() as u64).sum();
+        let types = samples
+            .iter()
+            .flat_map(|s| s.tokens.iter().map(|t| &t.lemma))
+            .collect::<HashSet<_>>()
+            .len() as u64;
+        assert_eq!(
+            (units.words, units.tokens, units.types),
+            (words, tokens, types)
+        );
+        match measure_x {
+            MeasureX::Tokens => assert_eq!(units.tokens, output.limit),
+            MeasureX::Words => assert_eq!(units.words, output.limit),
+            MeasureX::Samples => assert_eq!(samples.len() as u64, output.limit),
+            MeasureX::Types | MeasureX::Chars => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn test_partitions() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        no_significance: true,
+        dump_samples: true,
+        ..base_args()
+    };
+    // Number of samples in the subset that covers everything.
+    let count = |output: &Output| {
+        let dumped = output.samples.as_ref().unwrap();
+        let all = dumped
+            .iter()
+            .find(|s| s.category.is_none() && s.period == output.years)
+            .unwrap();
+        all.samples.len()
+    };
+    let whole = driver::calc(&driver_args, &input).unwrap();
+    let partitions = driver::calc_partitions(&driver_args, &input, "gender").unwrap();
+    assert_eq!(partitions.key, "gender");
+    let values = partitions
+        .partitions
+        .iter()
+        .map(|p| p.value.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(values, ["female", "male"]);
+    for p in &partitions.partitions {
+        assert_eq!(
+            p.output.restrict_samples,
+            Some(("gender".to_owned(), p.value.clone()))
+        );
+    }
+    let total: usize = partitions.partitions.iter().map(|p| count(&p.output)).sum();
+    assert_eq!(total, count(&whole));
+    let driver_args = DriverArgs {
+        restrict_samples: Some(("gender", "female")),
+        ..driver_args
+    };
+    driver::calc_partitions(&driver_args, &input, "gender").unwrap_err();
+}
+
+#[test]
+fn test_dump_samples() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        measure_y: MeasureY::Tokens,
+        category: Some("gender"),
+        dump_samples: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let dumped = output.samples.unwrap();
+    // Curves for each category, parents for each curve, and parents for each period.
+    assert_eq!(dumped.len(), 2 * 6 + 2 + 6);
+    for subset in &dumped {
+        let tokens: u64 = subset
+            .samples
+            .iter()
+            .flat_map(|s| s.tokens.iter())
+            .map(|t| t.count)
+            .sum();
+        let token_count: u64 = subset.samples.iter().map(|s| s.token_count).sum();
+        let x: u64 = subset.samples.iter().map(|s| s.x).sum();
+        assert_eq!(tokens, subset.total_y);
+        assert_eq!(token_count, subset.total_y);
+        assert_eq!(x, subset.total_x);
+    }
+    let all: u64 = dumped
+        .iter()
+        .filter(|s| s.category.is_some() && s.period == output.years)
+        .map(|s| s.total_y)
+        .sum();
+    assert_eq!(all, 10463);
+}
+
+#[test]
+fn test_undated_samples() {
+    init();
+    let mut samples = vec![];
+    for _ in 0..3 {
+        samples.push(synthetic_sample(1900, 5));
+        samples.push(synthetic_sample(1910, 5));
+    }
+    let mut undated = synthetic_sample(0, 7);
+    undated.as_object_mut().unwrap().remove("year");
+    samples.push(undated);
+    let mut undated = synthetic_sample(0, 9);
+    undated["year"] = serde_json::Value::Null;
+    samples.push(undated);
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": samples })).unwrap();
+    assert_eq!(input.samples.iter().filter(|s| s.year.is_none()).count(), 2);
+    let summary = input::summary(&input);
+    assert_eq!(summary.tokens, 6 * 5 + 7 + 9);
+    assert_eq!(summary.years, Some((1900, 1911)));
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::Tokens,
+        iter: 100,
+        window: 10,
+        step: 10,
+        dump_samples: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.years, (1900, 1911));
+    assert_eq!(output.periods, [(1900, 1910), (1910, 1920)]);
+    let dumped = output.samples.unwrap();
+    let total = |period: Years| dumped.iter().find(|s| s.period == period).unwrap().total_y;
+    // Undated samples are only included in the subset that covers all years.
+    assert_eq!(total((1900, 1910)), 3 * 5);
+    assert_eq!(total((1910, 1920)), 3 * 5);
+    assert_eq!(total(output.years), 6 * 5 + 7 + 9);
+    // A time period that covers all data is still a time period.
+    let driver_args = DriverArgs {
+        no_time: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.periods, [output.years]);
+    let dumped = output.samples.unwrap();
+    assert_eq!(dumped.len(), 1);
+    assert_eq!(dumped[0].total_y, 6 * 5);
+}
+
+#[test]
+fn test_only_undated_samples() {
+    init();
+    let mut undated = synthetic_sample(0, 7);
+    undated["year"] = serde_json::Value::Null;
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": [undated] })).unwrap();
+    assert!(driver::calc(&base_args(), &input).is_err());
+}
+
+#[test]
+fn test_sources() {
+    init();
+    let mut samples = vec![];
+    for author in ["a", "a", "a"] {
+        let mut s = synthetic_sample(1900, 5);
+        s["metadata"]["author"] = author.into();
+        samples.push(s);
+    }
+    for author in ["a", "b", "c"] {
+        let mut s = synthetic_sample(1910, 5);
+        s["metadata"]["author"] = author.into();
+        samples.push(s);
+    }
+    samples.push(synthetic_sample(1910, 5));
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": samples })).unwrap();
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::Sources,
+        iter: 1000,
+        window: 10,
+        step: 10,
+        ..base_args()
+    };
+    assert!(driver::calc(&driver_args, &input).is_err());
+    let driver_args = DriverArgs {
+        source_key: Some("author"),
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.measure_y, MeasureY::Sources);
+    assert_eq!(output.limit, 15);
+    let results = &output.curves[0].results;
+    assert_eq!(results.len(), 2);
+    // One author in 1900.
+    let avg = &results[0].average_at_limit;
+    assert_eq!(avg.high, avg.iter);
+    // Three samples out of four in 1910, one of which may lack the author.
+    let avg = &results[1].average_at_limit;
+    assert!(avg.low > 2 * avg.iter);
+    assert!(avg.high < 3 * avg.iter);
+}
+
+#[test]
+fn test_accumulate_by() {
+    init();
+    let sample = |id: usize, author: &str, lemmas: &[String]| {
+        let tokens = lemmas
+            .iter()
+            .map(|l| serde_json::json!({"lemma": l, "metadata": {}}))
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "id": format!("s{id}"),
+            "year": 1900,
+            "metadata": {"author": author, "id": format!("s{id}")},
+            "words": 100,
+            "tokens": tokens,
+        })
+    };
+    // One prolific author uses the same lemma in many samples,
+    // while each of the other authors has one sample with a lemma of their own.
+    let mut samples = vec![];
+    for i in 0..8 {
+        samples.push(sample(i, "prolific", &["p".to_owned()]));
+    }
+    for i in 0..8 {
+        samples.push(sample(8 + i, &format!("a{i}"), &[format!("a{i}")]));
+    }
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": samples })).unwrap();
+    let driver_args = DriverArgs {
+        iter: 10000,
+        no_time: true,
+        fixed_limit: Some(8),
+        ..base_args()
+    };
+    let per_sample = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        accumulate_by: Some("author"),
+        ..driver_args
+    };
+    let per_author = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(per_author.accumulate_by.as_deref(), Some("author"));
+    let a = &per_sample.curves[0].results[0].average_at_limit;
+    let b = &per_author.curves[0].results[0].average_at_limit;
+    // Sample by sample, we almost always see the lemma of the prolific author, and about 4 others.
+    assert!(a.low == a.high && a.high > 49 * a.iter / 10);
+    // Now the prolific author is one unit of 8 tokens, and it contains the limit unless
+    // it comes last, so we only see the authors that come before it.
+    assert!(b.low < b.high && b.high < a.low);
+    // The observed totals do not depend on the units of accumulation.
+    let (a, b) = (
+        &per_sample.curves[0].results[0],
+        &per_author.curves[0].results[0],
+    );
+    assert_eq!((a.size, a.expected_types), (b.size, b.expected_types));
+    // If each sample has its own value, this is the same as accumulating by sample.
+    let driver_args = DriverArgs {
+        accumulate_by: Some("id"),
+        ..driver_args
+    };
+    let mut output = driver::calc(&driver_args, &input).unwrap();
+    output.accumulate_by = None;
+    assert_eq!(output, per_sample);
+    let driver_args = DriverArgs {
+        split_samples: true,
+        ..driver_args
+    };
+    assert!(driver::calc(&driver_args, &input).is_err());
+}
+
+#[test]
+fn test_repeats() {
+    init();
+    let mut samples = vec![];
+    for n in 1..=10 {
+        samples.push(synthetic_sample(1900, n));
+        samples.push(synthetic_sample(1910, 2 * n));
+    }
+    // This determines the size limit.
+    for n in 2..=6 {
+        samples.push(synthetic_sample(1920, n));
+    }
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": samples })).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        window: 10,
+        step: 10,
+        seed: 123,
+        repeats: Some(10),
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    // Reproducible with a fixed base seed.
+    assert_eq!(output, driver::calc(&driver_args, &input).unwrap());
+    // The first repetition is the main result.
+    let plain_args = DriverArgs {
+        repeats: None,
+        ..driver_args
+    };
+    let plain = driver::calc(&plain_args, &input).unwrap();
+    let width = |output: &Output| {
+        let r = &output.curves[0].results[0];
+        let repeats = r.repeats.unwrap();
+        assert_eq!(repeats.repeats, 10);
+        assert!(repeats.low <= repeats.mean && repeats.mean <= repeats.high);
+        repeats.high - repeats.low
+    };
+    for (r, p) in output.curves[0]
+        .results
+        .iter()
+        .zip(&plain.curves[0].results)
+    {
+        assert_eq!(r.average_at_limit, p.average_at_limit);
+        assert!(p.repeats.is_none());
+    }
+    // A different base seed gives different results.
+    let other_args = DriverArgs {
+        seed: 124,
+        ..driver_args
+    };
+    let other = driver::calc(&other_args, &input).unwrap();
+    assert_ne!(
+        output.curves[0].results[0].repeats,
+        other.curves[0].results[0].repeats
+    );
+    // Bands shrink as the number of iterations grows.
+    let more_args = DriverArgs {
+        iter: 100000,
+        ..driver_args
+    };
+    let more = driver::calc(&more_args, &input).unwrap();
+    assert!(width(&more) < width(&output) / 2.0);
+    assert!(driver::calc(
+        &DriverArgs {
+            repeats: Some(0),
+            ..driver_args
+        },
+        &input
+    )
+    .is_err());
+}
+
+#[test]
+fn test_require_mark_key() {
+    init();
+    let sample = |id: &str, variants: &[Option<&str>]| {
+        let tokens = variants
+            .iter()
+            .enumerate()
+            .map(|(i, v)| match v {
+                None => serde_json::json!({"lemma": format!("w{i}"), "metadata": {}}),
+                Some(v) => {
+                    serde_json::json!({"lemma": format!("w{i}"), "metadata": {"variant": v}})
+                }
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "id": id,
+            "year": 1900,
+            "metadata": {},
+            "words": 100,
+            "tokens": tokens,
+        })
+    };
+    let good = serde_json::json!({ "samples": [
+        sample("a", &[Some("ity"), Some("ness")]),
+        sample("b", &[Some("ness")]),
+    ]});
+    let bad = serde_json::json!({ "samples": [
+        sample("a", &[Some("ity"), Some("ness")]),
+        sample("b", &[Some("ity"), None]),
+        sample("c", &[None]),
+    ]});
+    let good: Input = serde_json::from_value(good).unwrap();
+    let bad: Input = serde_json::from_value(bad).unwrap();
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::MarkedTokens,
+        iter: 100,
+        mark_tokens: Some(("variant", "ity")),
+        ..base_args()
+    };
+    // Without the flag, tokens without the key are unmarked.
+    let output = driver::calc(&driver_args, &bad).unwrap();
+    assert_eq!(output.curves[0].results.len(), 1);
+    let strict_args = DriverArgs {
+        require_mark_key: true,
+        ..driver_args
+    };
+    // Tokens with a different value are fine.
+    driver::calc(&strict_args, &good).unwrap();
+    // Tokens without the key are not.
+    let e = driver::calc(&strict_args, &bad).unwrap_err().to_string();
+    assert!(e.contains("variant"));
+    assert!(e.contains("2 samples: b, c"));
+    // Only tokens in scope matter.
+    let restricted_args = DriverArgs {
+        restrict_tokens: Some(("variant", "ity")),
+        ..strict_args
+    };
+    driver::calc(&restricted_args, &bad).unwrap();
+    let unmarked_args = DriverArgs {
+        mark_tokens: None,
+        ..strict_args
+    };
+    assert!(driver::calc(&unmarked_args, &good).is_err());
+}
+
+#[test]
+fn test_test_side() {
+    init();
+    // Many types in 1900, few types in 1910.
+    let mut samples = vec![];
+    for i in 0..10 {
+        let mut s = synthetic_sample(1900, 5);
+        for (j, t) in s["tokens"].as_array_mut().unwrap().iter_mut().enumerate() {
+            t["lemma"] = format!("u{i}-{j}").into();
+        }
+        samples.push(s);
+        samples.push(synthetic_sample(1910, 5));
+    }
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": samples })).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        window: 10,
+        step: 10,
+        ..base_args()
+    };
+    let expected = driver::calc(&driver_args, &input).unwrap();
+    let high = expected.curves[0].results[0].vs_time.unwrap();
+    assert!(high.is_significant(0.001));
+    assert_eq!(high.direction(), output::Direction::Above);
+    for (side, p_high, p_low) in [
+        (TestSide::TwoSided, 0.0, 0.0),
+        (TestSide::GreaterOnly, 0.0, 1.0),
+        (TestSide::LessOnly, 1.0, 0.0),
+    ] {
+        let driver_args = DriverArgs {
+            test_side: Some(side),
+            ..driver_args
+        };
+        let output = driver::calc(&driver_args, &input).unwrap();
+        assert_eq!(output.test_side, Some(side));
+        let results = &output.curves[0].results;
+        assert_eq!(results[0].vs_time, expected.curves[0].results[0].vs_time);
+        assert_eq!(results[0].vs_time_p, Some(p_high));
+        assert_eq!(results[1].vs_time_p, Some(p_low));
+        assert_eq!(results[0].vs_categories_p, None);
+    }
+    assert_eq!(expected.test_side, None);
+    assert_eq!(expected.curves[0].results[0].vs_time_p, None);
+}
+
+#[test]
+fn test_no_significance() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let data = slurp("integration-test/calc-expected/ceec-types-vs-tokens-gender.json");
+    let expected: Output = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        category: Some("gender"),
+        no_significance: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.limit, expected.limit);
+    assert_eq!(output.curves.len(), expected.curves.len());
+    for (c, e) in output.curves.iter().zip(expected.curves.iter()) {
+        assert_eq!(c.category, e.category);
+        assert_eq!(c.results.len(), e.results.len());
+        for (r, e) in c.results.iter().zip(e.results.iter()) {
+            assert_eq!(r.period, e.period);
+            assert_eq!(r.average_at_limit, e.average_at_limit);
+            assert_eq!(r.vs_time, None);
+            assert_eq!(r.vs_categories, None);
+        }
+    }
+    let json = serde_json::to_value(&output).unwrap();
+    let r = &json["curves"][0]["results"][0];
+    assert!(r["vs_time"].is_null());
+    assert!(r["vs_categories"].is_null());
+}
+
+#[test]
+fn test_log_file() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let outfile = dir.join("test-log-file.json");
+    let logfile = dir.join("test-log-file.log");
+    let _ = fs::remove_file(&logfile);
+    let status = process::Command::new(env!("CARGO_BIN_EXE_types3-calc"))
+        .arg(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sample-data/ceec.json"))
+        .arg(&outfile)
+        .args(["--window", "50", "--step", "50", "--iter", "100", "-vvv"])
+        .args(["--seed", "1234"])
+        .arg("--log-file")
+        .arg(&logfile)
+        .stderr(process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let log = fs::read_to_string(&logfile).unwrap();
+    assert!(!log.is_empty());
+    assert!(log.lines().all(|l| l.contains(" types3 > ")));
+    assert!(log.lines().any(|l| l.starts_with("DEBUG")));
+    assert!(log.lines().any(|l| l.ends_with("random seed: 1234")));
+}
+
+#[test]
+fn test_gzip_stdout() {
+    use std::io::Read;
+    let run = |extra: &[&str]| {
+        let out = process::Command::new(env!("CARGO_BIN_EXE_types3-calc"))
+            .arg(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sample-data/ceec.json"))
+            .arg("-")
+            .args(["--window", "50", "--step", "50", "--iter", "100", "-vvv"])
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert!(!out.stderr.is_empty());
+        out.stdout
+    };
+    let plain = run(&[]);
+    let compressed = run(&["--gzip-out"]);
+    assert_eq!(&compressed[..2], &[0x1f, 0x8b]);
+    let mut decompressed = vec![];
+    flate2::read::GzDecoder::new(&compressed[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, plain);
+    let output: Output = serde_json::from_slice(&plain).unwrap();
+    assert!(!output.curves.is_empty());
+}
+
+#[test]
+fn test_error_file_stdout() {
+    let out = process::Command::new(env!("CARGO_BIN_EXE_types3-calc"))
+        .arg(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sample-data/no-such-file.json"))
+        .arg("-")
+        .args(["--window", "50", "--step", "50"])
+        .args(["--error-file", "-"])
+        .stderr(process::Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    let error: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert!(!error["error"].as_str().unwrap().is_empty());
+}
+
+#[test]
+fn test_stat_stdout() {
+    let out = process::Command::new(env!("CARGO_BIN_EXE_types3-stat"))
+        .arg(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sample-data/ceec.json"))
+        .arg("-")
+        .args(["--window", "50", "--step", "50"])
+        .args(["--error-file", "-"])
+        .stderr(process::Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    let error: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert!(error["error"].as_str().unwrap().contains("standard output"));
+}
+
+#[test]
+fn test_gz_files() {
+    use std::io::{Read, Write};
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let infile = dir.join("test-gz-files-input.json.gz");
+    let mut encoder = flate2::write::GzEncoder::new(
+        fs::File::create(&infile).unwrap(),
+        flate2::Compression::default(),
+    );
+    encoder
+        .write_all(slurp("sample-data/ceec.json").as_bytes())
+        .unwrap();
+    encoder.finish().unwrap();
+    let run = |infile: &PathBuf, outfile: &PathBuf| {
+        let status = process::Command::new(env!("CARGO_BIN_EXE_types3-calc"))
+            .arg(infile)
+            .arg(outfile)
+            .args(["--window", "50", "--step", "50", "--iter", "100"])
+            .stderr(process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    let plain = dir.join("test-gz-files.json");
+    run(
+        &PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sample-data/ceec.json"),
+        &plain,
+    );
+    let compressed = dir.join("test-gz-files.json.gz");
+    run(&infile, &compressed);
+    let mut decompressed = String::new();
+    flate2::read::GzDecoder::new(fs::File::open(&compressed).unwrap())
+        .read_to_string(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, fs::read_to_string(&plain).unwrap());
+}
+
+#[test]
+fn test_read_input_gzip() {
+    use std::io::{Read, Write};
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sample-data/ceec.json");
+    let plain = input::read_input(path.to_str().unwrap()).unwrap();
+    // Compressed files are recognized by their contents, not by their names.
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let compressed = dir.join("test-read-input-gzip.json");
+    let mut encoder = flate2::write::GzEncoder::new(
+        fs::File::create(&compressed).unwrap(),
+        flate2::Compression::default(),
+    );
+    encoder
+        .write_all(slurp("sample-data/ceec.json").as_bytes())
+        .unwrap();
+    encoder.finish().unwrap();
+    let input = input::read_input(compressed.to_str().unwrap()).unwrap();
+    assert_eq!(input::summary(&input), input::summary(&plain));
+    let ids = |input: &Input| {
+        input
+            .samples
+            .iter()
+            .map(|s| s.id.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids(&input), ids(&plain));
+    assert!(input::read_input(dir.join("no-such-file.json").to_str().unwrap()).is_err());
+    let mut text = String::new();
+    input::open_file(compressed.to_str().unwrap())
+        .unwrap()
+        .read_to_string(&mut text)
+        .unwrap();
+    assert_eq!(text, slurp("sample-data/ceec.json"));
+}
+
+#[test]
+fn test_import_example() {
+    let data = slurp("data-format/example-long.csv");
+    let input = types3::import::import(&data, ',').unwrap();
+    let got = serde_json::to_value(&input).unwrap();
+    let expected: serde_json::Value =
+        serde_json::from_str(&slurp("data-format/example.json")).unwrap();
+    assert_eq!(got, expected);
+    let tsv = data.replace(',', "\t");
+    let input = types3::import::import(&tsv, '\t').unwrap();
+    assert_eq!(serde_json::to_value(&input).unwrap(), expected);
+}
+
+#[test]
+fn test_multiple_infiles() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let infile = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sample-data/ceec.json");
+    let data: serde_json::Value = serde_json::from_str(&slurp("sample-data/ceec.json")).unwrap();
+    let samples = data["samples"].as_array().unwrap();
+    // The periods are aligned with 1700, so that each period is within one part,
+    // with its samples in the same order as in the original file.
+    let (early, late): (Vec<_>, Vec<_>) = samples
+        .iter()
+        .cloned()
+        .partition(|s| s["year"].as_i64().is_some_and(|y| y < 1700));
+    let parts = [("early", early), ("late", late)].map(|(name, samples)| {
+        let path = dir.join(format!("test-multiple-infiles-{name}.json"));
+        fs::write(&path, serde_json::json!({ "samples": samples }).to_string()).unwrap();
+        path
+    });
+    let run = |infiles: &[&PathBuf]| {
+        let out = process::Command::new(env!("CARGO_BIN_EXE_types3-calc"))
+            .args(infiles)
+            .args(["-o", "-"])
+            .args(["--window", "50", "--step", "50", "--iter", "100"])
+            .args(["--error-file", "-"])
+            .stderr(process::Stdio::null())
+            .output()
+            .unwrap();
+        (out.status.success(), out.stdout)
+    };
+    let (ok, expected) = run(&[&infile]);
+    assert!(ok);
+    let (ok, merged) = run(&[&parts[0], &parts[1]]);
+    assert!(ok);
+    let expected: Output = serde_json::from_slice(&expected).unwrap();
+    let merged: Output = serde_json::from_slice(&merged).unwrap();
+    assert_eq!(merged.curves.len(), expected.curves.len());
+    for (c, ec) in merged.curves.iter().zip(&expected.curves) {
+        for (r, er) in c.results.iter().zip(&ec.results) {
+            assert_eq!(r.period, er.period);
+            assert_eq!(r.average_at_limit, er.average_at_limit);
+        }
+    }
+    let (ok, error) = run(&[&parts[0], &infile]);
+    assert!(!ok);
+    let error: serde_json::Value = serde_json::from_slice(&error).unwrap();
+    assert!(error["error"]
+        .as_str()
+        .unwrap()
+        .contains("appear in more than one input file"));
+}
+
+#[test]
+fn test_format_csv() {
+    let run = |extra: &[&str]| {
+        let out = process::Command::new(env!("CARGO_BIN_EXE_types3-calc"))
+            .arg(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sample-data/ceec.json"))
+            .arg("-")
+            .args(["--window", "50", "--step", "50", "--iter", "100"])
+            .args(["--category", "gender"])
+            .args(extra)
+            .stderr(process::Stdio::null())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        out.stdout
+    };
+    let output: Output = serde_json::from_slice(&run(&[])).unwrap();
+    let csv = run(&["--format", "csv"]);
+    let mut expected = vec![];
+    output::to_csv(&output, &mut expected).unwrap();
+    assert_eq!(csv, expected);
+    let csv = String::from_utf8(csv).unwrap();
+    let rows = output.curves.iter().map(|c| c.results.len()).sum::<usize>();
+    assert_eq!(csv.lines().count(), rows + 1);
+    assert!(csv
+        .lines()
+        .all(|l| l.split(',').count() == output::CSV_COLUMNS.len()));
+}
+
+#[test]
+fn test_split_output() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let outfile = dir.join("test-split-output.json");
+    let splitdir = dir.join("test-split-output");
+    let _ = fs::remove_dir_all(&splitdir);
+    let status = process::Command::new(env!("CARGO_BIN_EXE_types3-calc"))
+        .arg(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sample-data/ceec.json"))
+        .arg(&outfile)
+        .args(["--window", "50", "--step", "50", "--iter", "100"])
+        .args(["--category", "socmob"])
+        .arg("--split-output")
+        .arg(&splitdir)
+        .stderr(process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let expected: Output = serde_json::from_str(&fs::read_to_string(&outfile).unwrap()).unwrap();
+    let index: output::OIndex =
+        serde_json::from_str(&fs::read_to_string(splitdir.join("index.json")).unwrap()).unwrap();
+    assert!(index.output.curves.is_empty());
+    assert_eq!(index.files.len(), expected.curves.len());
+    let curves = index
+        .files
+        .iter()
+        .map(|f| {
+            let curve: output::OCurve =
+                serde_json::from_str(&fs::read_to_string(splitdir.join(&f.file)).unwrap()).unwrap();
+            assert_eq!(curve.category, f.category);
+            curve
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(index.combine(curves), expected);
+}
+
+#[test]
+fn test_convergence() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        category: Some("gender"),
+        iter: 10000,
+        ..base_args()
+    };
+    let expected = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        category: Some("gender"),
+        iter: 10000,
+        convergence: Some(&[0.5, 0.1]),
+        ..base_args()
+    };
+    let mut output = driver::calc(&driver_args, &input).unwrap();
+    for c in &mut output.curves {
+        for r in &mut c.results {
+            let convergence = r.convergence.take().unwrap();
+            assert_eq!(convergence.len(), 3);
+            let fractions = convergence.iter().map(|x| x.fraction).collect::<Vec<_>>();
+            assert_eq!(fractions, [0.1, 0.5, 1.0]);
+            let last = convergence.last().unwrap();
+            assert_eq!(last.average_at_limit, r.average_at_limit);
+            assert_eq!(last.vs_time, r.vs_time);
+            assert_eq!(last.vs_categories, r.vs_categories);
+            for (x, iter) in convergence.iter().zip([1000, 5000, 10000]) {
+                assert_eq!(x.average_at_limit.iter, iter);
+                assert_eq!(x.vs_time.unwrap().iter, iter);
+                assert_eq!(x.vs_categories.unwrap().iter, iter);
+            }
+        }
+    }
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_cancel() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let cancel = AtomicBool::new(false);
+    let driver_args = DriverArgs {
+        iter: 100,
+        cancel: Some(&cancel),
+        ..base_args()
+    };
+    assert!(driver::calc(&driver_args, &input).is_ok());
+    cancel.store(true, Ordering::Relaxed);
+    let err = driver::calc(&driver_args, &input).unwrap_err();
+    assert!(err.downcast_ref::<errors::Cancelled>().is_some());
+    assert_eq!(err.to_string(), "calculation cancelled");
+}
+
+#[test]
+fn test_cache() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let cache = Cache::new();
+    let driver_args = DriverArgs {
+        iter: 100,
+        category: Some("gender"),
+        repeats: Some(3),
+        ..base_args()
+    };
+    let expected = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        cache: Some(&cache),
+        ..driver_args
+    };
+    let cold = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(cold, expected);
+    let computed = cache.computed();
+    assert!(computed > 0);
+    assert_eq!(cache.hits(), 0);
+    let warm = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(warm, expected);
+    assert_eq!(cache.computed(), computed);
+    assert_eq!(cache.hits(), computed);
+    // Different parameters are not taken from the cache.
+    let driver_args = DriverArgs {
+        iter: 200,
+        ..driver_args
+    };
+    driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(cache.computed(), 2 * computed);
+    assert_eq!(cache.hits(), computed);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let rows: i64 = output.curves.iter().map(|c| c.results.len() as i64).sum();
+    assert!(rows > 0);
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let run1 = types3::sqlite::write_output(&mut conn, &output, "test", "ceec.json").unwrap();
+    let run2 = types3::sqlite::write_output(&mut conn, &output, "test", "ceec.json").unwrap();
+    assert_ne!(run1, run2);
+    let count =
+        |sql: &str, run: i64| -> i64 { conn.query_row(sql, [run], |row| row.get(0)).unwrap() };
+    assert_eq!(count("SELECT COUNT(*) FROM runs WHERE id = ?1", run1), 1);
+    assert_eq!(
+        count("SELECT COUNT(*) FROM results WHERE run = ?1", run1),
+        rows
+    );
+    assert_eq!(
+        count("SELECT COUNT(*) FROM results WHERE run = ?1", run2),
+        rows
+    );
+    let r = &output.curves[0].results[0];
+    let (value, start, above): (String, i64, i64) = conn
+        .query_row(
+            "SELECT category_value, period_start, vs_time_above FROM results WHERE run = ?1 ORDER BY rowid LIMIT 1",
+            [run1],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!(
+        Some(value),
+        output.curves[0].category.as_ref().map(|c| c.1.clone())
+    );
+    assert_eq!(start, r.period.0 as i64);
+    assert_eq!(above, r.vs_time.unwrap().above as i64);
+}
+
+#[test]
+fn test_ngram() {
+    init();
+    let data = r#"{"samples": [
+        {"id": "a", "year": 1900, "metadata": {}, "words": 4, "tokens": [
+            {"lemma": "a", "metadata": {}},
+            {"lemma": "b", "metadata": {}},
+            {"lemma": "a", "metadata": {}},
+            {"lemma": "b", "metadata": {}}
+        ]},
+        {"id": "b", "year": 1900, "metadata": {}, "words": 3, "tokens": [
+            {"lemma": "b", "metadata": {}},
+            {"lemma": "c", "metadata": {}},
+            {"lemma": "c", "metadata": {}}
+        ]}
+    ]}"#;
+    let input: Input = serde_json::from_str(data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 10,
+        no_time: true,
+        ..base_args()
+    };
+    let types = |output: &Output| {
+        assert_eq!(output.curves.len(), 1);
+        assert_eq!(output.curves[0].results.len(), 1);
+        let ar = output.curves[0].results[0].average_at_limit;
+        assert_eq!(ar.low, ar.high);
+        ar.low / ar.iter
+    };
+    let unigrams = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(unigrams.ngram, None);
+    assert_eq!(unigrams.limit, 7);
+    assert_eq!(types(&unigrams), 3);
+    let driver_args = DriverArgs {
+        ngram: 2,
+        ..driver_args
+    };
+    // Bigrams "a b", "b a", "a b" and "b c", "c c".
+    let bigrams = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(bigrams.ngram, Some(2));
+    assert_eq!(bigrams.limit, 5);
+    assert_eq!(types(&bigrams), 4);
+    let driver_args = DriverArgs {
+        ngram: 0,
+        ..driver_args
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err();
+    assert!(e.is::<errors::InvalidArgument>());
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_parquet() {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, Int16Type, UInt64Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let rows: usize = output.curves.iter().map(|c| c.results.len()).sum();
+    assert!(rows > 0);
+    let filename = std::env::temp_dir().join(format!("types3-parquet-{}.parquet", process::id()));
+    types3::parquet::write_output(fs::File::create(&filename).unwrap(), &output).unwrap();
+    let reader = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(&filename).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    fs::remove_file(&filename).unwrap();
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), rows);
+    let batch = &batches[0];
+    let column = |name: &str| batch.column_by_name(name).unwrap();
+    let c = &output.curves[0];
+    let r = &c.results[0];
+    assert_eq!(
+        column("category_key").as_string::<i32>().value(0),
+        c.category.as_ref().unwrap().0
+    );
+    assert_eq!(
+        column("category_value").as_string::<i32>().value(0),
+        c.category.as_ref().unwrap().1
+    );
+    assert_eq!(
+        column("period_start").as_primitive::<Int16Type>().value(0),
+        r.period.0
+    );
+    assert_eq!(
+        column("iter").as_primitive::<UInt64Type>().value(0),
+        r.average_at_limit.iter
+    );
+    let ar = r.average_at_limit;
+    let average = (ar.low + ar.high) as f64 / 2.0 / ar.iter as f64;
+    let got = column("average").as_primitive::<Float64Type>().value(0);
+    assert!((got - average).abs() < 1e-9);
+    let above = column("vs_time_above").as_primitive::<UInt64Type>();
+    assert_eq!(above.value(0), r.vs_time.unwrap().above);
+    assert!(column("vs_time_z").is_null(0));
+}
+
+/// Samples from 1900 to 1919; with `structure`, the two decades use different lemmas.
+fn year_label_input(structure: bool) -> Input {
+    let samples = (0..20)
+        .map(|i| {
+            let offset = if structure && i >= 10 { 5 } else { 0 };
+            let tokens = (0..5)
+                .map(|j| format!(r#"{{"lemma": "l{}", "metadata": {{}}}}"#, offset + j))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                r#"{{"id": "s{}", "year": {}, "metadata": {{}}, "words": 5, "tokens": [{}]}}"#,
+                i,
+                1900 + i,
+                tokens
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    serde_json::from_str(&format!(r#"{{"samples": [{samples}]}}"#)).unwrap()
+}
+
+#[test]
+fn test_null_model_year_label() {
+    init();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        window: 10,
+        step: 10,
+        null_model: NullModel::YearLabel,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &year_label_input(true)).unwrap();
+    assert_eq!(output.null_model, Some(NullModel::YearLabel));
+    let results = &output.curves[0].results;
+    assert_eq!(results.len(), 2);
+    for r in results {
+        // Each decade has fewer types than almost any random set of 10 samples.
+        let pr = r.vs_time.unwrap();
+        assert_eq!(pr.iter, 1000);
+        assert_eq!(pr.above, 0);
+        assert!(pr.below > 990, "{pr:?}");
+        assert!(pr.is_significant(0.01));
+        assert_eq!(r.vs_time_z, None);
+    }
+    let output = driver::calc(&driver_args, &year_label_input(false)).unwrap();
+    for r in &output.curves[0].results {
+        // All reassignments give the same subsets.
+        let pr = r.vs_time.unwrap();
+        assert_eq!((pr.above, pr.below, pr.iter), (0, 0, 1000));
+    }
+    let output = driver::calc(&base_args(), &year_label_input(false)).unwrap();
+    assert_eq!(output.null_model, None);
+}
+
+#[test]
+fn test_null_model_year_label_invalid() {
+    init();
+    let input = year_label_input(true);
+    let driver_args = DriverArgs {
+        iter: 10,
+        null_model: NullModel::YearLabel,
+        leave_out_self: true,
+        ..base_args()
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err();
+    assert!(e.is::<errors::InvalidArgument>());
+    let driver_args = DriverArgs {
+        leave_out_self: false,
+        convergence: Some(&[0.5]),
+        ..driver_args
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err();
+    assert!(e.is::<errors::InvalidArgument>());
+    for measure_y in [
+        MeasureY::Hapaxes,
+        MeasureY::TypeTokenRatio,
+        MeasureY::YuleK,
+        MeasureY::Entropy,
+        MeasureY::FrequentTypes { per_million: 1000 },
+    ] {
+        let driver_args = DriverArgs {
+            convergence: None,
+            measure_y,
+            ..driver_args
+        };
+        let e = driver::calc(&driver_args, &input).unwrap_err();
+        assert!(e.is::<errors::InvalidArgument>());
+        assert!(e.to_string().contains("monotone measure"));
+    }
+}
+
+#[test]
+fn test_distinct_orderings() {
+    init();
+    let data = r#"{"samples": [
+        {"id": "a", "year": 1900, "metadata": {}, "words": 2, "tokens": [
+            {"lemma": "x", "metadata": {}},
+            {"lemma": "y", "metadata": {}}
+        ]},
+        {"id": "b", "year": 1901, "metadata": {}, "words": 1, "tokens": [
+            {"lemma": "x", "metadata": {}}
+        ]}
+    ]}"#;
+    let input: Input = serde_json::from_str(data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        no_time: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.curves[0].results[0].distinct_orderings, None);
+    assert!(output.warnings.is_empty());
+    let driver_args = DriverArgs {
+        distinct_orderings: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.curves[0].results[0].distinct_orderings, Some(2));
+    assert_eq!(output.warnings.len(), 1);
+    assert!(
+        output.warnings[0].contains("only 2 distinct orderings of 2 samples"),
+        "{:?}",
+        output.warnings
+    );
+    let driver_args = DriverArgs {
+        iter: 2,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.curves[0].results[0].distinct_orderings, Some(2));
+    assert!(output.warnings.is_empty());
+}
+
+#[test]
+fn test_subsample() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let all = || types3::samples::get_samples(&(0, 9999), None, None, None, &[], &input.samples);
+    let n = all().len();
+    let years = |fraction, seed| {
+        let mut samples = all();
+        let kept = types3::samples::subsample(fraction, seed, &mut samples);
+        assert_eq!(kept, samples.len());
+        samples
+            .iter()
+            .map(|s| (s.year, s.words))
+            .collect::<Vec<_>>()
+    };
+    let half = years(0.5, 1);
+    assert_eq!(half, years(0.5, 1));
+    assert_ne!(half, years(0.5, 2));
+    assert!(half.len().abs_diff(n / 2) <= 1);
+    assert_eq!(years(1.0, 1).len(), n);
+
+    let driver_args = DriverArgs {
+        iter: 100,
+        subsample: Some(0.5),
+        seed: 1,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.subsample, Some(0.5));
+    let again = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(
+        serde_json::to_string(&output).unwrap(),
+        serde_json::to_string(&again).unwrap()
+    );
+    let full = driver::calc(
+        &DriverArgs {
+            subsample: None,
+            ..driver_args
+        },
+        &input,
+    )
+    .unwrap();
+    assert_eq!(full.subsample, None);
+    assert!(output.limit < full.limit);
+    let driver_args = DriverArgs {
+        subsample: Some(0.0),
+        ..driver_args
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err();
+    assert!(e.is::<errors::InvalidArgument>());
+}
+
+#[test]
+fn test_presence_absence() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        measure_y: MeasureY::Tokens,
+        ..base_args()
+    };
+    let raw = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        presence_absence: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert!(!raw.presence_absence);
+    assert!(output.presence_absence);
+    // Fewer tokens in total, and hence a lower size limit.
+    assert!(output.limit < raw.limit);
+    for c in &output.curves {
+        for r in &c.results {
+            let ar = r.average_at_limit;
+            assert!(ar.low <= output.limit * ar.iter);
+            assert!(ar.high >= output.limit * ar.iter);
+        }
+    }
+    let driver_args = DriverArgs {
+        split_samples: true,
+        ..driver_args
+    };
+    assert!(driver::calc(&driver_args, &input).is_err());
+}
+
+#[test]
+fn test_warnings() {
+    init();
+    // Category b only has samples in 1900, so its subset for 1910 is empty.
+    let mut samples = vec![];
+    for (year, cat) in [(1900, "a"), (1900, "b"), (1910, "a")] {
+        let mut s = synthetic_sample(year, 5);
+        s["metadata"]["c"] = cat.into();
+        samples.push(s);
+    }
+    samples.push(synthetic_sample(1910, 5));
+    let input: Input =
+        serde_json::from_str(&serde_json::json!({ "samples": samples }).to_string()).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        window: 10,
+        step: 10,
+        category: Some("c"),
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(
+        output.warnings,
+        [
+            "1 samples without metadata key c are not in any category",
+            "1910–1919, c = b: skipped, size 0 tokens is below the minimum size 1",
+        ]
+    );
+    let json = serde_json::to_value(&output).unwrap();
+    assert_eq!(json["warnings"].as_array().unwrap().len(), 2);
+    let driver_args = DriverArgs {
+        category: None,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert!(output.warnings.is_empty());
+    assert!(serde_json::to_value(&output)
+        .unwrap()
+        .get("warnings")
+        .is_none());
+}
+
+#[test]
+fn test_expected_types() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 10000,
+        split_samples: true,
+        expected_types: true,
+        no_significance: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for r in &output.curves[0].results {
+        let expected = r.expected_types.unwrap();
+        let ar = r.average_at_limit;
+        assert_eq!(ar.low, ar.high);
+        let avg = ar.low as f64 / ar.iter as f64;
+        assert!(
+            (avg - expected).abs() < 0.01 * expected,
+            "{avg} vs. {expected}"
+        );
+    }
+    let driver_args = DriverArgs {
+        expected_types: false,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert!(output.curves[0]
+        .results
+        .iter()
+        .all(|r| r.expected_types.is_none()));
+    let driver_args = DriverArgs {
+        expected_types: true,
+        measure_y: MeasureY::Tokens,
+        ..driver_args
+    };
+    assert!(driver::calc(&driver_args, &input).is_err());
+}
+
+#[test]
+fn test_comparison_n() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let count = |category: Option<&str>, period: Years| {
+        input
+            .samples
+            .iter()
+            .filter(|s| s.year.is_some_and(|y| period.0 <= y && y < period.1))
+            .filter(|s| {
+                category.is_none() || s.metadata.get("gender").map(|g| g as &str) == category
+            })
+            .count() as u64
+    };
+    let driver_args = DriverArgs {
+        category: Some("gender"),
+        iter: 100,
+        comparison_n: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for curve in &output.curves {
+        let category = curve.category.as_ref().map(|(_, v)| v as &str);
+        for r in &curve.results {
+            let n = r.comparison_n.unwrap();
+            assert_eq!(n.vs_time, count(category, output.years));
+            assert_eq!(n.vs_categories, Some(count(None, r.period)));
+        }
+    }
+    // The reference set does not include the subset itself.
+    let driver_args = DriverArgs {
+        category: None,
+        leave_out_self: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for r in &output.curves[0].results {
+        let n = r.comparison_n.unwrap();
+        assert_eq!(n.vs_time, count(None, output.years) - count(None, r.period));
+        assert_eq!(n.vs_categories, None);
+    }
+    let driver_args = DriverArgs {
+        comparison_n: false,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert!(output.curves[0]
+        .results
+        .iter()
+        .all(|r| r.comparison_n.is_none()));
+}
+
+#[test]
+fn test_extend() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    // The previous input lacks some samples of men in 1760–1779.
+    let mut prev_input: Input = serde_json::from_str(&data).unwrap();
+    let mut dropped = 0;
+    prev_input.samples.retain(|s| {
+        let keep = dropped == 3
+            || !(s.metadata.get("gender").map(|g| g as &str) == Some("male")
+                && s.year.is_some_and(|y| (1760..1780).contains(&y)));
+        if !keep {
+            dropped += 1;
+        }
+        keep
+    });
+    assert_eq!(dropped, 3);
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        fingerprints: true,
+        ..base_args()
+    };
+    let prev = driver::calc(&driver_args, &prev_input).unwrap();
+    let full_cache = Cache::new();
+    let full = driver::calc(
+        &DriverArgs {
+            cache: Some(&full_cache),
+            ..driver_args
+        },
+        &input,
+    )
+    .unwrap();
+    let cache = Cache::new();
+    let extended = driver::extend(
+        &prev,
+        &input,
+        &DriverArgs {
+            cache: Some(&cache),
+            ..driver_args
+        },
+    )
+    .unwrap();
+    assert_eq!(extended, full);
+    // All results of men change, but for women only 1760–1779 is compared with new samples.
+    let female = |o: &Output| {
+        o.curves
+            .iter()
+            .find(|c| c.category == Some(("gender".to_owned(), "female".to_owned())))
+            .unwrap()
+            .results
+            .iter()
+            .map(|r| (r.period, r.fingerprint.clone().unwrap()))
+            .collect::<Vec<_>>()
+    };
+    let changed = female(&prev)
+        .iter()
+        .zip(female(&full))
+        .filter(|(a, b)| **a != *b)
+        .map(|(a, _)| a.0)
+        .collect::<Vec<_>>();
+    assert_eq!(changed, [(1760, 1780)]);
+    assert!(0 < cache.computed() && cache.computed() < full_cache.computed());
+    // Extending from unrelated results reuses nothing.
+    let other = driver::calc(
+        &DriverArgs {
+            iter: 100,
+            ..driver_args
+        },
+        &input,
+    )
+    .unwrap();
+    let cache = Cache::new();
+    let extended = driver::extend(
+        &other,
+        &input,
+        &DriverArgs {
+            cache: Some(&cache),
+            ..driver_args
+        },
+    )
+    .unwrap();
+    assert_eq!(extended, full);
+    assert_eq!(cache.computed(), full_cache.computed());
+    let driver_args = DriverArgs {
+        fingerprints: false,
+        ..driver_args
+    };
+    assert!(driver::extend(&prev, &input, &driver_args).is_err());
+}
+
+#[test]
+fn test_min_sample_tokens() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let min_tokens = 5;
+    let kept = input
+        .samples
+        .iter()
+        .filter(|s| s.tokens.len() >= min_tokens)
+        .collect::<Vec<_>>();
+    assert!(kept.len() < input.samples.len());
+    let tokens = kept.iter().map(|s| s.tokens.len() as u64).sum::<u64>();
+    let types = kept
+        .iter()
+        .flat_map(|s| s.tokens.iter().map(|t| &t.lemma))
+        .collect::<HashSet<_>>()
+        .len() as u64;
+    let driver_args = DriverArgs {
+        iter: 100,
+        no_time: true,
+        dump_samples: true,
+        min_sample_tokens: Some(min_tokens as u64),
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.min_sample_tokens, Some(min_tokens as u64));
+    let dumped = output.samples.as_ref().unwrap();
+    assert_eq!(dumped.len(), 1);
+    assert_eq!(dumped[0].samples.len(), kept.len());
+    assert_eq!(dumped[0].total_x, tokens);
+    assert_eq!(dumped[0].total_y, types);
+    assert_eq!(
+        output.warnings,
+        [format!(
+            "{} samples with fewer than {} tokens are ignored",
+            input.samples.len() - kept.len(),
+            min_tokens
+        )]
+    );
+    // Without the restriction, everything is included.
+    let driver_args = DriverArgs {
+        min_sample_tokens: None,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let dumped = output.samples.as_ref().unwrap();
+    assert_eq!(dumped[0].samples.len(), input.samples.len());
+    assert!(dumped[0].total_x > tokens);
+    assert!(output.warnings.is_empty());
+}
+
+#[test]
+fn test_samples_at_limit() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let plain = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        samples_at_limit: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for (c, pc) in output.curves.iter().zip(&plain.curves) {
+        for (r, pr) in c.results.iter().zip(&pc.results) {
+            assert_eq!(pr.avg_samples_at_limit, None);
+            assert_eq!(pr.average_at_limit.samples, None);
+            let avg = r.avg_samples_at_limit.unwrap();
+            assert!(avg >= 1.0);
+            assert_eq!(
+                avg,
+                r.average_at_limit.samples.unwrap() as f64 / r.average_at_limit.iter as f64
+            );
+            let ar = AvgResult {
+                samples: None,
+                ..r.average_at_limit
+            };
+            assert_eq!(ar, pr.average_at_limit);
+        }
+    }
+}
+
+#[test]
+fn test_marginal() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let plain = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        marginal: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for (c, pc) in output.curves.iter().zip(&plain.curves) {
+        for (r, pr) in c.results.iter().zip(&pc.results) {
+            assert_eq!(pr.avg_marginal, None);
+            let ar = r.average_at_limit;
+            let marginal = ar.marginal.unwrap();
+            assert_eq!(r.avg_marginal, Some(marginal as f64 / ar.iter as f64));
+            // The gap between the bounds is never larger than the contribution of the last sample.
+            assert!(ar.high - ar.low <= marginal as u64);
+            assert_eq!(
+                AvgResult {
+                    marginal: None,
+                    ..ar
+                },
+                pr.average_at_limit
+            );
+        }
+    }
+}
+
+#[test]
+fn test_frequent_types() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let types = driver::calc(&driver_args, &input).unwrap();
+    // With fewer than a million tokens, a threshold of 1 per million is one token,
+    // so all types are frequent.
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::FrequentTypes { per_million: 1 },
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.curves, types.curves);
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::FrequentTypes {
+            per_million: 10_000,
+        },
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for (c, tc) in output.curves.iter().zip(&types.curves) {
+        for (r, tr) in c.results.iter().zip(&tc.results) {
+            assert!(r.average_at_limit.high <= tr.average_at_limit.high);
+        }
+    }
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::FrequentTypes { per_million: 0 },
+        ..driver_args
+    };
+    assert!(driver::calc(&driver_args, &input).is_err());
+}
+
+#[test]
+fn test_legomena() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        measure_y: MeasureY::Hapaxes,
+        ..base_args()
+    };
+    let hapaxes = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::Legomena { n: 1 },
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.curves, hapaxes.curves);
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::Legomena { n: 3 },
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.measure_y, MeasureY::Legomena { n: 3 });
+    for (c, hc) in output.curves.iter().zip(&hapaxes.curves) {
+        for (r, hr) in c.results.iter().zip(&hc.results) {
+            assert!(r.average_at_limit.high > 0);
+            // Tris legomena are rarer than hapaxes.
+            let (a, b) = (&r.average_at_limit, &hr.average_at_limit);
+            assert!(a.low + a.high < b.low + b.high);
+        }
+    }
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::Legomena { n: 0 },
+        ..driver_args
+    };
+    assert!(driver::calc(&driver_args, &input).is_err());
+}
+
+#[test]
+fn test_type_token_ratio() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let types = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::TypeTokenRatio,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.measure_y, MeasureY::TypeTokenRatio);
+    assert_eq!(output.limit, types.limit);
+    let scale = TYPE_TOKEN_RATIO_SCALE;
+    for (c, tc) in output.curves.iter().zip(&types.curves) {
+        for (r, tr) in c.results.iter().zip(&tc.results) {
+            let (a, b) = (&r.average_at_limit, &tr.average_at_limit);
+            assert!(0 < a.low && a.low <= a.high);
+            assert!(a.high <= scale * a.iter);
+            // Same subsets, so the ratio is consistent with the number of types.
+            assert!(a.low <= b.high * scale / output.limit);
+            assert!(a.high + a.iter >= b.low * scale / output.limit);
+        }
+    }
+}
+
+#[test]
+fn test_yule_k() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        measure_y: MeasureY::YuleK,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.measure_y, MeasureY::YuleK);
+    for c in &output.curves {
+        for r in &c.results {
+            let a = &r.average_at_limit;
+            assert!(0 < a.low && a.low <= a.high);
+            assert!(a.high < 10000 * YULE_K_SCALE * a.iter);
+        }
+    }
+}
+
+#[test]
+fn test_entropy() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        measure_y: MeasureY::Types,
+        ..base_args()
+    };
+    let types = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::Entropy,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.measure_y, MeasureY::Entropy);
+    for (c, tc) in output.curves.iter().zip(&types.curves) {
+        for (r, tr) in c.results.iter().zip(&tc.results) {
+            let (a, b) = (&r.average_at_limit, &tr.average_at_limit);
+            assert!(0 < a.low && a.low <= a.high);
+            // The entropy is at most log₂ of the number of types.
+            let max_types = b.high as f64 / b.iter as f64 + 1.0;
+            let max_entropy = max_types.log2() * ENTROPY_SCALE as f64;
+            assert!((a.low as f64 / a.iter as f64) < max_entropy);
+        }
+    }
+}
+
+#[test]
+fn test_known_types() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let types = driver::calc(&driver_args, &input).unwrap();
+    // If all lemmas are known, we count all types.
+    let all: HashSet<String> = input
+        .samples
+        .iter()
+        .flat_map(|s| s.tokens.iter().map(|t| t.lemma.clone()))
+        .collect();
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::KnownTypes,
+        vocabulary: Some(&all),
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(output.curves, types.curves);
+    // If no lemmas are known, all values are zero.
+    let none = HashSet::new();
+    let driver_args = DriverArgs {
+        vocabulary: Some(&none),
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for c in &output.curves {
+        for r in &c.results {
+            assert_eq!(r.average_at_limit.high, 0);
+        }
+    }
+    // Half of the lemmas are known, so we count fewer types.
+    let mut lemmas = all.iter().collect::<Vec<_>>();
+    lemmas.sort();
+    let half: HashSet<String> = lemmas.iter().step_by(2).map(|&l| l.clone()).collect();
+    let driver_args = DriverArgs {
+        vocabulary: Some(&half),
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for (c, tc) in output.curves.iter().zip(&types.curves) {
+        for (r, tr) in c.results.iter().zip(&tc.results) {
+            assert!(r.average_at_limit.high > 0);
+            assert!(r.average_at_limit.high < tr.average_at_limit.high);
+        }
+    }
+    let driver_args = DriverArgs {
+        vocabulary: None,
+        ..driver_args
+    };
+    assert!(driver::calc(&driver_args, &input).is_err());
+}
+
+#[test]
+fn test_extremes() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let plain = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        extremes: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for (c, pc) in output.curves.iter().zip(&plain.curves) {
+        for (r, pr) in c.results.iter().zip(&pc.results) {
+            let ar = r.average_at_limit;
+            let (min_y, max_y) = (ar.min_y.unwrap(), ar.max_y.unwrap());
+            // The average is between the extremes.
+            assert!(min_y * ar.iter <= ar.low);
+            assert!(ar.high <= max_y * ar.iter);
+            assert_eq!(
+                AvgResult {
+                    min_y: None,
+                    max_y: None,
+                    percentiles: None,
+                    ..ar
+                },
+                pr.average_at_limit
+            );
+        }
+    }
+}
+
+#[test]
+fn test_z_score() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 1000,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let plain = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        z_score: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let mut seen = 0;
+    for (c, pc) in output.curves.iter().zip(&plain.curves) {
+        for (r, pr) in c.results.iter().zip(&pc.results) {
+            assert_eq!(pr.vs_time_z, None);
+            assert_eq!(pr.vs_time.unwrap().moments, None);
+            let vs_time = r.vs_time.unwrap();
+            assert!(vs_time.moments.is_some());
+            assert_eq!(
+                PointResult {
+                    moments: None,
+                    ..vs_time
+                },
+                pr.vs_time.unwrap()
+            );
+            for (z, pr) in [
+                (r.vs_time_z, r.vs_time),
+                (r.vs_categories_z, r.vs_categories),
+            ] {
+                if let (Some(z), Some(pr)) = (z, pr) {
+                    seen += 1;
+                    match output::point_string(&pr).as_str() {
+                        "++++" => assert!(z > 0.0),
+                        "----" => assert!(z < 0.0),
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+    assert!(seen > 0);
+}
+
+#[test]
+fn test_also_mark_tokens() {
+    init();
+    let suffixes = ["ity", "ness", "ment"];
+    let samples = (0..40)
+        .map(|i| {
+            let tokens = (0..10)
+                .map(|j| {
+                    let lemma = (i * 7 + j * 3) % 50;
+                    serde_json::json!({
+                        "lemma": format!("w{lemma}"),
+                        "metadata": {
+                            "suffix": suffixes[lemma % 3],
+                            "origin": if lemma % 4 == 0 { "latin" } else { "native" },
+                        },
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::json!({
+                "id": format!("s{i}"),
+                "year": 1900 + i,
+                "metadata": {},
+                "words": 100,
+                "tokens": tokens,
+            })
+        })
+        .collect::<Vec<_>>();
+    let input: Input = serde_json::from_value(serde_json::json!({ "samples": samples })).unwrap();
+    let also = [("suffix", "ness"), ("origin", "latin")];
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::MarkedTypes,
+        measure_x: MeasureX::Types,
+        iter: 1000,
+        mark_tokens: Some(("suffix", "ity")),
+        also_mark_tokens: &also,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    assert_eq!(
+        output.also_mark_tokens,
+        [
+            ("suffix".to_owned(), "ness".to_owned()),
+            ("origin".to_owned(), "latin".to_owned())
+        ]
+    );
+    // Each marking gives the same results as marking only those tokens.
+    let single = [Some(("suffix", "ity")), Some(also[0]), Some(also[1])].map(|mark_tokens| {
+        driver::calc(
+            &DriverArgs {
+                mark_tokens,
+                also_mark_tokens: &[],
+                no_significance: true,
+                ..driver_args
+            },
+            &input,
+        )
+        .unwrap()
+    });
+    let results = &output.curves[0].results;
+    assert!(results.len() >= 2);
+    for (i, r) in results.iter().enumerate() {
+        let marked_types = r.marked_types.as_ref().unwrap();
+        assert_eq!(marked_types.len(), 3);
+        assert_eq!(marked_types[0].low, r.average_at_limit.low);
+        assert_eq!(marked_types[0].high, r.average_at_limit.high);
+        for (ar, o) in marked_types.iter().zip(&single) {
+            assert!(o.curves[0].results[i].marked_types.is_none());
+            assert_eq!(o.limit, output.limit);
+            assert_eq!(*ar, o.curves[0].results[i].average_at_limit);
+        }
+    }
+    // The markings differ.
+    assert_ne!(single[1].curves[0].results, single[2].curves[0].results);
+    let driver_args = DriverArgs {
+        measure_y: MeasureY::Types,
+        ..driver_args
+    };
+    assert!(driver::calc(&driver_args, &input).is_err());
+}
+
+#[test]
+fn test_category_spread() {
+    init();
+    let sample = |id: &str, year: i16, cat: &str, lemmas: &[&str]| {
+        let tokens = lemmas
+            .iter()
+            .map(|l| format!(r#"{{"lemma": "{l}", "metadata": {{}}}}"#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            r#"{{"id": "{id}", "year": {year}, "metadata": {{"c": "{cat}"}}, "words": {}, "tokens": [{tokens}]}}"#,
+            lemmas.len()
+        )
+    };
+    let calc = |samples: &[String], category_spread| {
+        let data = format!(r#"{{"samples": [{}]}}"#, samples.join(", "));
+        let input: Input = serde_json::from_str(&data).unwrap();
+        let driver_args = DriverArgs {
+            iter: 100,
+            no_time: true,
+            category: Some("c"),
+            category_spread,
+            ..base_args()
+        };
+        driver::calc(&driver_args, &input).unwrap()
+    };
+    // Categories with identical samples.
+    let identical = [
+        sample("a", 1900, "x", &["p", "q"]),
+        sample("b", 1900, "x", &["p", "r"]),
+        sample("c", 1900, "y", &["p", "q"]),
+        sample("d", 1900, "y", &["p", "r"]),
+    ];
+    assert_eq!(calc(&identical, false).category_spread, None);
+    let spread = calc(&identical, true).category_spread.unwrap();
+    assert_eq!(spread.len(), 1);
+    assert_eq!(spread[0].categories, 2);
+    assert_eq!((spread[0].range, spread[0].variance), (0.0, 0.0));
+    // Category y repeats the same type, category x does not.
+    let divergent = [
+        sample("a", 1900, "x", &["p", "q"]),
+        sample("b", 1900, "x", &["r", "s"]),
+        sample("c", 1900, "y", &["p", "p"]),
+        sample("d", 1900, "y", &["p", "p"]),
+    ];
+    let spread = calc(&divergent, true).category_spread.unwrap();
+    assert_eq!(spread.len(), 1);
+    assert_eq!((spread[0].min, spread[0].max), (1.0, 4.0));
+    assert_eq!(spread[0].range, 3.0);
+    assert_eq!(spread[0].variance, 2.25);
+}
+
+#[test]
+fn test_category_spread_requires_category() {
+    init();
+    let input: Input = serde_json::from_str(&slurp("sample-data/ceec.json")).unwrap();
+    let driver_args = DriverArgs {
+        iter: 10,
+        category_spread: true,
+        ..base_args()
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err();
+    assert!(e.is::<errors::InvalidArgument>());
+}
+
+#[test]
+fn test_calc_prepared() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let restrict_samples = Some(("gender", "female"));
+    let mark_tokens = Some(("variant", "ity"));
+    let prepared = samples::prepare(&input, restrict_samples, None, mark_tokens);
+    for (measure_y, category) in [
+        (MeasureY::Types, None),
+        (MeasureY::MarkedTypes, None),
+        (MeasureY::Tokens, Some("socmob")),
+    ] {
+        let driver_args = DriverArgs {
+            iter: 100,
+            measure_y,
+            category,
+            restrict_samples,
+            mark_tokens,
+            start: 1700,
+            ..base_args()
+        };
+        let expected = driver::calc(&driver_args, &input).unwrap();
+        let output = driver::calc_prepared(&driver_args, &input, &prepared).unwrap();
+        assert_eq!(output, expected);
+    }
+    let driver_args = DriverArgs {
+        iter: 100,
+        ngram: 2,
+        ..base_args()
+    };
+    let e = driver::calc_prepared(&driver_args, &input, &prepared).unwrap_err();
+    assert!(e.is::<errors::InvalidArgument>());
+}
+
+#[test]
+fn test_basis() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let calc = |measure_y, measure_x| {
+        let driver_args = DriverArgs {
+            iter: 10,
+            measure_y,
+            measure_x,
+            fixed_limit: Some(1000),
+            basis: true,
+            ..base_args()
+        };
+        driver::calc(&driver_args, &input).unwrap()
+    };
+    let types = calc(MeasureY::Types, MeasureX::Tokens);
+    let basis = types.basis.unwrap();
+    assert_eq!(basis, types.get_basis());
+    assert_eq!(
+        (
+            basis.measure_y,
+            basis.measure_x,
+            basis.limit,
+            basis.split_samples
+        ),
+        (MeasureY::Types, MeasureX::Tokens, 1000, false)
+    );
+    let hapaxes = calc(MeasureY::Hapaxes, MeasureX::Tokens);
+    assert!(types.comparable_with(&hapaxes));
+    let hapaxes_words = calc(MeasureY::Hapaxes, MeasureX::Words);
+    assert!(!types.comparable_with(&hapaxes_words));
+    assert!(!hapaxes.comparable_with(&hapaxes_words));
+    let output = driver::calc(
+        &DriverArgs {
+            iter: 10,
+            ..base_args()
+        },
+        &input,
+    )
+    .unwrap();
+    assert_eq!(output.basis, None);
+}
+
+#[test]
+fn test_type_target() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        type_target: Some(100),
+        extremes: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let mut checked = 0;
+    for r in &output.curves[0].results {
+        let t = r.x_at_target.unwrap();
+        assert_eq!((t.target, t.iter), (100, 100));
+        if t.reached > 0 {
+            let (low, median, high) = (t.low.unwrap(), t.median.unwrap(), t.high.unwrap());
+            let mean = t.mean.unwrap();
+            assert!(low <= median && median <= high);
+            assert!(low as f64 <= mean && mean <= high as f64);
+        }
+        // Types only increase, so the extremes at the limit tell on which side the target is.
+        let ar = r.average_at_limit;
+        if ar.min_y.unwrap() >= 100 {
+            assert_eq!(t.reached, t.iter);
+            assert!(t.high.unwrap() <= output.limit);
+            checked += 1;
+        } else if ar.max_y.unwrap() < 100 && t.reached > 0 {
+            assert!(t.low.unwrap() > output.limit);
+            checked += 1;
+        }
+    }
+    assert!(checked > 0);
+    let driver_args = DriverArgs {
+        type_target: Some(0),
+        ..driver_args
+    };
+    let e = driver::calc(&driver_args, &input).unwrap_err();
+    assert!(e.is::<errors::InvalidArgument>());
+}
+
+#[cfg(feature = "schemars")]
+#[test]
+fn test_schema() {
+    use types3::schema;
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let validator = jsonschema::validator_for(&schema::input_schema()).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&data).unwrap();
+    assert!(validator.is_valid(&value));
+    let driver_args = DriverArgs {
+        iter: 100,
+        category: Some("gender"),
+        display_bucket: Some(100),
+        explain_limit: true,
+        limit_units: true,
+        extremes: true,
+        z_score: true,
+        category_spread: true,
+        basis: true,
+        type_target: Some(100),
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    let validator = jsonschema::validator_for(&schema::output_schema()).unwrap();
+    let mut value = serde_json::to_value(&output).unwrap();
+    if let Err(e) = validator.validate(&value) {
+        panic!("{e}");
+    }
+    value["measure_y"] = "words".into();
+    assert!(!validator.is_valid(&value));
+}
+
+#[test]
+fn test_observed_ci() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        observed_ci: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for r in &output.curves[0].results {
+        let ci = r.observed_ci.unwrap();
+        assert_eq!(ci.iter, 100);
+        assert!(ci.low <= ci.high);
+        assert!(ci.low as f64 <= ci.mean && ci.mean <= ci.high as f64);
+    }
+    let output = driver::calc(
+        &DriverArgs {
+            iter: 100,
+            ..base_args()
+        },
+        &input,
+    )
+    .unwrap();
+    assert!(output.curves[0]
+        .results
+        .iter()
+        .all(|r| r.observed_ci.is_none()));
+}
+
+#[test]
+fn test_extrapolate() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        display_bucket: Some(100),
+        extrapolate: Some(100000),
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for r in &output.curves[0].results {
+        let curve = r.extrapolation.as_ref().unwrap();
+        let size = r.size.unwrap();
+        assert_eq!(curve.last().unwrap().x, 100000);
+        assert!(curve.iter().any(|p| p.x == size && !p.extrapolated));
+        for (a, b) in curve.iter().zip(&curve[1..]) {
+            assert!(a.expected <= b.expected);
+        }
+    }
+    for (measure_y, extrapolate) in [(MeasureY::Hapaxes, Some(1000)), (MeasureY::Types, Some(0))] {
+        let driver_args = DriverArgs {
+            measure_y,
+            extrapolate,
+            ..driver_args
+        };
+        let e = driver::calc(&driver_args, &input).unwrap_err();
+        assert!(e.is::<errors::InvalidArgument>());
+    }
+}
+
+#[test]
+fn test_mean_curve() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        category: Some("gender"),
+        mean_curve: true,
+        ..base_args()
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for c in &output.curves {
+        for r in &c.results {
+            let curve = r.mean_curve.as_ref().unwrap();
+            assert_eq!(curve.iter, r.average_at_limit.iter);
+            assert_eq!(curve.points.len() as u64, output::MEAN_CURVE_STEPS + 1);
+            let last = curve.points.last().unwrap();
+            assert_eq!(last.x, output.limit);
+            assert_eq!(
+                (last.low, last.high),
+                (r.average_at_limit.low, r.average_at_limit.high)
+            );
+            for (a, b) in curve.points.iter().zip(&curve.points[1..]) {
+                assert!(a.x < b.x);
+                assert!(a.low <= b.low && a.high <= b.high);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_full_curve() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let plain = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        full_curve: true,
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for (c, pc) in output.curves.iter().zip(&plain.curves) {
+        for (r, pr) in c.results.iter().zip(&pc.results) {
+            assert!(pr.curve.is_none());
+            assert_eq!(r.average_at_limit, pr.average_at_limit);
+            let curve = r.curve.as_ref().unwrap();
+            assert!(curve.len() as u64 <= output::FULL_CURVE_STEPS + 1);
+            let (x, ar) = curve[0];
+            assert_eq!((x, ar.low, ar.high), (0, 0, 0));
+            // The curve goes beyond the size limit, up to all samples of the subset.
+            let &(x, ar) = curve.last().unwrap();
+            assert!(x >= output.limit);
+            assert_eq!(ar.low, ar.high);
+            for ((x1, a1), (x2, a2)) in curve.iter().zip(&curve[1..]) {
+                assert!(x1 < x2);
+                assert_eq!(a1.iter, r.average_at_limit.iter);
+                assert!(a1.low <= a2.low && a1.high <= a2.high);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_percentiles() {
+    init();
+    let data = slurp("sample-data/ceec.json");
+    let input: Input = serde_json::from_str(&data).unwrap();
+    let driver_args = DriverArgs {
+        iter: 100,
+        category: Some("gender"),
+        ..base_args()
+    };
+    let plain = driver::calc(&driver_args, &input).unwrap();
+    let driver_args = DriverArgs {
+        percentiles: Some((0.025, 0.975)),
+        ..driver_args
+    };
+    let output = driver::calc(&driver_args, &input).unwrap();
+    for (c, pc) in output.curves.iter().zip(&plain.curves) {
+        for (r, pr) in c.results.iter().zip(&pc.results) {
+            assert_eq!(pr.average_at_limit.percentiles, None);
+            let (low, high) = r.average_at_limit.percentiles.unwrap();
+            assert_eq!(
+                AvgResult {
+                    percentiles: None,
+                    ..r.average_at_limit
+                },
+                pr.average_at_limit
+            );
+            let iter = r.average_at_limit.iter;
+            assert!(low * iter <= r.average_at_limit.low);
+            assert!(r.average_at_limit.high <= high * iter);
+        }
+    }
+    for percentiles in [(0.9, 0.1), (-0.1, 0.5), (0.5, 1.5)] {
+        let driver_args = DriverArgs {
+            percentiles: Some(percentiles),
+            ..driver_args
+        };
+        assert!(driver::calc(&driver_args, &input).is_err());
+    }
+}